@@ -1,93 +1,600 @@
+mod paths;
+
 use std::{
+    cell::Cell,
     cmp,
-    collections::HashMap,
-    env, fs,
-    io::{self, Read, stdout},
-    mem,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Read},
+    mem, panic,
     path::{Path, PathBuf},
-    process::Command,
-    time::{Duration, SystemTime},
+    process::{Command, ExitCode, ExitStatus, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{Context, Result, anyhow};
+use arboard::Clipboard;
+use chrono::{
+    DateTime, Local, NaiveDate,
+    format::{Item, StrftimeItems},
+};
 use content_inspector::ContentType;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    cursor::Show,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use dirs::config_dir;
-use fs_extra::dir::{CopyOptions as DirCopyOptions, copy as copy_dir};
+use flate2::read::GzDecoder;
+use fs_extra::dir::{
+    CopyOptions as DirCopyOptions, TransitProcessResult, copy as copy_dir,
+    copy_with_progress as copy_dir_with_progress,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use ratatui::{
     Frame, Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Wrap,
+    },
 };
-use serde::Deserialize;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use similar::{ChangeTag, TextDiff};
 use tokio::{
     runtime::{Handle, Runtime},
     sync::mpsc::{UnboundedReceiver, UnboundedSender, error::TryRecvError, unbounded_channel},
 };
-use toml;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const PREVIEW_MAX_BYTES: usize = 8 * 1024;
 const PREVIEW_MAX_LINES: usize = 80;
+const PREVIEW_CACHE_MAX: usize = 128;
 const PREVIEW_DIR_ENTRIES: usize = 12;
+const OFFICE_XML_MAX_BYTES: u64 = 4 * 1024 * 1024;
+const DELETE_SCAN_LIMIT: usize = 1000;
+const SIZE_BAR_WIDTH: usize = 8;
+const HASH_CHUNK_BYTES: usize = 64 * 1024;
+const HASH_PROGRESS_BYTES: u64 = 64 * 1024 * 1024;
+const TREE_MAX_LINES: usize = 5000;
+const VIEWER_PAGE_LINES: usize = 20;
+const DIFF_MAX_BYTES: usize = 1024 * 1024;
+const MARQUEE_DELAY: Duration = Duration::from_millis(1000);
+const MARQUEE_STEP: Duration = Duration::from_millis(250);
+const FOOTER_MIN_SEGMENT: usize = 8;
+const PREVIEW_COUNT_LIMIT: usize = 1000;
+const CHANGE_HIGHLIGHT: Duration = Duration::from_secs(3);
+const TRASH_MAX_ITEMS: usize = 200;
+const TRASH_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+const PARALLEL_SCAN_THRESHOLD: usize = 256;
+const SCAN_MAX_THREADS: usize = 8;
+const OPERATION_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+const SCAN_RETRY_BACKOFF: Duration = Duration::from_millis(250);
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+const EXIT_ERROR: u8 = 1;
+const EXIT_NOTHING_CHOSEN: u8 = 2;
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+const CONFIG_RELOAD_DEBOUNCE: Duration = Duration::from_millis(300);
+const TOAST_MAX_LINES: usize = 12;
+const FIND_MAX_RESULTS: usize = 1000;
+const RECENT_MAX_RESULTS: usize = 50;
+const RECENT_MAX_DEPTH: usize = 8;
+const RECENT_STREAM_INTERVAL: Duration = Duration::from_millis(200);
+const FINDER_MAX_FILES: usize = 100_000;
+const FINDER_STREAM_INTERVAL: Duration = Duration::from_millis(100);
+const GREP_MAX_MATCHES: usize = 1000;
+const GREP_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const GREP_LINE_WIDTH: usize = 200;
+const DEFAULT_LOCATE_MAX_RESULTS: usize = 20;
+const MACRO_MAX_DEPTH: usize = 8;
+const SORT_MEMORY_MAX_DIRS: usize = 500;
+const HISTORY_MAX: usize = 100;
+const FRECENCY_MAX_RANK: f64 = 10_000.0;
+const MUTATING_COMMANDS: &[&str] = &[
+    "rename",
+    "rename-regex",
+    "delete",
+    "delete!",
+    "mkdir",
+    "touch",
+    "new",
+    "paste",
+    "copy",
+    "move",
+    "flatten",
+    "restore",
+    "chmod",
+    "split",
+    "cat",
+    "extract",
+    "queue",
+    "edit",
+    "sh",
+];
+
+struct CommandSpec {
+    name: &'static str,
+    summary: &'static str,
+    needs_args: bool,
+}
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "pwd",
+        summary: "Show the current directory",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "refresh",
+        summary: "Reload the listing",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "refresh!",
+        summary: "Drop caches and reload",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "config",
+        summary: "Edit the config file",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "rename",
+        summary: "Rename the selection",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "rename-regex",
+        summary: "Rename marked entries with a regex substitution (previewed first)",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "delete",
+        summary: "Move the selection or marks to the trash",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "delete!",
+        summary: "Delete the selection or marks permanently",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "restore",
+        summary: "Restore from the trash",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "mkdir",
+        summary: "Create a directory",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "touch",
+        summary: "Create an empty file",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "new",
+        summary: "Create a file and open it in $EDITOR",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "paste",
+        summary: "Paste clipboard text or files",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "copy",
+        summary: "Copy the selection or marks",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "move",
+        summary: "Move the selection or marks",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "dual",
+        summary: "Toggle a second file listing in place of the preview",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "miller",
+        summary: "Toggle parent, current and preview columns",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "chmod",
+        summary: "Change permissions",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "split",
+        summary: "Split a file into parts",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "cat",
+        summary: "Join marked files",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "flatten",
+        summary: "Move a directory's contents up",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "grep",
+        summary: "Search file contents below the current dir",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "find",
+        summary: "Fuzzy-find files below the current dir (or list names containing text)",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "locate",
+        summary: "Locate an exact name",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "cancel",
+        summary: "Cancel the running copy, move, delete or other file job",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "recent",
+        summary: "List recently modified files below here",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "filter",
+        summary: "Hide entries not matching a pattern",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "gitignore",
+        summary: "Dim or hide gitignored entries",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "select",
+        summary: "Mark entries matching a glob",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "unselect",
+        summary: "Unmark entries matching a glob",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "since",
+        summary: "Show recently modified entries",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "sort",
+        summary: "Change the sort order",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "sort!",
+        summary: "Reverse the sort order",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "times",
+        summary: "Toggle absolute times",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "group",
+        summary: "Toggle the grouped view",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "dirsfirst",
+        summary: "Toggle directories before files",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "relnum",
+        summary: "Toggle relative line numbers",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "note",
+        summary: "Edit the directory note",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "marks",
+        summary: "List, clear or make marks global",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "queue",
+        summary: "Review, run or toggle staged operations",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "df",
+        summary: "Show disk usage",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "extract",
+        summary: "Extract archive members",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "pline",
+        summary: "Jump the preview to a line",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "tree",
+        summary: "Show a directory tree",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "diff",
+        summary: "Diff two files",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "favorites",
+        summary: "List starred files",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "z",
+        summary: "Jump to the most frecent directory matching the fragments",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "bookmarks",
+        summary: "List bookmarked directories",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "yankname",
+        summary: "Copy the selection's name",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "yankpath",
+        summary: "Copy the selection's path",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "yankstem",
+        summary: "Copy the name without extension",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "yankrel",
+        summary: "Copy a relative path",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "edit",
+        summary: "Open in $EDITOR",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "openwith",
+        summary: "Pick an application",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "sh",
+        summary: "Run a shell or one command",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "cd",
+        summary: "Change directory",
+        needs_args: true,
+    },
+    CommandSpec {
+        name: "tabnew",
+        summary: "Open a tab on the current or given directory",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "tabclose",
+        summary: "Close the current tab",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "hash",
+        summary: "Checksum the selected file",
+        needs_args: false,
+    },
+    CommandSpec {
+        name: "help",
+        summary: "List commands",
+        needs_args: false,
+    },
+];
+
+enum ExitReason {
+    Quit,
+    Chose(PathBuf),
+    NothingChosen,
+}
+
+impl ExitReason {
+    fn code(&self) -> ExitCode {
+        match self {
+            Self::Quit | Self::Chose(_) => ExitCode::SUCCESS,
+            Self::NothingChosen => ExitCode::from(EXIT_NOTHING_CHOSEN),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    install_panic_hook();
+    let result = init_terminal()
+        .context("failed to init terminal")
+        .and_then(|mut terminal| {
+            let app_result = run_app(&mut terminal);
+            cleanup_terminal(&mut terminal).context("failed to restore terminal")?;
+            app_result
+        });
+    match result {
+        Ok(reason) => {
+            if let ExitReason::Chose(path) = &reason {
+                println!("{}", path.display());
+            }
+            reason.code()
+        }
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn install_panic_hook() {
+    install_panic_hook_with(|| {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stderr(), LeaveAlternateScreen, Show);
+    });
+}
+
+/// Runs `restore` before the default hook, but only for panics on the
+/// installing thread; a panicking worker must not tear down a live UI.
+fn install_panic_hook_with(restore: impl Fn() + Send + Sync + 'static) {
+    let ui_thread = thread::current().id();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == ui_thread {
+            restore();
+        }
+        default_hook(info);
+    }));
+}
+
+fn read_only_flag() -> bool {
+    env::args().skip(1).any(|arg| arg == "--read-only")
+}
 
-fn main() -> Result<()> {
-    let mut terminal = init_terminal().context("failed to init terminal")?;
-    let app_result = run_app(&mut terminal);
-    cleanup_terminal(&mut terminal).context("failed to restore terminal")?;
-    app_result
+fn chooser_flag() -> bool {
+    env::args().skip(1).any(|arg| arg == "--choose")
 }
 
-fn init_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
+fn init_terminal() -> Result<Terminal<CrosstermBackend<io::Stderr>>> {
     enable_raw_mode().context("enable raw mode")?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen).context("switch to alternate screen")?;
-    let backend = CrosstermBackend::new(stdout);
+    let mut stderr = io::stderr();
+    execute!(stderr, EnterAlternateScreen).context("switch to alternate screen")?;
+    let backend = CrosstermBackend::new(stderr);
     Terminal::new(backend).context("spawn terminal backend")
 }
 
-fn cleanup_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn cleanup_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>) -> Result<()> {
     disable_raw_mode().context("disable raw mode")?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen).context("leave alternate screen")?;
     terminal.show_cursor().context("show cursor")
 }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>) -> Result<ExitReason> {
     let runtime = Runtime::new().context("start async runtime")?;
     let (fs_dispatcher, mut fs_rx) = FsDispatcher::new(&runtime);
-    let config = load_config();
+    let (mut config, config_problems) = startup_config(read_config());
+    config.read_only |= read_only_flag();
     let mut app = App::new(fs_dispatcher, config).context("construct app")?;
+    if let Some((title, lines)) = config_problems {
+        app.show_error_toast(title, lines);
+    }
+    app.chooser = chooser_flag();
     let tick_rate = Duration::from_millis(150);
 
     loop {
         app.drain_fs_events(&mut fs_rx);
         process_external_commands(&mut app, terminal);
+        app.tick(Instant::now());
         terminal
             .draw(|frame| render(frame, &app))
             .context("draw frame")?;
-        if poll_and_handle_events(&mut app, tick_rate)? {
+        if app.quit_requested || poll_and_handle_events(&mut app, tick_rate)? {
             break;
         }
     }
-    Ok(())
+    if app.frecency.dirty {
+        let _ = save_frecency(&app.frecency);
+    }
+    if app.state_dirty {
+        let _ = app.save_state();
+    }
+    Ok(match app.chosen.take() {
+        Some(path) => ExitReason::Chose(path),
+        None if app.chooser => ExitReason::NothingChosen,
+        None => ExitReason::Quit,
+    })
 }
 
-fn process_external_commands(app: &mut App, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) {
+fn process_external_commands(app: &mut App, terminal: &mut Terminal<CrosstermBackend<io::Stderr>>) {
     while let Some(command) = app.take_external_command() {
         let result = match command {
-            ExternalCommand::Edit { path, name } => run_editor(terminal, &path)
-                .and_then(|_| app.refresh_with_message(false, format!("Edited {}", name))),
+            ExternalCommand::EditLine { path, line } => {
+                run_editor(terminal, std::slice::from_ref(&path), Some(line)).and_then(|_| {
+                    app.refresh_with_message(false, format!("Edited {}", display_name(&path)))
+                })
+            }
+            ExternalCommand::Edit { paths, name } => {
+                run_editor(terminal, &paths, None).and_then(|_| {
+                    if config_file_path().is_some_and(|config| paths.contains(&config)) {
+                        if let Err(err) = app.reload_config() {
+                            app.set_error(format!("Kept previous config: {err:#}"));
+                        }
+                        Ok(())
+                    } else {
+                        app.refresh_with_message(false, format!("Edited {}", name))
+                    }
+                })
+            }
+            ExternalCommand::OpenWith { command, path } => {
+                run_terminal_program(terminal, &command, &path).and_then(|_| {
+                    app.refresh_with_message(false, format!("Returned from {}", command.join(" ")))
+                })
+            }
             ExternalCommand::Shell { dir } => run_shell(terminal, &dir)
                 .and_then(|_| app.refresh_with_message(false, "Returned from shell")),
+            ExternalCommand::Run { dir, command } => run_shell_command(terminal, &dir, &command)
+                .and_then(|status| {
+                    let message = if status.success() {
+                        format!("Ran: {command}")
+                    } else {
+                        format!(
+                            "'{command}' exited with status {}",
+                            status
+                                .code()
+                                .map(|c| c.to_string())
+                                .unwrap_or_else(|| "unknown".into())
+                        )
+                    };
+                    app.refresh_with_message(false, message)
+                }),
         };
         if let Err(err) = result {
-            app.status = format!("External command failed: {err:#}");
+            app.set_error(format!("External command failed: {err:#}"));
         }
     }
 }
@@ -95,10 +602,8 @@ fn process_external_commands(app: &mut App, terminal: &mut Terminal<CrosstermBac
 fn poll_and_handle_events(app: &mut App, tick_rate: Duration) -> Result<bool> {
     if event::poll(tick_rate).context("poll for events")? {
         match event::read().context("read event")? {
-            Event::Key(key) if key.kind == KeyEventKind::Press => {
-                if handle_key_event(app, key)? {
-                    return Ok(true);
-                }
+            Event::Key(key) if key.kind == KeyEventKind::Press && handle_key_event(app, key)? => {
+                return Ok(true);
             }
             _ => {}
         }
@@ -107,24 +612,119 @@ fn poll_and_handle_events(app: &mut App, tick_rate: Duration) -> Result<bool> {
 }
 
 fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool> {
+    if let Some(toast) = app.toast.take()
+        && toast.is_error
+    {
+        return Ok(false);
+    }
     match app.input_mode.clone() {
         InputMode::Normal => handle_normal_mode(app, key),
         InputMode::Search { .. } => handle_search_mode(app, key),
+        InputMode::Filter { .. } => handle_filter_mode(app, key),
         InputMode::Command { .. } => handle_command_mode(app, key),
+        InputMode::PreviewSearch { .. } => handle_preview_search_mode(app, key),
+        InputMode::Viewer { .. } => handle_viewer_mode(app, key),
+        InputMode::Note { .. } => handle_note_mode(app, key),
+        InputMode::Palette { .. } => handle_palette_mode(app, key),
+        InputMode::Fuzzy { .. } => handle_fuzzy_mode(app, key),
+        InputMode::DiskUsage { .. } => {
+            if matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                app.cancel_overlay();
+                app.status = "Disk usage closed".into();
+            }
+            Ok(false)
+        }
+        InputMode::Picker { .. } => handle_picker_mode(app, key),
         InputMode::Confirm { .. } => handle_confirm_mode(app, key),
     }
 }
 
 fn handle_normal_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    if key.code != KeyCode::Char('d') {
+        app.awaiting_d = false;
+    }
+    let register_key = app.awaiting_register.take();
+    let z_prefix = mem::take(&mut app.awaiting_z);
+    if let Some(action) = app.awaiting_bookmark.take() {
+        app.clear_pending_count();
+        let result = match key.code {
+            KeyCode::Char(name) if name.is_ascii_alphanumeric() && action == 'm' => {
+                app.set_bookmark(name)
+            }
+            KeyCode::Char(name) if name.is_ascii_alphanumeric() => app.jump_to_bookmark(name),
+            _ => {
+                app.status = "Bookmark canceled".into();
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            app.set_error(format!("Bookmark failed: {err:#}"));
+        }
+        return Ok(false);
+    }
     match key.code {
-        KeyCode::Char('q') => return Ok(true),
+        KeyCode::Char('q') => {
+            if app.queued.is_empty() {
+                return Ok(true);
+            }
+            app.awaiting_g = false;
+            app.clear_pending_count();
+            app.input_mode = InputMode::Confirm {
+                message: format!("{} queued operation(s) have not run.", app.queued.len()),
+                action: ConfirmAction::Quit,
+            };
+            app.status = "Execute, discard or cancel".into();
+        }
+        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.awaiting_g = false;
+            app.start_palette();
+        }
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.awaiting_g = false;
+            app.cancel_job();
+        }
+        KeyCode::Char('d') if z_prefix => {
+            app.awaiting_g = false;
+            app.toggle_dirs_first();
+        }
+        KeyCode::Char('d') => {
+            app.awaiting_g = false;
+            if !app.awaiting_d {
+                app.awaiting_d = true;
+                app.status = "Press d again to delete".into();
+            } else {
+                app.awaiting_d = false;
+                let count = app.take_count().unwrap_or(1);
+                if app.read_only {
+                    app.status = "delete: read-only mode".into();
+                } else if app.archive.is_some() {
+                    app.status = "delete: archives are read-only".into();
+                } else if let Err(err) = app.request_delete_range(count) {
+                    app.set_error(format!("Delete failed: {err:#}"));
+                }
+            }
+        }
         KeyCode::Char('j') | KeyCode::Down => {
             app.awaiting_g = false;
-            app.move_selection_by_count(1)
+            app.move_focused_by_count(1)
         }
         KeyCode::Char('k') | KeyCode::Up => {
             app.awaiting_g = false;
-            app.move_selection_by_count(-1)
+            app.move_focused_by_count(-1)
+        }
+        KeyCode::Tab | KeyCode::BackTab if app.dual.is_some() => {
+            app.awaiting_g = false;
+            if let Err(err) = app.swap_panes() {
+                app.set_error(format!("Pane switch failed: {err:#}"));
+            }
+        }
+        KeyCode::Tab => {
+            app.awaiting_g = false;
+            app.cycle_focus(1);
+        }
+        KeyCode::BackTab => {
+            app.awaiting_g = false;
+            app.cycle_focus(-1);
         }
         KeyCode::Char('g') => {
             if app.awaiting_g {
@@ -136,6 +736,25 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.status = "Press g again to jump to entry".into();
             }
         }
+        KeyCode::Char(ch @ ('t' | 'T')) if app.awaiting_g => {
+            app.awaiting_g = false;
+            let result = match (ch, app.take_count()) {
+                ('t', Some(count)) => app.switch_tab(count.saturating_sub(1)),
+                ('t', None) => app.cycle_tab(1),
+                (_, count) => app.cycle_tab(-(count.unwrap_or(1) as isize)),
+            };
+            if let Err(err) = result {
+                app.set_error(format!("Tab switch failed: {err:#}"));
+            }
+        }
+        KeyCode::Char(ch @ '1'..='9') if key.modifiers.contains(KeyModifiers::ALT) => {
+            app.awaiting_g = false;
+            app.clear_pending_count();
+            let index = ch as usize - '1' as usize;
+            if let Err(err) = app.switch_tab(index) {
+                app.set_error(format!("Tab switch failed: {err:#}"));
+            }
+        }
         KeyCode::Char('G') => {
             app.awaiting_g = false;
             if let Some(count) = app.take_count() {
@@ -145,24 +764,81 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.jump_to_end();
             }
         }
+        KeyCode::Char('%') => {
+            app.awaiting_g = false;
+            match app.take_count() {
+                Some(percent) => app.jump_to_percent(percent),
+                None => app.status = "Prefix % with a percentage, e.g. 50%".into(),
+            }
+        }
         KeyCode::Char('r') => {
             app.awaiting_g = false;
             handle_refresh(app);
             app.clear_pending_count();
         }
+        KeyCode::Char('R') => {
+            app.awaiting_g = false;
+            app.hard_refresh();
+            app.clear_pending_count();
+        }
+        KeyCode::Char(ch @ ('m' | '\'')) => {
+            app.awaiting_g = false;
+            app.clear_pending_count();
+            app.awaiting_bookmark = Some(ch);
+            app.status = if ch == 'm' {
+                "Bookmark the current directory as (a-z, 0-9)".into()
+            } else {
+                "Jump to bookmark (a-z, 0-9)".into()
+            };
+        }
+        KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.awaiting_g = false;
+            if let Err(err) = app.history_step(true) {
+                app.set_error(format!("Error: {err:#}"));
+            }
+        }
+        KeyCode::Char(ch @ ('H' | 'L')) => {
+            app.awaiting_g = false;
+            if let Err(err) = app.history_step(ch == 'H') {
+                app.set_error(format!("Error: {err:#}"));
+            }
+        }
+        KeyCode::Char('h') if z_prefix => {
+            app.clear_pending_count();
+            app.toggle_hidden();
+        }
+        KeyCode::Char('i') if z_prefix => {
+            if let Err(err) = app.command_gitignore("") {
+                app.set_error(format!("gitignore failed: {err:#}"));
+            }
+        }
+        KeyCode::Char('z') => {
+            app.awaiting_g = false;
+            app.awaiting_z = true;
+            app.status =
+                "Press h to toggle hidden files, d directories first, i gitignored entries".into();
+        }
+        KeyCode::Char('.') => {
+            app.awaiting_g = false;
+            app.clear_pending_count();
+            app.toggle_hidden();
+        }
         KeyCode::Char('h') | KeyCode::Left => {
             app.awaiting_g = false;
             if let Err(err) = app.open_parent() {
-                app.status = format!("Error: {err:#}");
+                app.set_error(format!("Error: {err:#}"));
             }
             app.clear_pending_count();
         }
         KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
             app.awaiting_g = false;
             if let Err(err) = app.enter_selection() {
-                app.status = format!("Error: {err:#}");
+                app.set_error(format!("Error: {err:#}"));
             }
             app.clear_pending_count();
+            if app.chosen.is_some() {
+                return Ok(true);
+            }
         }
         KeyCode::Char('n') => {
             app.awaiting_g = false;
@@ -175,13 +851,112 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
             app.clear_pending_count();
         }
         KeyCode::Char('/') => {
+            app.awaiting_g = false;
+            app.start_finder();
+        }
+        KeyCode::Char('f') => {
             app.awaiting_g = false;
             app.start_search();
         }
+        KeyCode::Char('?') => {
+            app.awaiting_g = false;
+            app.start_preview_search();
+        }
+        KeyCode::Char(']') => {
+            app.awaiting_g = false;
+            app.preview_search_next();
+            app.clear_pending_count();
+        }
+        KeyCode::Char('[') => {
+            app.awaiting_g = false;
+            app.preview_search_prev();
+            app.clear_pending_count();
+        }
+        KeyCode::Char('J') => {
+            app.awaiting_g = false;
+            app.scroll_preview_by_count(1);
+        }
+        KeyCode::Char('K') => {
+            app.awaiting_g = false;
+            app.scroll_preview_by_count(-1);
+        }
         KeyCode::Char(':') => {
             app.awaiting_g = false;
             app.start_command();
         }
+        KeyCode::Char(' ') => {
+            app.awaiting_g = false;
+            app.toggle_mark();
+            app.clear_pending_count();
+        }
+        KeyCode::Char(ch @ ('y' | 'x' | 'p')) => {
+            app.awaiting_g = false;
+            app.clear_pending_count();
+            if register_key != Some(ch) {
+                app.awaiting_register = Some(ch);
+                let action = match ch {
+                    'y' => "yank",
+                    'x' => "cut",
+                    _ => "paste",
+                };
+                app.status = format!("Press {ch} again to {action}");
+            } else if ch == 'p' {
+                if app.read_only {
+                    app.status = "paste: read-only mode".into();
+                } else if app.archive.is_some() {
+                    app.status = "paste: archives are read-only".into();
+                } else if let Err(err) = app.paste_register() {
+                    app.set_error(format!("Paste failed: {err:#}"));
+                }
+            } else {
+                let kind = if ch == 'y' {
+                    RegisterKind::Yank
+                } else {
+                    RegisterKind::Cut
+                };
+                if let Err(err) = app.fill_register(kind) {
+                    app.set_error(format!("{err:#}"));
+                }
+            }
+        }
+        KeyCode::Char('v') => {
+            app.awaiting_g = false;
+            app.toggle_visual();
+            app.clear_pending_count();
+        }
+        KeyCode::Esc if app.visual.is_some() => {
+            app.toggle_visual();
+        }
+        KeyCode::Esc if app.filter.is_some() => {
+            app.clear_pending_count();
+            app.set_filter(None);
+        }
+        KeyCode::Char('F') => app.start_filter(),
+        KeyCode::Char('s') => {
+            app.awaiting_g = false;
+            app.cycle_sort();
+        }
+        KeyCode::Char('o') => {
+            app.awaiting_g = false;
+            if let Err(err) = app.command_open_with() {
+                app.set_error(format!("Open failed: {err:#}"));
+            }
+            app.clear_pending_count();
+        }
+        KeyCode::Char('*') => {
+            app.awaiting_g = false;
+            if let Err(err) = app.toggle_favorite() {
+                app.set_error(format!("Favorite failed: {err:#}"));
+            }
+            app.clear_pending_count();
+        }
+        KeyCode::Char('P') => {
+            app.awaiting_g = false;
+            if let Err(err) = app.toggle_pin() {
+                app.set_error(format!("Pin failed: {err:#}"));
+            }
+            app.clear_pending_count();
+        }
         KeyCode::Char(ch) if ch.is_ascii_digit() => {
             app.accumulate_count(ch);
         }
@@ -227,6 +1002,36 @@ fn handle_search_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
     Ok(false)
 }
 
+fn handle_filter_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Esc => {
+            app.cancel_overlay();
+            app.set_filter(None);
+        }
+        KeyCode::Enter => {
+            if let InputMode::Filter { buffer } = &app.input_mode {
+                let pattern = buffer.clone();
+                app.cancel_overlay();
+                app.set_filter(Some(pattern));
+            }
+        }
+        KeyCode::Backspace => {
+            if let InputMode::Filter { buffer } = &mut app.input_mode {
+                buffer.pop();
+            }
+            app.preview_filter();
+        }
+        KeyCode::Char(ch) if !ch.is_control() => {
+            if let InputMode::Filter { buffer } = &mut app.input_mode {
+                buffer.push(ch);
+            }
+            app.preview_filter();
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
 fn handle_command_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
     match key.code {
         KeyCode::Esc => {
@@ -239,21 +1044,34 @@ fn handle_command_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
                     app.set_overlay_feedback("Enter a command");
                 } else {
                     let command = buffer.clone();
+                    let targets = app.result_targets.take();
                     app.cancel_overlay();
-                    app.run_command(command);
+                    match targets {
+                        Some(paths) => app.run_on_results(paths, command),
+                        None => app.run_command(command),
+                    }
                 }
             }
         }
+        KeyCode::Tab => {
+            if let Err(err) = app.complete_cd() {
+                app.set_overlay_feedback(format!("{err:#}"));
+            }
+        }
         KeyCode::Backspace => {
             if let InputMode::Command { buffer, .. } = &mut app.input_mode {
                 buffer.pop();
             }
+            app.cd_completions = None;
+            app.pending_zoxide = None;
             app.clear_overlay_feedback();
         }
         KeyCode::Char(ch) if !ch.is_control() => {
             if let InputMode::Command { buffer, .. } = &mut app.input_mode {
                 buffer.push(ch);
             }
+            app.cd_completions = None;
+            app.pending_zoxide = None;
             app.clear_overlay_feedback();
         }
         _ => {}
@@ -261,41 +1079,330 @@ fn handle_command_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
     Ok(false)
 }
 
-fn handle_confirm_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+fn handle_palette_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
     match key.code {
-        KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
+        KeyCode::Esc => {
             app.cancel_overlay();
-            app.status = "Action canceled".into();
+            app.status = "Palette canceled".into();
         }
-        KeyCode::Enter | KeyCode::Char('y') | KeyCode::Char('Y') => {
-            if let InputMode::Confirm { action, .. } =
-                mem::replace(&mut app.input_mode, InputMode::Normal)
-            {
-                match app.execute_confirm_action(action) {
-                    Ok(_) => {}
-                    Err(err) => app.status = format!("Action failed: {err:#}"),
-                }
+        KeyCode::Enter => app.choose_palette_item(),
+        KeyCode::Up => app.move_palette(-1),
+        KeyCode::Down => app.move_palette(1),
+        KeyCode::Char('p') if control => app.move_palette(-1),
+        KeyCode::Char('n') if control => app.move_palette(1),
+        KeyCode::Backspace => {
+            if let InputMode::Palette { buffer, selected } = &mut app.input_mode {
+                buffer.pop();
+                *selected = 0;
+            }
+        }
+        KeyCode::Char(ch) if !ch.is_control() && !control => {
+            if let InputMode::Palette { buffer, selected } = &mut app.input_mode {
+                buffer.push(ch);
+                *selected = 0;
             }
-            app.clear_pending_count();
         }
         _ => {}
     }
     Ok(false)
 }
 
-fn handle_refresh(app: &mut App) {
-    if let Err(err) = app.refresh_async(false) {
-        app.status = format!("Error: {err:#}");
-    }
-}
-
-fn run_editor(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, path: &Path) -> Result<()> {
-    suspend_terminal(terminal)?;
-    let editor = resolve_editor();
+fn handle_fuzzy_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Esc => {
+            app.cancel_overlay();
+            app.status = "Finder canceled".into();
+        }
+        KeyCode::Enter => app.choose_fuzzy_item(),
+        KeyCode::Up => app.move_fuzzy(-1),
+        KeyCode::Down => app.move_fuzzy(1),
+        KeyCode::Char('p') if control => app.move_fuzzy(-1),
+        KeyCode::Char('n') if control => app.move_fuzzy(1),
+        KeyCode::Backspace => {
+            if let InputMode::Fuzzy {
+                buffer, selected, ..
+            } = &mut app.input_mode
+            {
+                buffer.pop();
+                *selected = 0;
+            }
+        }
+        KeyCode::Char(ch) if !ch.is_control() && !control => {
+            if let InputMode::Fuzzy {
+                buffer, selected, ..
+            } = &mut app.input_mode
+            {
+                buffer.push(ch);
+                *selected = 0;
+            }
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_note_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.cancel_overlay();
+            app.status = "Note discarded".into();
+        }
+        KeyCode::Esc => {
+            let InputMode::Note { dir, buffer, .. } = &app.input_mode else {
+                return Ok(false);
+            };
+            match save_note(dir, buffer) {
+                Ok(()) => {
+                    app.has_note = !buffer.trim().is_empty();
+                    app.input_mode = InputMode::Normal;
+                    app.status = if app.has_note {
+                        "Note saved".into()
+                    } else {
+                        "Note removed".into()
+                    };
+                }
+                Err(err) => app.set_error(format!(
+                    "Saving note failed: {err:#} (Esc retries, Ctrl-C discards)"
+                )),
+            }
+        }
+        _ => {
+            if let InputMode::Note { buffer, cursor, .. } = &mut app.input_mode {
+                edit_text(buffer, cursor, key);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Applies an editing key to a multi-line buffer at `cursor` (a byte offset on a char
+/// boundary). Returns false for keys that are not editing keys.
+fn edit_text(buffer: &mut String, cursor: &mut usize, key: KeyEvent) -> bool {
+    let line_start = buffer[..*cursor].rfind('\n').map_or(0, |pos| pos + 1);
+    let line_end = buffer[*cursor..]
+        .find('\n')
+        .map_or(buffer.len(), |pos| *cursor + pos);
+    let prev_char = buffer[..*cursor].chars().next_back().map(char::len_utf8);
+    let next_char = buffer[*cursor..].chars().next().map(char::len_utf8);
+    match key.code {
+        KeyCode::Char(ch) if !ch.is_control() && !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            buffer.insert(*cursor, ch);
+            *cursor += ch.len_utf8();
+        }
+        KeyCode::Enter => {
+            buffer.insert(*cursor, '\n');
+            *cursor += 1;
+        }
+        KeyCode::Backspace => {
+            if let Some(len) = prev_char {
+                *cursor -= len;
+                buffer.remove(*cursor);
+            }
+        }
+        KeyCode::Delete => {
+            if next_char.is_some() {
+                buffer.remove(*cursor);
+            }
+        }
+        KeyCode::Left => *cursor -= prev_char.unwrap_or(0),
+        KeyCode::Right => *cursor += next_char.unwrap_or(0),
+        KeyCode::Home => *cursor = line_start,
+        KeyCode::End => *cursor = line_end,
+        KeyCode::Up | KeyCode::Down => {
+            let column = buffer[line_start..*cursor].chars().count();
+            let target = if key.code == KeyCode::Up {
+                match line_start.checked_sub(1) {
+                    Some(prev_end) => buffer[..prev_end].rfind('\n').map_or(0, |pos| pos + 1),
+                    None => return true,
+                }
+            } else if line_end < buffer.len() {
+                line_end + 1
+            } else {
+                return true;
+            };
+            let target_line = buffer[target..].split('\n').next().unwrap_or_default();
+            *cursor = target
+                + target_line
+                    .char_indices()
+                    .nth(column)
+                    .map_or(target_line.len(), |(offset, _)| offset);
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn handle_preview_search_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Esc => {
+            app.cancel_overlay();
+            app.status = "Preview search canceled".into();
+        }
+        KeyCode::Enter => {
+            if let InputMode::PreviewSearch { buffer, .. } = &app.input_mode {
+                if buffer.is_empty() {
+                    app.set_overlay_feedback("Enter a search query");
+                } else {
+                    let query = buffer.clone();
+                    app.cancel_overlay();
+                    app.apply_preview_search(&query);
+                }
+            }
+        }
+        KeyCode::Backspace => {
+            if let InputMode::PreviewSearch { buffer, .. } = &mut app.input_mode {
+                buffer.pop();
+            }
+            app.clear_overlay_feedback();
+        }
+        KeyCode::Char(ch) if !ch.is_control() => {
+            if let InputMode::PreviewSearch { buffer, .. } = &mut app.input_mode {
+                buffer.push(ch);
+            }
+            app.clear_overlay_feedback();
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_viewer_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            app.cancel_overlay();
+            app.status = "Viewer closed".into();
+        }
+        KeyCode::Char('j') | KeyCode::Down => app.scroll_viewer(1),
+        KeyCode::Char('k') | KeyCode::Up => app.scroll_viewer(-1),
+        KeyCode::PageDown | KeyCode::Char(' ') => app.scroll_viewer(VIEWER_PAGE_LINES as isize),
+        KeyCode::PageUp => app.scroll_viewer(-(VIEWER_PAGE_LINES as isize)),
+        KeyCode::Char('g') | KeyCode::Home => app.scroll_viewer(isize::MIN),
+        KeyCode::Char('G') | KeyCode::End => app.scroll_viewer(isize::MAX),
+        KeyCode::Char('y') => {
+            if let Err(err) = app.copy_viewer_text() {
+                app.set_error(format!("Copy failed: {err:#}"));
+            }
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_picker_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            app.cancel_overlay();
+            app.status = "Selection canceled".into();
+        }
+        KeyCode::Char('j') | KeyCode::Down => app.move_picker(1),
+        KeyCode::Char('k') | KeyCode::Up => app.move_picker(-1),
+        KeyCode::Char(ch @ '1'..='9') => {
+            let index = ch as usize - '1' as usize;
+            if let InputMode::Picker {
+                items, selected, ..
+            } = &mut app.input_mode
+                && index < items.len()
+            {
+                *selected = index;
+                app.choose_picker_item();
+            }
+        }
+        KeyCode::Enter | KeyCode::Char('l') => app.choose_picker_item(),
+        KeyCode::Char('d') if app.is_reviewing_queue() => app.drop_queued(),
+        KeyCode::Char('x') if app.is_reviewing_queue() => app.discard_queue(),
+        KeyCode::Char(' ') => app.toggle_result_mark(),
+        KeyCode::Char('a') => app.toggle_all_result_marks(),
+        KeyCode::Char('e') => app.apply_to_results("edit"),
+        KeyCode::Char('d') => app.apply_to_results("delete"),
+        KeyCode::Char('c') => app.apply_to_results("copy"),
+        KeyCode::Char('m') => app.apply_to_results("move"),
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_confirm_mode(app: &mut App, key: KeyEvent) -> Result<bool> {
+    if let InputMode::Confirm {
+        action: ConfirmAction::Quit,
+        ..
+    } = app.input_mode
+    {
+        return Ok(handle_quit_confirm(app, key));
+    }
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
+            if let InputMode::Confirm {
+                action: ConfirmAction::LoadLargeDir { path },
+                ..
+            } = mem::replace(&mut app.input_mode, InputMode::Normal)
+            {
+                app.cancel_overlay();
+                app.decline_large_dir(path);
+                return Ok(false);
+            }
+            app.cancel_overlay();
+            app.status = "Action canceled".into();
+        }
+        KeyCode::Enter | KeyCode::Char('y') | KeyCode::Char('Y') => {
+            if let InputMode::Confirm { action, .. } =
+                mem::replace(&mut app.input_mode, InputMode::Normal)
+            {
+                match app.execute_confirm_action(action) {
+                    Ok(_) => {}
+                    Err(err) => app.set_error(format!("Action failed: {err:#}")),
+                }
+            }
+            app.clear_pending_count();
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_quit_confirm(app: &mut App, key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('e') | KeyCode::Char('E') => {
+            app.cancel_overlay();
+            match app.run_queue() {
+                Ok(()) => {
+                    app.quit_after_queue = true;
+                    app.status = "Running the queue, quitting when it is done".into();
+                }
+                Err(err) => app.set_error(format!("queue failed: {err:#}")),
+            }
+            false
+        }
+        KeyCode::Char('d') | KeyCode::Char('D') => true,
+        KeyCode::Esc | KeyCode::Char('c') | KeyCode::Char('C') | KeyCode::Char('n') => {
+            app.cancel_overlay();
+            app.status = "Quit canceled".into();
+            false
+        }
+        _ => false,
+    }
+}
+
+fn handle_refresh(app: &mut App) {
+    if let Err(err) = app.refresh_async(false) {
+        app.set_error(format!("Error: {err:#}"));
+    }
+}
+
+fn run_editor(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    paths: &[PathBuf],
+    line: Option<usize>,
+) -> Result<()> {
+    suspend_terminal(terminal)?;
+    let editor = resolve_editor();
     let status_result = Command::new(&editor)
-        .arg(path)
+        .args(line.map(|line| format!("+{line}")))
+        .args(paths)
+        .stdout(child_stdout())
         .status()
-        .with_context(|| format!("launching {} for {}", editor, path.display()));
+        .with_context(|| format!("launching {} for {} file(s)", editor, paths.len()));
     let resume_result = resume_terminal(terminal);
     let status = status_result?;
     resume_result?;
@@ -317,11 +1424,75 @@ fn resolve_editor() -> String {
         .unwrap_or_else(|_| "vi".into())
 }
 
-fn run_shell(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, dir: &Path) -> Result<()> {
+fn run_terminal_program(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    command: &[String],
+    path: &Path,
+) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty command"))?;
+    suspend_terminal(terminal)?;
+    let status_result = Command::new(program)
+        .args(args)
+        .arg(path)
+        .stdout(child_stdout())
+        .status()
+        .with_context(|| format!("launching {} for {}", program, path.display()));
+    let resume_result = resume_terminal(terminal);
+    let status = status_result?;
+    resume_result?;
+    if !status.success() {
+        return Err(anyhow!(
+            "{} exited with status {}",
+            program,
+            status
+                .code()
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "unknown".into())
+        ));
+    }
+    Ok(())
+}
+
+/// Stdout for interactive children: stdout may be a `--choose` pipe, so
+/// they write to the terminal the UI is drawn on instead.
+fn child_stdout() -> Stdio {
+    Stdio::from(io::stderr())
+}
+
+fn spawn_detached(command: &[String], path: &Path) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("Empty command"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("launching {} for {}", program, path.display()))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn system_open_command() -> Vec<String> {
+    if cfg!(target_os = "macos") {
+        vec!["open".into()]
+    } else if cfg!(windows) {
+        vec!["cmd".into(), "/C".into(), "start".into(), "".into()]
+    } else {
+        vec!["xdg-open".into()]
+    }
+}
+
+fn run_shell(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, dir: &Path) -> Result<()> {
     suspend_terminal(terminal)?;
     let shell = resolve_shell();
     let status_result = Command::new(&shell)
         .current_dir(dir)
+        .stdout(child_stdout())
         .status()
         .with_context(|| format!("launching shell {} in {}", shell, dir.display()));
     let resume_result = resume_terminal(terminal);
@@ -339,11 +1510,40 @@ fn run_shell(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, dir: &Path)
     Ok(())
 }
 
+fn run_shell_command(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    dir: &Path,
+    command: &str,
+) -> Result<ExitStatus> {
+    suspend_terminal(terminal)?;
+    let shell = resolve_shell();
+    let status_result = Command::new(&shell)
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .stdout(child_stdout())
+        .status()
+        .with_context(|| format!("running '{}' with {}", command, shell));
+    if status_result.is_ok() {
+        eprint!("\nPress Enter to return to wayfinder...");
+        let _ = io::Write::flush(&mut io::stderr());
+        let _ = io::stdin().read_line(&mut String::new());
+    }
+    let resume_result = resume_terminal(terminal);
+    let status = status_result?;
+    resume_result?;
+    Ok(status)
+}
+
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
 fn resolve_shell() -> String {
     env::var("SHELL").unwrap_or_else(|_| "/bin/sh".into())
 }
 
-fn suspend_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn suspend_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>) -> Result<()> {
     disable_raw_mode().context("disable raw mode for external command")?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)
         .context("leave alternate screen for external command")?;
@@ -351,7 +1551,7 @@ fn suspend_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Re
     Ok(())
 }
 
-fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>) -> Result<()> {
     enable_raw_mode().context("enable raw mode after external command")?;
     execute!(terminal.backend_mut(), EnterAlternateScreen)
         .context("re-enter alternate screen after external command")?;
@@ -373,1179 +1573,15014 @@ fn render(frame: &mut Frame, app: &App) {
     draw_body(frame, layout[1], app);
     draw_footer(frame, layout[2], app);
     draw_overlay(frame, app);
+    draw_viewer(frame, app);
+    draw_picker(frame, app);
+    draw_note(frame, app);
+    draw_disk_usage(frame, app);
+    draw_palette(frame, app);
+    draw_fuzzy(frame, app);
+    draw_toast(frame, app);
+}
+
+fn draw_toast(frame: &mut Frame, app: &App) {
+    let Some(toast) = &app.toast else {
+        return;
+    };
+    let area = frame.size();
+    let lines: Vec<&String> = toast.lines.iter().take(TOAST_MAX_LINES).collect();
+    let mut text: Vec<Line> = lines.iter().map(|line| Line::from(line.as_str())).collect();
+    if toast.lines.len() > TOAST_MAX_LINES {
+        text.push(Line::from(format!(
+            "... and {} more",
+            toast.lines.len() - TOAST_MAX_LINES
+        )));
+    }
+    let longest = text
+        .iter()
+        .map(|line| line.width())
+        .chain([toast.title.width()])
+        .max()
+        .unwrap_or(0);
+    let width = (longest as u16 + 4)
+        .min(area.width.saturating_sub(2))
+        .max(20.min(area.width));
+    let height = (text.len() as u16 + 2).min(area.height.saturating_sub(2));
+    let rect = Rect {
+        x: area.x + area.width.saturating_sub(width + 1),
+        y: area.y + 1,
+        width,
+        height,
+    };
+    let color = if toast.is_error {
+        app.theme.error
+    } else {
+        app.theme.success
+    };
+    let hint = if toast.is_error { " (any key)" } else { "" };
+    frame.render_widget(Clear, rect);
+    frame.render_widget(
+        Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(color))
+                .title(format!("{}{hint}", toast.title)),
+        ),
+        rect,
+    );
 }
 
 fn draw_header(frame: &mut Frame, area: Rect, app: &App) {
     let title = Span::styled(
         "Wayfinder",
         Style::default()
-            .fg(Color::Yellow)
+            .fg(app.theme.title)
             .add_modifier(Modifier::BOLD),
     );
-    let path = Span::styled(
-        app.current_dir.display().to_string(),
-        Style::default().fg(Color::Cyan),
-    );
-    let line = Line::from(vec![title, Span::raw(" - "), path]);
-    let widget = Paragraph::new(line).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title("Current Directory"),
+    let location = app.archive.as_ref().map_or_else(
+        || app.current_dir.display().to_string(),
+        |view| view.location(),
     );
+    let path = Span::styled(location, Style::default().fg(app.theme.path));
+    let mut spans = vec![title, Span::raw(" - "), path];
+    if app.has_note {
+        spans.push(Span::styled(
+            "  [note]",
+            Style::default().fg(app.theme.accent),
+        ));
+    }
+    spans.push(Span::styled(
+        format!(
+            "  [sort: {}{}]",
+            app.sort.label(),
+            if app.dirs_first { "" } else { ", mixed" }
+        ),
+        Style::default().fg(app.theme.muted),
+    ));
+    if let Some(filter) = &app.filter {
+        spans.push(Span::styled(
+            format!("  [filter: {}]", filter.pattern),
+            Style::default().fg(app.theme.accent),
+        ));
+    }
+    if let Some(mount) = &app.mount_info {
+        spans.push(Span::styled(
+            format!("  [{} on {}]", mount.fs_type, mount.mount_point.display()),
+            Style::default().fg(app.theme.muted),
+        ));
+    }
+    if app.tabs.len() > 1 {
+        spans.push(Span::raw("  "));
+        for index in 0..app.tabs.len() {
+            let label = format!(" {} {} ", index + 1, app.tab_label(index));
+            let style = if index == app.active_tab {
+                Style::default()
+                    .fg(app.theme.accent)
+                    .add_modifier(Modifier::BOLD | Modifier::REVERSED)
+            } else {
+                Style::default().fg(app.theme.muted)
+            };
+            spans.push(Span::styled(label, style));
+        }
+    }
+    let line = Line::from(spans);
+    let widget = Paragraph::new(line).block(Block::default().borders(Borders::ALL).title(
+        if app.archive.is_some() {
+            "Archive (read-only, h at the top leaves)"
+        } else {
+            "Current Directory"
+        },
+    ));
     frame.render_widget(widget, area);
 }
 
 fn draw_body(frame: &mut Frame, area: Rect, app: &App) {
+    let vertical = app.layout.orientation.is_vertical(area);
+    let list_width = if app.dual.is_some() {
+        50
+    } else {
+        app.layout.list_width
+    };
     let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(area);
-
-    let list_items: Vec<ListItem> = app
-        .entries
-        .iter()
-        .map(|entry| {
-            let icon = if entry.is_dir { "[D]" } else { "[F]" };
-            let line = Line::from(vec![
-                Span::styled(icon, Style::default().fg(Color::LightBlue)),
-                Span::raw(" "),
-                Span::raw(&entry.name),
-            ]);
-            ListItem::new(line)
+        .direction(if vertical {
+            Direction::Vertical
+        } else {
+            Direction::Horizontal
         })
-        .collect();
-
-    let list = List::new(list_items)
-        .block(Block::default().borders(Borders::ALL).title("Files"))
-        .highlight_style(
-            Style::default()
-                .fg(Color::Black)
-                .bg(Color::LightGreen)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol("> ");
+        .constraints([
+            Constraint::Percentage(list_width),
+            Constraint::Percentage(100 - list_width),
+        ])
+        .split(area);
 
-    let mut list_state = app.list_state();
-    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+    if let Some(dual) = &app.dual {
+        let (active, other) = if dual.right_active {
+            (chunks[1], chunks[0])
+        } else {
+            (chunks[0], chunks[1])
+        };
+        let location = app.archive.as_ref().map_or_else(
+            || app.current_dir.display().to_string(),
+            |view| view.location(),
+        );
+        draw_file_list(frame, active, app, location);
+        draw_other_pane(frame, other, app, &dual.other);
+        return;
+    }
+    if app.layout.miller {
+        let rest = 100 - app.layout.parent_width;
+        let list_share = rest * list_width / 100;
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(app.layout.parent_width),
+                Constraint::Percentage(list_share),
+                Constraint::Percentage(rest - list_share),
+            ])
+            .split(area);
+        draw_parent_column(frame, columns[0], app);
+        draw_file_list(frame, columns[1], app, "Files".into());
+        draw_preview(frame, columns[2], app);
+        return;
+    }
+    draw_file_list(frame, chunks[0], app, "Files".into());
 
+    let side_by_side = vertical && !Orientation::Auto.is_vertical(chunks[1]);
     let right = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(25), Constraint::Percentage(75)])
+        .direction(if side_by_side {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        })
+        .constraints([
+            Constraint::Percentage(app.layout.detail_height),
+            Constraint::Percentage(100 - app.layout.detail_height),
+        ])
         .split(chunks[1]);
 
     let detail = Paragraph::new(app.describe_selection())
         .wrap(Wrap { trim: true })
-        .block(Block::default().borders(Borders::ALL).title("Details"));
+        .scroll((app.details_scroll.min(u16::MAX as usize) as u16, 0))
+        .block(app.pane_block("Details", Focus::Details));
     frame.render_widget(detail, right[0]);
 
-    let preview = Paragraph::new(app.preview.body.as_str())
-        .wrap(Wrap { trim: false })
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(app.preview.title.as_str()),
-        );
-    frame.render_widget(preview, right[1]);
+    draw_preview(frame, right[1], app);
 }
 
-fn draw_footer(frame: &mut Frame, area: Rect, app: &App) {
-    let footer = Paragraph::new(app.footer_text())
-        .style(Style::default().fg(Color::Gray))
-        .block(Block::default().borders(Borders::ALL));
-    frame.render_widget(footer, area);
+fn draw_preview(frame: &mut Frame, area: Rect, app: &App) {
+    let lines = preview_lines(app);
+    // `preview_scroll` counts logical lines; the paragraph scrolls by
+    // wrapped rows, so count the rows the lines above it take up.
+    let above = lines[..app.preview_scroll.min(lines.len())].to_vec();
+    let rows = Paragraph::new(above)
+        .wrap(Wrap { trim: false })
+        .line_count(area.width.saturating_sub(2));
+    let preview = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((rows.min(u16::MAX as usize) as u16, 0))
+        .block(app.pane_block(app.preview.title.as_str(), Focus::Preview));
+    frame.render_widget(preview, area);
 }
 
-fn draw_overlay(frame: &mut Frame, app: &App) {
-    if let Some((title, content)) = app.overlay_prompt() {
-        let area = overlay_area(frame.size());
-        frame.render_widget(Clear, area);
-        let widget =
-            Paragraph::new(content).block(Block::default().borders(Borders::ALL).title(title));
-        frame.render_widget(widget, area);
-    }
+fn draw_parent_column(frame: &mut Frame, area: Rect, app: &App) {
+    let Some((dir, entries)) = &app.parent_listing else {
+        frame.render_widget(Block::default().borders(Borders::ALL), area);
+        return;
+    };
+    let selected = app.current_dir.file_name().and_then(|name| {
+        entries
+            .iter()
+            .position(|entry| entry.path.file_name() == Some(name))
+    });
+    draw_plain_list(
+        frame,
+        area,
+        app,
+        display_name(dir),
+        entries,
+        selected,
+        &app.marked,
+    );
 }
 
-fn overlay_area(area: Rect) -> Rect {
-    let height = 3u16;
-    let width = area.width.saturating_sub(2);
-    let x = area.x + 1;
-    let y = area.y + area.height.saturating_sub(height + 1);
-    Rect {
-        x,
-        y,
-        width,
-        height,
+fn draw_file_list(frame: &mut Frame, area: Rect, app: &App, title: String) {
+    let number_width = if app.relative_numbers {
+        app.entries.len().to_string().len().max(2)
+    } else {
+        0
+    };
+    let fixed_width: usize = app
+        .columns
+        .iter()
+        .map(|column| app.column_width(*column).unwrap_or(0))
+        .sum::<usize>()
+        + app.columns.len().saturating_sub(1)
+        + if app.relative_numbers {
+            number_width + 1
+        } else {
+            0
+        };
+    let name_budget = (area.width as usize).saturating_sub(2 + 2 + fixed_width);
+    let mut list_items: Vec<ListItem> = app
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let mut spans = Vec::new();
+            if app.relative_numbers {
+                let (number, color) = if index == app.selected {
+                    (index + 1, app.theme.accent)
+                } else {
+                    (index.abs_diff(app.selected), app.theme.muted)
+                };
+                spans.push(Span::styled(
+                    format!("{number:>number_width$} "),
+                    Style::default().fg(color),
+                ));
+            }
+            for (pos, column) in app.columns.iter().enumerate() {
+                if pos > 0 {
+                    spans.push(Span::raw(" "));
+                }
+                match column {
+                    ListColumn::Icon => {
+                        let icon = if entry.is_dir { "[D]" } else { "[F]" };
+                        spans.push(Span::styled(icon, Style::default().fg(app.theme.directory)));
+                    }
+                    ListColumn::Bar => {
+                        let bar = entry
+                            .size
+                            .map(|size| size_bar(size, app.max_entry_size, SIZE_BAR_WIDTH))
+                            .unwrap_or_default();
+                        spans.push(Span::styled(
+                            format!("{bar:<width$}", width = SIZE_BAR_WIDTH),
+                            Style::default().fg(app.theme.bar),
+                        ));
+                    }
+                    ListColumn::Name => {
+                        let pad = pos + 1 < app.columns.len();
+                        spans.extend(name_spans(app, index, entry, name_budget, pad));
+                    }
+                    ListColumn::Size => {
+                        let size = entry.size.map(format_size).unwrap_or_default();
+                        spans.push(Span::raw(format!("{size:>10}")));
+                    }
+                    ListColumn::Modified => {
+                        let width = app.column_width(ListColumn::Modified).unwrap_or(4);
+                        let text = match app.absolute_time_format() {
+                            Some(format) => entry
+                                .modified
+                                .map(|time| format_time(time, format))
+                                .unwrap_or_default(),
+                            None => entry.modified.map(format_age).unwrap_or_default(),
+                        };
+                        spans.push(Span::styled(
+                            format!("{text:>width$}"),
+                            Style::default().fg(app.theme.muted),
+                        ));
+                    }
+                    ListColumn::Permissions => {
+                        let mode = entry
+                            .mode
+                            .map(|mode| format_mode(mode, entry.is_dir))
+                            .unwrap_or_default();
+                        spans.push(Span::styled(
+                            format!("{mode:<10}"),
+                            Style::default().fg(app.theme.muted),
+                        ));
+                    }
+                }
+            }
+            let mut style = Style::default();
+            if !entry.is_dir
+                && let (Some(size), Some(threshold)) = (entry.size, app.warn_size)
+                && size >= threshold
+            {
+                style = style.fg(app.theme.warning);
+            }
+            if entry.ignored && app.gitignore == GitignoreMode::Dim {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            if app.is_marked(entry) {
+                style = style.fg(app.theme.accent);
+            }
+            if let Some(color) = app.change_highlight(entry) {
+                style = style.bg(color);
+            }
+            ListItem::new(Line::from(spans)).style(style)
+        })
+        .collect();
+    let group_starts = app.group_starts();
+    for (pos, (start, group)) in group_starts.iter().enumerate().rev() {
+        let end = group_starts
+            .get(pos + 1)
+            .map_or(app.entries.len(), |(next, _)| *next);
+        list_items.insert(
+            *start,
+            ListItem::new(Line::styled(
+                format!("── {} ({}) ", group.label(), end - start),
+                Style::default()
+                    .fg(app.theme.title)
+                    .add_modifier(Modifier::BOLD),
+            )),
+        );
     }
-}
+    let total_rows = list_items.len();
 
-#[derive(Clone)]
-enum InputMode {
-    Normal,
-    Search {
-        buffer: String,
-        feedback: Option<String>,
-    },
-    Command {
-        buffer: String,
-        feedback: Option<String>,
-    },
-    Confirm {
-        message: String,
-        action: ConfirmAction,
-    },
-}
+    let list = List::new(list_items)
+        .block(app.pane_block(title, Focus::List))
+        .highlight_style(app.theme.selection())
+        .highlight_symbol("> ");
 
-#[derive(Default, Deserialize)]
-struct RawConfig {
-    #[serde(default)]
-    command_aliases: HashMap<String, String>,
+    let mut list_state = app.list_state();
+    frame.render_stateful_widget(list, area, &mut list_state);
+    app.list_offset.set(list_state.offset());
+    let visible_rows = area.height.saturating_sub(2) as usize;
+    if total_rows > visible_rows {
+        let mut scrollbar_state =
+            ScrollbarState::new(total_rows).position(list_state.selected().unwrap_or(0));
+        frame.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None),
+            area.inner(&Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut scrollbar_state,
+        );
+    }
 }
 
-#[derive(Clone)]
-struct Config {
-    command_aliases: HashMap<String, String>,
+fn draw_other_pane(frame: &mut Frame, area: Rect, app: &App, tab: &Tab) {
+    let title = tab
+        .archive
+        .as_ref()
+        .map_or_else(|| tab.dir.display().to_string(), |view| view.location());
+    let marked = if app.global_marks {
+        &app.marked
+    } else {
+        &tab.marked
+    };
+    let selected = (!tab.entries.is_empty()).then_some(tab.selected);
+    draw_plain_list(frame, area, app, title, &tab.entries, selected, marked);
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        let mut aliases = HashMap::new();
-        aliases.insert("rm".into(), "delete".into());
-        aliases.insert("cp".into(), "copy".into());
-        aliases.insert("mv".into(), "move".into());
-        Self {
-            command_aliases: aliases,
-        }
-    }
+fn draw_plain_list(
+    frame: &mut Frame,
+    area: Rect,
+    app: &App,
+    title: String,
+    entries: &[FileEntry],
+    selected: Option<usize>,
+    marked: &HashSet<PathBuf>,
+) {
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|entry| {
+            let icon = if entry.is_dir { "[D]" } else { "[F]" };
+            let size = entry.size.map(format_size).unwrap_or_default();
+            let mut style = if marked.contains(&entry.path) {
+                Style::default().fg(app.theme.accent)
+            } else {
+                Style::default()
+            };
+            if entry.ignored && app.gitignore == GitignoreMode::Dim {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            ListItem::new(Line::from(vec![
+                Span::styled(icon, Style::default().fg(app.theme.directory)),
+                Span::raw(" "),
+                Span::raw(entry.name.clone()),
+                Span::styled(format!("  {size}"), Style::default().fg(app.theme.muted)),
+            ]))
+            .style(style)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::UNDERLINED))
+        .highlight_symbol("  ");
+    let mut state = ratatui::widgets::ListState::default().with_selected(selected);
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
-fn load_config() -> Config {
-    let mut config = Config::default();
-    if let Some(mut dir) = config_dir() {
-        dir.push("wayfinder");
-        let path = dir.join("config.toml");
-        if let Ok(contents) = fs::read_to_string(&path) {
-            match toml::from_str::<RawConfig>(&contents) {
-                Ok(raw) => {
-                    for (alias, command) in raw.command_aliases {
-                        config
-                            .command_aliases
-                            .insert(alias.to_lowercase(), command.to_lowercase());
-                    }
-                }
-                Err(err) => eprintln!("Failed to parse config {}: {err}", path.display()),
-            }
-        }
+fn name_spans<'a>(
+    app: &App,
+    index: usize,
+    entry: &'a FileEntry,
+    budget: usize,
+    pad: bool,
+) -> Vec<Span<'a>> {
+    let pinned = app.is_pinned(entry);
+    let favorite = app.is_favorite(entry);
+    let mut name_width = budget;
+    if entry.is_executable {
+        name_width = name_width.saturating_sub(1);
+    }
+    if pinned {
+        name_width = name_width.saturating_sub(" [pinned]".len());
+    }
+    if favorite {
+        name_width = name_width.saturating_sub(2);
+    }
+    let marker = match entry.dir_status {
+        Some(DirStatus::Empty) => Some((" (empty)", app.theme.muted)),
+        Some(DirStatus::Unreadable) => Some((" 🔒", app.theme.error)),
+        None => None,
+    };
+    if let Some((text, _)) = marker {
+        name_width = name_width.saturating_sub(text.width());
+    }
+    let name = if app.long_names == LongNameMode::Scroll && index == app.selected {
+        marquee(&entry.name, name_width, app.marquee_step())
+    } else {
+        truncate_middle(&entry.name, name_width)
+    };
+    let mut used = name.width();
+    let mut spans = Vec::new();
+    if entry.is_executable {
+        spans.push(Span::styled(
+            format!("{name}*"),
+            Style::default().fg(app.theme.success),
+        ));
+        used += 1;
+    } else if let Some((_, color)) = marker {
+        spans.push(Span::styled(name, Style::default().fg(color)));
+    } else {
+        spans.push(Span::raw(name));
+    }
+    if let Some((text, color)) = marker {
+        spans.push(Span::styled(text, Style::default().fg(color)));
+        used += text.width();
+    }
+    if favorite {
+        spans.push(Span::styled(" ★", Style::default().fg(app.theme.accent)));
+        used += 2;
     }
-    config
+    if pinned {
+        spans.push(Span::styled(
+            " [pinned]",
+            Style::default().fg(app.theme.accent),
+        ));
+        used += " [pinned]".len();
+    }
+    if pad && used < budget {
+        spans.push(Span::raw(" ".repeat(budget - used)));
+    }
+    spans
 }
 
-fn split_command(input: &str) -> (&str, &str) {
-    if let Some((cmd, rest)) = input.split_once(char::is_whitespace) {
-        (cmd, rest.trim_start())
-    } else {
-        (input, "")
+fn format_age(time: SystemTime) -> String {
+    let secs = time.elapsed().map(|age| age.as_secs()).unwrap_or(0);
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3_600 => format!("{}m", secs / 60),
+        3_600..86_400 => format!("{}h", secs / 3_600),
+        86_400..31_536_000 => format!("{}d", secs / 86_400),
+        _ => format!("{}y", secs / 31_536_000),
     }
 }
 
-#[derive(Clone)]
-enum ConfirmAction {
-    Delete { entry: FileEntry, path: PathBuf },
+fn format_time(time: SystemTime, format: &str) -> String {
+    DateTime::<Local>::from(time).format(format).to_string()
 }
 
-#[derive(Clone)]
-enum ExternalCommand {
-    Edit { path: PathBuf, name: String },
-    Shell { dir: PathBuf },
+fn valid_time_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
 }
 
-#[derive(Clone)]
-struct PreviewPane {
-    title: String,
-    body: String,
+fn format_mode(mode: u32, is_dir: bool) -> String {
+    let mut text = String::with_capacity(10);
+    text.push(if is_dir { 'd' } else { '-' });
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    text
 }
 
-impl PreviewPane {
-    fn new<T: Into<String>, B: Into<String>>(title: T, body: B) -> Self {
-        Self {
-            title: title.into(),
-            body: body.into(),
+fn fit_segments(segments: &[String], width: usize) -> String {
+    const SEPARATOR: &str = " | ";
+    let mut line = String::new();
+    for segment in segments {
+        let separator = if line.is_empty() { "" } else { SEPARATOR };
+        let room = width.saturating_sub(line.width() + separator.len());
+        if segment.width() <= room {
+            line.push_str(separator);
+            line.push_str(segment);
+            continue;
+        }
+        if line.is_empty() || room >= FOOTER_MIN_SEGMENT {
+            line.push_str(separator);
+            line.push_str(&truncate_end(segment, room));
         }
+        break;
     }
+    line
+}
 
-    fn empty() -> Self {
-        Self::new("Preview", "No file selected")
+fn truncate_end(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
     }
-
-    fn loading() -> Self {
-        Self::new("Preview", "Loading preview...")
+    if width == 0 {
+        return String::new();
     }
-
-    fn error(message: impl Into<String>) -> Self {
-        Self::new("Preview", message)
+    let mut result = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        used += ch.width().unwrap_or(0);
+        if used > width - 1 {
+            break;
+        }
+        result.push(ch);
     }
+    result.push('…');
+    result
 }
 
-struct App {
-    current_dir: PathBuf,
-    entries: Vec<FileEntry>,
-    selected: usize,
-    status: String,
-    fs: FsDispatcher,
-    pending_token: Option<u64>,
-    next_token: u64,
-    is_loading: bool,
-    input_mode: InputMode,
-    pending_count: Option<usize>,
-    last_search: Option<String>,
-    last_action_message: Option<String>,
-    pending_external: Option<ExternalCommand>,
-    preview: PreviewPane,
-    awaiting_g: bool,
-    command_aliases: HashMap<String, String>,
-}
-
-impl App {
-    const HELP_LINE: &'static str = "j/k navigate | h/l change dirs | q quit";
-
-    fn new(fs: FsDispatcher, config: Config) -> Result<Self> {
-        let current_dir = std::env::current_dir().context("read current dir")?;
-        let mut app = Self {
-            current_dir,
-            entries: Vec::new(),
-            selected: 0,
-            status: String::new(),
-            fs,
-            pending_token: None,
-            next_token: 0,
-            is_loading: false,
-            input_mode: InputMode::Normal,
-            pending_count: None,
-            last_search: None,
-            last_action_message: None,
-            pending_external: None,
-            preview: PreviewPane::loading(),
-            awaiting_g: false,
-            command_aliases: config.command_aliases,
-        };
-        app.refresh_async(true)?;
-        Ok(app)
+fn truncate_middle(name: &str, width: usize) -> String {
+    if name.width() <= width {
+        return name.to_string();
     }
-
-    fn refresh_async(&mut self, clear_entries: bool) -> Result<()> {
-        if clear_entries {
-            self.entries.clear();
-            self.selected = 0;
-            self.preview = PreviewPane::loading();
-        }
-        let token = self.next_token;
-        self.next_token += 1;
-        let path = self.current_dir.clone();
-        self.fs
-            .request_directory_scan(path.clone(), token)
-            .context("queue directory scan")?;
-
-        self.pending_token = Some(token);
-        self.is_loading = true;
-        self.status = format!("Loading {} ...", path.display());
-        Ok(())
+    if width == 0 {
+        return String::new();
     }
-
-    fn refresh_with_message<S: Into<String>>(
-        &mut self,
-        clear_entries: bool,
-        message: S,
-    ) -> Result<()> {
-        self.last_action_message = Some(message.into());
-        self.refresh_async(clear_entries)
+    let head_budget = (width - 1) / 2;
+    let tail_budget = width - 1 - head_budget;
+    let mut head = String::new();
+    let mut used = 0;
+    for ch in name.chars() {
+        used += ch.width().unwrap_or(0);
+        if used > head_budget {
+            break;
+        }
+        head.push(ch);
     }
-
-    fn move_selection(&mut self, delta: isize) {
-        if self.entries.is_empty() {
-            self.selected = 0;
-            self.preview = PreviewPane::empty();
-            return;
+    let mut tail: Vec<char> = Vec::new();
+    used = 0;
+    for ch in name.chars().rev() {
+        used += ch.width().unwrap_or(0);
+        if used > tail_budget {
+            break;
         }
-        let len = self.entries.len() as isize;
-        let next = (self.selected as isize + delta).rem_euclid(len);
-        self.selected = next as usize;
-        self.update_preview();
+        tail.push(ch);
     }
+    head.push('…');
+    head.extend(tail.into_iter().rev());
+    head
+}
 
-    fn move_selection_by_count(&mut self, delta: isize) {
-        let count = self.consume_count_or(1);
-        let scaled = delta.saturating_mul(count as isize);
-        self.move_selection(scaled);
+fn marquee(name: &str, width: usize, step: usize) -> String {
+    if name.width() <= width {
+        return name.to_string();
     }
-
-    fn accumulate_count(&mut self, digit: char) {
-        if let Some(value) = digit.to_digit(10) {
-            let next = self
-                .pending_count
-                .unwrap_or(0)
-                .saturating_mul(10)
-                .saturating_add(value as usize);
-            let capped = next.min(9_999);
-            self.pending_count = Some(capped);
-            self.status = format!("Count: {capped}");
+    let looped: Vec<char> = name.chars().chain("   ".chars()).collect();
+    let start = step % looped.len();
+    let mut visible = String::new();
+    let mut used = 0;
+    for ch in looped.iter().cycle().skip(start) {
+        used += ch.width().unwrap_or(0);
+        if used > width {
+            break;
         }
+        visible.push(*ch);
     }
+    visible
+}
 
-    fn consume_count_or(&mut self, default: usize) -> usize {
-        self.pending_count.take().unwrap_or(default)
+fn size_bar(size: u64, max: u64, width: usize) -> String {
+    const PARTIALS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    if max == 0 {
+        return String::new();
     }
-
-    fn take_count(&mut self) -> Option<usize> {
-        self.pending_count.take()
+    let eighths = ((size as f64 / max as f64) * (width * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(PARTIALS[eighths % 8]);
     }
+    bar
+}
 
-    fn clear_pending_count(&mut self) {
-        self.pending_count = None;
+fn preview_lines(app: &App) -> Vec<Line<'_>> {
+    let needle = app.preview_query.as_deref().unwrap_or("");
+    if let Some(styled) = &app.preview.styled
+        && needle.is_empty()
+    {
+        return styled.clone();
     }
+    app.preview
+        .body
+        .lines()
+        .map(|line| {
+            let ranges = find_case_insensitive(line, needle);
+            if ranges.is_empty() {
+                return Line::raw(line);
+            }
+            let highlight = Style::default()
+                .fg(app.theme.selection_fg)
+                .bg(app.theme.accent);
+            let mut spans = Vec::new();
+            let mut cursor = 0;
+            for (start, end) in ranges {
+                if start > cursor {
+                    spans.push(Span::raw(&line[cursor..start]));
+                }
+                spans.push(Span::styled(&line[start..end], highlight));
+                cursor = end;
+            }
+            if cursor < line.len() {
+                spans.push(Span::raw(&line[cursor..]));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
 
-    fn resolve_command_alias(&self, cmd: &str) -> String {
-        let key = cmd.to_lowercase();
-        self.command_aliases.get(&key).cloned().unwrap_or(key)
-    }
+fn fuzzy_score(candidate: &str, query: &str) -> Option<i64> {
+    fuzzy_match(candidate, query).map(|(score, _)| score)
+}
 
-    fn jump_to_index(&mut self, index: usize) {
-        if self.entries.is_empty() {
-            self.selected = 0;
-            self.preview = PreviewPane::empty();
-            return;
+fn fuzzy_match(candidate: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+    let candidate: Vec<char> = candidate
+        .chars()
+        .map(|ch| ch.to_lowercase().next().unwrap_or(ch))
+        .collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut positions = Vec::new();
+    for ch in query
+        .to_lowercase()
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+    {
+        let index = next + candidate[next..].iter().position(|&c| c == ch)?;
+        if index == next && next > 0 {
+            score += 5;
         }
-        let max_idx = self.entries.len().saturating_sub(1);
-        self.selected = index.min(max_idx);
-        self.update_preview();
-    }
-
-    fn jump_to_end(&mut self) {
-        if !self.entries.is_empty() {
-            self.selected = self.entries.len() - 1;
-            self.update_preview();
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
         }
+        score += 1 - (index - next) as i64;
+        positions.push(index);
+        next = index + 1;
     }
+    Some((score, positions))
+}
 
-    fn enter_selection(&mut self) -> Result<()> {
-        if let Some(entry) = self.entries.get(self.selected).cloned() {
-            if entry.is_dir {
-                let previous = self.current_dir.clone();
-                self.current_dir.push(&entry.name);
-                if let Err(err) = self.refresh_async(true) {
-                    self.current_dir = previous;
-                    return Err(err);
-                }
-                self.reset_search_state();
-            } else {
-                self.status = format!("'{}' is not a directory", entry.name);
+fn fuzzy_label(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn fuzzy_filter(candidates: &[PathBuf], base: &Path, query: &str) -> Vec<(usize, Vec<usize>)> {
+    let mut scored: Vec<(i64, usize, Vec<usize>)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, path)| {
+            let (score, positions) = fuzzy_match(&fuzzy_label(path, base), query)?;
+            Some((score, index, positions))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _, _)| cmp::Reverse(*score));
+    scored
+        .into_iter()
+        .map(|(_, index, positions)| (index, positions))
+        .collect()
+}
+
+fn find_case_insensitive(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    if needle.is_empty() {
+        return ranges;
+    }
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    let mut indices = haystack.char_indices();
+    while let Some((start, _)) = indices.next() {
+        let mut remaining = needle.iter();
+        let mut end = start;
+        let mut matched = true;
+        for (offset, ch) in haystack[start..].char_indices() {
+            let mut lowered = ch.to_lowercase();
+            if remaining.len() == 0 {
+                break;
+            }
+            if !lowered.all(|c| remaining.next() == Some(&c)) {
+                matched = false;
+                break;
             }
+            end = start + offset + ch.len_utf8();
         }
-        Ok(())
-    }
-
-    fn open_parent(&mut self) -> Result<()> {
-        let previous = self.current_dir.clone();
-        if self.current_dir.pop() {
-            if let Err(err) = self.refresh_async(true) {
-                self.current_dir = previous;
-                return Err(err);
+        if matched && remaining.len() == 0 {
+            ranges.push((start, end));
+            while indices.clone().next().is_some_and(|(idx, _)| idx < end) {
+                indices.next();
             }
-            self.reset_search_state();
         }
-        Ok(())
     }
+    ranges
+}
 
-    fn start_search(&mut self) {
-        self.clear_pending_count();
-        let buffer = self.last_search.clone().unwrap_or_default();
-        self.input_mode = InputMode::Search {
-            buffer,
-            feedback: None,
-        };
-        self.status = "Search: type to filter, Enter to apply".into();
-    }
+fn draw_footer(frame: &mut Frame, area: Rect, app: &App) {
+    let width = area.width.saturating_sub(2) as usize;
+    let footer = Paragraph::new(app.footer_text(width))
+        .style(Style::default().fg(app.theme.footer))
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(footer, area);
+}
 
-    fn start_command(&mut self) {
-        self.clear_pending_count();
-        self.input_mode = InputMode::Command {
-            buffer: String::new(),
-            feedback: None,
-        };
-        self.status = "Command: Enter to run, Esc to cancel".into();
+fn draw_overlay(frame: &mut Frame, app: &App) {
+    if let Some((title, content)) = app.overlay_prompt() {
+        let area = overlay_area(frame.size());
+        frame.render_widget(Clear, area);
+        let widget =
+            Paragraph::new(content).block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(widget, area);
     }
+}
 
-    fn cancel_overlay(&mut self) {
-        self.input_mode = InputMode::Normal;
-        self.clear_pending_count();
+fn draw_viewer(frame: &mut Frame, app: &App) {
+    if let InputMode::Viewer {
+        title,
+        lines,
+        scroll,
+    } = &app.input_mode
+    {
+        let area = viewer_area(frame.size());
+        frame.render_widget(Clear, area);
+        let title = format!(
+            "{title} ({}/{}) j/k scroll, y copy, q close",
+            scroll + 1,
+            lines.len()
+        );
+        let widget = Paragraph::new(lines.clone())
+            .scroll((cmp::min(*scroll, u16::MAX as usize) as u16, 0))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(widget, area);
     }
+}
 
-    fn set_overlay_feedback(&mut self, message: impl Into<String>) {
-        let text = Some(message.into());
-        match &mut self.input_mode {
-            InputMode::Search { feedback, .. } => *feedback = text,
-            InputMode::Command { feedback, .. } => *feedback = text,
-            _ => {}
-        }
+fn draw_note(frame: &mut Frame, app: &App) {
+    if let InputMode::Note {
+        dir,
+        buffer,
+        cursor,
+    } = &app.input_mode
+    {
+        let area = viewer_area(frame.size());
+        frame.render_widget(Clear, area);
+        let visible = area.height.saturating_sub(2) as usize;
+        let cursor_line = buffer[..*cursor].matches('\n').count();
+        let scroll = (cursor_line + 1).saturating_sub(visible);
+        let reversed = Style::default().add_modifier(Modifier::REVERSED);
+        let mut offset = 0;
+        let text: Vec<Line> = buffer
+            .split('\n')
+            .map(|line| {
+                let start = offset;
+                offset += line.len() + 1;
+                if !(start..=start + line.len()).contains(cursor) {
+                    return Line::raw(line);
+                }
+                let (before, rest) = line.split_at(*cursor - start);
+                let mut chars = rest.chars();
+                let under = chars.next().map_or(" ".to_string(), String::from);
+                Line::from(vec![
+                    Span::raw(before),
+                    Span::styled(under, reversed),
+                    Span::raw(chars.as_str()),
+                ])
+            })
+            .collect();
+        let title = format!("Note for {} (Esc save, Ctrl-C discard)", dir.display());
+        let widget = Paragraph::new(text)
+            .scroll((scroll.min(u16::MAX as usize) as u16, 0))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(widget, area);
     }
+}
 
-    fn clear_overlay_feedback(&mut self) {
-        match &mut self.input_mode {
-            InputMode::Search { feedback, .. } => *feedback = None,
-            InputMode::Command { feedback, .. } => *feedback = None,
-            _ => {}
+fn draw_disk_usage(frame: &mut Frame, app: &App) {
+    let InputMode::DiskUsage { dir, space } = &app.input_mode else {
+        return;
+    };
+    let screen = frame.size();
+    let width = screen.width.saturating_sub(4).min(70);
+    let area = Rect {
+        x: screen.x + (screen.width.saturating_sub(width)) / 2,
+        y: screen.y + screen.height.saturating_sub(7) / 2,
+        width,
+        height: 7.min(screen.height),
+    };
+    frame.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Disk usage of {} (Esc close)", dir.display()));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(1)])
+        .split(inner.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        }));
+    match space {
+        Ok(space) => {
+            let used = space.total.saturating_sub(space.free);
+            let ratio = if space.total == 0 {
+                0.0
+            } else {
+                used as f64 / space.total as f64
+            };
+            let color = if ratio >= 0.9 {
+                app.theme.error
+            } else {
+                app.theme.success
+            };
+            frame.render_widget(
+                Gauge::default()
+                    .gauge_style(Style::default().fg(color))
+                    .ratio(ratio.clamp(0.0, 1.0))
+                    .label(format!("{:.0}% used", ratio * 100.0)),
+                rows[0],
+            );
+            frame.render_widget(
+                Paragraph::new(format!(
+                    "Used {} of {}, {} available",
+                    format_size(used),
+                    format_size(space.total),
+                    format_size(space.available)
+                )),
+                rows[1],
+            );
         }
+        Err(err) => frame.render_widget(
+            Paragraph::new(format!("Could not read free space: {err}"))
+                .style(Style::default().fg(app.theme.error))
+                .wrap(Wrap { trim: true }),
+            inner,
+        ),
     }
+}
 
-    fn list_state(&self) -> ratatui::widgets::ListState {
-        let mut state = ratatui::widgets::ListState::default();
-        if !self.entries.is_empty() {
-            state.select(Some(self.selected));
-        }
-        state
-    }
+fn draw_palette(frame: &mut Frame, app: &App) {
+    let InputMode::Palette { buffer, selected } = &app.input_mode else {
+        return;
+    };
+    let matches = app.palette_matches(buffer);
+    let screen = frame.size();
+    let width = screen.width.saturating_sub(4).min(70);
+    let height = (matches.len() as u16 + 3)
+        .max(5)
+        .min(screen.height.saturating_sub(2));
+    let area = Rect {
+        x: screen.x + (screen.width.saturating_sub(width)) / 2,
+        y: screen.y + 1,
+        width,
+        height,
+    };
+    frame.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Commands ({} matches)", matches.len()));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+    frame.render_widget(
+        Paragraph::new(format!("> {buffer}")).style(Style::default().fg(app.theme.accent)),
+        rows[0],
+    );
+    let name_width = matches
+        .iter()
+        .map(|item| item.name.width())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|item| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:name_width$}  ", item.name)),
+                Span::styled(item.summary.as_str(), Style::default().fg(app.theme.muted)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(app.theme.selection())
+        .highlight_symbol("> ");
+    let mut state = ratatui::widgets::ListState::default();
+    state.select((!matches.is_empty()).then_some(*selected));
+    frame.render_stateful_widget(list, rows[1], &mut state);
+}
 
-    fn selected_entry(&self) -> Option<&FileEntry> {
-        self.entries.get(self.selected)
-    }
+fn draw_fuzzy(frame: &mut Frame, app: &App) {
+    let InputMode::Fuzzy {
+        title,
+        buffer,
+        selected,
+        candidates,
+        base,
+        ..
+    } = &app.input_mode
+    else {
+        return;
+    };
+    let matches = fuzzy_filter(candidates, base, buffer);
+    let screen = frame.size();
+    let width = screen.width.saturating_sub(4).min(90);
+    let height = (matches.len() as u16 + 3)
+        .max(5)
+        .min(screen.height.saturating_sub(2));
+    let area = Rect {
+        x: screen.x + (screen.width.saturating_sub(width)) / 2,
+        y: screen.y + 1,
+        width,
+        height,
+    };
+    frame.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{title} ({} matches)", matches.len()));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+    frame.render_widget(
+        Paragraph::new(format!("> {buffer}")).style(Style::default().fg(app.theme.accent)),
+        rows[0],
+    );
+    let hit = Style::default()
+        .fg(app.theme.accent)
+        .add_modifier(Modifier::BOLD);
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|(index, positions)| {
+            let label = fuzzy_label(&candidates[*index], base);
+            let spans: Vec<Span> = label
+                .chars()
+                .enumerate()
+                .map(|(pos, ch)| {
+                    if positions.contains(&pos) {
+                        Span::styled(ch.to_string(), hit)
+                    } else {
+                        Span::raw(ch.to_string())
+                    }
+                })
+                .collect();
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(app.theme.selection())
+        .highlight_symbol("> ");
+    let mut state = ratatui::widgets::ListState::default();
+    state.select((!matches.is_empty()).then_some(*selected));
+    frame.render_stateful_widget(list, rows[1], &mut state);
+}
 
-    fn selected_path(&self) -> Option<PathBuf> {
-        self.selected_entry()
-            .map(|entry| self.current_dir.join(&entry.name))
+fn draw_picker(frame: &mut Frame, app: &App) {
+    if let InputMode::Picker {
+        title,
+        items,
+        selected,
+        action,
+    } = &app.input_mode
+    {
+        let (results, picked) = match action {
+            PickerAction::Results { paths, picked } => (paths.as_slice(), Some(picked)),
+            _ => (&[][..], None),
+        };
+        let area = picker_area(frame.size(), items);
+        frame.render_widget(Clear, area);
+        let list_items: Vec<ListItem> = items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                let label = if idx < 9 {
+                    format!("{} {item}", idx + 1)
+                } else {
+                    format!("  {item}")
+                };
+                if let Some(picked) = picked
+                    && results.get(idx).is_some_and(|path| picked.contains(path))
+                {
+                    ListItem::new(label).style(Style::default().fg(app.theme.accent))
+                } else {
+                    ListItem::new(label)
+                }
+            })
+            .collect();
+        let list = List::new(list_items)
+            .block(Block::default().borders(Borders::ALL).title(title.as_str()))
+            .highlight_style(app.theme.selection())
+            .highlight_symbol("> ");
+        let mut state = ratatui::widgets::ListState::default();
+        state.select(Some(*selected));
+        frame.render_stateful_widget(list, area, &mut state);
     }
+}
 
-    fn take_external_command(&mut self) -> Option<ExternalCommand> {
-        self.pending_external.take()
+fn picker_area(area: Rect, items: &[String]) -> Rect {
+    let longest = items.iter().map(|item| item.width()).max().unwrap_or(0);
+    let width = (longest as u16 + 8).clamp(30, area.width.saturating_sub(4).max(1));
+    let height = (items.len() as u16 + 2).min(area.height.saturating_sub(2).max(3));
+    Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
     }
+}
 
-    fn describe_selection(&self) -> String {
-        if self.is_loading {
-            "Loading directory...".into()
-        } else {
-            self.entries
-                .get(self.selected)
-                .map(|entry| entry.describe())
-                .unwrap_or_else(|| "No entries".into())
-        }
+fn viewer_area(area: Rect) -> Rect {
+    Rect {
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
     }
+}
 
-    fn overlay_prompt(&self) -> Option<(String, String)> {
-        match &self.input_mode {
-            InputMode::Normal => None,
-            InputMode::Search { buffer, feedback } => {
-                let mut content = format!("/{}", buffer);
-                if let Some(msg) = feedback {
-                    content.push('\n');
-                    content.push_str(msg);
-                }
-                Some(("Search".into(), content))
-            }
-            InputMode::Command { buffer, feedback } => {
-                let mut content = format!(":{}", buffer);
-                if let Some(msg) = feedback {
-                    content.push('\n');
-                    content.push_str(msg);
-                }
-                Some(("Command".into(), content))
-            }
-            InputMode::Confirm { message, .. } => {
-                Some(("Confirm".into(), format!("{message} [y/n]")))
-            }
-        }
+fn overlay_area(area: Rect) -> Rect {
+    let height = 3u16;
+    let width = area.width.saturating_sub(2);
+    let x = area.x + 1;
+    let y = area.y + area.height.saturating_sub(height + 1);
+    Rect {
+        x,
+        y,
+        width,
+        height,
     }
+}
 
-    fn clamp_selection(&mut self) {
-        if self.selected >= self.entries.len() {
-            self.selected = self.entries.len().saturating_sub(1);
-        }
-        self.update_preview();
-    }
+#[derive(Clone)]
+enum InputMode {
+    Normal,
+    Search {
+        buffer: String,
+        feedback: Option<String>,
+    },
+    Command {
+        buffer: String,
+        feedback: Option<String>,
+    },
+    PreviewSearch {
+        buffer: String,
+        feedback: Option<String>,
+    },
+    Viewer {
+        title: String,
+        lines: Vec<Line<'static>>,
+        scroll: usize,
+    },
+    Picker {
+        title: String,
+        items: Vec<String>,
+        selected: usize,
+        action: PickerAction,
+    },
+    Confirm {
+        message: String,
+        action: ConfirmAction,
+    },
+    Note {
+        dir: PathBuf,
+        buffer: String,
+        cursor: usize,
+    },
+    DiskUsage {
+        dir: PathBuf,
+        space: FsResult<DiskSpace>,
+    },
+    Palette {
+        buffer: String,
+        selected: usize,
+    },
+    Fuzzy {
+        title: String,
+        buffer: String,
+        selected: usize,
+        candidates: Vec<PathBuf>,
+        base: PathBuf,
+        action: FuzzyAction,
+    },
+    Filter {
+        buffer: String,
+    },
+}
 
-    fn drain_fs_events(&mut self, rx: &mut UnboundedReceiver<FsEvent>) {
-        loop {
-            match rx.try_recv() {
-                Ok(event) => self.handle_fs_event(event),
-                Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => {
-                    self.status = "Filesystem worker disconnected".into();
-                    self.is_loading = false;
-                    break;
-                }
-            }
-        }
-    }
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FuzzyAction {
+    Visit,
+    Select,
+    Reveal,
+}
 
-    fn handle_fs_event(&mut self, event: FsEvent) {
-        match event {
-            FsEvent::DirectoryLoaded {
-                path,
-                token,
-                result,
-            } => {
-                if Some(token) != self.pending_token {
-                    return;
-                }
-                self.pending_token = None;
-                self.is_loading = false;
-                match result {
-                    Ok(entries) => {
-                        self.entries = entries;
-                        self.clamp_selection();
-                        if let Some(message) = self.last_action_message.take() {
-                            self.status = message;
-                        } else {
-                            self.status = format!(
-                                "Loaded {} entries from {}",
-                                self.entries.len(),
-                                path.display()
-                            );
-                        }
-                    }
-                    Err(err) => {
-                        self.entries.clear();
-                        self.selected = 0;
-                        self.last_action_message = None;
-                        self.status = format!("Error loading {}: {}", path.display(), err);
-                    }
-                }
-            }
+#[derive(Default, Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    command_aliases: HashMap<String, String>,
+    #[serde(default)]
+    macros: HashMap<String, String>,
+    #[serde(default)]
+    macro_continue_on_error: Option<bool>,
+    #[serde(default)]
+    confirm_cross_device_move: Option<bool>,
+    #[serde(default)]
+    confirm_transfers: Option<bool>,
+    #[serde(default)]
+    read_only: Option<bool>,
+    #[serde(default)]
+    trash: Option<String>,
+    #[serde(default)]
+    auto_parent_on_empty: Option<bool>,
+    #[serde(default)]
+    zoxide: Option<bool>,
+    #[serde(default)]
+    show_hidden: Option<bool>,
+    #[serde(default)]
+    gitignore: Option<String>,
+    #[serde(default)]
+    remember_sort: Option<String>,
+    #[serde(default)]
+    dirs_first: Option<bool>,
+    global_marks: Option<bool>,
+    #[serde(default)]
+    collapse_single_child: Option<bool>,
+    relative_numbers: Option<bool>,
+    #[serde(default)]
+    size_bars: Option<bool>,
+    #[serde(default)]
+    long_names: Option<String>,
+    #[serde(default)]
+    preview_dir_details: Option<bool>,
+    #[serde(default)]
+    preview_ansi: Option<bool>,
+    #[serde(default)]
+    preview_skip_extensions: Vec<String>,
+    #[serde(default)]
+    dir_indicators: Option<bool>,
+    #[serde(default)]
+    scan_retries: Option<u32>,
+    #[serde(default)]
+    large_dir_threshold: Option<usize>,
+    #[serde(default)]
+    mount_info: Option<bool>,
+    #[serde(default)]
+    time_format: Option<String>,
+    #[serde(default)]
+    locate_max_results: Option<usize>,
+    #[serde(default)]
+    theme_file: Option<String>,
+    #[serde(default)]
+    warn_size: Option<String>,
+    #[serde(default)]
+    status_timeout: Option<u64>,
+    #[serde(default)]
+    error_status_timeout: Option<u64>,
+    #[serde(default)]
+    pins: RawPins,
+    #[serde(default)]
+    grouped_view: Option<bool>,
+    #[serde(default)]
+    groups: RawGroups,
+    #[serde(default)]
+    columns: Option<RawColumns>,
+    #[serde(default)]
+    layout: RawLayout,
+    #[serde(default)]
+    dir_overrides: HashMap<String, DirOverride>,
+    #[serde(default)]
+    open_with: Vec<OpenWithApp>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum SortKey {
+    #[default]
+    Name,
+    Natural,
+    Size,
+    Modified,
+    Extension,
+}
+
+impl SortKey {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "name" => Some(Self::Name),
+            "natural" | "nat" => Some(Self::Natural),
+            "size" => Some(Self::Size),
+            "modified" | "mtime" => Some(Self::Modified),
+            "extension" | "ext" => Some(Self::Extension),
+            _ => None,
         }
     }
 
-    fn footer_text(&self) -> String {
-        let mut segments: Vec<String> = Vec::new();
-        if !self.status.is_empty() {
-            segments.push(self.status.clone());
-        }
-        if let Some(count) = self.pending_count {
-            segments.push(format!("count {}", count));
+    fn label(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Natural => "natural",
+            Self::Size => "size",
+            Self::Modified => "modified",
+            Self::Extension => "extension",
         }
-        segments.push(Self::HELP_LINE.into());
-        segments.join(" | ")
     }
 
-    fn search_next(&mut self) {
-        if self.entries.is_empty() {
-            self.status = "No entries to search".into();
-            return;
-        }
-        let query = match self.last_search.clone() {
-            Some(q) => q,
-            None => {
-                self.status = "No previous search".into();
-                return;
-            }
-        };
-        let start = (self.selected + 1) % self.entries.len();
-        if let Some(index) = self.find_match(&query, start) {
-            self.selected = index;
-            self.status = format!("Match: {}", self.entries[index].name);
-            self.update_preview();
-        } else {
-            self.status = format!("No more matches for '{query}'");
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Natural,
+            Self::Natural => Self::Size,
+            Self::Size => Self::Modified,
+            Self::Modified => Self::Extension,
+            Self::Extension => Self::Name,
         }
     }
+}
 
-    fn search_prev(&mut self) {
-        if self.entries.is_empty() {
-            self.status = "No entries to search".into();
-            return;
-        }
-        let query = match self.last_search.clone() {
-            Some(q) => q,
-            None => {
-                self.status = "No previous search".into();
-                return;
-            }
-        };
-        let len = self.entries.len();
-        let start = if len == 0 {
-            0
-        } else {
-            (self.selected + len - 1) % len
-        };
-        if let Some(index) = self.find_match_reverse(&query, start) {
-            self.selected = index;
-            self.status = format!("Match: {}", self.entries[index].name);
-            self.update_preview();
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+struct SortSpec {
+    key: SortKey,
+    reverse: bool,
+}
+
+impl SortSpec {
+    fn label(self) -> String {
+        if self.reverse {
+            format!("{} (reversed)", self.key.label())
         } else {
-            self.status = format!("No previous matches for '{query}'");
+            self.key.label().into()
         }
     }
+}
 
-    fn find_match(&self, query: &str, start_index: usize) -> Option<usize> {
-        if self.entries.is_empty() {
-            return None;
-        }
-        let needle = query.to_lowercase();
-        let len = self.entries.len();
-        for offset in 0..len {
-            let index = (start_index + offset) % len;
-            if self.entries[index].name.to_lowercase().contains(&needle) {
-                return Some(index);
-            }
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct DirOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reverse: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remembered: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+struct RawLayout {
+    #[serde(default)]
+    list_width: Option<u16>,
+    #[serde(default)]
+    detail_height: Option<u16>,
+    #[serde(default)]
+    orientation: Option<String>,
+    #[serde(default)]
+    dual_pane: Option<bool>,
+    #[serde(default)]
+    miller: Option<bool>,
+    #[serde(default)]
+    parent_width: Option<u16>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Orientation {
+    Auto,
+    Horizontal,
+    Vertical,
+}
+
+impl Orientation {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "horizontal" => Some(Self::Horizontal),
+            "vertical" => Some(Self::Vertical),
+            _ => None,
         }
-        None
     }
 
-    fn find_match_reverse(&self, query: &str, start_index: usize) -> Option<usize> {
-        if self.entries.is_empty() {
-            return None;
+    fn is_vertical(self, area: Rect) -> bool {
+        match self {
+            Self::Auto => area.width < area.height.saturating_mul(2),
+            Self::Horizontal => false,
+            Self::Vertical => true,
         }
-        let needle = query.to_lowercase();
-        let len = self.entries.len();
-        let mut index = start_index % len;
-        for _ in 0..len {
-            if self.entries[index].name.to_lowercase().contains(&needle) {
-                return Some(index);
-            }
-            if index == 0 {
-                index = len - 1;
-            } else {
-                index -= 1;
-            }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LayoutOptions {
+    list_width: u16,
+    detail_height: u16,
+    orientation: Orientation,
+    dual_pane: bool,
+    miller: bool,
+    parent_width: u16,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            list_width: 50,
+            detail_height: 25,
+            orientation: Orientation::Auto,
+            dual_pane: false,
+            miller: false,
+            parent_width: 20,
         }
-        None
     }
+}
 
-    fn apply_search(&mut self, query: &str) {
-        if self.entries.is_empty() {
-            self.status = "No entries to search".into();
-            return;
+#[derive(Clone, Copy)]
+struct Theme {
+    title: Color,
+    path: Color,
+    muted: Color,
+    directory: Color,
+    bar: Color,
+    accent: Color,
+    success: Color,
+    warning: Color,
+    error: Color,
+    selection_fg: Color,
+    selection_bg: Color,
+    footer: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            title: Color::Yellow,
+            path: Color::Cyan,
+            muted: Color::DarkGray,
+            directory: Color::LightBlue,
+            bar: Color::Magenta,
+            accent: Color::Yellow,
+            success: Color::Green,
+            warning: Color::LightRed,
+            error: Color::Red,
+            selection_fg: Color::Black,
+            selection_bg: Color::LightGreen,
+            footer: Color::Gray,
         }
-        let start = self.selected;
-        self.last_search = Some(query.to_string());
-        if let Some(index) = self.find_match(query, start) {
-            self.selected = index;
-            self.status = format!("Match: {}", self.entries[index].name);
-            self.update_preview();
+    }
+}
+
+impl Theme {
+    fn selection(&self) -> Style {
+        Style::default()
+            .fg(self.selection_fg)
+            .bg(self.selection_bg)
+            .add_modifier(Modifier::BOLD)
+    }
+}
+
+fn load_base16_theme(path: &Path) -> Result<Theme> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let mut palette = [None; 16];
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(slot) = key
+            .trim()
+            .strip_prefix("base0")
+            .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+        else {
+            continue;
+        };
+        let value = value
+            .split(" #")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches(['"', '\'']);
+        palette[slot] = Some(
+            parse_hex_color(value.trim_start_matches('#'))
+                .ok_or_else(|| anyhow!("invalid color '{value}' for base0{slot:X}"))?,
+        );
+    }
+    let color = |slot: usize| palette[slot].ok_or_else(|| anyhow!("missing base0{slot:X}"));
+    Ok(Theme {
+        title: color(0xA)?,
+        path: color(0xC)?,
+        muted: color(0x3)?,
+        directory: color(0xD)?,
+        bar: color(0xE)?,
+        accent: color(0xA)?,
+        success: color(0xB)?,
+        warning: color(0x9)?,
+        error: color(0x8)?,
+        selection_fg: color(0x0)?,
+        selection_bg: color(0xB)?,
+        footer: color(0x4)?,
+    })
+}
+
+fn parse_hex_color(hex: &str) -> Option<Color> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::Rgb(
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ))
+}
+
+#[derive(Default, Deserialize)]
+struct RawColumns {
+    #[serde(default)]
+    order: Vec<String>,
+}
+
+#[derive(Clone, Deserialize)]
+struct OpenWithApp {
+    #[serde(default)]
+    name: Option<String>,
+    command: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    terminal: bool,
+}
+
+impl OpenWithApp {
+    fn matches(&self, extension: &str) -> bool {
+        self.extensions
+            .iter()
+            .any(|ext| ext == "*" || ext.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    }
+
+    fn label(&self) -> String {
+        let name = self.name.as_deref().unwrap_or(&self.command);
+        if self.terminal {
+            format!("{name} (terminal)")
         } else {
-            self.status = format!("No match for '{query}'");
+            name.to_string()
         }
     }
+}
 
-    fn reset_search_state(&mut self) {
-        self.last_search = None;
-        if let InputMode::Search { buffer, .. } = &mut self.input_mode {
-            buffer.clear();
+#[derive(Default, Deserialize)]
+struct RawGroups {
+    #[serde(default)]
+    images: Vec<String>,
+    #[serde(default)]
+    documents: Vec<String>,
+    #[serde(default)]
+    code: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct RawPins {
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Config {
+    command_aliases: HashMap<String, String>,
+    macros: HashMap<String, String>,
+    macro_continue_on_error: bool,
+    confirm_cross_device_move: bool,
+    confirm_transfers: bool,
+    read_only: bool,
+    trash: TrashBackend,
+    auto_parent_on_empty: bool,
+    zoxide: bool,
+    show_hidden: bool,
+    gitignore: GitignoreMode,
+    sort_memory: SortMemory,
+    dirs_first: bool,
+    global_marks: bool,
+    collapse_single_child: bool,
+    relative_numbers: bool,
+    pin_names: Vec<String>,
+    pin_paths: Vec<PathBuf>,
+    columns: Vec<ListColumn>,
+    layout: LayoutOptions,
+    status_timeout: Option<Duration>,
+    error_status_timeout: Option<Duration>,
+    time_format: Option<String>,
+    locate_max_results: usize,
+    theme: Theme,
+    warn_size: Option<u64>,
+    grouped: bool,
+    group_extensions: HashMap<String, EntryGroup>,
+    long_names: LongNameMode,
+    open_with: Vec<OpenWithApp>,
+    preview: PreviewOptions,
+    show_mount_info: bool,
+    scan: ScanOptions,
+    dir_overrides: HashMap<PathBuf, DirOverride>,
+}
+
+/// Config values that can also be toggled at runtime. A reload only overrides the ones whose
+/// value in the file changed, so toggles made in the session survive unrelated edits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ConfigToggles {
+    show_hidden: bool,
+    gitignore: GitignoreMode,
+    dirs_first: bool,
+    relative_numbers: bool,
+    absolute_times: bool,
+}
+
+impl ConfigToggles {
+    fn of(config: &Config) -> Self {
+        Self {
+            show_hidden: config.show_hidden,
+            gitignore: config.gitignore,
+            dirs_first: config.dirs_first,
+            relative_numbers: config.relative_numbers,
+            absolute_times: config.time_format.is_some(),
         }
     }
+}
 
-    fn run_command(&mut self, input: String) {
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            self.status = "Empty command".into();
-            return;
+impl Default for Config {
+    fn default() -> Self {
+        let mut aliases = HashMap::new();
+        aliases.insert("rm".into(), "delete".into());
+        aliases.insert("cp".into(), "copy".into());
+        aliases.insert("mv".into(), "move".into());
+        Self {
+            command_aliases: aliases,
+            macros: HashMap::new(),
+            macro_continue_on_error: false,
+            confirm_cross_device_move: false,
+            confirm_transfers: false,
+            read_only: false,
+            trash: TrashBackend::System,
+            auto_parent_on_empty: false,
+            zoxide: false,
+            show_hidden: true,
+            gitignore: GitignoreMode::Off,
+            sort_memory: SortMemory::Directory,
+            dirs_first: true,
+            global_marks: false,
+            collapse_single_child: false,
+            relative_numbers: false,
+            pin_names: Vec::new(),
+            pin_paths: Vec::new(),
+            columns: vec![ListColumn::Icon, ListColumn::Name],
+            layout: LayoutOptions::default(),
+            status_timeout: Some(Duration::from_secs(5)),
+            error_status_timeout: None,
+            time_format: None,
+            locate_max_results: DEFAULT_LOCATE_MAX_RESULTS,
+            theme: Theme::default(),
+            warn_size: None,
+            grouped: false,
+            group_extensions: default_group_extensions(),
+            long_names: LongNameMode::Truncate,
+            open_with: Vec::new(),
+            preview: PreviewOptions {
+                ansi: true,
+                ..PreviewOptions::default()
+            },
+            show_mount_info: false,
+            scan: ScanOptions::default(),
+            dir_overrides: HashMap::new(),
         }
-        let (cmd, args) = split_command(trimmed);
-        let command = self.resolve_command_alias(cmd);
-        match command.as_str() {
-            "pwd" => self.status = format!("{}", self.current_dir.display()),
-            "refresh" => {
-                if let Err(err) = self.refresh_async(false) {
-                    self.status = format!("Refresh failed: {err:#}");
-                } else {
-                    self.status = "Refresh requested".into();
-                }
-            }
-            "q" | "quit" => {
-                self.status = "Use 'q' in normal mode to quit".into();
+    }
+}
+
+const CONFIG_TEMPLATE: &str = r#"# Wayfinder configuration. Uncomment and edit options, then save; :config reloads on exit.
+
+# [command_aliases]
+# rm = "delete"
+
+# [macros]
+# backup = "copy ../backup/ ; refresh"
+
+# confirm_cross_device_move = true
+# confirm_transfers = false
+# macro_continue_on_error = false
+# read_only = false
+# trash = "system"
+# auto_parent_on_empty = false
+# Share visited directories with zoxide and complete :cd from its database
+# zoxide = false
+# show_hidden = true
+# Dim or hide entries matched by .gitignore: "off", "dim" or "hide"
+# gitignore = "off"
+# Remember the sort chosen with :sort or s: "directory", "global" or "off"
+# remember_sort = "directory"
+# dirs_first = true
+# global_marks = false
+# collapse_single_child = false
+# relative_numbers = false
+# size_bars = true
+# warn_size = "1GiB"
+# long_names = "truncate"
+# preview_dir_details = true
+# preview_ansi = true
+# preview_skip_extensions = ["log", "bin"]
+# dir_indicators = false
+# scan_retries = 2
+# large_dir_threshold = 100000
+# mount_info = false
+# time_format = "%Y-%m-%d %H:%M"
+# theme_file = "themes/base16.yaml"
+# locate_max_results = 20
+# status_timeout = 5
+# error_status_timeout = 0
+
+# [pins]
+# names = ["src"]
+
+# [columns]
+# order = ["icon", "name", "size", "modified"]
+
+# [layout]
+# list_width = 50
+# detail_height = 25
+# orientation = "auto"
+# Start with two file listings side by side (toggle with :dual)
+# dual_pane = false
+# Show parent, current and preview columns, ranger style (toggle with :miller)
+# miller = false
+# parent_width = 20
+"#;
+
+/// The config to start with, plus the title and lines of a toast describing
+/// anything wrong with it; the terminal is already in the alternate screen.
+fn startup_config(
+    result: Result<(Config, Vec<String>)>,
+) -> (Config, Option<(String, Vec<String>)>) {
+    match result {
+        Ok((config, warnings)) if warnings.is_empty() => (config, None),
+        Ok((config, warnings)) => {
+            let title = format!("Loaded config with {} warning(s)", warnings.len());
+            (config, Some((title, warnings)))
+        }
+        Err(err) => {
+            let title = "Config not loaded, using defaults".to_string();
+            (Config::default(), Some((title, vec![format!("{err:#}")])))
+        }
+    }
+}
+
+fn config_file_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("wayfinder").join("config.toml"))
+}
+
+fn read_config() -> Result<(Config, Vec<String>)> {
+    match config_file_path() {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(contents) => parse_config(&path, &contents),
+            Err(_) => Ok((Config::default(), Vec::new())),
+        },
+        None => Ok((Config::default(), Vec::new())),
+    }
+}
+
+fn parse_config(path: &Path, contents: &str) -> Result<(Config, Vec<String>)> {
+    let mut config = Config::default();
+    let mut warnings = Vec::new();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    match toml::from_str::<RawConfig>(contents) {
+        Ok(raw) => {
+            for (alias, command) in raw.command_aliases {
+                config
+                    .command_aliases
+                    .insert(alias.to_lowercase(), command.to_lowercase());
             }
-            "rename" => {
-                if args.is_empty() {
-                    self.status = "Usage: :rename <new_name>".into();
-                } else if let Err(err) = self.command_rename(args) {
-                    self.status = format!("Rename failed: {err:#}");
+            for (name, body) in raw.macros {
+                config.macros.insert(name.to_lowercase(), body);
+            }
+            if let Some(continue_on_error) = raw.macro_continue_on_error {
+                config.macro_continue_on_error = continue_on_error;
+            }
+            if let Some(confirm) = raw.confirm_cross_device_move {
+                config.confirm_cross_device_move = confirm;
+            }
+            if let Some(confirm) = raw.confirm_transfers {
+                config.confirm_transfers = confirm;
+            }
+            if let Some(read_only) = raw.read_only {
+                config.read_only = read_only;
+            }
+            match raw.trash.as_deref() {
+                None => {}
+                Some("system") => config.trash = TrashBackend::System,
+                Some("internal") => config.trash = TrashBackend::Internal,
+                Some(other) => warnings.push(format!(
+                    "Unknown trash '{other}' in {} (use system or internal)",
+                    path.display()
+                )),
+            }
+            if let Some(auto_parent) = raw.auto_parent_on_empty {
+                config.auto_parent_on_empty = auto_parent;
+            }
+            if let Some(zoxide) = raw.zoxide {
+                config.zoxide = zoxide;
+            }
+            if let Some(dirs_first) = raw.dirs_first {
+                config.dirs_first = dirs_first;
+            }
+            if let Some(show_hidden) = raw.show_hidden {
+                config.show_hidden = show_hidden;
+            }
+            if let Some(global_marks) = raw.global_marks {
+                config.global_marks = global_marks;
+            }
+            if let Some(collapse) = raw.collapse_single_child {
+                config.collapse_single_child = collapse;
+            }
+            if let Some(relative) = raw.relative_numbers {
+                config.relative_numbers = relative;
+            }
+            if raw.size_bars == Some(true) {
+                config.columns = vec![ListColumn::Icon, ListColumn::Bar, ListColumn::Name];
+            }
+            if let Some(columns) = raw.columns {
+                config.columns = columns
+                    .order
+                    .iter()
+                    .filter_map(|name| {
+                        let column = ListColumn::parse(name);
+                        if column.is_none() {
+                            warnings.push(format!(
+                                "Unknown column '{name}' in {}, skipping",
+                                path.display()
+                            ));
+                        }
+                        column
+                    })
+                    .collect();
+                if !config.columns.contains(&ListColumn::Name) {
+                    config.columns.push(ListColumn::Name);
                 }
             }
-            "delete" => {
-                if let Err(err) = self.request_delete_confirmation() {
-                    self.status = format!("Delete failed: {err:#}");
+            match raw.layout.list_width {
+                Some(width) if (10..=90).contains(&width) => {
+                    config.layout.list_width = width;
                 }
+                Some(width) => warnings.push(format!(
+                    "layout.list_width {width} in {} is out of range (10-90)",
+                    path.display()
+                )),
+                None => {}
             }
-            "mkdir" => {
-                if args.is_empty() {
-                    self.status = "Usage: :mkdir <name>".into();
-                } else if let Err(err) = self.command_mkdir(args) {
-                    self.status = format!("mkdir failed: {err:#}");
+            match raw.layout.detail_height {
+                Some(height) if (5..=80).contains(&height) => {
+                    config.layout.detail_height = height;
                 }
+                Some(height) => warnings.push(format!(
+                    "layout.detail_height {height} in {} is out of range (5-80)",
+                    path.display()
+                )),
+                None => {}
             }
-            "touch" => {
-                if args.is_empty() {
-                    self.status = "Usage: :touch <name>".into();
-                } else if let Err(err) = self.command_touch(args) {
-                    self.status = format!("touch failed: {err:#}");
+            if let Some(value) = raw.layout.orientation.as_deref() {
+                match Orientation::parse(value) {
+                    Some(orientation) => config.layout.orientation = orientation,
+                    None => warnings.push(format!(
+                        "Unknown layout.orientation '{value}' in {} (use auto, horizontal or vertical)",
+                        path.display()
+                    )),
                 }
             }
-            "copy" => {
-                if args.is_empty() {
-                    self.status = "Usage: :copy <destination>".into();
-                } else if let Err(err) = self.command_copy(args) {
-                    self.status = format!("copy failed: {err:#}");
+            if let Some(dual) = raw.layout.dual_pane {
+                config.layout.dual_pane = dual;
+            }
+            if let Some(miller) = raw.layout.miller {
+                config.layout.miller = miller;
+            }
+            match raw.layout.parent_width {
+                Some(width) if (10..=40).contains(&width) => {
+                    config.layout.parent_width = width;
                 }
+                Some(width) => warnings.push(format!(
+                    "layout.parent_width {width} in {} is out of range (10-40)",
+                    path.display()
+                )),
+                None => {}
             }
-            "move" => {
-                if args.is_empty() {
-                    self.status = "Usage: :move <destination>".into();
-                } else if let Err(err) = self.command_move(args) {
-                    self.status = format!("move failed: {err:#}");
+            match raw.gitignore.as_deref().map(GitignoreMode::parse) {
+                None => {}
+                Some(Some(mode)) => {
+                    config.gitignore = mode;
+                    config.scan.gitignore = mode != GitignoreMode::Off;
                 }
+                Some(None) => warnings.push(format!(
+                    "Unknown gitignore mode '{}' in {} (use off, dim or hide)",
+                    raw.gitignore.as_deref().unwrap_or_default(),
+                    path.display()
+                )),
             }
-            "sh" => {
-                if let Err(err) = self.command_shell() {
-                    self.status = format!("shell failed: {err:#}");
+            match raw.remember_sort.as_deref() {
+                None => {}
+                Some("off") => config.sort_memory = SortMemory::Off,
+                Some("directory") => config.sort_memory = SortMemory::Directory,
+                Some("global") => config.sort_memory = SortMemory::Global,
+                Some(other) => warnings.push(format!(
+                    "Unknown remember_sort '{other}' in {} (use directory, global or off)",
+                    path.display()
+                )),
+            }
+            match raw.long_names.as_deref() {
+                None => {}
+                Some("truncate") => config.long_names = LongNameMode::Truncate,
+                Some("scroll") => config.long_names = LongNameMode::Scroll,
+                Some(other) => warnings.push(format!(
+                    "Unknown long_names mode '{other}' in {} (use truncate or scroll)",
+                    path.display()
+                )),
+            }
+            if let Some(details) = raw.preview_dir_details {
+                config.preview.dir_details = details;
+            }
+            if let Some(ansi) = raw.preview_ansi {
+                config.preview.ansi = ansi;
+            }
+            config.preview.skip_extensions = raw
+                .preview_skip_extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect();
+            if let Some(indicators) = raw.dir_indicators {
+                config.scan.dir_indicators = indicators;
+            }
+            if let Some(retries) = raw.scan_retries {
+                config.scan.retries = retries;
+            }
+            if let Some(threshold) = raw.large_dir_threshold {
+                config.scan.large_dir_threshold = threshold;
+            }
+            if let Some(show) = raw.mount_info {
+                config.show_mount_info = show;
+            }
+            match raw.time_format {
+                Some(format) if valid_time_format(&format) => {
+                    config.preview.time_format = Some(format.clone());
+                    config.time_format = Some(format);
                 }
+                Some(format) => warnings.push(format!(
+                    "Invalid time_format '{format}' in {}, using {DEFAULT_TIME_FORMAT}",
+                    path.display()
+                )),
+                None => {}
             }
-            "edit" => {
-                if let Err(err) = self.command_edit() {
-                    self.status = format!("edit failed: {err:#}");
+            if let Some(theme_file) = raw.theme_file {
+                let theme_path = paths::expand(&theme_file, dir);
+                match load_base16_theme(&theme_path) {
+                    Ok(theme) => config.theme = theme,
+                    Err(err) => warnings.push(format!(
+                        "Failed to load theme {}: {err:#}, using default colors",
+                        theme_path.display()
+                    )),
                 }
             }
-            "cd" => {
-                if args.is_empty() {
-                    self.status = "Usage: :cd <path>".into();
-                } else if let Err(err) = self.command_cd(args) {
-                    self.status = format!("cd failed: {err:#}");
+            if let Some(size) = raw.warn_size {
+                match parse_size(&size) {
+                    Ok(bytes) => config.warn_size = Some(bytes),
+                    Err(err) => warnings.push(format!("{err} for warn_size in {}", path.display())),
                 }
             }
-            "help" => {
-                self.status = "Commands: pwd, refresh, rename, delete, mkdir, touch, copy, move, edit, sh, cd, help".into();
+            if let Some(grouped) = raw.grouped_view {
+                config.grouped = grouped;
             }
-            other => {
-                self.status = format!("Unknown command: {other}");
+            for (group, extensions) in [
+                (EntryGroup::Images, &raw.groups.images),
+                (EntryGroup::Documents, &raw.groups.documents),
+                (EntryGroup::Code, &raw.groups.code),
+            ] {
+                for ext in extensions {
+                    config
+                        .group_extensions
+                        .insert(ext.trim_start_matches('.').to_lowercase(), group);
+                }
+            }
+            if let Some(limit) = raw.locate_max_results {
+                config.locate_max_results = limit.max(1);
+            }
+            if let Some(secs) = raw.status_timeout {
+                config.status_timeout = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            if let Some(secs) = raw.error_status_timeout {
+                config.error_status_timeout = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            config.pin_names = raw.pins.names;
+            config.pin_paths = raw.pins.paths;
+            config.open_with = raw.open_with;
+            for (dir, settings) in raw.dir_overrides {
+                if let Some(sort) = &settings.sort
+                    && SortKey::parse(sort).is_none()
+                {
+                    warnings.push(format!(
+                        "Unknown sort '{sort}' for {dir} in {}, skipping",
+                        path.display()
+                    ));
+                    continue;
+                }
+                if let Some(since) = &settings.since
+                    && let Err(err) = parse_duration(since)
+                {
+                    warnings.push(format!("{err} for {dir} in {}, skipping", path.display()));
+                    continue;
+                }
+                config
+                    .dir_overrides
+                    .insert(paths::expand_user(&dir), settings);
+            }
+        }
+        Err(err) => {
+            return Err(anyhow!("Failed to parse config {}: {err}", path.display()));
+        }
+    }
+    Ok((config, warnings))
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct PersistedState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sort: Option<String>,
+    #[serde(default)]
+    reverse: bool,
+    #[serde(default)]
+    pins: Vec<PathBuf>,
+    #[serde(default)]
+    favorites: Vec<PathBuf>,
+    #[serde(default)]
+    dir_overrides: BTreeMap<String, DirOverride>,
+    #[serde(default)]
+    bookmarks: BTreeMap<String, PathBuf>,
+}
+
+fn state_file_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("wayfinder").join("state.toml"))
+}
+
+fn load_state() -> Result<PersistedState> {
+    let Some(path) = state_file_path() else {
+        return Ok(PersistedState::default());
+    };
+    match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse state {}", path.display())),
+        Err(_) => Ok(PersistedState::default()),
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+struct DirVisits {
+    rank: f64,
+    last: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct FrecencyDb {
+    #[serde(default)]
+    dirs: BTreeMap<String, DirVisits>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl FrecencyDb {
+    fn visit(&mut self, dir: &Path, now: u64) {
+        let visits = self
+            .dirs
+            .entry(dir.to_string_lossy().into_owned())
+            .or_insert(DirVisits {
+                rank: 0.0,
+                last: now,
+            });
+        visits.rank += 1.0;
+        visits.last = now;
+        let total: f64 = self.dirs.values().map(|visits| visits.rank).sum();
+        if total > FRECENCY_MAX_RANK {
+            for visits in self.dirs.values_mut() {
+                visits.rank *= 0.9;
+            }
+            self.dirs.retain(|_, visits| visits.rank >= 1.0);
+        }
+        self.dirty = true;
+    }
+
+    fn score(visits: DirVisits, now: u64) -> f64 {
+        let age = now.saturating_sub(visits.last);
+        let factor = if age < 60 * 60 {
+            4.0
+        } else if age < 24 * 60 * 60 {
+            2.0
+        } else if age < 7 * 24 * 60 * 60 {
+            0.5
+        } else {
+            0.25
+        };
+        visits.rank * factor
+    }
+
+    fn ranked(&self, now: u64) -> Vec<PathBuf> {
+        let mut scored: Vec<(f64, PathBuf)> = self
+            .dirs
+            .iter()
+            .map(|(dir, visits)| (Self::score(*visits, now), PathBuf::from(dir)))
+            .filter(|(_, dir)| dir.is_dir())
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().map(|(_, dir)| dir).collect()
+    }
+
+    fn best_match(&self, fragments: &[String], now: u64, exclude: &Path) -> Option<PathBuf> {
+        self.ranked(now)
+            .into_iter()
+            .find(|dir| dir != exclude && frecency_matches(&dir.to_string_lossy(), fragments))
+    }
+}
+
+fn frecency_matches(path: &str, fragments: &[String]) -> bool {
+    let path = path.to_lowercase();
+    let mut rest = path.as_str();
+    for fragment in fragments {
+        let needle = fragment.to_lowercase();
+        match rest.find(&needle) {
+            Some(pos) => rest = &rest[pos + needle.len()..],
+            None => return false,
+        }
+    }
+    fragments.last().is_none_or(|last| {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(&path);
+        name.contains(&last.to_lowercase())
+    })
+}
+
+fn frecency_file_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("wayfinder").join("frecency.toml"))
+}
+
+fn load_frecency() -> FrecencyDb {
+    frecency_file_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save_frecency(db: &FrecencyDb) -> Result<()> {
+    let path = frecency_file_path().ok_or_else(|| anyhow!("No config directory available"))?;
+    ensure_parent_dir(&path)?;
+    let contents = toml::to_string(db).context("serializing visited directories")?;
+    fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))
+}
+
+fn zoxide_query(fragments: &[String], exclude: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new("zoxide")
+        .arg("query")
+        .arg("--list")
+        .arg("--exclude")
+        .arg(exclude)
+        .arg("--")
+        .args(fragments)
+        .stdin(Stdio::null())
+        .output()
+        .context("running zoxide")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        if stderr.contains("no match found") {
+            return Ok(Vec::new());
+        }
+        return Err(anyhow!(
+            "zoxide exited with {}{}",
+            output.status,
+            if stderr.is_empty() {
+                String::new()
+            } else {
+                format!(": {stderr}")
             }
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Drops the least recently remembered sorts past the cap; `:sort save` entries are kept.
+fn forget_oldest_sorts(overrides: &mut BTreeMap<String, DirOverride>) {
+    let mut remembered: Vec<(u64, String)> = overrides
+        .iter()
+        .filter_map(|(dir, settings)| Some((settings.remembered?, dir.clone())))
+        .collect();
+    let excess = remembered.len().saturating_sub(SORT_MEMORY_MAX_DIRS);
+    if excess == 0 {
+        return;
+    }
+    remembered.sort();
+    for (_, dir) in remembered.into_iter().take(excess) {
+        overrides.remove(&dir);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn save_state(state: &PersistedState) -> Result<()> {
+    let path = state_file_path().ok_or_else(|| anyhow!("No config directory available"))?;
+    ensure_parent_dir(&path)?;
+    let contents = toml::to_string_pretty(state).context("serializing state")?;
+    fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))
+}
+
+fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, suffix) = input.split_at(split);
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration '{input}' (e.g. 30m, 2h, 3d)"))?;
+    let unit = match suffix.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => {
+            return Err(anyhow!(
+                "Unknown duration unit '{other}' (use s, m, h or d)"
+            ));
+        }
+    };
+    Ok(Duration::from_secs(value.saturating_mul(unit)))
+}
+
+fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .unwrap_or(input.len());
+    let (digits, suffix) = input.split_at(split);
+    let value: f64 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{input}' (e.g. 500MB, 1GiB)"))?;
+    let unit: u64 = match suffix.trim() {
+        "" | "B" => 1,
+        "K" | "KiB" => 1 << 10,
+        "M" | "MiB" => 1 << 20,
+        "G" | "GiB" => 1 << 30,
+        "T" | "TiB" => 1 << 40,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        other => {
+            return Err(anyhow!(
+                "Unknown size unit '{other}' (use B, KiB, MiB, GiB, TiB or KB, MB, GB, TB)"
+            ));
+        }
+    };
+    Ok((value * unit as f64) as u64)
+}
+
+fn split_macro(body: &str) -> Vec<String> {
+    let mut steps = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (_, '\\') => {
+                current.push(ch);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            (Some(open), ch) if ch == open => {
+                quote = None;
+                current.push(ch);
+            }
+            (None, '"' | '\'') => {
+                quote = Some(ch);
+                current.push(ch);
+            }
+            (None, ';') => steps.push(mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+    steps.push(current);
+    steps
+        .into_iter()
+        .map(|step| step.trim().to_string())
+        .filter(|step| !step.is_empty())
+        .collect()
+}
+
+fn split_command(input: &str) -> (&str, &str) {
+    if let Some((cmd, rest)) = input.split_once(char::is_whitespace) {
+        (cmd, rest.trim_start())
+    } else {
+        (input, "")
+    }
+}
+
+fn parse_args(input: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (None | Some('"'), '\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow!("Trailing backslash in arguments"))?;
+                current.push(escaped);
+                in_token = true;
+            }
+            (Some(_), ch) => current.push(ch),
+            (None, '"' | '\'') => {
+                quote = Some(ch);
+                in_token = true;
+            }
+            (None, ch) if ch.is_whitespace() => {
+                if in_token {
+                    args.push(mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            (None, ch) => {
+                current.push(ch);
+                in_token = true;
+            }
+        }
+    }
+    if let Some(open) = quote {
+        return Err(anyhow!("Unterminated {open} quote in arguments"));
+    }
+    if in_token {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+fn single_arg(input: &str) -> Result<String> {
+    let mut args = parse_args(input)?;
+    match args.len() {
+        0 => Err(anyhow!("Missing argument")),
+        1 => Ok(args.remove(0)),
+        _ => Err(anyhow!(
+            "Expected one argument; quote names with spaces, e.g. \"my file.txt\""
+        )),
+    }
+}
+
+#[derive(Clone)]
+enum Operation {
+    Copy { src: PathBuf, dest: PathBuf },
+    Move { src: PathBuf, dest: PathBuf },
+    Delete { path: PathBuf },
+    Remove { path: PathBuf },
+}
+
+impl Operation {
+    fn describe(&self) -> String {
+        match self {
+            Self::Copy { src, dest } => format!("copy {} -> {}", src.display(), dest.display()),
+            Self::Move { src, dest } => format!("move {} -> {}", src.display(), dest.display()),
+            Self::Delete { path } => format!("delete {}", path.display()),
+            Self::Remove { path } => format!("delete {} permanently", path.display()),
+        }
+    }
+
+    fn verbs(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Copy { .. } => ("Copying", "Copied"),
+            Self::Move { .. } => ("Moving", "Moved"),
+            Self::Delete { .. } => ("Trashing", "Trashed"),
+            Self::Remove { .. } => ("Deleting", "Deleted"),
+        }
+    }
+
+    fn weight(&self) -> u64 {
+        match self {
+            Self::Copy { src, .. } if src.is_dir() => {
+                summarize_tree(src, usize::MAX).files.max(1) as u64
+            }
+            _ => 1,
+        }
+    }
+
+    /// Runs one operation; `Ok(Some(note))` means it succeeded with something worth reporting.
+    fn run(
+        &self,
+        trash: TrashBackend,
+        on_file: &mut dyn FnMut(),
+        cancel: &AtomicBool,
+    ) -> Result<Option<String>> {
+        match self {
+            Self::Copy { dest, .. } | Self::Move { dest, .. }
+                if dest.exists() || dest.is_symlink() =>
+            {
+                Err(anyhow!("{} already exists", dest.display()))
+            }
+            Self::Copy { src, dest } => if src.is_dir() {
+                copy_directory_with_progress(src, dest, on_file, cancel)
+            } else {
+                copy_file_cancellable(src, dest, cancel)
+            }
+            .map(|()| None),
+            Self::Move { src, dest } => {
+                move_path_cancellable(src, dest, on_file, cancel).map(|copied| {
+                    copied.then(|| "across filesystems: copied, then removed the original".into())
+                })
+            }
+            Self::Delete { path } => {
+                soft_delete(path, trash).with_context(|| format!("deleting {}", path.display()))
+            }
+            Self::Remove { path } => remove_path(path).map(|()| None),
+        }
+    }
+}
+
+fn batch_verbs(operations: &[Operation]) -> (&'static str, &'static str) {
+    let mut verbs = operations.iter().map(Operation::verbs);
+    let first = verbs.next().unwrap_or(("Running", "Ran"));
+    if verbs.all(|verb| verb == first) {
+        first
+    } else {
+        ("Running", "Ran")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TrashBackend {
+    System,
+    Internal,
+}
+
+#[derive(Clone)]
+enum ConfirmAction {
+    Delete {
+        path: PathBuf,
+    },
+    Move {
+        src: PathBuf,
+        dest: PathBuf,
+    },
+    Copy {
+        src: PathBuf,
+        dest: PathBuf,
+    },
+    DeleteMarked {
+        paths: Vec<PathBuf>,
+    },
+    Transfer {
+        operations: Vec<Operation>,
+    },
+    DeleteRange {
+        paths: Vec<PathBuf>,
+        select: Option<String>,
+    },
+    Remove {
+        paths: Vec<PathBuf>,
+    },
+    LoadLargeDir {
+        path: PathBuf,
+    },
+    Quit,
+}
+
+impl ConfirmAction {
+    fn choices(&self) -> &'static str {
+        match self {
+            Self::Quit => "[e]xecute and quit / [d]iscard and quit / [c]ancel",
+            _ => "[y/n]",
         }
     }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ListColumn {
+    Icon,
+    Bar,
+    Name,
+    Size,
+    Modified,
+    Permissions,
+}
+
+impl ListColumn {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "icon" => Some(Self::Icon),
+            "bar" => Some(Self::Bar),
+            "name" => Some(Self::Name),
+            "size" => Some(Self::Size),
+            "modified" => Some(Self::Modified),
+            "permissions" => Some(Self::Permissions),
+            _ => None,
+        }
+    }
+
+    fn width(self) -> Option<usize> {
+        match self {
+            Self::Icon => Some(3),
+            Self::Bar => Some(SIZE_BAR_WIDTH),
+            Self::Name => None,
+            Self::Size => Some(10),
+            Self::Modified => Some(4),
+            Self::Permissions => Some(10),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EntryGroup {
+    Directories,
+    Images,
+    Documents,
+    Code,
+    Other,
+}
+
+impl EntryGroup {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Directories => "Directories",
+            Self::Images => "Images",
+            Self::Documents => "Documents",
+            Self::Code => "Code",
+            Self::Other => "Other",
+        }
+    }
+}
+
+fn default_group_extensions() -> HashMap<String, EntryGroup> {
+    let images = [
+        "png", "jpg", "jpeg", "gif", "bmp", "svg", "webp", "ico", "tif", "tiff", "heic",
+    ];
+    let documents = [
+        "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "rst", "tex", "epub", "csv", "xls",
+        "xlsx", "ods", "ppt", "pptx", "odp",
+    ];
+    let code = [
+        "rs", "c", "h", "cc", "cpp", "hpp", "go", "java", "kt", "py", "rb", "php", "js", "jsx",
+        "ts", "tsx", "lua", "swift", "cs", "hs", "ml", "sh", "bash", "zsh", "fish", "html", "css",
+        "scss", "sql", "json", "toml", "yaml", "yml", "xml",
+    ];
+    let mut table = HashMap::new();
+    for (group, extensions) in [
+        (EntryGroup::Images, &images[..]),
+        (EntryGroup::Documents, &documents[..]),
+        (EntryGroup::Code, &code[..]),
+    ] {
+        for ext in extensions {
+            table.insert(ext.to_string(), group);
+        }
+    }
+    table
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    List,
+    Details,
+    Preview,
+}
+
+impl Focus {
+    const ALL: [Focus; 3] = [Focus::List, Focus::Details, Focus::Preview];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::List => "file list",
+            Self::Details => "details",
+            Self::Preview => "preview",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LongNameMode {
+    Truncate,
+    Scroll,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortMemory {
+    Off,
+    Directory,
+    Global,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GitignoreMode {
+    Off,
+    Dim,
+    Hide,
+}
+
+impl GitignoreMode {
+    fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "dim" => Some(Self::Dim),
+            "hide" => Some(Self::Hide),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Dim => "dim",
+            Self::Hide => "hide",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Dim,
+            Self::Dim => Self::Hide,
+            Self::Hide => Self::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "md5" => Some(Self::Md5),
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+}
+
+struct PendingTree {
+    token: u64,
+    root: PathBuf,
+}
+
+struct PaletteItem {
+    name: String,
+    summary: String,
+    needs_args: bool,
+}
+
+struct PendingLocate {
+    token: u64,
+    name: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RegisterKind {
+    Yank,
+    Cut,
+}
+
+struct Register {
+    kind: RegisterKind,
+    paths: Vec<PathBuf>,
+}
+
+struct VisualMark {
+    anchor: PathBuf,
+    base: HashSet<PathBuf>,
+}
+
+struct DualPane {
+    other: Tab,
+    right_active: bool,
+    token: Option<u64>,
+}
+
+#[derive(Default)]
+struct DirHistory {
+    back: Vec<(PathBuf, Option<String>)>,
+    forward: Vec<(PathBuf, Option<String>)>,
+}
+
+impl DirHistory {
+    fn record(&mut self, dir: PathBuf, selected: Option<String>) {
+        if self.back.last().is_some_and(|(last, _)| *last == dir) {
+            self.back.pop();
+        }
+        self.back.push((dir, selected));
+        if self.back.len() > HISTORY_MAX {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+}
+
+#[derive(Clone)]
+struct NameFilter {
+    pattern: String,
+    glob: Option<Regex>,
+}
+
+impl NameFilter {
+    fn new(pattern: &str) -> Self {
+        let glob = is_glob(pattern).then(|| glob_regex(pattern).ok()).flatten();
+        Self {
+            pattern: pattern.to_string(),
+            glob,
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match &self.glob {
+            Some(glob) => glob.is_match(name),
+            None => name.to_lowercase().contains(&self.pattern.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tab {
+    dir: PathBuf,
+    all_entries: Vec<FileEntry>,
+    entries: Vec<FileEntry>,
+    selected: usize,
+    last_search: Option<String>,
+    filter: Option<NameFilter>,
+    marked: HashSet<PathBuf>,
+    archive: Option<ArchiveView>,
+    history: DirHistory,
+}
+
+struct PendingRecent {
+    token: u64,
+    opened: bool,
+}
+
+struct PendingFinder {
+    token: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+struct PendingGlob {
+    token: u64,
+    root: PathBuf,
+    pattern: String,
+    select: bool,
+}
+
+struct PendingFind {
+    token: u64,
+    cancel: Arc<AtomicBool>,
+    query: String,
+    paths: Vec<PathBuf>,
+    opened: bool,
+}
+
+struct PendingGrep {
+    token: u64,
+    cancel: Arc<AtomicBool>,
+    pattern: String,
+    matches: Vec<GrepMatch>,
+    opened: bool,
+}
+
+#[derive(Clone)]
+struct GrepMatch {
+    path: PathBuf,
+    line: usize,
+    text: String,
+}
+
+struct PendingHash {
+    token: u64,
+    copy: bool,
+}
+
+#[derive(Clone)]
+enum PickerAction {
+    OpenWith {
+        path: PathBuf,
+        apps: Vec<OpenWithApp>,
+    },
+    JumpTo {
+        paths: Vec<PathBuf>,
+    },
+    Bookmarks {
+        dirs: Vec<PathBuf>,
+    },
+    Results {
+        paths: Vec<PathBuf>,
+        picked: HashSet<PathBuf>,
+    },
+    Recent {
+        paths: Vec<PathBuf>,
+    },
+    Restore {
+        item: PathBuf,
+        info_path: PathBuf,
+        original: PathBuf,
+    },
+    Queue,
+    Rename {
+        renames: Vec<(PathBuf, PathBuf)>,
+    },
+    Grep {
+        matches: Vec<(PathBuf, usize)>,
+    },
+}
+
+#[derive(Default)]
+struct Caches {
+    previews: HashMap<PathBuf, (Option<SystemTime>, Option<u64>, PreviewPane)>,
+    mounts: HashMap<PathBuf, Option<MountInfo>>,
+    repo_roots: HashMap<PathBuf, PathBuf>,
+    ignore_files: HashMap<
+        PathBuf,
+        (
+            Option<SystemTime>,
+            Option<Arc<ignore::gitignore::Gitignore>>,
+        ),
+    >,
+    global_excludes: HashMap<PathBuf, Vec<Arc<ignore::gitignore::Gitignore>>>,
+}
+
+impl Caches {
+    fn preview(&self, entry: &FileEntry) -> Option<PreviewPane> {
+        let (modified, size, preview) = self.previews.get(&entry.path)?;
+        (*modified == entry.modified && *size == entry.size).then(|| preview.clone())
+    }
+
+    fn store_preview(&mut self, entry: &FileEntry, preview: PreviewPane) {
+        if self.previews.len() >= PREVIEW_CACHE_MAX {
+            self.previews.clear();
+        }
+        self.previews
+            .insert(entry.path.clone(), (entry.modified, entry.size, preview));
+    }
+
+    fn mount_info(&mut self, dir: &Path) -> Option<MountInfo> {
+        self.mounts
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| mount_info(dir))
+            .clone()
+    }
+
+    /// Gitignore rules for the entries of `dir`. Parsed ignore files are
+    /// kept per repository root and only re-read when they change; a cached
+    /// root is dropped once its `.git` is gone, and directories outside any
+    /// repository are probed again on every scan so `git init` is noticed.
+    fn ignore_rules(&mut self, dir: &Path) -> IgnoreRules {
+        let cached = self
+            .repo_roots
+            .get(dir)
+            .filter(|root| root.join(".git").exists())
+            .cloned();
+        let repo = match cached {
+            Some(root) => root,
+            None => {
+                self.repo_roots.remove(dir);
+                let Some(root) = find_git_root(dir) else {
+                    return IgnoreRules::default();
+                };
+                self.repo_roots.insert(dir.to_path_buf(), root.clone());
+                root
+            }
+        };
+        let mut rules = IgnoreRules {
+            in_repo: true,
+            matchers: Vec::new(),
+        };
+        for ancestor in dir.ancestors() {
+            rules
+                .matchers
+                .extend(self.ignore_file(ancestor, &ancestor.join(".gitignore")));
+            if ancestor == repo {
+                break;
+            }
+        }
+        rules.matchers.extend(
+            self.global_excludes
+                .entry(repo.clone())
+                .or_insert_with(|| repo_excludes(&repo))
+                .iter()
+                .cloned(),
+        );
+        rules
+    }
+
+    fn ignore_file(
+        &mut self,
+        root: &Path,
+        file: &Path,
+    ) -> Option<Arc<ignore::gitignore::Gitignore>> {
+        let modified = fs::metadata(file).and_then(|meta| meta.modified()).ok();
+        if let Some((cached, rules)) = self.ignore_files.get(file)
+            && *cached == modified
+        {
+            return rules.clone();
+        }
+        let rules = modified.and_then(|_| gitignore_rules(root, file));
+        self.ignore_files
+            .insert(file.to_path_buf(), (modified, rules.clone()));
+        rules
+    }
+
+    fn clear_all(&mut self) {
+        self.previews.clear();
+        self.mounts.clear();
+        self.repo_roots.clear();
+        self.ignore_files.clear();
+        self.global_excludes.clear();
+    }
+}
+
+#[derive(Clone)]
+enum ExternalCommand {
+    Edit { paths: Vec<PathBuf>, name: String },
+    EditLine { path: PathBuf, line: usize },
+    Shell { dir: PathBuf },
+    Run { dir: PathBuf, command: String },
+    OpenWith { command: Vec<String>, path: PathBuf },
+}
+
+#[derive(Clone, Default)]
+struct PreviewOptions {
+    dir_details: bool,
+    skip_extensions: Vec<String>,
+    ansi: bool,
+    time_format: Option<String>,
+}
+
+#[derive(Clone)]
+struct PreviewPane {
+    title: String,
+    body: String,
+    styled: Option<Vec<Line<'static>>>,
+}
+
+impl PreviewPane {
+    fn new<T: Into<String>, B: Into<String>>(title: T, body: B) -> Self {
+        Self {
+            title: title.into(),
+            body: body.into(),
+            styled: None,
+        }
+    }
+
+    fn empty() -> Self {
+        Self::new("Preview", "No file selected")
+    }
+
+    fn loading() -> Self {
+        Self::new("Preview", "Loading preview...")
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self::new("Preview", message)
+    }
+}
+
+struct App {
+    current_dir: PathBuf,
+    all_entries: Vec<FileEntry>,
+    entries: Vec<FileEntry>,
+    selected: usize,
+    status: String,
+    fs: FsDispatcher,
+    pending_token: Option<u64>,
+    next_token: u64,
+    is_loading: bool,
+    input_mode: InputMode,
+    pending_count: Option<usize>,
+    last_search: Option<String>,
+    last_action_message: Option<String>,
+    pending_external: Option<ExternalCommand>,
+    preview: PreviewPane,
+    preview_scroll: usize,
+    details_scroll: usize,
+    focus: Focus,
+    preview_query: Option<String>,
+    awaiting_g: bool,
+    awaiting_d: bool,
+    awaiting_register: Option<char>,
+    awaiting_bookmark: Option<char>,
+    awaiting_z: bool,
+    register: Option<Register>,
+    caches: Caches,
+    has_note: bool,
+    chooser: bool,
+    chosen: Option<PathBuf>,
+    config_watcher: Option<RecommendedWatcher>,
+    config_reload_at: Option<Instant>,
+    config_toggles: ConfigToggles,
+    command_aliases: HashMap<String, String>,
+    macros: HashMap<String, String>,
+    macro_continue_on_error: bool,
+    macro_depth: usize,
+    confirm_cross_device_move: bool,
+    confirm_transfers: bool,
+    read_only: bool,
+    trash: TrashBackend,
+    auto_parent_on_empty: bool,
+    zoxide: bool,
+    cd_completions: Option<(Vec<PathBuf>, usize)>,
+    pending_zoxide: Option<u64>,
+    show_hidden: bool,
+    gitignore: GitignoreMode,
+    sort_memory: SortMemory,
+    dirs_first: bool,
+    global_marks: bool,
+    deleted_since_load: bool,
+    collapse_single_child: bool,
+    relative_numbers: bool,
+    collapse_root: Option<PathBuf>,
+    collapse_pending: bool,
+    pin_names: Vec<String>,
+    pin_paths: Vec<PathBuf>,
+    state: PersistedState,
+    frecency: FrecencyDb,
+    persist_state: bool,
+    state_dirty: bool,
+    columns: Vec<ListColumn>,
+    layout: LayoutOptions,
+    max_entry_size: u64,
+    pending_hash: Option<PendingHash>,
+    pending_preview: Option<u64>,
+    queue_mode: bool,
+    queued: Vec<Operation>,
+    quit_after_queue: bool,
+    quit_requested: bool,
+    clipboard: Option<Clipboard>,
+    modified_within: Option<(String, Duration)>,
+    filter: Option<NameFilter>,
+    since_from_override: bool,
+    sort: SortSpec,
+    dir_overrides: HashMap<PathBuf, DirOverride>,
+    scan_options: ScanOptions,
+    show_mount_info: bool,
+    mount_info: Option<MountInfo>,
+    time_format: String,
+    absolute_times: bool,
+    toast: Option<Toast>,
+    pending_tree: Option<PendingTree>,
+    pending_locate: Option<PendingLocate>,
+    pending_recent: Option<PendingRecent>,
+    pending_finder: Option<PendingFinder>,
+    pending_archive: Option<u64>,
+    pending_find: Option<PendingFind>,
+    pending_glob: Option<PendingGlob>,
+    pending_grep: Option<PendingGrep>,
+    result_targets: Option<Vec<PathBuf>>,
+    archive: Option<ArchiveView>,
+    pending_job: Option<u64>,
+    waiting_jobs: VecDeque<WaitingJob>,
+    job_progress: Option<String>,
+    job_cancel: Option<Arc<AtomicBool>>,
+    job_counts_files: bool,
+    locate_max_results: usize,
+    theme: Theme,
+    warn_size: Option<u64>,
+    grouped: bool,
+    group_extensions: HashMap<String, EntryGroup>,
+    marked: HashSet<PathBuf>,
+    visual: Option<VisualMark>,
+    error_status: Option<String>,
+    status_seen: String,
+    status_since: Instant,
+    status_timeout: Option<Duration>,
+    error_status_timeout: Option<Duration>,
+    long_names: LongNameMode,
+    marquee_anchor: (usize, Instant),
+    loaded_dir: Option<PathBuf>,
+    large_dirs_allowed: HashSet<PathBuf>,
+    list_offset: Cell<usize>,
+    changed_at: HashMap<PathBuf, Instant>,
+    last_seen: HashMap<PathBuf, (Option<u64>, Option<SystemTime>)>,
+    open_with: Vec<OpenWithApp>,
+    pending_select: Option<String>,
+    preview_options: PreviewOptions,
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    dual: Option<DualPane>,
+    parent_listing: Option<(PathBuf, Vec<FileEntry>)>,
+    parent_token: Option<u64>,
+    history: DirHistory,
+}
+
+impl App {
+    const HELP_LINE: &'static str = "j/k navigate | h/l change dirs | q quit";
+
+    fn new(fs: FsDispatcher, config: Config) -> Result<Self> {
+        let (state, state_error) = match load_state() {
+            Ok(state) => (state, None),
+            Err(err) => (PersistedState::default(), Some(err)),
+        };
+        let mut app = Self::with_state(fs, config, state, load_frecency())?;
+        if let Some(err) = state_error {
+            app.show_error_toast("State not restored", vec![format!("{err:#}")]);
+        }
+        app.persist_state = true;
+        app.config_watcher = config_file_path().and_then(|path| app.fs.watch_config(path).ok());
+        Ok(app)
+    }
+
+    fn with_state(
+        fs: FsDispatcher,
+        config: Config,
+        state: PersistedState,
+        frecency: FrecencyDb,
+    ) -> Result<Self> {
+        let current_dir = std::env::current_dir().context("read current dir")?;
+        let mut app = Self {
+            current_dir,
+            all_entries: Vec::new(),
+            entries: Vec::new(),
+            selected: 0,
+            status: String::new(),
+            fs,
+            pending_token: None,
+            next_token: 0,
+            is_loading: false,
+            input_mode: InputMode::Normal,
+            pending_count: None,
+            last_search: None,
+            last_action_message: None,
+            pending_external: None,
+            preview: PreviewPane::loading(),
+            preview_scroll: 0,
+            details_scroll: 0,
+            focus: Focus::List,
+            preview_query: None,
+            awaiting_g: false,
+            awaiting_d: false,
+            awaiting_register: None,
+            awaiting_bookmark: None,
+            awaiting_z: false,
+            register: None,
+            caches: Caches::default(),
+            has_note: false,
+            chooser: false,
+            chosen: None,
+            config_watcher: None,
+            config_reload_at: None,
+            config_toggles: ConfigToggles::of(&config),
+            command_aliases: config.command_aliases,
+            macros: config.macros,
+            macro_continue_on_error: config.macro_continue_on_error,
+            macro_depth: 0,
+            confirm_cross_device_move: config.confirm_cross_device_move,
+            confirm_transfers: config.confirm_transfers,
+            read_only: config.read_only,
+            trash: config.trash,
+            auto_parent_on_empty: config.auto_parent_on_empty,
+            zoxide: config.zoxide,
+            cd_completions: None,
+            pending_zoxide: None,
+            global_marks: config.global_marks,
+            show_hidden: config.show_hidden,
+            gitignore: config.gitignore,
+            sort_memory: config.sort_memory,
+            dirs_first: config.dirs_first,
+            collapse_single_child: config.collapse_single_child,
+            relative_numbers: config.relative_numbers,
+            collapse_root: None,
+            collapse_pending: false,
+            deleted_since_load: false,
+            pin_names: config.pin_names,
+            pin_paths: config.pin_paths,
+            state,
+            frecency,
+            persist_state: false,
+            state_dirty: false,
+            columns: config.columns,
+            layout: config.layout,
+            max_entry_size: 0,
+            pending_hash: None,
+            pending_preview: None,
+            queue_mode: false,
+            queued: Vec::new(),
+            quit_after_queue: false,
+            quit_requested: false,
+            clipboard: None,
+            modified_within: None,
+            filter: None,
+            since_from_override: false,
+            sort: SortSpec::default(),
+            dir_overrides: config.dir_overrides,
+            scan_options: config.scan,
+            show_mount_info: config.show_mount_info,
+            mount_info: None,
+            absolute_times: config.time_format.is_some(),
+            toast: None,
+            time_format: config
+                .time_format
+                .unwrap_or_else(|| DEFAULT_TIME_FORMAT.into()),
+            pending_tree: None,
+            pending_locate: None,
+            pending_recent: None,
+            pending_finder: None,
+            pending_find: None,
+            pending_glob: None,
+            pending_archive: None,
+            pending_grep: None,
+            result_targets: None,
+            archive: None,
+            pending_job: None,
+            waiting_jobs: VecDeque::new(),
+            job_progress: None,
+            job_cancel: None,
+            job_counts_files: false,
+            locate_max_results: config.locate_max_results,
+            theme: config.theme,
+            warn_size: config.warn_size,
+            grouped: config.grouped,
+            group_extensions: config.group_extensions,
+            marked: HashSet::new(),
+            visual: None,
+            error_status: None,
+            status_seen: String::new(),
+            status_since: Instant::now(),
+            status_timeout: config.status_timeout,
+            error_status_timeout: config.error_status_timeout,
+            long_names: config.long_names,
+            marquee_anchor: (0, Instant::now()),
+            loaded_dir: None,
+            large_dirs_allowed: HashSet::new(),
+            list_offset: Cell::new(0),
+            changed_at: HashMap::new(),
+            last_seen: HashMap::new(),
+            open_with: config.open_with,
+            pending_select: None,
+            tabs: vec![Tab::default()],
+            active_tab: 0,
+            dual: None,
+            parent_listing: None,
+            parent_token: None,
+            history: DirHistory::default(),
+            preview_options: config.preview,
+        };
+        if app.layout.dual_pane {
+            app.dual = Some(DualPane {
+                other: Tab {
+                    dir: app.current_dir.clone(),
+                    ..Tab::default()
+                },
+                right_active: false,
+                token: None,
+            });
+        }
+        app.refresh_async(true)?;
+        Ok(app)
+    }
+
+    fn save_state(&mut self) -> Result<()> {
+        if self.persist_state {
+            save_state(&self.state)?;
+        }
+        self.state_dirty = false;
+        Ok(())
+    }
+
+    fn apply_config(&mut self, config: Config) {
+        let toggles = ConfigToggles::of(&config);
+        self.command_aliases = config.command_aliases;
+        self.macros = config.macros;
+        self.macro_continue_on_error = config.macro_continue_on_error;
+        self.confirm_cross_device_move = config.confirm_cross_device_move;
+        self.confirm_transfers = config.confirm_transfers;
+        self.read_only = config.read_only || read_only_flag();
+        self.trash = config.trash;
+        self.auto_parent_on_empty = config.auto_parent_on_empty;
+        self.zoxide = config.zoxide;
+        self.global_marks = config.global_marks;
+        let previous = mem::replace(&mut self.config_toggles, toggles);
+        if toggles.show_hidden != previous.show_hidden {
+            self.show_hidden = toggles.show_hidden;
+        }
+        if toggles.gitignore != previous.gitignore {
+            self.gitignore = toggles.gitignore;
+        }
+        if toggles.dirs_first != previous.dirs_first {
+            self.dirs_first = toggles.dirs_first;
+        }
+        if toggles.relative_numbers != previous.relative_numbers {
+            self.relative_numbers = toggles.relative_numbers;
+        }
+        if toggles.absolute_times != previous.absolute_times {
+            self.absolute_times = toggles.absolute_times;
+        }
+        self.sort_memory = config.sort_memory;
+        self.collapse_single_child = config.collapse_single_child;
+        self.pin_names = config.pin_names;
+        self.pin_paths = config.pin_paths;
+        self.columns = config.columns;
+        self.layout = config.layout;
+        self.dir_overrides = config.dir_overrides;
+        self.scan_options = config.scan;
+        self.show_mount_info = config.show_mount_info;
+        self.mount_info = self
+            .show_mount_info
+            .then(|| self.caches.mount_info(&self.current_dir))
+            .flatten();
+        self.time_format = config
+            .time_format
+            .unwrap_or_else(|| DEFAULT_TIME_FORMAT.into());
+        self.locate_max_results = config.locate_max_results;
+        self.theme = config.theme;
+        self.warn_size = config.warn_size;
+        self.grouped = config.grouped;
+        self.group_extensions = config.group_extensions;
+        self.rebuild_view();
+        self.status_timeout = config.status_timeout;
+        self.error_status_timeout = config.error_status_timeout;
+        self.long_names = config.long_names;
+        self.open_with = config.open_with;
+        self.preview_options = config.preview;
+        self.caches.previews.clear();
+    }
+
+    fn reload_config(&mut self) -> Result<()> {
+        let (config, warnings) = read_config()?;
+        self.apply_config(config);
+        let message = if warnings.is_empty() {
+            "Reloaded config".to_string()
+        } else {
+            let message = format!("Reloaded config with {} warning(s)", warnings.len());
+            self.show_error_toast(message.clone(), warnings);
+            message
+        };
+        self.refresh_with_message(false, message)
+    }
+
+    fn start_job<F>(&mut self, label: String, job: F) -> Result<()>
+    where
+        F: FnOnce(&dyn Fn(u64, u64), &AtomicBool) -> Result<String> + Send + 'static,
+    {
+        if self.pending_job.is_some() {
+            self.status = format!("{label} waits for the running job");
+            self.waiting_jobs.push_back(WaitingJob::Job {
+                label,
+                job: Box::new(job),
+            });
+            return Ok(());
+        }
+        let token = self.next_token;
+        self.next_token += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.fs
+            .request_job(token, label.clone(), cancel.clone(), job)
+            .context("queue file job")?;
+        self.pending_job = Some(token);
+        self.job_cancel = Some(cancel);
+        self.status = format!("{label}...");
+        Ok(())
+    }
+
+    fn command_split(&mut self, args: &str) -> Result<()> {
+        let chunk = parse_size(&single_arg(args)?)?;
+        if chunk == 0 {
+            return Err(anyhow!("Chunk size must be greater than zero"));
+        }
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to split"))?;
+        if entry.is_dir {
+            return Err(anyhow!("'{}' is a directory", entry.name));
+        }
+        let total = fs::metadata(&entry.path)
+            .with_context(|| format!("reading {}", entry.name))?
+            .len();
+        let parts = chunk_paths(&entry.path, total, chunk)?;
+        let conflicts: Vec<String> = parts
+            .iter()
+            .filter(|part| part.exists())
+            .map(|part| display_name(part))
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "{} part name(s) already exist\n{}",
+                conflicts.len(),
+                conflicts.join("\n")
+            ));
+        }
+        let path = entry.path.clone();
+        self.start_job(
+            format!("Splitting {}", entry.name),
+            move |progress, cancel| split_file(&path, chunk, progress, cancel),
+        )
+    }
+
+    fn command_cat(&mut self, args: &str) -> Result<()> {
+        let output = self.current_dir.join(single_arg(args)?);
+        let inputs = self.sorted_marks();
+        if inputs.is_empty() {
+            return Err(anyhow!("Mark the files to concatenate first"));
+        }
+        if let Some(dir) = inputs.iter().find(|path| path.is_dir()) {
+            return Err(anyhow!("'{}' is a directory", display_name(dir)));
+        }
+        if output.exists() || output.is_symlink() {
+            return Err(anyhow!("'{}' already exists", display_name(&output)));
+        }
+        self.marked.clear();
+        let label = format!("Concatenating {} files", inputs.len());
+        self.start_job(label, move |progress, cancel| {
+            concat_files(&inputs, &output, progress, cancel)
+        })
+    }
+
+    fn command_df(&mut self) {
+        let dir = self.current_dir.clone();
+        let space = disk_space(&dir).map_err(|err| format!("{err:#}"));
+        self.clear_pending_count();
+        self.input_mode = InputMode::DiskUsage { dir, space };
+    }
+
+    fn command_note(&mut self) -> Result<()> {
+        let dir = self.current_dir.clone();
+        let buffer = load_note(&dir)?;
+        self.clear_pending_count();
+        let cursor = buffer.len();
+        self.input_mode = InputMode::Note {
+            dir,
+            buffer,
+            cursor,
+        };
+        self.status = "Editing note".into();
+        Ok(())
+    }
+
+    fn command_config(&mut self) -> Result<()> {
+        let path = config_file_path().ok_or_else(|| anyhow!("No config directory"))?;
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+            }
+            fs::write(&path, CONFIG_TEMPLATE)
+                .with_context(|| format!("writing {}", path.display()))?;
+        }
+        if self.config_watcher.is_none() {
+            self.config_watcher = self.fs.watch_config(path.clone()).ok();
+        }
+        self.status = format!("Launching editor for {}", path.display());
+        self.pending_external = Some(ExternalCommand::Edit {
+            paths: vec![path],
+            name: "config".into(),
+        });
+        Ok(())
+    }
+
+    fn refresh_async(&mut self, clear_entries: bool) -> Result<()> {
+        if let Some(view) = &self.archive {
+            let (archive, kind) = (view.archive.clone(), view.kind);
+            self.request_archive_listing(archive, kind);
+            return Ok(());
+        }
+        self.pending_archive = None;
+        if clear_entries {
+            self.all_entries.clear();
+            self.entries.clear();
+            self.selected = 0;
+            self.preview = PreviewPane::loading();
+        }
+        let token = self.next_token;
+        self.next_token += 1;
+        let path = self.current_dir.clone();
+        let mut options = self.scan_options;
+        if self.large_dirs_allowed.contains(&path) {
+            options.large_dir_threshold = 0;
+        }
+        let ignore = self.scan_rules(&path);
+        self.fs
+            .request_directory_scan(path.clone(), token, options, ignore)
+            .context("queue directory scan")?;
+
+        self.pending_token = Some(token);
+        self.is_loading = true;
+        self.status = format!("Loading {} ...", path.display());
+        Ok(())
+    }
+
+    fn hard_refresh(&mut self) {
+        self.caches.clear_all();
+        self.mount_info = self
+            .show_mount_info
+            .then(|| self.caches.mount_info(&self.current_dir))
+            .flatten();
+        if let Err(err) = self.refresh_with_message(false, "Cleared caches and reloaded") {
+            self.set_error(format!("Refresh failed: {err:#}"));
+        }
+    }
+
+    fn refresh_with_message<S: Into<String>>(
+        &mut self,
+        clear_entries: bool,
+        message: S,
+    ) -> Result<()> {
+        self.last_action_message = Some(message.into());
+        self.refresh_async(clear_entries)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.entries.is_empty() {
+            self.selected = 0;
+            self.preview = PreviewPane::empty();
+            return;
+        }
+        let len = self.entries.len() as isize;
+        let next = (self.selected as isize + delta).rem_euclid(len);
+        self.selected = next as usize;
+        self.extend_visual();
+        self.update_preview();
+    }
+
+    fn move_selection_by_count(&mut self, delta: isize) {
+        let count = self.consume_count_or(1);
+        let scaled = delta.saturating_mul(count as isize);
+        self.move_selection(scaled);
+    }
+
+    fn accumulate_count(&mut self, digit: char) {
+        if let Some(value) = digit.to_digit(10) {
+            let next = self
+                .pending_count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(value as usize);
+            let capped = next.min(9_999);
+            self.pending_count = Some(capped);
+            self.status = if self.focus == Focus::List && !self.entries.is_empty() {
+                let last = self.entries.len() - 1;
+                format!(
+                    "Count: {capped} (j → entry {}, k → entry {})",
+                    (self.selected + capped).min(last) + 1,
+                    self.selected.saturating_sub(capped) + 1
+                )
+            } else {
+                format!("Count: {capped}")
+            };
+        }
+    }
+
+    fn consume_count_or(&mut self, default: usize) -> usize {
+        self.pending_count.take().unwrap_or(default)
+    }
+
+    fn take_count(&mut self) -> Option<usize> {
+        self.pending_count.take()
+    }
+
+    fn clear_pending_count(&mut self) {
+        self.pending_count = None;
+    }
+
+    fn resolve_command_alias(&self, cmd: &str) -> String {
+        let key = cmd.to_lowercase();
+        self.command_aliases.get(&key).cloned().unwrap_or(key)
+    }
+
+    fn jump_to_index(&mut self, index: usize) {
+        if self.entries.is_empty() {
+            self.selected = 0;
+            self.preview = PreviewPane::empty();
+            return;
+        }
+        let max_idx = self.entries.len().saturating_sub(1);
+        self.selected = index.min(max_idx);
+        self.extend_visual();
+        self.update_preview();
+    }
+
+    fn jump_to_percent(&mut self, percent: usize) {
+        let percent = percent.min(100);
+        let index = (percent * self.entries.len()).div_ceil(100);
+        self.jump_to_index(index.saturating_sub(1));
+    }
+
+    fn jump_to_end(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = self.entries.len() - 1;
+            self.extend_visual();
+            self.update_preview();
+        }
+    }
+
+    fn enter_selection(&mut self) -> Result<()> {
+        if let Some(entry) = self.entries.get(self.selected).cloned() {
+            if entry.is_dir
+                && let Some(view) = &mut self.archive
+            {
+                view.prefix = view.member_path(&entry.name);
+                self.reset_search_state();
+                self.forget_marks();
+                self.selected = 0;
+                self.show_archive_dir();
+            } else if entry.is_dir {
+                let previous = self.current_dir.clone();
+                self.current_dir = entry.path.clone();
+                if let Err(err) = self.refresh_async(true) {
+                    self.current_dir = previous;
+                    return Err(err);
+                }
+                self.history.record(previous, Some(entry.name.clone()));
+                self.reset_search_state();
+                self.forget_marks();
+                self.collapse_root = self.collapse_single_child.then_some(entry.path);
+                self.collapse_pending = self.collapse_single_child;
+            } else if self.chooser {
+                self.chosen = Some(entry.path);
+            } else if self.archive.is_some() {
+                self.status = format!(
+                    "'{}' is inside an archive, :extract copies it out",
+                    entry.name
+                );
+            } else if let Some(kind) = ArchiveKind::detect(&entry.path) {
+                self.request_archive_listing(entry.path, kind);
+            } else {
+                self.status = format!("'{}' is not a directory", entry.name);
+            }
+        }
+        Ok(())
+    }
+
+    fn open_parent(&mut self) -> Result<()> {
+        if let Some(view) = &mut self.archive {
+            let select = if view.prefix.is_empty() {
+                let name = display_name(&view.archive);
+                return self.navigate_to(self.current_dir.clone(), Some(name), "Left archive");
+            } else {
+                let (parent, name) = view.prefix.rsplit_once('/').unwrap_or(("", &view.prefix));
+                let name = name.to_string();
+                view.prefix = parent.to_string();
+                name
+            };
+            self.reset_search_state();
+            self.forget_marks();
+            self.pending_select = Some(select);
+            self.show_archive_dir();
+            return Ok(());
+        }
+        if let Some(root) = self.collapse_root.take()
+            && self.current_dir != root
+            && self.current_dir.starts_with(&root)
+            && let (Some(parent), Some(name)) = (root.parent(), root.file_name())
+        {
+            let name = name.to_string_lossy().into_owned();
+            return self.navigate_to(parent.to_path_buf(), Some(name), "Left collapsed chain");
+        }
+        let previous = self.current_dir.clone();
+        let selected = self.selected_entry().map(|entry| entry.name.clone());
+        if self.current_dir.pop() {
+            if let Err(err) = self.refresh_async(true) {
+                self.current_dir = previous;
+                return Err(err);
+            }
+            self.history.record(previous, selected);
+            self.reset_search_state();
+            self.forget_marks();
+        }
+        Ok(())
+    }
+
+    fn start_search(&mut self) {
+        self.clear_pending_count();
+        let buffer = self.last_search.clone().unwrap_or_default();
+        self.input_mode = InputMode::Search {
+            buffer,
+            feedback: None,
+        };
+        self.status = "Search: type to filter, Enter to apply".into();
+    }
+
+    fn start_command(&mut self) {
+        self.start_command_with("");
+    }
+
+    fn start_filter(&mut self) {
+        self.clear_pending_count();
+        let buffer = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.pattern.clone())
+            .unwrap_or_default();
+        self.input_mode = InputMode::Filter { buffer };
+        self.status = "Filter: type to narrow, Enter to keep, Esc to clear".into();
+    }
+
+    fn preview_filter(&mut self) {
+        if let InputMode::Filter { buffer } = &self.input_mode {
+            self.filter = (!buffer.is_empty()).then(|| NameFilter::new(buffer));
+        }
+        self.rebuild_view();
+        self.update_preview();
+    }
+
+    fn set_filter(&mut self, pattern: Option<String>) {
+        self.filter = pattern
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| NameFilter::new(&pattern));
+        self.rebuild_view();
+        self.update_preview();
+        self.status = match &self.filter {
+            Some(filter) => format!(
+                "Showing {} of {} entries matching '{}'",
+                self.entries.len(),
+                self.view_of(&self.all_entries).len(),
+                filter.pattern
+            ),
+            None => "Filter cleared".into(),
+        };
+    }
+
+    fn command_filter(&mut self, args: &str) {
+        self.clear_pending_count();
+        let pattern = args.trim();
+        self.set_filter((!pattern.is_empty()).then(|| pattern.to_string()));
+    }
+
+    fn start_command_with(&mut self, text: &str) {
+        self.clear_pending_count();
+        self.input_mode = InputMode::Command {
+            buffer: text.into(),
+            feedback: None,
+        };
+        self.status = "Command: Enter to run, Esc to cancel".into();
+    }
+
+    fn start_palette(&mut self) {
+        self.clear_pending_count();
+        self.input_mode = InputMode::Palette {
+            buffer: String::new(),
+            selected: 0,
+        };
+        self.status = "Palette: type to filter, Enter to run, Esc to cancel".into();
+    }
+
+    fn palette_matches(&self, query: &str) -> Vec<PaletteItem> {
+        let mut items: Vec<PaletteItem> = COMMANDS
+            .iter()
+            .map(|spec| PaletteItem {
+                name: spec.name.to_string(),
+                summary: spec.summary.to_string(),
+                needs_args: spec.needs_args,
+            })
+            .collect();
+        let mut aliases: Vec<(&String, &String)> = self.command_aliases.iter().collect();
+        aliases.sort();
+        items.extend(aliases.into_iter().map(|(alias, target)| {
+            PaletteItem {
+                name: alias.clone(),
+                summary: format!("Alias for {target}"),
+                needs_args: COMMANDS
+                    .iter()
+                    .any(|spec| spec.name == target && spec.needs_args),
+            }
+        }));
+        let mut macros: Vec<(&String, &String)> = self.macros.iter().collect();
+        macros.sort();
+        items.extend(macros.into_iter().map(|(name, body)| PaletteItem {
+            name: name.clone(),
+            summary: format!("Macro: {body}"),
+            needs_args: false,
+        }));
+        let mut scored: Vec<(i64, PaletteItem)> = items
+            .into_iter()
+            .filter_map(|item| {
+                let score = fuzzy_score(&item.name, query)
+                    .or_else(|| fuzzy_score(&item.summary, query).map(|score| score - 100))?;
+                Some((score, item))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| cmp::Reverse(*score));
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+
+    fn choose_palette_item(&mut self) {
+        let InputMode::Palette { buffer, selected } = &self.input_mode else {
+            return;
+        };
+        let Some(item) = self.palette_matches(buffer).into_iter().nth(*selected) else {
+            self.status = "No matching command".into();
+            return;
+        };
+        if item.needs_args {
+            self.start_command_with(&format!("{} ", item.name));
+        } else {
+            self.cancel_overlay();
+            self.run_command(item.name);
+        }
+    }
+
+    fn move_palette(&mut self, delta: isize) {
+        let InputMode::Palette { buffer, selected } = &self.input_mode else {
+            return;
+        };
+        let total = self.palette_matches(buffer).len();
+        if total == 0 {
+            return;
+        }
+        let next = (*selected as isize + delta).rem_euclid(total as isize) as usize;
+        if let InputMode::Palette { selected, .. } = &mut self.input_mode {
+            *selected = next;
+        }
+    }
+
+    fn start_fuzzy(
+        &mut self,
+        title: &str,
+        candidates: Vec<PathBuf>,
+        base: PathBuf,
+        action: FuzzyAction,
+    ) {
+        self.clear_pending_count();
+        self.input_mode = InputMode::Fuzzy {
+            title: title.into(),
+            buffer: String::new(),
+            selected: 0,
+            candidates,
+            base,
+            action,
+        };
+        self.status = "Type to filter, Enter to jump, Esc to cancel".into();
+    }
+
+    fn start_finder(&mut self) {
+        if self.entries.is_empty() {
+            self.clear_pending_count();
+            self.status = "Nothing to find in an empty directory".into();
+            return;
+        }
+        let candidates = self
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        let base = self.current_dir.clone();
+        self.start_fuzzy("Find", candidates, base, FuzzyAction::Select);
+    }
+
+    fn choose_fuzzy_item(&mut self) {
+        let InputMode::Fuzzy {
+            buffer,
+            selected,
+            candidates,
+            base,
+            action,
+            ..
+        } = &self.input_mode
+        else {
+            return;
+        };
+        let action = *action;
+        let Some(path) = fuzzy_filter(candidates, base, buffer)
+            .get(*selected)
+            .map(|(index, _)| candidates[*index].clone())
+        else {
+            self.status = "No match".into();
+            return;
+        };
+        self.cancel_overlay();
+        if action == FuzzyAction::Select {
+            match self.entries.iter().position(|entry| entry.path == path) {
+                Some(index) => {
+                    self.jump_to_index(index);
+                    self.status = format!("Selected {}", display_name(&path));
+                }
+                None => self.status = format!("{} is no longer listed", display_name(&path)),
+            }
+            return;
+        }
+        let result = if path.is_dir() && action == FuzzyAction::Visit {
+            let message = format!("Jumped to {}", path.display());
+            self.navigate_to(path, None, message)
+        } else {
+            self.reveal_path(&path)
+        };
+        if let Err(err) = result {
+            self.set_error(format!("Jump failed: {err:#}"));
+        }
+    }
+
+    fn move_fuzzy(&mut self, delta: isize) {
+        let InputMode::Fuzzy {
+            buffer,
+            selected,
+            candidates,
+            base,
+            ..
+        } = &mut self.input_mode
+        else {
+            return;
+        };
+        let total = fuzzy_filter(candidates, base, buffer).len();
+        if total > 0 {
+            *selected = (*selected as isize + delta).rem_euclid(total as isize) as usize;
+        }
+    }
+
+    fn complete_cd(&mut self) -> Result<()> {
+        let InputMode::Command { buffer, .. } = &self.input_mode else {
+            return Ok(());
+        };
+        let Some(fragment) = buffer.strip_prefix("cd ") else {
+            return Ok(());
+        };
+        let (index, dirs) = match self.cd_completions.take() {
+            Some((dirs, index)) => ((index + 1) % dirs.len(), dirs),
+            None => {
+                let fragments = parse_args(fragment)?;
+                if self.zoxide {
+                    let token = self.next_token;
+                    self.next_token += 1;
+                    self.pending_zoxide = Some(token);
+                    self.fs
+                        .request_zoxide_query(fragments, self.current_dir.clone(), token);
+                    self.set_overlay_feedback("Asking zoxide...");
+                    return Ok(());
+                }
+                let dirs: Vec<PathBuf> = self
+                    .frecency
+                    .ranked(unix_now())
+                    .into_iter()
+                    .filter(|dir| {
+                        *dir != self.current_dir
+                            && frecency_matches(&dir.to_string_lossy(), &fragments)
+                    })
+                    .collect();
+                if dirs.is_empty() {
+                    return Err(anyhow!("No visited directory matches"));
+                }
+                (0, dirs)
+            }
+        };
+        self.show_cd_completion(dirs, index);
+        Ok(())
+    }
+
+    fn show_cd_completion(&mut self, dirs: Vec<PathBuf>, index: usize) {
+        let completed = format!("cd {}", shell_quote(&dirs[index]));
+        let count = dirs.len();
+        if let InputMode::Command { buffer, feedback } = &mut self.input_mode {
+            *buffer = completed;
+            *feedback = Some(format!("{}/{count}, Tab for the next match", index + 1));
+        }
+        self.cd_completions = Some((dirs, index));
+    }
+
+    fn command_z(&mut self, args: &str) -> Result<()> {
+        let fragments = parse_args(args)?;
+        let now = unix_now();
+        if fragments.is_empty() {
+            let dirs = self.frecency.ranked(now);
+            if dirs.is_empty() {
+                self.status = "No visited directories yet".into();
+                return Ok(());
+            }
+            self.start_fuzzy(
+                "Frecent directories",
+                dirs,
+                PathBuf::new(),
+                FuzzyAction::Visit,
+            );
+            return Ok(());
+        }
+        let dir = self
+            .frecency
+            .best_match(&fragments, now, &self.current_dir)
+            .ok_or_else(|| anyhow!("No visited directory matches '{}'", fragments.join(" ")))?;
+        let message = format!("Jumped to {}", dir.display());
+        self.navigate_to(dir, None, message)
+    }
+
+    fn cancel_overlay(&mut self) {
+        let closed = mem::replace(&mut self.input_mode, InputMode::Normal);
+        self.cd_completions = None;
+        self.pending_zoxide = None;
+        self.result_targets = None;
+        match closed {
+            InputMode::Fuzzy { .. } => {
+                if let Some(finder) = self.pending_finder.take() {
+                    finder.cancel.store(true, Ordering::Relaxed);
+                }
+            }
+            InputMode::Picker {
+                action: PickerAction::Grep { .. },
+                ..
+            } => self.stop_grep(),
+            InputMode::Picker {
+                action: PickerAction::Results { .. },
+                ..
+            } => self.stop_find(),
+            _ => {}
+        }
+        self.clear_pending_count();
+    }
+
+    fn set_overlay_feedback(&mut self, message: impl Into<String>) {
+        let text = Some(message.into());
+        match &mut self.input_mode {
+            InputMode::Search { feedback, .. } => *feedback = text,
+            InputMode::Command { feedback, .. } => *feedback = text,
+            InputMode::PreviewSearch { feedback, .. } => *feedback = text,
+            _ => {}
+        }
+    }
+
+    fn clear_overlay_feedback(&mut self) {
+        match &mut self.input_mode {
+            InputMode::Search { feedback, .. } => *feedback = None,
+            InputMode::Command { feedback, .. } => *feedback = None,
+            InputMode::PreviewSearch { feedback, .. } => *feedback = None,
+            _ => {}
+        }
+    }
+
+    fn list_state(&self) -> ratatui::widgets::ListState {
+        let mut state = ratatui::widgets::ListState::default().with_offset(self.list_offset.get());
+        if !self.entries.is_empty() {
+            let headers = self
+                .group_starts()
+                .iter()
+                .filter(|(start, _)| *start <= self.selected)
+                .count();
+            state.select(Some(self.selected + headers));
+        }
+        state
+    }
+
+    fn entry_group(&self, entry: &FileEntry) -> EntryGroup {
+        if entry.is_dir {
+            return EntryGroup::Directories;
+        }
+        Path::new(&entry.name)
+            .extension()
+            .and_then(|ext| {
+                self.group_extensions
+                    .get(&ext.to_string_lossy().to_lowercase())
+                    .copied()
+            })
+            .unwrap_or(EntryGroup::Other)
+    }
+
+    fn group_starts(&self) -> Vec<(usize, EntryGroup)> {
+        if !self.grouped {
+            return Vec::new();
+        }
+        let mut starts: Vec<(usize, EntryGroup)> = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let group = self.entry_group(entry);
+            if starts.last().is_none_or(|(_, last)| *last != group) {
+                starts.push((index, group));
+            }
+        }
+        starts
+    }
+
+    fn toggle_grouped_view(&mut self) {
+        self.grouped = !self.grouped;
+        self.rebuild_view();
+        self.status = if self.grouped {
+            "Grouping entries by type".into()
+        } else {
+            "Flat listing".into()
+        };
+    }
+
+    fn selected_entry(&self) -> Option<&FileEntry> {
+        self.entries.get(self.selected)
+    }
+
+    fn selected_path(&self) -> Option<PathBuf> {
+        self.selected_entry().map(|entry| entry.path.clone())
+    }
+
+    fn take_external_command(&mut self) -> Option<ExternalCommand> {
+        self.pending_external.take()
+    }
+
+    fn describe_selection(&self) -> String {
+        if self.is_loading {
+            "Loading directory...".into()
+        } else {
+            self.entries
+                .get(self.selected)
+                .map(|entry| entry.describe(self.absolute_time_format()))
+                .unwrap_or_else(|| "No entries".into())
+        }
+    }
+
+    fn overlay_prompt(&self) -> Option<(String, String)> {
+        match &self.input_mode {
+            InputMode::Normal
+            | InputMode::Viewer { .. }
+            | InputMode::Picker { .. }
+            | InputMode::Note { .. }
+            | InputMode::DiskUsage { .. }
+            | InputMode::Palette { .. }
+            | InputMode::Fuzzy { .. } => None,
+            InputMode::Filter { buffer } => Some(("Filter".into(), format!("filter: {buffer}"))),
+            InputMode::Search { buffer, feedback } => {
+                let mut content = format!("/{}", buffer);
+                if let Some(msg) = feedback {
+                    content.push('\n');
+                    content.push_str(msg);
+                }
+                Some(("Search".into(), content))
+            }
+            InputMode::Command { buffer, feedback } => {
+                let mut content = format!(":{}", buffer);
+                if let Some(msg) = feedback {
+                    content.push('\n');
+                    content.push_str(msg);
+                }
+                Some(("Command".into(), content))
+            }
+            InputMode::PreviewSearch { buffer, feedback } => {
+                let mut content = format!("?{}", buffer);
+                if let Some(msg) = feedback {
+                    content.push('\n');
+                    content.push_str(msg);
+                }
+                Some(("Preview Search".into(), content))
+            }
+            InputMode::Confirm { message, action } => {
+                Some(("Confirm".into(), format!("{message} {}", action.choices())))
+            }
+        }
+    }
+
+    fn clamp_selection(&mut self) {
+        if self.selected >= self.entries.len() {
+            self.selected = self.entries.len().saturating_sub(1);
+        }
+        self.update_preview();
+    }
+
+    fn drain_fs_events(&mut self, rx: &mut UnboundedReceiver<FsEvent>) {
+        loop {
+            match rx.try_recv() {
+                Ok(event) => self.handle_fs_event(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.set_error("Filesystem worker disconnected");
+                    self.is_loading = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn handle_fs_event(&mut self, event: FsEvent) {
+        match event {
+            FsEvent::LargeDirectory {
+                path,
+                token,
+                threshold,
+            } => {
+                if Some(token) != self.pending_token {
+                    return;
+                }
+                self.pending_token = None;
+                self.is_loading = false;
+                if matches!(self.input_mode, InputMode::Normal) {
+                    self.input_mode = InputMode::Confirm {
+                        message: format!(
+                            "{} has more than {threshold} entries, load anyway?",
+                            path.display()
+                        ),
+                        action: ConfirmAction::LoadLargeDir { path },
+                    };
+                    self.status = "Load with y, go back with n".into();
+                } else {
+                    self.status = format!(
+                        "{} has more than {threshold} entries, not loaded (:refresh asks again)",
+                        path.display()
+                    );
+                }
+            }
+            FsEvent::ScanRetry {
+                token,
+                attempt,
+                retries,
+                error,
+            } => {
+                if Some(token) == self.pending_token {
+                    self.status = format!("Retrying scan ({attempt}/{retries}): {error}");
+                }
+            }
+            FsEvent::DirectoryLoaded {
+                path,
+                token,
+                result,
+                skipped,
+            } => {
+                if self.parent_token == Some(token) {
+                    self.parent_token = None;
+                    if let (Ok(entries), Some(parent)) = (result, self.current_dir.parent())
+                        && path == parent
+                    {
+                        let entries = self.view_of(&entries);
+                        self.parent_listing = Some((path, entries));
+                    }
+                    return;
+                }
+                if let Some(dual) = &mut self.dual
+                    && dual.token == Some(token)
+                {
+                    dual.token = None;
+                    if let Ok(entries) = result {
+                        self.set_other_entries(entries);
+                    }
+                    return;
+                }
+                if Some(token) != self.pending_token {
+                    return;
+                }
+                self.pending_token = None;
+                self.is_loading = false;
+                match result {
+                    Ok(entries) => {
+                        if self.loaded_dir.as_deref() != Some(path.as_path()) {
+                            self.frecency.visit(&path, unix_now());
+                            if self.zoxide {
+                                self.fs.zoxide_add(path.clone());
+                            }
+                            if let Err(err) = self.refresh_parent_column() {
+                                self.set_error(format!("Refresh failed: {err:#}"));
+                            }
+                            self.apply_dir_override(&path);
+                            self.mount_info = self
+                                .show_mount_info
+                                .then(|| self.caches.mount_info(&path))
+                                .flatten();
+                            self.has_note = note_path(&path).is_some_and(|note| note.exists());
+                        }
+                        self.track_changes(&path, &entries);
+                        if self.other_pane_dir() == Some(path.as_path()) {
+                            self.set_other_entries(entries.clone());
+                        }
+                        self.all_entries = entries;
+                        self.max_entry_size = self
+                            .all_entries
+                            .iter()
+                            .filter_map(|entry| entry.size)
+                            .max()
+                            .unwrap_or(0);
+                        self.rebuild_view();
+                        if let Some(name) = self.pending_select.take()
+                            && let Some(index) =
+                                self.entries.iter().position(|entry| entry.name == name)
+                        {
+                            self.selected = index;
+                            self.update_preview();
+                        }
+                        if mem::take(&mut self.collapse_pending)
+                            && let [entry] = self.entries.as_slice()
+                            && entry.is_dir
+                            && !entry.path.is_symlink()
+                        {
+                            self.current_dir = entry.path.clone();
+                            self.collapse_pending = true;
+                            if let Err(err) = self.refresh_async(true) {
+                                self.current_dir = path;
+                                self.collapse_pending = false;
+                                self.set_error(format!("Navigation failed: {err:#}"));
+                            }
+                            return;
+                        }
+                        if mem::take(&mut self.deleted_since_load)
+                            && self.auto_parent_on_empty
+                            && self.all_entries.is_empty()
+                            && let (Some(parent), Some(name)) = (path.parent(), path.file_name())
+                        {
+                            let name = name.to_string_lossy().into_owned();
+                            let message = format!("{name} is empty, moved up");
+                            if let Err(err) =
+                                self.navigate_to(parent.to_path_buf(), Some(name), message)
+                            {
+                                self.set_error(format!("Navigation failed: {err:#}"));
+                            }
+                            return;
+                        }
+                        if let Some(message) = self.last_action_message.take() {
+                            self.status = message;
+                        } else {
+                            self.status = format!(
+                                "Loaded {} entries from {}",
+                                self.entries.len(),
+                                path.display()
+                            );
+                        }
+                        if skipped > 0 {
+                            self.set_error(format!(
+                                "Skipped {skipped} unreadable entries in {}",
+                                path.display()
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        self.all_entries.clear();
+                        self.entries.clear();
+                        self.selected = 0;
+                        self.last_action_message = None;
+                        self.set_error(format!("Error loading {}: {}", path.display(), err));
+                    }
+                }
+            }
+            FsEvent::HashProgress {
+                token,
+                name,
+                processed,
+                total,
+            } => {
+                if self.pending_hash.as_ref().map(|p| p.token) != Some(token) {
+                    return;
+                }
+                let percent = (processed * 100).checked_div(total).unwrap_or(100);
+                self.status = format!("Hashing {name}: {percent}%");
+            }
+            FsEvent::HashComputed {
+                token,
+                name,
+                algorithm,
+                result,
+            } => {
+                let Some(pending) = self.pending_hash.take_if(|p| p.token == token) else {
+                    return;
+                };
+                match result {
+                    Ok(digest) => {
+                        self.status = format!("{} {name}: {digest}", algorithm.label());
+                        if pending.copy {
+                            match self.write_clipboard(&digest) {
+                                Ok(()) => self.status.push_str(" (copied)"),
+                                Err(err) => {
+                                    self.status.push_str(&format!(" (copy failed: {err:#})"))
+                                }
+                            }
+                        }
+                    }
+                    Err(err) => self.set_error(format!("hash failed: {err}")),
+                }
+            }
+            FsEvent::ArchiveListed {
+                token,
+                archive,
+                kind,
+                result,
+            } => self.handle_archive_listed(token, archive, kind, result),
+            FsEvent::PreviewLoaded {
+                token,
+                entry,
+                result,
+            } => {
+                if self.pending_preview != Some(token) {
+                    return;
+                }
+                self.pending_preview = None;
+                self.preview = match result {
+                    Ok(preview) => {
+                        self.caches.store_preview(&entry, preview.clone());
+                        preview
+                    }
+                    Err(err) => PreviewPane::error(format!("Preview error: {err}")),
+                };
+            }
+            FsEvent::ZoxideMatches { token, result } => {
+                if self
+                    .pending_zoxide
+                    .take_if(|pending| *pending == token)
+                    .is_none()
+                    || !matches!(self.input_mode, InputMode::Command { .. })
+                {
+                    return;
+                }
+                match result {
+                    Ok(dirs) if dirs.is_empty() => {
+                        self.set_overlay_feedback("No visited directory matches")
+                    }
+                    Ok(dirs) => self.show_cd_completion(dirs, 0),
+                    Err(err) => self.set_overlay_feedback(format!("zoxide failed: {err}")),
+                }
+            }
+            FsEvent::TreeBuilt { token, result } => {
+                let Some(pending) = self.pending_tree.take_if(|p| p.token == token) else {
+                    return;
+                };
+                match result {
+                    Ok(lines) => {
+                        self.status = format!("Tree of {}", pending.root.display());
+                        self.open_viewer(
+                            format!("Tree {}", pending.root.display()),
+                            lines.into_iter().map(Line::from).collect(),
+                        );
+                    }
+                    Err(err) => self.set_error(format!("tree failed: {err}")),
+                }
+            }
+            FsEvent::JobProgress {
+                token,
+                label,
+                processed,
+                total,
+            } => {
+                if self.pending_job != Some(token) {
+                    return;
+                }
+                let percent = (processed * 100).checked_div(total).unwrap_or(100);
+                self.status = format!("{label}: {percent}%");
+            }
+            FsEvent::JobFinished { token, result } => {
+                if self.pending_job != Some(token) {
+                    return;
+                }
+                self.pending_job = None;
+                let canceled = self.take_job_canceled();
+                match result {
+                    Ok(message) => {
+                        if let Err(err) = self.refresh_with_message(false, message) {
+                            self.set_error(format!("Refresh failed: {err:#}"));
+                        }
+                    }
+                    Err(_) if canceled => {
+                        if let Err(err) =
+                            self.refresh_with_message(false, "Job canceled, partial output removed")
+                        {
+                            self.set_error(format!("Refresh failed: {err:#}"));
+                        }
+                    }
+                    Err(err) => self.set_error(err),
+                }
+                self.start_waiting_job();
+            }
+            FsEvent::OperationProgress {
+                token,
+                label,
+                done,
+                total,
+            } => {
+                if self.pending_job != Some(token) {
+                    return;
+                }
+                let unit = if self.job_counts_files {
+                    "files"
+                } else {
+                    "items"
+                };
+                self.job_progress = Some(format!("{label} {done}/{total} {unit}…"));
+            }
+            FsEvent::OperationsFinished {
+                token,
+                verb,
+                results,
+            } => {
+                if self.pending_job != Some(token) {
+                    return;
+                }
+                self.pending_job = None;
+                self.job_progress = None;
+                let canceled = self.take_job_canceled();
+                let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+                let noted = results
+                    .iter()
+                    .filter(|(_, result)| matches!(result, Ok(Some(_))))
+                    .count();
+                let lines = results
+                    .iter()
+                    .map(|(label, result)| match result {
+                        Ok(None) => format!("ok  {label}"),
+                        Ok(Some(note)) => format!("ok  {label} ({note})"),
+                        Err(err) => format!("failed  {label}: {err}"),
+                    })
+                    .collect();
+                let message = if canceled {
+                    let message = format!(
+                        "Canceled after {} of {} operation(s)",
+                        results.len() - failed,
+                        results.len()
+                    );
+                    self.show_toast(message.clone(), lines);
+                    message
+                } else if failed == 0 && noted > 0 {
+                    let message = format!("{verb} {} item(s), {noted} with notes", results.len());
+                    self.show_error_toast(message.clone(), lines);
+                    message
+                } else if failed == 0 {
+                    let message = format!("{verb} {} item(s)", results.len());
+                    if results.len() > 1 {
+                        self.show_toast(message.clone(), lines);
+                    }
+                    message
+                } else {
+                    let message = format!("{failed} of {} operation(s) failed", results.len());
+                    self.show_error_toast(message.clone(), lines);
+                    message
+                };
+                if mem::take(&mut self.quit_after_queue) && failed == 0 {
+                    self.quit_requested = true;
+                }
+                if failed == 0 {
+                    self.marked.clear();
+                }
+                self.marked
+                    .retain(|path| path.exists() || path.is_symlink());
+                self.deleted_since_load = true;
+                if let Err(err) = self.refresh_with_message(false, message) {
+                    self.set_error(format!("Refresh failed: {err:#}"));
+                }
+                if self.other_pane_dir() != Some(self.current_dir.as_path())
+                    && let Err(err) = self.refresh_other_pane()
+                {
+                    self.set_error(format!("Refresh failed: {err:#}"));
+                }
+                self.start_waiting_job();
+            }
+            FsEvent::ConfigChanged => {
+                self.config_reload_at = Some(Instant::now() + CONFIG_RELOAD_DEBOUNCE);
+            }
+            FsEvent::GrepMatches {
+                token,
+                matches,
+                done,
+            } => self.handle_grep_matches(token, matches, done),
+            FsEvent::GlobMatches { token, paths } => self.handle_glob_matches(token, paths),
+            FsEvent::FoundPaths { token, paths, done } => {
+                self.handle_found_paths(token, paths, done)
+            }
+            FsEvent::FinderPaths { token, paths, done } => {
+                if self
+                    .pending_finder
+                    .as_ref()
+                    .is_none_or(|finder| finder.token != token)
+                {
+                    return;
+                }
+                if done {
+                    self.pending_finder = None;
+                }
+                if let InputMode::Fuzzy {
+                    title,
+                    buffer,
+                    selected,
+                    candidates,
+                    base,
+                    ..
+                } = &mut self.input_mode
+                {
+                    let pinned = fuzzy_filter(candidates, base, buffer)
+                        .get(*selected)
+                        .map(|(index, _)| *index);
+                    candidates.extend(paths);
+                    if let Some(pinned) = pinned {
+                        *selected = fuzzy_filter(candidates, base, buffer)
+                            .iter()
+                            .position(|(index, _)| *index == pinned)
+                            .unwrap_or(0);
+                    }
+                    let count = candidates.len();
+                    *title = if !done {
+                        format!("Find ({count} files, scanning)")
+                    } else if count >= FINDER_MAX_FILES {
+                        format!("Find (first {count} files)")
+                    } else {
+                        format!("Find ({count} files)")
+                    };
+                }
+            }
+            FsEvent::RecentFiles { token, files, done } => {
+                let Some(pending) = self.pending_recent.as_mut().filter(|p| p.token == token)
+                else {
+                    return;
+                };
+                let opened = pending.opened;
+                if done {
+                    self.pending_recent = None;
+                }
+                if files.is_empty() {
+                    if done {
+                        self.status = format!("No files under {}", self.current_dir.display());
+                    }
+                    return;
+                }
+                let items: Vec<String> = files
+                    .iter()
+                    .map(|(path, modified)| {
+                        format!(
+                            "{:>4}  {}",
+                            format_age(*modified),
+                            path.strip_prefix(&self.current_dir)
+                                .unwrap_or(path)
+                                .display()
+                        )
+                    })
+                    .collect();
+                let paths: Vec<PathBuf> = files.into_iter().map(|(path, _)| path).collect();
+                let suffix = if done { "" } else { ", scanning" };
+                let title = format!("Recent files ({}{suffix})", paths.len());
+                self.status = if done {
+                    format!("{} most recently modified files", paths.len())
+                } else {
+                    "Scanning for recent files...".into()
+                };
+                match &mut self.input_mode {
+                    InputMode::Picker {
+                        title: shown_title,
+                        items: shown,
+                        selected,
+                        action: PickerAction::Recent { paths: shown_paths },
+                    } => {
+                        *selected = (*selected).min(items.len() - 1);
+                        *shown_title = title;
+                        *shown = items;
+                        *shown_paths = paths;
+                    }
+                    InputMode::Normal if !opened => {
+                        self.open_picker(title, items, PickerAction::Recent { paths });
+                        if let Some(pending) = &mut self.pending_recent {
+                            pending.opened = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            FsEvent::LocateFinished { token, paths } => {
+                let Some(pending) = self.pending_locate.take_if(|p| p.token == token) else {
+                    return;
+                };
+                if paths.is_empty() {
+                    self.status = format!("No file named '{}'", pending.name);
+                    return;
+                }
+                let items = paths
+                    .iter()
+                    .map(|path| {
+                        path.strip_prefix(&self.current_dir)
+                            .unwrap_or(path)
+                            .display()
+                            .to_string()
+                    })
+                    .collect();
+                let count = paths.len();
+                let more = if count >= self.locate_max_results {
+                    "+"
+                } else {
+                    ""
+                };
+                self.status = format!("Found {count}{more} '{}'", pending.name);
+                self.open_picker(
+                    format!("locate '{}' ({count}{more})", pending.name),
+                    items,
+                    PickerAction::JumpTo { paths },
+                );
+            }
+        }
+    }
+
+    fn open_viewer(&mut self, title: String, lines: Vec<Line<'static>>) {
+        self.clear_pending_count();
+        self.input_mode = InputMode::Viewer {
+            title,
+            lines,
+            scroll: 0,
+        };
+    }
+
+    fn open_picker(&mut self, title: String, items: Vec<String>, action: PickerAction) {
+        self.clear_pending_count();
+        self.input_mode = InputMode::Picker {
+            title,
+            items,
+            selected: 0,
+            action,
+        };
+    }
+
+    fn move_picker(&mut self, delta: isize) {
+        if let InputMode::Picker {
+            items, selected, ..
+        } = &mut self.input_mode
+            && !items.is_empty()
+        {
+            let len = items.len() as isize;
+            *selected = (*selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    fn choose_picker_item(&mut self) {
+        if let InputMode::Picker {
+            selected, action, ..
+        } = mem::replace(&mut self.input_mode, InputMode::Normal)
+        {
+            let result = match action {
+                PickerAction::OpenWith { path, apps } => match apps.get(selected) {
+                    Some(app) => self.launch_open_with(app, path),
+                    None => Ok(()),
+                },
+                PickerAction::JumpTo { paths } | PickerAction::Recent { paths } => {
+                    match paths.get(selected) {
+                        Some(path) => self.reveal_path(path),
+                        None => Ok(()),
+                    }
+                }
+                PickerAction::Results { paths, .. } => {
+                    self.stop_find();
+                    match paths.get(selected) {
+                        Some(path) => self.reveal_path(path),
+                        None => Ok(()),
+                    }
+                }
+                PickerAction::Grep { matches } => {
+                    self.stop_grep();
+                    match matches.get(selected) {
+                        Some(_) if self.read_only => Err(anyhow!("read-only mode")),
+                        Some((path, line)) => {
+                            self.status = format!("Launching editor for {}", display_name(path));
+                            self.pending_external = Some(ExternalCommand::EditLine {
+                                path: path.clone(),
+                                line: *line,
+                            });
+                            Ok(())
+                        }
+                        None => Ok(()),
+                    }
+                }
+                PickerAction::Bookmarks { dirs } => match dirs.get(selected) {
+                    Some(dir) if dir.is_dir() => {
+                        self.navigate_to(dir.clone(), None, format!("Jumped to {}", dir.display()))
+                    }
+                    Some(dir) => Err(anyhow!("{} no longer exists", dir.display())),
+                    None => Ok(()),
+                },
+                PickerAction::Restore {
+                    item,
+                    info_path,
+                    original,
+                } => {
+                    let dest = if selected == 0 {
+                        original.clone()
+                    } else {
+                        suffixed_path(&original)
+                    };
+                    self.finish_restore(&item, &info_path, &original, &dest)
+                }
+                PickerAction::Queue => self.run_queue(),
+                PickerAction::Rename { renames } => self.apply_renames(renames),
+            };
+            if let Err(err) = result {
+                self.set_error(format!("Action failed: {err:#}"));
+            }
+        }
+    }
+
+    fn scroll_viewer(&mut self, delta: isize) {
+        if let InputMode::Viewer { lines, scroll, .. } = &mut self.input_mode {
+            let max = lines.len().saturating_sub(1);
+            *scroll = scroll.saturating_add_signed(delta).min(max);
+        }
+    }
+
+    fn copy_viewer_text(&mut self) -> Result<()> {
+        let InputMode::Viewer { lines, .. } = &self.input_mode else {
+            return Ok(());
+        };
+        let text = lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.write_clipboard(&text)?;
+        self.status = "Copied viewer contents to clipboard".into();
+        Ok(())
+    }
+
+    fn set_error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        let mut lines = message.lines();
+        self.status = lines.next().unwrap_or_default().to_string();
+        self.error_status = Some(self.status.clone());
+        let details: Vec<String> = lines.map(str::to_string).collect();
+        if !details.is_empty() {
+            self.show_error_toast(self.status.clone(), details);
+        }
+    }
+
+    fn show_error_toast(&mut self, title: impl Into<String>, lines: Vec<String>) {
+        self.toast = Some(Toast {
+            title: title.into(),
+            lines,
+            is_error: true,
+            expires_at: None,
+        });
+    }
+
+    fn show_toast(&mut self, title: impl Into<String>, lines: Vec<String>) {
+        self.toast = Some(Toast {
+            title: title.into(),
+            lines,
+            is_error: false,
+            expires_at: Some(Instant::now() + TOAST_DURATION),
+        });
+    }
+
+    fn track_changes(&mut self, dir: &Path, entries: &[FileEntry]) {
+        // Compare against the previous scan rather than `all_entries`, which a
+        // clearing refresh (e.g. after a rename) has already emptied.
+        let seen = mem::replace(
+            &mut self.last_seen,
+            entries
+                .iter()
+                .map(|entry| (entry.path.clone(), (entry.size, entry.modified)))
+                .collect(),
+        );
+        if self.loaded_dir.as_deref() != Some(dir) {
+            self.changed_at.clear();
+            self.loaded_dir = Some(dir.to_path_buf());
+            return;
+        }
+        let now = Instant::now();
+        for entry in entries {
+            if seen.get(&entry.path) != Some(&(entry.size, entry.modified)) {
+                self.changed_at.insert(entry.path.clone(), now);
+            }
+        }
+    }
+
+    fn change_highlight(&self, entry: &FileEntry) -> Option<Color> {
+        let elapsed = self.changed_at.get(&entry.path)?.elapsed();
+        if elapsed >= CHANGE_HIGHLIGHT {
+            return None;
+        }
+        let strength = 1.0 - elapsed.as_secs_f32() / CHANGE_HIGHLIGHT.as_secs_f32();
+        Some(Color::Rgb(
+            (110.0 * strength) as u8,
+            (90.0 * strength) as u8,
+            0,
+        ))
+    }
+
+    fn tick(&mut self, now: Instant) {
+        self.expire_status(now);
+        if self.config_reload_at.is_some_and(|at| now >= at) {
+            self.config_reload_at = None;
+            if let Err(err) = self.reload_config() {
+                self.set_error(format!("Kept previous config: {err:#}"));
+            }
+        }
+        if self
+            .toast
+            .as_ref()
+            .and_then(|toast| toast.expires_at)
+            .is_some_and(|expires_at| now >= expires_at)
+        {
+            self.toast = None;
+        }
+        self.changed_at
+            .retain(|_, at| now.duration_since(*at) < CHANGE_HIGHLIGHT);
+        if self.marquee_anchor.0 != self.selected {
+            self.marquee_anchor = (self.selected, now);
+        }
+    }
+
+    fn marquee_step(&self) -> usize {
+        let elapsed = self
+            .marquee_anchor
+            .1
+            .elapsed()
+            .saturating_sub(MARQUEE_DELAY);
+        (elapsed.as_millis() / MARQUEE_STEP.as_millis()) as usize
+    }
+
+    fn expire_status(&mut self, now: Instant) {
+        if self.status != self.status_seen {
+            self.status_seen = self.status.clone();
+            self.status_since = now;
+            return;
+        }
+        let busy = self.is_loading
+            || self.pending_hash.is_some()
+            || self.pending_tree.is_some()
+            || self.pending_locate.is_some()
+            || self.pending_job.is_some();
+        if self.status.is_empty() || busy || !matches!(self.input_mode, InputMode::Normal) {
+            return;
+        }
+        let timeout = if self.error_status.as_deref() == Some(self.status.as_str()) {
+            self.error_status_timeout
+        } else {
+            self.status_timeout
+        };
+        if timeout.is_some_and(|timeout| now.duration_since(self.status_since) >= timeout) {
+            self.status.clear();
+            self.error_status = None;
+        }
+    }
+
+    fn system_clipboard(&mut self) -> Result<&mut Clipboard> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(Clipboard::new().context("opening system clipboard")?);
+        }
+        self.clipboard
+            .as_mut()
+            .ok_or_else(|| anyhow!("System clipboard unavailable"))
+    }
+
+    fn write_clipboard(&mut self, text: &str) -> Result<()> {
+        self.system_clipboard()?
+            .set_text(text.to_string())
+            .context("writing to clipboard")
+    }
+
+    fn command_paste(&mut self, args: &str) -> Result<()> {
+        if args.trim().is_empty() {
+            let files = self
+                .system_clipboard()?
+                .get()
+                .file_list()
+                .unwrap_or_default();
+            if !files.is_empty() {
+                return self.paste_files(&files);
+            }
+        }
+        let text = self
+            .system_clipboard()?
+            .get_text()
+            .context("reading clipboard")?;
+        self.paste_text(args, &text)
+    }
+
+    fn paste_files(&mut self, files: &[PathBuf]) -> Result<()> {
+        for src in files {
+            let name = src
+                .file_name()
+                .ok_or_else(|| anyhow!("Cannot paste {}", src.display()))?;
+            let mut dest = self.current_dir.join(name);
+            if dest.exists() {
+                dest = suffixed_path(&dest);
+            }
+            if src.is_dir() {
+                copy_directory(src, &dest)?;
+            } else {
+                fs::copy(src, &dest)
+                    .with_context(|| format!("copying {} to {}", src.display(), dest.display()))?;
+            }
+        }
+        self.refresh_with_message(
+            false,
+            format!("Pasted {} file(s) from the clipboard", files.len()),
+        )
+    }
+
+    fn paste_text(&mut self, args: &str, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Err(anyhow!("Clipboard is empty"));
+        }
+        let path = if args.trim().is_empty() {
+            let path = self.current_dir.join("clipboard.txt");
+            if path.exists() {
+                suffixed_path(&path)
+            } else {
+                path
+            }
+        } else {
+            let name = self.validate_new_name(&single_arg(args)?, "")?;
+            self.current_dir.join(name)
+        };
+        fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .and_then(|mut file| io::Write::write_all(&mut file, text.as_bytes()))
+            .with_context(|| format!("writing {}", path.display()))?;
+        let name = display_name(&path);
+        self.pending_select = Some(name.clone());
+        self.refresh_with_message(
+            false,
+            format!("Pasted {} into {name}", format_size(text.len() as u64)),
+        )
+    }
+
+    fn footer_text(&self, width: usize) -> String {
+        let mut segments: Vec<String> = Vec::new();
+        if self.read_only {
+            segments.push("[read-only]".into());
+        }
+        if !self.status.is_empty() {
+            segments.push(self.status.clone());
+        }
+        if let Some(count) = self.pending_count {
+            segments.push(format!("count {}", count));
+        }
+        if let Some(progress) = &self.job_progress {
+            segments.push(progress.clone());
+        }
+        if self.visual.is_some() {
+            segments.push("VISUAL".into());
+        }
+        if let Some(register) = &self.register {
+            segments.push(format!(
+                "[{} {}]",
+                if register.kind == RegisterKind::Yank {
+                    "yank"
+                } else {
+                    "cut"
+                },
+                register.paths.len()
+            ));
+        }
+        if let Some(summary) = self.mark_summary() {
+            segments.push(summary);
+        }
+        if self.queue_mode || !self.queued.is_empty() {
+            segments.push(format!("[queue {}]", self.queued.len()));
+        }
+        if let Some((label, _)) = &self.modified_within {
+            segments.push(format!("since {label}"));
+        }
+        segments.push(Self::HELP_LINE.into());
+        fit_segments(&segments, width)
+    }
+
+    fn search_next(&mut self) {
+        if self.entries.is_empty() {
+            self.status = "No entries to search".into();
+            return;
+        }
+        let query = match self.last_search.clone() {
+            Some(q) => q,
+            None => {
+                self.status = "No previous search".into();
+                return;
+            }
+        };
+        let start = (self.selected + 1) % self.entries.len();
+        if let Some(index) = self.find_match(&query, start) {
+            self.selected = index;
+            self.status = format!("Match: {}", self.entries[index].name);
+            self.update_preview();
+        } else {
+            self.status = format!("No more matches for '{query}'");
+        }
+    }
+
+    fn search_prev(&mut self) {
+        if self.entries.is_empty() {
+            self.status = "No entries to search".into();
+            return;
+        }
+        let query = match self.last_search.clone() {
+            Some(q) => q,
+            None => {
+                self.status = "No previous search".into();
+                return;
+            }
+        };
+        let len = self.entries.len();
+        let start = if len == 0 {
+            0
+        } else {
+            (self.selected + len - 1) % len
+        };
+        if let Some(index) = self.find_match_reverse(&query, start) {
+            self.selected = index;
+            self.status = format!("Match: {}", self.entries[index].name);
+            self.update_preview();
+        } else {
+            self.status = format!("No previous matches for '{query}'");
+        }
+    }
+
+    fn find_match(&self, query: &str, start_index: usize) -> Option<usize> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let needle = query.to_lowercase();
+        let len = self.entries.len();
+        for offset in 0..len {
+            let index = (start_index + offset) % len;
+            if self.entries[index].name.to_lowercase().contains(&needle) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    fn find_match_reverse(&self, query: &str, start_index: usize) -> Option<usize> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let needle = query.to_lowercase();
+        let len = self.entries.len();
+        let mut index = start_index % len;
+        for _ in 0..len {
+            if self.entries[index].name.to_lowercase().contains(&needle) {
+                return Some(index);
+            }
+            if index == 0 {
+                index = len - 1;
+            } else {
+                index -= 1;
+            }
+        }
+        None
+    }
+
+    fn apply_search(&mut self, query: &str) {
+        if self.entries.is_empty() {
+            self.status = "No entries to search".into();
+            return;
+        }
+        let start = self.selected;
+        self.last_search = Some(query.to_string());
+        if let Some(index) = self.find_match(query, start) {
+            self.selected = index;
+            self.status = format!("Match: {}", self.entries[index].name);
+            self.update_preview();
+        } else {
+            self.status = format!("No match for '{query}'");
+        }
+    }
+
+    fn start_preview_search(&mut self) {
+        self.clear_pending_count();
+        let buffer = self.preview_query.clone().unwrap_or_default();
+        self.input_mode = InputMode::PreviewSearch {
+            buffer,
+            feedback: None,
+        };
+        self.status = "Preview search: type a query, Enter to highlight".into();
+    }
+
+    fn apply_preview_search(&mut self, query: &str) {
+        self.preview_query = Some(query.to_string());
+        let matches = self.preview_match_lines();
+        match matches
+            .iter()
+            .find(|&&line| line >= self.preview_scroll)
+            .or_else(|| matches.first())
+        {
+            Some(&line) => {
+                self.preview_scroll = line;
+                self.status = format!(
+                    "{} preview line(s) match '{query}', ]/[ to cycle",
+                    matches.len()
+                );
+            }
+            None => self.status = format!("No match for '{query}' in preview"),
+        }
+    }
+
+    fn preview_match_lines(&self) -> Vec<usize> {
+        let Some(query) = self.preview_query.as_deref() else {
+            return Vec::new();
+        };
+        self.preview
+            .body
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !find_case_insensitive(line, query).is_empty())
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn preview_search_next(&mut self) {
+        let matches = self.preview_match_lines();
+        let next = matches
+            .iter()
+            .find(|&&line| line > self.preview_scroll)
+            .or_else(|| matches.first());
+        self.jump_preview_match(next.copied(), matches.len());
+    }
+
+    fn preview_search_prev(&mut self) {
+        let matches = self.preview_match_lines();
+        let prev = matches
+            .iter()
+            .rev()
+            .find(|&&line| line < self.preview_scroll)
+            .or_else(|| matches.last());
+        self.jump_preview_match(prev.copied(), matches.len());
+    }
+
+    fn jump_preview_match(&mut self, line: Option<usize>, total: usize) {
+        match (line, self.preview_query.as_deref()) {
+            (_, None) => self.status = "No previous preview search".into(),
+            (None, Some(query)) => self.status = format!("No match for '{query}' in preview"),
+            (Some(line), Some(_)) => {
+                self.preview_scroll = line;
+                self.status = format!("Preview line {} ({total} matches)", line + 1);
+            }
+        }
+    }
+
+    fn cycle_focus(&mut self, step: isize) {
+        self.clear_pending_count();
+        let index = Focus::ALL
+            .iter()
+            .position(|focus| *focus == self.focus)
+            .unwrap_or(0);
+        let next = (index as isize + step).rem_euclid(Focus::ALL.len() as isize);
+        self.focus = Focus::ALL[next as usize];
+        self.status = format!("Focus: {}", self.focus.label());
+    }
+
+    fn move_focused_by_count(&mut self, delta: isize) {
+        match self.focus {
+            Focus::List => self.move_selection_by_count(delta),
+            Focus::Details => {
+                let count = self.consume_count_or(1);
+                let max = self.describe_selection().lines().count().saturating_sub(1);
+                let next = self
+                    .details_scroll
+                    .saturating_add_signed(delta.saturating_mul(count as isize));
+                self.details_scroll = next.min(max);
+            }
+            Focus::Preview => self.scroll_preview_by_count(delta),
+        }
+    }
+
+    fn pane_block<'a>(&self, title: impl Into<Line<'a>>, pane: Focus) -> Block<'a> {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        if self.focus == pane {
+            block.border_style(Style::default().fg(self.theme.accent))
+        } else {
+            block
+        }
+    }
+
+    fn scroll_preview_by_count(&mut self, delta: isize) {
+        let count = self.consume_count_or(1);
+        let max = self.preview.body.lines().count().saturating_sub(1);
+        let next = self
+            .preview_scroll
+            .saturating_add_signed(delta.saturating_mul(count as isize));
+        self.preview_scroll = next.min(max);
+    }
+
+    fn command_preview_line(&mut self, args: &str) -> Result<()> {
+        let line: usize = args
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Usage: :pline <line number>"))?;
+        let total = self.preview.body.lines().count();
+        if total == 0 {
+            return Err(anyhow!("Preview is empty"));
+        }
+        self.preview_scroll = line.saturating_sub(1).min(total - 1);
+        self.status = format!("Preview at line {} of {total}", self.preview_scroll + 1);
+        Ok(())
+    }
+
+    fn is_pinned(&self, entry: &FileEntry) -> bool {
+        if !entry.is_dir {
+            return false;
+        }
+        if self.pin_names.contains(&entry.name) {
+            return true;
+        }
+        self.pin_paths.contains(&entry.path) || self.state.pins.contains(&entry.path)
+    }
+
+    fn is_visible(&self, entry: &FileEntry) -> bool {
+        if !self.show_hidden && entry.name.starts_with('.') {
+            return false;
+        }
+        if self.gitignore == GitignoreMode::Hide && entry.ignored {
+            return false;
+        }
+        if let Some((_, window)) = &self.modified_within {
+            let recent = entry
+                .modified
+                .and_then(|time| time.elapsed().ok())
+                .is_some_and(|age| age <= *window);
+            if !recent {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn view_of(&self, all_entries: &[FileEntry]) -> Vec<FileEntry> {
+        let mut entries: Vec<FileEntry> = all_entries
+            .iter()
+            .filter(|entry| self.is_visible(entry))
+            .cloned()
+            .collect();
+        sort_entries_by(&mut entries, self.sort, self.dirs_first);
+        entries.sort_by_key(|entry| !self.is_pinned(entry));
+        if self.grouped {
+            entries.sort_by_key(|entry| self.entry_group(entry));
+        }
+        entries
+    }
+
+    fn matches_filter(&self, entry: &FileEntry) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&entry.name))
+    }
+
+    fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.rebuild_view();
+        self.update_preview();
+        self.status = if self.show_hidden {
+            "Showing hidden files".into()
+        } else {
+            "Hiding hidden files".into()
+        };
+    }
+
+    fn set_gitignore_mode(&mut self, mode: GitignoreMode) -> Result<()> {
+        let rescan = (mode == GitignoreMode::Off) != (self.gitignore == GitignoreMode::Off);
+        self.gitignore = mode;
+        self.scan_options.gitignore = mode != GitignoreMode::Off;
+        let message = format!("Gitignored entries: {}", mode.label());
+        if !rescan {
+            self.rebuild_view();
+            self.update_preview();
+            self.status = message;
+            return Ok(());
+        }
+        self.refresh_other_pane()?;
+        self.refresh_parent_column()?;
+        self.refresh_with_message(false, message)
+    }
+
+    fn command_gitignore(&mut self, args: &str) -> Result<()> {
+        self.clear_pending_count();
+        let mode = match args.trim() {
+            "" => self.gitignore.next(),
+            other => GitignoreMode::parse(other)
+                .ok_or_else(|| anyhow!("Unknown mode '{other}' (use off, dim or hide)"))?,
+        };
+        self.set_gitignore_mode(mode)
+    }
+
+    fn rebuild_view(&mut self) {
+        let selected_name = self.selected_entry().map(|entry| entry.name.clone());
+        let mut entries = self.view_of(&self.all_entries);
+        entries.retain(|entry| self.matches_filter(entry));
+        self.entries = entries;
+        if let Some(index) =
+            selected_name.and_then(|name| self.entries.iter().position(|entry| entry.name == name))
+        {
+            self.selected = index;
+        }
+        self.clamp_selection();
+    }
+
+    fn command_since(&mut self, args: &str) -> Result<()> {
+        let args = args.trim();
+        if args.is_empty() {
+            self.modified_within = None;
+            self.since_from_override = false;
+            self.rebuild_view();
+            self.update_preview();
+            self.status = "Modification time filter cleared".into();
+            return Ok(());
+        }
+        let window = parse_duration(args)?;
+        self.modified_within = Some((args.to_string(), window));
+        self.since_from_override = false;
+        self.rebuild_view();
+        self.update_preview();
+        self.status = format!(
+            "Showing {} entries modified within {args}",
+            self.entries.len()
+        );
+        Ok(())
+    }
+
+    fn absolute_time_format(&self) -> Option<&str> {
+        self.absolute_times.then_some(self.time_format.as_str())
+    }
+
+    fn column_width(&self, column: ListColumn) -> Option<usize> {
+        match (column, self.absolute_time_format()) {
+            (ListColumn::Modified, Some(format)) => {
+                Some(format_time(SystemTime::now(), format).width())
+            }
+            _ => column.width(),
+        }
+    }
+
+    fn toggle_time_display(&mut self) {
+        self.absolute_times = !self.absolute_times;
+        self.status = match self.absolute_time_format() {
+            Some(format) => format!("Showing absolute times ({format})"),
+            None => "Showing relative times".into(),
+        };
+    }
+
+    fn dir_override(&self, dir: &Path) -> Option<&DirOverride> {
+        self.state
+            .dir_overrides
+            .get(dir.to_string_lossy().as_ref())
+            .or_else(|| self.dir_overrides.get(dir))
+    }
+
+    fn apply_dir_override(&mut self, dir: &Path) {
+        let settings = self.dir_override(dir).cloned().unwrap_or_default();
+        let fallback = match self.sort_memory {
+            SortMemory::Global => SortSpec {
+                key: self
+                    .state
+                    .sort
+                    .as_deref()
+                    .and_then(SortKey::parse)
+                    .unwrap_or_default(),
+                reverse: self.state.reverse,
+            },
+            _ => SortSpec::default(),
+        };
+        self.sort = match settings.sort.as_deref().and_then(SortKey::parse) {
+            Some(key) => SortSpec {
+                key,
+                reverse: settings.reverse.unwrap_or(false),
+            },
+            None => fallback,
+        };
+        match settings
+            .since
+            .and_then(|label| parse_duration(&label).ok().map(|window| (label, window)))
+        {
+            Some(filter) => {
+                self.modified_within = Some(filter);
+                self.since_from_override = true;
+            }
+            None if self.since_from_override => {
+                self.modified_within = None;
+                self.since_from_override = false;
+            }
+            None => {}
+        }
+    }
+
+    fn command_sort(&mut self, args: &str, reverse: bool) -> Result<()> {
+        match args.trim() {
+            "" if reverse => {
+                self.sort.reverse = !self.sort.reverse;
+                self.rebuild_view();
+                self.update_preview();
+                self.status = format!("Sorted by {}", self.sort.label());
+                self.remember_sort();
+                self.save_state()?;
+            }
+            "" => {
+                self.status = format!("Sorted by {}", self.sort.label());
+            }
+            "save" => {
+                let settings = DirOverride {
+                    sort: Some(self.sort.key.label().into()),
+                    reverse: Some(self.sort.reverse),
+                    since: self
+                        .modified_within
+                        .as_ref()
+                        .map(|(label, _)| label.clone()),
+                    remembered: None,
+                };
+                self.state
+                    .dir_overrides
+                    .insert(self.current_dir.to_string_lossy().into_owned(), settings);
+                self.save_state()?;
+                self.status = format!("Saved sort and filter for {}", self.current_dir.display());
+            }
+            "forget" => {
+                let key = self.current_dir.to_string_lossy().into_owned();
+                if self.state.dir_overrides.remove(&key).is_none() {
+                    return Err(anyhow!("No saved settings for this directory"));
+                }
+                self.save_state()?;
+                self.status = format!("Forgot saved settings for {}", self.current_dir.display());
+            }
+            value => {
+                let (value, reverse) = match value.strip_prefix('-') {
+                    Some(rest) => (rest, true),
+                    None => (value, reverse),
+                };
+                let key = SortKey::parse(value).ok_or_else(|| {
+                    anyhow!(
+                        "Unknown sort '{value}' (name, natural, size, modified, extension, save, forget)"
+                    )
+                })?;
+                self.sort = SortSpec { key, reverse };
+                self.rebuild_view();
+                self.update_preview();
+                self.status = format!("Sorted by {}", self.sort.label());
+                self.remember_sort();
+                self.save_state()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cycle_sort(&mut self) {
+        self.clear_pending_count();
+        self.sort.key = self.sort.key.next();
+        self.rebuild_view();
+        self.update_preview();
+        self.status = format!("Sorted by {}", self.sort.label());
+        self.remember_sort();
+    }
+
+    fn toggle_dirs_first(&mut self) {
+        self.clear_pending_count();
+        self.dirs_first = !self.dirs_first;
+        self.rebuild_view();
+        self.update_preview();
+        self.status = if self.dirs_first {
+            "Directories first".into()
+        } else {
+            "Directories mixed with files".into()
+        };
+    }
+
+    /// Records the current sort in memory; `:sort` saves it right away, `s` leaves it for exit.
+    fn remember_sort(&mut self) {
+        match self.sort_memory {
+            SortMemory::Off => return,
+            SortMemory::Directory if self.archive.is_some() => return,
+            SortMemory::Directory => {
+                let overrides = &mut self.state.dir_overrides;
+                let key = self.current_dir.to_string_lossy().into_owned();
+                let saved = overrides
+                    .get(&key)
+                    .is_some_and(|settings| settings.remembered.is_none());
+                let settings = overrides.entry(key).or_default();
+                settings.sort = Some(self.sort.key.label().into());
+                settings.reverse = Some(self.sort.reverse);
+                if !saved {
+                    settings.remembered = Some(unix_now());
+                    forget_oldest_sorts(overrides);
+                }
+            }
+            SortMemory::Global => {
+                self.state.sort = Some(self.sort.key.label().into());
+                self.state.reverse = self.sort.reverse;
+            }
+        }
+        self.state_dirty = true;
+    }
+
+    fn toggle_pin(&mut self) -> Result<()> {
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to pin"))?;
+        self.ensure_outside_archive("Pinning")?;
+        if !entry.is_dir {
+            return Err(anyhow!("Only directories can be pinned"));
+        }
+        let path = entry.path.clone();
+        let pinned = if let Some(pos) = self.state.pins.iter().position(|p| *p == path) {
+            self.state.pins.remove(pos);
+            false
+        } else if self.is_pinned(&entry) {
+            return Err(anyhow!("'{}' is pinned in the config file", entry.name));
+        } else {
+            self.state.pins.push(path);
+            true
+        };
+        self.save_state()?;
+        self.rebuild_view();
+        self.status = if pinned {
+            format!("Pinned {}", entry.name)
+        } else {
+            format!("Unpinned {}", entry.name)
+        };
+        Ok(())
+    }
+
+    fn is_marked(&self, entry: &FileEntry) -> bool {
+        self.marked.contains(&entry.path)
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(path) = self.selected_path() else {
+            self.status = "No selection to mark".into();
+            return;
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        self.status = format!("{} marked", self.marked.len());
+        self.move_selection(1);
+    }
+
+    fn toggle_visual(&mut self) {
+        if self.visual.take().is_some() {
+            self.status = format!("Visual mode off, {} marked", self.marked.len());
+            return;
+        }
+        let Some(anchor) = self.selected_path() else {
+            self.status = "No selection to mark".into();
+            return;
+        };
+        self.visual = Some(VisualMark {
+            anchor,
+            base: self.marked.clone(),
+        });
+        self.extend_visual();
+        self.status = "Visual mode on: move to mark a range, v or Esc to turn it off".into();
+    }
+
+    fn extend_visual(&mut self) {
+        let Some(visual) = &self.visual else {
+            return;
+        };
+        let Some(anchor) = self
+            .entries
+            .iter()
+            .position(|entry| entry.path == visual.anchor)
+        else {
+            self.visual = None;
+            return;
+        };
+        let (start, end) = (anchor.min(self.selected), anchor.max(self.selected));
+        let mut marked = visual.base.clone();
+        marked.extend(
+            self.entries[start..=end.min(self.entries.len() - 1)]
+                .iter()
+                .map(|entry| entry.path.clone()),
+        );
+        self.marked = marked;
+    }
+
+    fn fill_register(&mut self, kind: RegisterKind) -> Result<()> {
+        self.ensure_outside_archive(if kind == RegisterKind::Yank {
+            "yank"
+        } else {
+            "cut"
+        })?;
+        let mut paths = self.sorted_marks();
+        if paths.is_empty() {
+            paths.push(
+                self.selected_path()
+                    .ok_or_else(|| anyhow!("No selection to put in the register"))?,
+            );
+        }
+        self.visual = None;
+        self.status = format!(
+            "{} {} item(s), pp to paste",
+            if kind == RegisterKind::Yank {
+                "Yanked"
+            } else {
+                "Cut"
+            },
+            paths.len()
+        );
+        self.register = Some(Register { kind, paths });
+        Ok(())
+    }
+
+    fn paste_register(&mut self) -> Result<()> {
+        let register = self
+            .register
+            .as_ref()
+            .ok_or_else(|| anyhow!("Register is empty, yank with yy or cut with xx"))?;
+        let mut pairs = Vec::new();
+        let mut conflicts = Vec::new();
+        for src in &register.paths {
+            guard_current_dir(&self.current_dir, src, "paste")?;
+            let Some(name) = src.file_name() else {
+                continue;
+            };
+            let dest = self.current_dir.join(name);
+            if dest.exists() || dest.is_symlink() {
+                conflicts.push(name.to_string_lossy().into_owned());
+            }
+            pairs.push((src.clone(), dest));
+        }
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "{} name(s) already exist here\n{}",
+                conflicts.len(),
+                conflicts.join("\n")
+            ));
+        }
+        let kind = register.kind;
+        let operations: Vec<Operation> = pairs
+            .iter()
+            .cloned()
+            .map(|(src, dest)| match kind {
+                RegisterKind::Yank => Operation::Copy { src, dest },
+                RegisterKind::Cut => Operation::Move { src, dest },
+            })
+            .collect();
+        if kind == RegisterKind::Cut {
+            self.register = None;
+        }
+        self.start_transfer(operations)
+    }
+
+    /// Queues or runs a batch of copies or moves, asking first when
+    /// `confirm_transfers` is set or a move would cross filesystems.
+    fn start_transfer(&mut self, operations: Vec<Operation>) -> Result<()> {
+        if self.queue_mode {
+            return self.enqueue(operations);
+        }
+        let crossing = self.cross_device_moves(&operations);
+        if !self.confirm_transfers && crossing == 0 {
+            return self.run_operations(operations);
+        }
+        let verb = match operations.first() {
+            Some(Operation::Move { .. }) => "Move",
+            _ => "Copy",
+        };
+        let dest = operations
+            .iter()
+            .find_map(|operation| match operation {
+                Operation::Copy { dest, .. } | Operation::Move { dest, .. } => Some(dest),
+                _ => None,
+            })
+            .map(|dest| canonical_destination(dest))
+            .and_then(|dest| dest.parent().map(|dir| dir.display().to_string()))
+            .unwrap_or_default();
+        let mut message = format!("{verb} {} item(s) to {dest}", operations.len());
+        if crossing > 0 {
+            message.push_str(&format!(
+                ", {crossing} across filesystems (slow copy+delete)"
+            ));
+        }
+        message.push_str(", proceed?");
+        self.input_mode = InputMode::Confirm {
+            message,
+            action: ConfirmAction::Transfer { operations },
+        };
+        self.status = format!("Confirm {} with y/n", verb.to_lowercase());
+        Ok(())
+    }
+
+    /// Moves in `operations` that cross filesystems, when those need a prompt.
+    fn cross_device_moves(&self, operations: &[Operation]) -> usize {
+        if !self.confirm_cross_device_move {
+            return 0;
+        }
+        operations
+            .iter()
+            .filter(|operation| {
+                matches!(operation, Operation::Move { src, dest } if is_cross_device(src, dest))
+            })
+            .count()
+    }
+
+    /// Marks that file operations act on: marked dot-entries are left out
+    /// while hidden files are not shown, and so are marks in the current dir
+    /// that the filter or gitignore mode hides.
+    fn sorted_marks(&self) -> Vec<PathBuf> {
+        let shown: HashSet<&Path> = self
+            .entries
+            .iter()
+            .map(|entry| entry.path.as_path())
+            .collect();
+        let mut paths: Vec<PathBuf> = self
+            .marked
+            .iter()
+            .filter(|path| self.show_hidden || !display_name(path).starts_with('.'))
+            .filter(|path| {
+                path.parent() != Some(self.current_dir.as_path()) || shown.contains(path.as_path())
+            })
+            .cloned()
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn scan_rules(&mut self, dir: &Path) -> IgnoreRules {
+        if !self.scan_options.gitignore {
+            return IgnoreRules::default();
+        }
+        self.caches.ignore_rules(dir)
+    }
+
+    fn walk_filter(&self) -> WalkFilter {
+        WalkFilter {
+            show_hidden: self.show_hidden,
+            skip_ignored: self.gitignore == GitignoreMode::Hide,
+        }
+    }
+
+    fn toggle_result_mark(&mut self) {
+        let InputMode::Picker {
+            selected,
+            action: PickerAction::Results { paths, picked },
+            ..
+        } = &mut self.input_mode
+        else {
+            return;
+        };
+        let Some(path) = paths.get(*selected).cloned() else {
+            return;
+        };
+        if !picked.remove(&path) {
+            picked.insert(path);
+        }
+        self.status = format!("{} of the results marked", picked.len());
+        self.move_picker(1);
+    }
+
+    fn toggle_all_result_marks(&mut self) {
+        let InputMode::Picker {
+            action: PickerAction::Results { paths, picked },
+            ..
+        } = &mut self.input_mode
+        else {
+            return;
+        };
+        if paths.iter().all(|path| picked.contains(path)) {
+            picked.clear();
+        } else {
+            picked.extend(paths.iter().cloned());
+        }
+        self.status = format!("{} of the results marked", picked.len());
+    }
+
+    /// Runs `command` on the marked results, or on all of them when none
+    /// are marked. The paths go straight to the operation, so `self.marked`
+    /// is left alone.
+    fn apply_to_results(&mut self, command: &str) {
+        let InputMode::Picker {
+            action: PickerAction::Results { paths, picked },
+            ..
+        } = &self.input_mode
+        else {
+            return;
+        };
+        let mut targets: Vec<PathBuf> = if picked.is_empty() {
+            paths.clone()
+        } else {
+            picked.iter().cloned().collect()
+        };
+        targets.sort();
+        self.cancel_overlay();
+        let result = match command {
+            "edit" => self.edit_results(targets),
+            "delete" => self.confirm_delete(targets, "result(s)"),
+            _ => {
+                self.start_command_with(&format!("{command} "));
+                self.status = format!("Destination directory for {} result(s)", targets.len());
+                self.result_targets = Some(targets);
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            self.set_error(format!("{command} failed: {err:#}"));
+        }
+    }
+
+    fn edit_results(&mut self, paths: Vec<PathBuf>) -> Result<()> {
+        let paths: Vec<PathBuf> = paths.into_iter().filter(|path| !path.is_dir()).collect();
+        if paths.is_empty() {
+            return Err(anyhow!("No files among the results"));
+        }
+        let name = format!("{} file(s)", paths.len());
+        self.status = format!("Launching editor for {name}");
+        self.pending_external = Some(ExternalCommand::Edit { paths, name });
+        Ok(())
+    }
+
+    /// Runs a command typed at the destination prompt opened from the
+    /// results picker. Copy and move act on `paths`; anything else is run as
+    /// a normal command.
+    fn run_on_results(&mut self, paths: Vec<PathBuf>, line: String) {
+        let (command, target) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        if !matches!(command, "copy" | "move") {
+            self.run_command(line);
+            return;
+        }
+        if let Err(err) = self.transfer_results(command, target.trim(), paths) {
+            self.set_error(format!("{command} failed: {err:#}"));
+        }
+    }
+
+    fn transfer_results(&mut self, command: &str, target: &str, paths: Vec<PathBuf>) -> Result<()> {
+        if self.read_only {
+            return Err(anyhow!("read-only mode"));
+        }
+        if target.is_empty() {
+            return Err(anyhow!("Destination path required"));
+        }
+        let pairs = self.destinations(paths, target)?;
+        if command == "move" {
+            for (src, _) in &pairs {
+                guard_current_dir(&self.current_dir, src, "move")?;
+            }
+        }
+        let dirs: HashSet<&Path> = pairs.iter().filter_map(|(src, _)| src.parent()).collect();
+        let elsewhere = pairs
+            .iter()
+            .filter(|(src, _)| src.parent() != Some(self.current_dir.as_path()))
+            .count();
+        let mut message = format!(
+            "{} {} result(s) from {} director{} ({elsewhere} outside the current one) to {}",
+            if command == "copy" { "Copy" } else { "Move" },
+            pairs.len(),
+            dirs.len(),
+            if dirs.len() == 1 { "y" } else { "ies" },
+            pairs
+                .first()
+                .and_then(|(_, dest)| dest.parent())
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default()
+        );
+        let operations: Vec<Operation> = pairs
+            .into_iter()
+            .map(|(src, dest)| match command {
+                "copy" => Operation::Copy { src, dest },
+                _ => Operation::Move { src, dest },
+            })
+            .collect();
+        if self.queue_mode {
+            return self.enqueue(operations);
+        }
+        let crossing = self.cross_device_moves(&operations);
+        if crossing > 0 {
+            message.push_str(&format!(
+                ", {crossing} across filesystems (slow copy+delete)"
+            ));
+        }
+        message.push('?');
+        self.input_mode = InputMode::Confirm {
+            message,
+            action: ConfirmAction::Transfer { operations },
+        };
+        self.status = format!("Confirm {command} with y/n");
+        Ok(())
+    }
+
+    fn command_find(&mut self, query: &str) {
+        self.stop_find();
+        let token = self.next_token;
+        self.next_token += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.fs.request_find(
+            self.current_dir.clone(),
+            query.into(),
+            self.walk_filter(),
+            token,
+            cancel.clone(),
+        );
+        self.status = format!("Finding '{query}'... (:cancel stops)");
+        self.pending_find = Some(PendingFind {
+            token,
+            cancel,
+            query: query.into(),
+            paths: Vec::new(),
+            opened: false,
+        });
+    }
+
+    fn stop_find(&mut self) {
+        if let Some(find) = self.pending_find.take() {
+            find.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn result_item(&self, path: &Path) -> String {
+        path.strip_prefix(&self.current_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    fn handle_found_paths(&mut self, token: u64, paths: Vec<PathBuf>, done: bool) {
+        let Some(mut find) = self.pending_find.take().filter(|find| find.token == token) else {
+            return;
+        };
+        let new_items: Vec<String> = paths.iter().map(|path| self.result_item(path)).collect();
+        find.paths.extend(paths.iter().cloned());
+        let count = find.paths.len();
+        let title = match (done, count >= FIND_MAX_RESULTS) {
+            (false, _) => format!("find '{}' ({count}, searching)", find.query),
+            (true, true) => format!("find '{}' (first {count})", find.query),
+            (true, false) => format!("find '{}' ({count})", find.query),
+        };
+        if find.opened {
+            if let InputMode::Picker {
+                title: picker_title,
+                items,
+                action: PickerAction::Results { paths: results, .. },
+                ..
+            } = &mut self.input_mode
+            {
+                *picker_title = title;
+                items.extend(new_items);
+                results.extend(paths);
+            }
+        } else if matches!(self.input_mode, InputMode::Normal) && count > 0 {
+            let items = find
+                .paths
+                .iter()
+                .map(|path| self.result_item(path))
+                .collect();
+            self.open_picker(
+                title,
+                items,
+                PickerAction::Results {
+                    paths: find.paths.clone(),
+                    picked: HashSet::new(),
+                },
+            );
+            self.status = "Space/a mark, e edit, d delete, c copy, m move, Enter jump".into();
+            find.opened = true;
+        } else if done && count == 0 {
+            self.status = format!("No matches for '{}'", find.query);
+        }
+        if !done {
+            self.pending_find = Some(find);
+        } else if !find.opened && count > 0 {
+            self.status = format!(
+                "{count} match(es) for '{}'; :find again to list them",
+                find.query
+            );
+        }
+    }
+
+    fn enqueue(&mut self, operations: Vec<Operation>) -> Result<()> {
+        let count = operations.len();
+        self.queued.extend(operations);
+        self.marked.clear();
+        self.status = format!(
+            "Queued {count} operation(s), {} pending (:queue to review)",
+            self.queued.len()
+        );
+        Ok(())
+    }
+
+    fn command_queue(&mut self, args: &str) -> Result<()> {
+        match args.trim() {
+            "" => self.review_queue(),
+            "on" => {
+                self.queue_mode = true;
+                self.status = "Queue mode on: copy, move and delete are staged".into();
+            }
+            "off" => {
+                self.queue_mode = false;
+                self.status = format!("Queue mode off, {} pending", self.queued.len());
+            }
+            "run" => self.run_queue()?,
+            "clear" => self.discard_queue(),
+            other => {
+                return Err(anyhow!(
+                    "Unknown queue option '{other}' (use on, off, run or clear)"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn review_queue(&mut self) {
+        if self.queued.is_empty() {
+            self.status = "Queue is empty".into();
+            return;
+        }
+        let items = self.queued.iter().map(Operation::describe).collect();
+        let count = self.queued.len();
+        self.open_picker(format!("Queue ({count})"), items, PickerAction::Queue);
+        self.status = "Enter run all, d drop, x discard all, Esc close".into();
+    }
+
+    fn is_reviewing_queue(&self) -> bool {
+        matches!(
+            self.input_mode,
+            InputMode::Picker {
+                action: PickerAction::Queue,
+                ..
+            }
+        )
+    }
+
+    fn drop_queued(&mut self) {
+        let InputMode::Picker { selected, .. } = self.input_mode else {
+            return;
+        };
+        if selected < self.queued.len() {
+            let removed = self.queued.remove(selected);
+            self.status = format!("Dropped {}", removed.describe());
+        }
+        if self.queued.is_empty() {
+            self.cancel_overlay();
+            return;
+        }
+        let items: Vec<String> = self.queued.iter().map(Operation::describe).collect();
+        if let InputMode::Picker {
+            title,
+            items: shown,
+            selected,
+            ..
+        } = &mut self.input_mode
+        {
+            *title = format!("Queue ({})", items.len());
+            *selected = (*selected).min(items.len() - 1);
+            *shown = items;
+        }
+    }
+
+    fn discard_queue(&mut self) {
+        let count = mem::take(&mut self.queued).len();
+        if self.is_reviewing_queue() {
+            self.cancel_overlay();
+        }
+        self.status = format!("Discarded {count} queued operation(s)");
+    }
+
+    fn run_queue(&mut self) -> Result<()> {
+        if self.queued.is_empty() {
+            return Err(anyhow!("Queue is empty"));
+        }
+        for operation in &self.queued {
+            if let Operation::Move { src: path, .. }
+            | Operation::Delete { path }
+            | Operation::Remove { path } = operation
+            {
+                guard_current_dir(&self.current_dir, path, "change")?;
+            }
+        }
+        let operations = mem::take(&mut self.queued);
+        self.run_operations(operations)
+    }
+
+    /// Starts the next job that was requested while another one was running.
+    fn start_waiting_job(&mut self) {
+        let result = match self.waiting_jobs.pop_front() {
+            None => return,
+            Some(WaitingJob::Operations(operations)) => self.run_operations(operations),
+            Some(WaitingJob::Job { label, job }) => self.start_job(label, job),
+        };
+        if let Err(err) = result {
+            self.set_error(format!("Starting the next job failed: {err:#}"));
+        }
+    }
+
+    fn cancel_job(&mut self) {
+        match (&self.pending_job, &self.job_cancel) {
+            (Some(_), Some(cancel)) => {
+                cancel.store(true, Ordering::Relaxed);
+                let waiting = mem::take(&mut self.waiting_jobs).len();
+                self.status = if waiting > 0 {
+                    format!("Canceling the running job and {waiting} waiting one(s)...")
+                } else {
+                    "Canceling the running job...".into()
+                };
+            }
+            _ if self.pending_grep.is_some() || self.pending_find.is_some() => {
+                self.stop_grep();
+                self.stop_find();
+                self.status = "Search canceled".into();
+            }
+            _ => self.status = "No file job is running".into(),
+        }
+    }
+
+    fn take_job_canceled(&mut self) -> bool {
+        self.job_cancel
+            .take()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    fn run_operations(&mut self, operations: Vec<Operation>) -> Result<()> {
+        let (label, _) = batch_verbs(&operations);
+        let count = operations.len();
+        if self.pending_job.is_some() {
+            self.status = format!("{label} {count} item(s) after the running job");
+            self.waiting_jobs
+                .push_back(WaitingJob::Operations(operations));
+            return Ok(());
+        }
+        let token = self.next_token;
+        self.next_token += 1;
+        self.job_counts_files = operations
+            .iter()
+            .all(|operation| matches!(operation, Operation::Copy { .. }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.fs
+            .request_operations(token, operations, self.trash, cancel.clone())
+            .context("queue file operations")?;
+        self.pending_job = Some(token);
+        self.job_cancel = Some(cancel);
+        self.status = format!("{label} {count} item(s)...");
+        Ok(())
+    }
+
+    fn mark_summary(&self) -> Option<String> {
+        if self.marked.is_empty() {
+            return None;
+        }
+        let marks = self.sorted_marks();
+        let mut bytes = 0;
+        let mut dirs = 0;
+        let mut elsewhere = 0;
+        for path in &marks {
+            let entry = self.all_entries.iter().find(|entry| entry.path == *path);
+            match entry {
+                Some(entry) if entry.is_dir => dirs += 1,
+                Some(entry) => bytes += entry.size.unwrap_or(0),
+                None => elsewhere += 1,
+            }
+        }
+        let scope = if self.global_marks { " globally" } else { "" };
+        let mut summary = format!("{} marked{scope}, {}", marks.len(), format_size(bytes));
+        if dirs > 0 {
+            summary.push_str(&format!(" + {dirs} dir(s)"));
+        }
+        if elsewhere > 0 {
+            summary.push_str(&format!(" + {elsewhere} elsewhere"));
+        }
+        let hidden = self.marked.len() - marks.len();
+        if hidden > 0 {
+            summary.push_str(&format!(" ({hidden} hidden)"));
+        }
+        Some(summary)
+    }
+
+    fn forget_marks(&mut self) {
+        self.visual = None;
+        if self.global_marks {
+            self.marked
+                .retain(|path| path.exists() || path.is_symlink());
+        } else {
+            self.marked.clear();
+        }
+    }
+
+    fn command_marks(&mut self, args: &str) -> Result<()> {
+        match args.trim() {
+            "" => {
+                if self.marked.is_empty() {
+                    self.status = "No marks".into();
+                    return Ok(());
+                }
+                let paths = self.sorted_marks();
+                let items = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                self.open_picker(
+                    format!("Marks ({})", paths.len()),
+                    items,
+                    PickerAction::Results {
+                        paths,
+                        picked: HashSet::new(),
+                    },
+                );
+                self.status = "Space/a mark, e edit, d delete, c copy, m move, Enter jump".into();
+            }
+            "clear" => {
+                let count = self.marked.len();
+                self.marked.clear();
+                self.status = format!("Cleared {count} mark(s)");
+            }
+            "global" => {
+                self.global_marks = !self.global_marks;
+                self.status = if self.global_marks {
+                    "Marks are kept across directories".into()
+                } else {
+                    "Marks are cleared when leaving a directory".into()
+                };
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unknown marks option '{other}' (use clear or global)"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn command_select(&mut self, args: &str, select: bool) -> Result<()> {
+        self.clear_pending_count();
+        let pattern = args.trim();
+        if pattern.is_empty() {
+            return Err(anyhow!("Usage: select <glob>"));
+        }
+        let glob = glob_regex(pattern)?;
+        if pattern.contains('/') && self.archive.is_none() {
+            let token = self.next_token;
+            self.next_token += 1;
+            self.fs
+                .request_glob(self.current_dir.clone(), glob, self.walk_filter(), token);
+            self.status = format!("Matching '{pattern}' below the current dir...");
+            self.pending_glob = Some(PendingGlob {
+                token,
+                root: self.current_dir.clone(),
+                pattern: pattern.into(),
+                select,
+            });
+            return Ok(());
+        }
+        let paths = self
+            .entries
+            .iter()
+            .filter(|entry| glob.is_match(&entry.name))
+            .map(|entry| entry.path.clone())
+            .collect();
+        self.apply_selection(paths, pattern, select);
+        Ok(())
+    }
+
+    fn handle_glob_matches(&mut self, token: u64, paths: Vec<PathBuf>) {
+        let Some(glob) = self.pending_glob.take_if(|glob| glob.token == token) else {
+            return;
+        };
+        if glob.root != self.current_dir {
+            return;
+        }
+        self.apply_selection(paths, &glob.pattern, glob.select);
+    }
+
+    fn apply_selection(&mut self, paths: Vec<PathBuf>, pattern: &str, select: bool) {
+        let mut elsewhere = 0;
+        let changed = paths
+            .into_iter()
+            .filter(|path| {
+                let changed = if select {
+                    self.marked.insert(path.clone())
+                } else {
+                    self.marked.remove(path)
+                };
+                if changed && path.parent() != Some(self.current_dir.as_path()) {
+                    elsewhere += 1;
+                }
+                changed
+            })
+            .count();
+        let mut status = format!(
+            "{} {changed} entr{} matching '{pattern}'",
+            if select { "Marked" } else { "Unmarked" },
+            if changed == 1 { "y" } else { "ies" },
+        );
+        if elsewhere > 0 {
+            status.push_str(&format!(", {elsewhere} in subdirectories"));
+        }
+        status.push_str(&format!(" ({} marked)", self.marked.len()));
+        self.status = status;
+    }
+
+    fn marked_entries(&self) -> Vec<FileEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| self.is_marked(entry))
+            .cloned()
+            .collect()
+    }
+
+    fn reset_search_state(&mut self) {
+        self.last_search = None;
+        self.filter = None;
+        if let InputMode::Search { buffer, .. } = &mut self.input_mode {
+            buffer.clear();
+        }
+    }
+
+    fn run_macro(&mut self, name: &str, body: &str) {
+        if self.macro_depth >= MACRO_MAX_DEPTH {
+            self.set_error(format!(
+                "{name}: macros nested more than {MACRO_MAX_DEPTH} deep, stopping"
+            ));
+            return;
+        }
+        let steps = split_macro(body);
+        if steps.is_empty() {
+            self.status = format!("{name}: macro is empty");
+            return;
+        }
+        self.macro_depth += 1;
+        let mut failed = 0;
+        for (index, step) in steps.iter().enumerate() {
+            self.error_status = None;
+            self.run_command(step.clone());
+            if self.error_status.is_some() {
+                failed += 1;
+                if !self.macro_continue_on_error {
+                    let error = self.status.clone();
+                    self.set_error(format!("{name} stopped at '{step}': {error}"));
+                    break;
+                }
+            }
+            let remaining = steps.len() - index - 1;
+            if remaining > 0 && !matches!(self.input_mode, InputMode::Normal) {
+                self.status = format!(
+                    "{name}: '{step}' is waiting for input, skipped {remaining} remaining step(s)"
+                );
+                break;
+            }
+        }
+        self.macro_depth -= 1;
+        if failed > 0 && self.macro_continue_on_error {
+            self.set_error(format!("{name}: {failed} of {} steps failed", steps.len()));
+        }
+    }
+
+    fn run_command(&mut self, input: String) {
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            self.set_error("Empty command");
+            return;
+        }
+        let (cmd, args) = split_command(trimmed);
+        let command = self.resolve_command_alias(cmd);
+        if let Some(body) = self.macros.get(&command).cloned() {
+            if args.is_empty() {
+                self.run_macro(&command, &body);
+            } else {
+                self.set_error(format!("{command}: macros take no arguments"));
+            }
+            return;
+        }
+        if self.read_only && MUTATING_COMMANDS.contains(&command.as_str()) {
+            self.set_error(format!("{command}: read-only mode"));
+            return;
+        }
+        if self.archive.is_some()
+            && command != "extract"
+            && MUTATING_COMMANDS.contains(&command.as_str())
+        {
+            self.set_error(format!("{command}: archives are read-only, use :extract"));
+            return;
+        }
+        match command.as_str() {
+            "pwd" => self.status = format!("{}", self.current_dir.display()),
+            "refresh" => {
+                if let Err(err) = self.refresh_async(false) {
+                    self.set_error(format!("Refresh failed: {err:#}"));
+                } else {
+                    self.status = "Refresh requested".into();
+                }
+            }
+            "refresh!" => self.hard_refresh(),
+            "config" => {
+                if let Err(err) = self.command_config() {
+                    self.set_error(format!("config failed: {err:#}"));
+                }
+            }
+            "q" | "quit" => {
+                self.status = "Use 'q' in normal mode to quit".into();
+            }
+            "rename" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :rename <new_name>");
+                } else if let Err(err) = self.command_rename(args) {
+                    self.set_error(format!("Rename failed: {err:#}"));
+                }
+            }
+            "rename-regex" => {
+                if let Err(err) = self.command_rename_regex(args) {
+                    self.set_error(format!("rename-regex failed: {err:#}"));
+                }
+            }
+            "delete" => {
+                if let Err(err) = self.request_delete_confirmation() {
+                    self.set_error(format!("Delete failed: {err:#}"));
+                }
+            }
+            "delete!" => {
+                if let Err(err) = self.request_permanent_delete() {
+                    self.set_error(format!("Delete failed: {err:#}"));
+                }
+            }
+            "mkdir" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :mkdir <name>");
+                } else if let Err(err) = self.command_mkdir(args) {
+                    self.set_error(format!("mkdir failed: {err:#}"));
+                }
+            }
+            "touch" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :touch <name>");
+                } else if let Err(err) = self.command_touch(args) {
+                    self.set_error(format!("touch failed: {err:#}"));
+                }
+            }
+            "new" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :new <name>");
+                } else if let Err(err) = self.command_new(args) {
+                    self.set_error(format!("new failed: {err:#}"));
+                }
+            }
+            "paste" => {
+                if let Err(err) = self.command_paste(args) {
+                    self.set_error(format!("paste failed: {err:#}"));
+                }
+            }
+            "copy" | "move" => {
+                let target = match (args.is_empty(), self.other_pane_dir()) {
+                    (true, Some(dir)) => shell_quote(dir),
+                    _ => args.to_string(),
+                };
+                if target.is_empty() {
+                    self.set_error(format!("Usage: :{command} <destination>"));
+                } else if command == "copy" {
+                    if let Err(err) = self.command_copy(&target) {
+                        self.set_error(format!("copy failed: {err:#}"));
+                    }
+                } else if let Err(err) = self.command_move(&target) {
+                    self.set_error(format!("move failed: {err:#}"));
+                }
+            }
+            "dual" => self.command_dual(),
+            "miller" => {
+                if let Err(err) = self.command_miller() {
+                    self.set_error(format!("miller failed: {err:#}"));
+                }
+            }
+            "sh" => {
+                if let Err(err) = self.command_shell(args) {
+                    self.set_error(format!("shell failed: {err:#}"));
+                }
+            }
+            "edit" => {
+                if let Err(err) = self.command_edit() {
+                    self.set_error(format!("edit failed: {err:#}"));
+                }
+            }
+            "filter" => self.command_filter(args),
+            "gitignore" => {
+                if let Err(err) = self.command_gitignore(args) {
+                    self.set_error(format!("gitignore failed: {err:#}"));
+                }
+            }
+            "select" | "unselect" => {
+                if let Err(err) = self.command_select(args, command == "select") {
+                    self.set_error(format!("{command} failed: {err:#}"));
+                }
+            }
+            "since" => {
+                if let Err(err) = self.command_since(args) {
+                    self.set_error(format!("since failed: {err:#}"));
+                }
+            }
+            "diff" => {
+                if let Err(err) = self.command_diff(args) {
+                    self.set_error(format!("diff failed: {err:#}"));
+                }
+            }
+            "tree" => {
+                if let Err(err) = self.command_tree(args) {
+                    self.set_error(format!("tree failed: {err:#}"));
+                }
+            }
+            "chmod" => {
+                if let Err(err) = self.command_chmod(args) {
+                    self.set_error(format!("chmod failed: {err:#}"));
+                }
+            }
+            "flatten" => {
+                if let Err(err) = self.command_flatten() {
+                    self.set_error(format!("flatten failed: {err:#}"));
+                }
+            }
+            "grep" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :grep <pattern>");
+                } else if let Err(err) = self.command_grep(args) {
+                    self.set_error(format!("grep failed: {err:#}"));
+                }
+            }
+            "find" => {
+                if !args.is_empty() {
+                    self.command_find(args);
+                } else {
+                    self.start_recursive_finder();
+                }
+            }
+            "locate" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :locate <name>");
+                } else if let Err(err) = self.command_locate(args) {
+                    self.set_error(format!("locate failed: {err:#}"));
+                }
+            }
+            "times" => self.toggle_time_display(),
+            "group" => self.toggle_grouped_view(),
+            "dirsfirst" => self.toggle_dirs_first(),
+            "relnum" => {
+                self.relative_numbers = !self.relative_numbers;
+                self.status = if self.relative_numbers {
+                    "Relative numbers on".into()
+                } else {
+                    "Relative numbers off".into()
+                };
+            }
+            "split" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :split <size>");
+                } else if let Err(err) = self.command_split(args) {
+                    self.set_error(format!("split failed: {err:#}"));
+                }
+            }
+            "cat" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :cat <output>");
+                } else if let Err(err) = self.command_cat(args) {
+                    self.set_error(format!("cat failed: {err:#}"));
+                }
+            }
+            "df" => self.command_df(),
+            "cancel" => self.cancel_job(),
+            "recent" => {
+                if let Err(err) = self.command_recent(args) {
+                    self.set_error(format!("recent failed: {err:#}"));
+                }
+            }
+            "queue" => {
+                if let Err(err) = self.command_queue(args) {
+                    self.set_error(format!("queue failed: {err:#}"));
+                }
+            }
+            "marks" => {
+                if let Err(err) = self.command_marks(args) {
+                    self.set_error(format!("marks failed: {err:#}"));
+                }
+            }
+            "extract" => {
+                if let Err(err) = self.command_extract() {
+                    self.set_error(format!("extract failed: {err:#}"));
+                }
+            }
+            "note" => {
+                if let Err(err) = self.command_note() {
+                    self.set_error(format!("note failed: {err:#}"));
+                }
+            }
+            "pline" => {
+                if let Err(err) = self.command_preview_line(args) {
+                    self.set_error(format!("pline failed: {err:#}"));
+                }
+            }
+            "restore" => {
+                if let Err(err) = self.command_restore() {
+                    self.set_error(format!("restore failed: {err:#}"));
+                }
+            }
+            "sort" | "sort!" => {
+                if let Err(err) = self.command_sort(args, command == "sort!") {
+                    self.set_error(format!("sort failed: {err:#}"));
+                }
+            }
+            "favorites" => {
+                if let Err(err) = self.command_favorites(args) {
+                    self.set_error(format!("favorites failed: {err:#}"));
+                }
+            }
+            "z" => {
+                if let Err(err) = self.command_z(args) {
+                    self.set_error(format!("z failed: {err:#}"));
+                }
+            }
+            "bookmarks" => {
+                if let Err(err) = self.command_bookmarks(args) {
+                    self.set_error(format!("bookmarks failed: {err:#}"));
+                }
+            }
+            "yankrel" => {
+                if let Err(err) = self.command_yank_relative(args) {
+                    self.set_error(format!("yankrel failed: {err:#}"));
+                }
+            }
+            "yankname" | "yankpath" | "yankstem" => {
+                if let Err(err) = self.command_yank_part(&command[4..]) {
+                    self.set_error(format!("{command} failed: {err:#}"));
+                }
+            }
+            "openwith" => {
+                if let Err(err) = self.command_open_with() {
+                    self.set_error(format!("openwith failed: {err:#}"));
+                }
+            }
+            "hash" => {
+                if let Err(err) = self.command_hash(args) {
+                    self.set_error(format!("hash failed: {err:#}"));
+                }
+            }
+            "tabnew" => {
+                if let Err(err) = self.command_tabnew(args) {
+                    self.set_error(format!("tabnew failed: {err:#}"));
+                }
+            }
+            "tabclose" => {
+                if let Err(err) = self.command_tabclose() {
+                    self.set_error(format!("tabclose failed: {err:#}"));
+                }
+            }
+            "cd" => {
+                if args.is_empty() {
+                    self.set_error("Usage: :cd <path>");
+                } else if let Err(err) = self.command_cd(args) {
+                    self.set_error(format!("cd failed: {err:#}"));
+                }
+            }
+            "help" => {
+                let names: Vec<&str> = COMMANDS.iter().map(|spec| spec.name).collect();
+                self.status = format!("Commands: {}", names.join(", "));
+            }
+            other => {
+                self.set_error(format!("Unknown command: {other}"));
+            }
+        }
+    }
+
+    fn command_rename_regex(&mut self, args: &str) -> Result<()> {
+        let [pattern, replacement] = <[String; 2]>::try_from(parse_args(args)?)
+            .map_err(|_| anyhow!("Usage: :rename-regex <pattern> <replacement>"))?;
+        let regex = Regex::new(&pattern).with_context(|| format!("invalid pattern '{pattern}'"))?;
+        let mut sources = self.sorted_marks();
+        if sources.is_empty() {
+            sources.push(
+                self.selected_path()
+                    .ok_or_else(|| anyhow!("No selection to rename"))?,
+            );
+        }
+        let mut renames = Vec::new();
+        let mut problems = Vec::new();
+        let mut targets = HashSet::new();
+        for src in sources {
+            let Some(name) = src.file_name().and_then(OsStr::to_str).map(str::to_string) else {
+                problems.push(format!("{}: name is not valid UTF-8", display_name(&src)));
+                continue;
+            };
+            let new_name = regex.replace_all(&name, replacement.as_str()).into_owned();
+            if new_name == name {
+                continue;
+            }
+            if let Err(err) = self.validate_new_name(&new_name, &name) {
+                problems.push(format!("{name}: {err}"));
+                continue;
+            }
+            let dest = src.with_file_name(&new_name);
+            if dest.exists() || dest.is_symlink() || !targets.insert(dest.clone()) {
+                problems.push(format!("{name}: {new_name} already exists"));
+                continue;
+            }
+            guard_current_dir(&self.current_dir, &src, "rename")?;
+            renames.push((src, dest));
+        }
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "{} name(s) cannot be renamed\n{}",
+                problems.len(),
+                problems.join("\n")
+            ));
+        }
+        if renames.is_empty() {
+            self.status = format!("'{pattern}' changes no names");
+            return Ok(());
+        }
+        let items = renames
+            .iter()
+            .map(|(src, dest)| format!("{} → {}", display_name(src), display_name(dest)))
+            .collect();
+        let count = renames.len();
+        self.open_picker(
+            format!("Rename {count} item(s)?"),
+            items,
+            PickerAction::Rename { renames },
+        );
+        self.status = "Enter renames all, Esc cancels".into();
+        Ok(())
+    }
+
+    fn apply_renames(&mut self, renames: Vec<(PathBuf, PathBuf)>) -> Result<()> {
+        let mut failures = Vec::new();
+        let mut renamed = 0;
+        for (src, dest) in &renames {
+            if dest.exists() || dest.is_symlink() {
+                failures.push(format!("{}: already exists", display_name(dest)));
+                continue;
+            }
+            match fs::rename(src, dest) {
+                Ok(()) => {
+                    renamed += 1;
+                    if self.marked.remove(src) {
+                        self.marked.insert(dest.clone());
+                    }
+                }
+                Err(err) => failures.push(format!("{}: {err}", display_name(src))),
+            }
+        }
+        if !failures.is_empty() {
+            self.show_error_toast(
+                format!("{} of {} rename(s) failed", failures.len(), renames.len()),
+                failures,
+            );
+        }
+        self.refresh_with_message(false, format!("Renamed {renamed} item(s)"))
+    }
+
+    fn command_rename(&mut self, new_name: &str) -> Result<()> {
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to rename"))?;
+        let new_name = self.validate_new_name(&single_arg(new_name)?, &entry.name)?;
+        let src = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to rename"))?;
+        guard_current_dir(&self.current_dir, &src, "rename")?;
+        let dest = self.current_dir.join(&new_name);
+        if dest.exists() {
+            return Err(anyhow!("A file named '{}' already exists", new_name));
+        }
+        fs::rename(&src, &dest)
+            .with_context(|| format!("renaming {} -> {}", entry.name, new_name))?;
+        self.refresh_with_message(true, format!("Renamed {} -> {}", entry.name, new_name))?;
+        Ok(())
+    }
+
+    fn request_delete_confirmation(&mut self) -> Result<()> {
+        let marks = self.sorted_marks();
+        if !marks.is_empty() {
+            return self.confirm_delete(marks, "marked item(s)");
+        }
+        self.request_delete_selected()
+    }
+
+    fn confirm_delete(&mut self, paths: Vec<PathBuf>, noun: &str) -> Result<()> {
+        for path in &paths {
+            guard_current_dir(&self.current_dir, path, "delete")?;
+        }
+        if self.queue_mode {
+            return self.enqueue(
+                paths
+                    .into_iter()
+                    .map(|path| Operation::Delete { path })
+                    .collect(),
+            );
+        }
+        let dirs: HashSet<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+        let elsewhere = paths
+            .iter()
+            .filter(|path| path.parent() != Some(self.current_dir.as_path()))
+            .count();
+        self.input_mode = InputMode::Confirm {
+            message: format!(
+                "Delete {} {noun} across {} director{} ({elsewhere} outside the current one)?",
+                paths.len(),
+                dirs.len(),
+                if dirs.len() == 1 { "y" } else { "ies" }
+            ),
+            action: ConfirmAction::DeleteMarked { paths },
+        };
+        self.status = "Confirm delete with y/n".into();
+        Ok(())
+    }
+
+    fn request_delete_selected(&mut self) -> Result<()> {
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to delete"))?;
+        let path = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to delete"))?;
+        guard_current_dir(&self.current_dir, &path, "delete")?;
+        if self.queue_mode {
+            return self.enqueue(vec![Operation::Delete { path }]);
+        }
+        let message = if entry.is_dir {
+            let summary = summarize_tree(&path, DELETE_SCAN_LIMIT);
+            let (items, bytes) = if summary.truncated {
+                (
+                    format!("{}+ items", DELETE_SCAN_LIMIT),
+                    format!("{}+", format_size(summary.bytes)),
+                )
+            } else {
+                (
+                    format!("{} items", summary.items),
+                    format_size(summary.bytes),
+                )
+            };
+            format!("Delete directory '{}' ({items}, {bytes})?", entry.name)
+        } else {
+            let size = entry.size.map(format_size).unwrap_or_else(|| "—".into());
+            format!("Delete '{}' ({})?", entry.name, size)
+        };
+        self.input_mode = InputMode::Confirm {
+            message,
+            action: ConfirmAction::Delete { path },
+        };
+        self.status = "Confirm delete with y/n".into();
+        Ok(())
+    }
+
+    fn request_delete_range(&mut self, count: usize) -> Result<()> {
+        if count <= 1 {
+            return self.request_delete_selected();
+        }
+        let end = (self.selected + count).min(self.entries.len());
+        let range = self
+            .entries
+            .get(self.selected..end)
+            .filter(|range| !range.is_empty())
+            .ok_or_else(|| anyhow!("No selection to delete"))?;
+        let paths: Vec<PathBuf> = range.iter().map(|entry| entry.path.clone()).collect();
+        for path in &paths {
+            guard_current_dir(&self.current_dir, path, "delete")?;
+        }
+        if self.queue_mode {
+            return self.enqueue(
+                paths
+                    .into_iter()
+                    .map(|path| Operation::Delete { path })
+                    .collect(),
+            );
+        }
+        let mut names: Vec<&str> = range
+            .iter()
+            .take(3)
+            .map(|entry| entry.name.as_str())
+            .collect();
+        if range.len() > 3 {
+            names.push("...");
+        }
+        let select = self
+            .entries
+            .get(end)
+            .or_else(|| {
+                self.selected
+                    .checked_sub(1)
+                    .and_then(|idx| self.entries.get(idx))
+            })
+            .map(|entry| entry.name.clone());
+        self.input_mode = InputMode::Confirm {
+            message: format!("Delete {} entries ({})?", paths.len(), names.join(", ")),
+            action: ConfirmAction::DeleteRange { paths, select },
+        };
+        self.status = "Confirm delete with y/n".into();
+        Ok(())
+    }
+
+    fn delete_marked(&mut self, paths: Vec<PathBuf>) -> Result<()> {
+        for path in &paths {
+            guard_current_dir(&self.current_dir, path, "delete")?;
+        }
+        self.run_operations(
+            paths
+                .into_iter()
+                .map(|path| Operation::Delete { path })
+                .collect(),
+        )
+    }
+
+    fn request_permanent_delete(&mut self) -> Result<()> {
+        let mut paths = self.sorted_marks();
+        if paths.is_empty() {
+            paths.push(
+                self.selected_path()
+                    .ok_or_else(|| anyhow!("No selection to delete"))?,
+            );
+        }
+        for path in &paths {
+            guard_current_dir(&self.current_dir, path, "delete")?;
+        }
+        if self.queue_mode {
+            return self.enqueue(
+                paths
+                    .into_iter()
+                    .map(|path| Operation::Remove { path })
+                    .collect(),
+            );
+        }
+        let target = match paths.as_slice() {
+            [path] => format!("'{}'", display_name(path)),
+            _ => format!("{} item(s)", paths.len()),
+        };
+        self.input_mode = InputMode::Confirm {
+            message: format!("Permanently delete {target}? This cannot be undone"),
+            action: ConfirmAction::Remove { paths },
+        };
+        self.status = "Confirm permanent delete with y/n".into();
+        Ok(())
+    }
+
+    fn remove_permanently(&mut self, paths: Vec<PathBuf>) -> Result<()> {
+        for path in &paths {
+            guard_current_dir(&self.current_dir, path, "delete")?;
+        }
+        self.run_operations(
+            paths
+                .into_iter()
+                .map(|path| Operation::Remove { path })
+                .collect(),
+        )
+    }
+
+    fn command_restore(&mut self) -> Result<()> {
+        let mut items = trash_items(&trash_dir()?)?;
+        if self.trash == TrashBackend::System {
+            let log = SystemTrashLog::load();
+            items.extend(
+                system_trash_items()
+                    .into_iter()
+                    .filter(|item| log.contains(&item.info)),
+            );
+        }
+        let Some(latest) = items.into_iter().max_by_key(|item| item.info.deleted_at) else {
+            self.status = "Trash is empty".into();
+            return Ok(());
+        };
+        let original = latest.info.original;
+        if original.exists() || original.is_symlink() {
+            let items = vec![
+                format!("Overwrite {}", original.display()),
+                format!("Restore as {}", display_name(&suffixed_path(&original))),
+            ];
+            self.open_picker(
+                format!("{} already exists", display_name(&original)),
+                items,
+                PickerAction::Restore {
+                    item: latest.item,
+                    info_path: latest.info_path,
+                    original,
+                },
+            );
+            self.status = "Choose how to restore, Esc cancels".into();
+            return Ok(());
+        }
+        self.finish_restore(&latest.item, &latest.info_path, &original, &original)
+    }
+
+    fn finish_restore(
+        &mut self,
+        item: &Path,
+        info_path: &Path,
+        original: &Path,
+        dest: &Path,
+    ) -> Result<()> {
+        let mut message = format!("Restored {}", dest.display());
+        if dest == original && (dest.exists() || dest.is_symlink()) {
+            // Take the item out of the trash first so trashing the occupant
+            // cannot evict it, then let the occupant take its place there.
+            let staged =
+                suffixed_path(&dest.with_file_name(format!(".{}.restore", display_name(dest))));
+            let info =
+                fs::read(info_path).with_context(|| format!("reading {}", info_path.display()))?;
+            move_path(item, &staged)?;
+            fs::remove_file(info_path)
+                .with_context(|| format!("removing {}", info_path.display()))?;
+            let trashed = match info_path.parent() {
+                Some(dir) if item.parent() == Some(dir) => trash_into(dest, dir),
+                _ => soft_delete(dest, TrashBackend::System),
+            };
+            match trashed {
+                Ok(note) => {
+                    message = format!(
+                        "Restored {}, moved the old one to the trash",
+                        dest.display()
+                    );
+                    if let Some(note) = note {
+                        message.push_str(&format!(" ({note})"));
+                    }
+                }
+                Err(err) => {
+                    let _ = move_path(&staged, item).and_then(|_| {
+                        fs::write(info_path, info).context("rewriting the trash record")
+                    });
+                    return Err(err.context(format!("moving {} to the trash", dest.display())));
+                }
+            }
+            fs::rename(&staged, dest).with_context(|| format!("renaming {}", staged.display()))?;
+        } else {
+            ensure_parent_dir(dest)?;
+            move_path(item, dest)?;
+            fs::remove_file(info_path)
+                .with_context(|| format!("removing {}", info_path.display()))?;
+        }
+        match (dest.parent(), dest.file_name()) {
+            (Some(dir), Some(name)) if dir != self.current_dir && dir.is_dir() => self.navigate_to(
+                dir.to_path_buf(),
+                Some(name.to_string_lossy().into_owned()),
+                message,
+            ),
+            (_, name) => {
+                self.pending_select = name.map(|name| name.to_string_lossy().into_owned());
+                self.refresh_with_message(false, message)
+            }
+        }
+    }
+
+    fn command_mkdir(&mut self, name: &str) -> Result<()> {
+        let name = self.validate_new_name(&single_arg(name)?, "")?;
+        let path = self.current_dir.join(&name);
+        fs::create_dir(&path).with_context(|| format!("creating directory {}", name))?;
+        self.refresh_with_message(false, format!("Created directory {}", name))?;
+        Ok(())
+    }
+
+    fn command_touch(&mut self, name: &str) -> Result<()> {
+        let name = self.validate_new_name(&single_arg(name)?, "")?;
+        let path = self.current_dir.join(&name);
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("creating file {}", name))?;
+        self.refresh_with_message(false, format!("Touched {}", name))?;
+        Ok(())
+    }
+
+    fn command_new(&mut self, name: &str) -> Result<()> {
+        let name = self.validate_new_name(&single_arg(name)?, "")?;
+        let path = self.current_dir.join(&name);
+        fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("creating file {}", name))?;
+        self.pending_select = Some(name.clone());
+        self.status = format!("Launching editor for {name}");
+        self.pending_external = Some(ExternalCommand::Edit {
+            paths: vec![path],
+            name,
+        });
+        Ok(())
+    }
+
+    fn command_edit(&mut self) -> Result<()> {
+        let marks = self.sorted_marks();
+        if !marks.is_empty() {
+            let paths: Vec<PathBuf> = marks.into_iter().filter(|path| !path.is_dir()).collect();
+            if paths.is_empty() {
+                return Err(anyhow!("No marked files to edit"));
+            }
+            let name = format!("{} file(s)", paths.len());
+            self.status = format!("Launching editor for {name}");
+            self.pending_external = Some(ExternalCommand::Edit { paths, name });
+            return Ok(());
+        }
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to edit"))?;
+        if entry.is_dir {
+            return Err(anyhow!("Cannot edit a directory"));
+        }
+        let path = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to edit"))?;
+        self.pending_external = Some(ExternalCommand::Edit {
+            paths: vec![path],
+            name: entry.name.clone(),
+        });
+        self.status = format!("Launching editor for {}", entry.name);
+        Ok(())
+    }
+
+    fn command_open_with(&mut self) -> Result<()> {
+        self.ensure_outside_archive("Opening with an application")?;
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to open"))?;
+        if entry.is_dir {
+            return Err(anyhow!("Cannot open a directory with an application"));
+        }
+        let path = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to open"))?;
+        let extension = Path::new(&entry.name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let apps: Vec<OpenWithApp> = self
+            .open_with
+            .iter()
+            .filter(|app| app.matches(&extension))
+            .cloned()
+            .collect();
+        if apps.is_empty() {
+            spawn_detached(&system_open_command(), &path)?;
+            self.status = format!("Opened {} with the system default", entry.name);
+            return Ok(());
+        }
+        let items = apps.iter().map(OpenWithApp::label).collect();
+        self.open_picker(
+            format!("Open {} with", entry.name),
+            items,
+            PickerAction::OpenWith { path, apps },
+        );
+        self.status = "Choose an application, Enter to launch, Esc to cancel".into();
+        Ok(())
+    }
+
+    fn launch_open_with(&mut self, app: &OpenWithApp, path: PathBuf) -> Result<()> {
+        let command: Vec<String> = app.command.split_whitespace().map(str::to_string).collect();
+        if command.is_empty() {
+            return Err(anyhow!("Empty command for {}", app.label()));
+        }
+        if app.terminal {
+            self.status = format!("Launching {}", command.join(" "));
+            self.pending_external = Some(ExternalCommand::OpenWith { command, path });
+        } else {
+            spawn_detached(&command, &path)?;
+            self.status = format!("Opened {} with {}", display_name(&path), command[0]);
+        }
+        Ok(())
+    }
+
+    fn command_yank_relative(&mut self, args: &str) -> Result<()> {
+        let (text, note) = self.relative_path_text(args)?;
+        self.write_clipboard(&text)?;
+        self.status = format!("Copied {text} ({note})");
+        Ok(())
+    }
+
+    fn relative_path_text(&self, args: &str) -> Result<(String, String)> {
+        let path = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to yank"))?;
+        let (base, base_label) = match args.trim() {
+            "" => match find_git_root(&self.current_dir) {
+                Some(root) => (root, "git root"),
+                None => (self.current_dir.clone(), "current directory"),
+            },
+            value => (
+                paths::normalize(&self.current_dir.join(single_arg(value)?)),
+                "given base",
+            ),
+        };
+        Ok(match path.strip_prefix(&base) {
+            Ok(relative) => (
+                relative.display().to_string(),
+                format!("relative to {base_label} {}", base.display()),
+            ),
+            Err(_) => (
+                path.display().to_string(),
+                format!("absolute, not under {}", base.display()),
+            ),
+        })
+    }
+
+    fn command_yank_part(&mut self, part: &str) -> Result<()> {
+        let text = self.selection_part_text(part)?;
+        self.write_clipboard(&text)?;
+        self.status = format!("Copied {part}: {text}");
+        Ok(())
+    }
+
+    fn selection_part_text(&self, part: &str) -> Result<String> {
+        let entry = self
+            .selected_entry()
+            .ok_or_else(|| anyhow!("No selection to yank"))?;
+        Ok(match part {
+            "name" => entry.name.clone(),
+            "stem" => Path::new(&entry.name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| entry.name.clone()),
+            _ => self
+                .current_dir
+                .join(entry.file_name())
+                .display()
+                .to_string(),
+        })
+    }
+
+    fn command_shell(&mut self, args: &str) -> Result<()> {
+        let dir = self.current_dir.clone();
+        let template = args.trim();
+        if !template.is_empty() {
+            let command = if template.contains("{}") {
+                let path = self
+                    .selected_path()
+                    .ok_or_else(|| anyhow!("No selection to substitute for {{}}"))?;
+                template.replace("{}", &shell_quote(&path))
+            } else {
+                template.to_string()
+            };
+            self.status = format!("Running: {command}");
+            self.pending_external = Some(ExternalCommand::Run { dir, command });
+            return Ok(());
+        }
+        self.pending_external = Some(ExternalCommand::Shell { dir: dir.clone() });
+        self.status = format!("Launching shell in {}", dir.display());
+        Ok(())
+    }
+
+    fn command_diff(&mut self, args: &str) -> Result<()> {
+        let (old, new) = if args.trim().is_empty() {
+            let marked = self.marked_entries();
+            if marked.len() != 2 || marked.iter().any(|entry| entry.is_dir) {
+                return Err(anyhow!(
+                    "Mark exactly two files or use :diff <other> against the selection"
+                ));
+            }
+            (marked[0].path.clone(), marked[1].path.clone())
+        } else {
+            let entry = self
+                .selected_entry()
+                .cloned()
+                .ok_or_else(|| anyhow!("No selection to diff"))?;
+            if entry.is_dir {
+                return Err(anyhow!("Cannot diff a directory"));
+            }
+            let other = paths::expand(&single_arg(args)?, &self.current_dir);
+            (entry.path.clone(), other)
+        };
+        let lines = build_diff(&old, &new)?;
+        let title = format!("Diff {} -> {}", display_name(&old), display_name(&new));
+        self.status = title.clone();
+        self.open_viewer(title, lines);
+        Ok(())
+    }
+
+    fn command_tree(&mut self, args: &str) -> Result<()> {
+        let depth = match args.trim() {
+            "" => None,
+            value => Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Usage: :tree [depth]"))?,
+            ),
+        };
+        let token = self.next_token;
+        self.next_token += 1;
+        let root = self.current_dir.clone();
+        self.fs
+            .request_tree(root.clone(), depth, self.walk_filter(), token)
+            .context("queue tree walk")?;
+        self.status = format!("Building tree of {} ...", root.display());
+        self.pending_tree = Some(PendingTree { token, root });
+        Ok(())
+    }
+
+    fn command_grep(&mut self, args: &str) -> Result<()> {
+        let pattern = single_arg(args)?;
+        let regex = Regex::new(&pattern).with_context(|| format!("invalid pattern '{pattern}'"))?;
+        self.stop_grep();
+        let token = self.next_token;
+        self.next_token += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.fs
+            .request_grep(
+                self.current_dir.clone(),
+                regex,
+                self.walk_filter(),
+                token,
+                cancel.clone(),
+            )
+            .context("queue content search")?;
+        self.status = format!("Searching for '{pattern}'... (:cancel stops)");
+        self.pending_grep = Some(PendingGrep {
+            token,
+            cancel,
+            pattern,
+            matches: Vec::new(),
+            opened: false,
+        });
+        Ok(())
+    }
+
+    fn stop_grep(&mut self) {
+        if let Some(grep) = self.pending_grep.take() {
+            grep.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn grep_item(&self, found: &GrepMatch) -> String {
+        format!(
+            "{}:{}: {}",
+            found
+                .path
+                .strip_prefix(&self.current_dir)
+                .unwrap_or(&found.path)
+                .display(),
+            found.line,
+            found.text
+        )
+    }
+
+    fn handle_grep_matches(&mut self, token: u64, matches: Vec<GrepMatch>, done: bool) {
+        let Some(mut grep) = self.pending_grep.take().filter(|grep| grep.token == token) else {
+            return;
+        };
+        let new_items: Vec<String> = matches.iter().map(|found| self.grep_item(found)).collect();
+        let new_pairs: Vec<(PathBuf, usize)> = matches
+            .iter()
+            .map(|found| (found.path.clone(), found.line))
+            .collect();
+        grep.matches.extend(matches);
+        let count = grep.matches.len();
+        let title = match (done, count >= GREP_MAX_MATCHES) {
+            (false, _) => format!("grep '{}' ({count}, searching)", grep.pattern),
+            (true, true) => format!("grep '{}' (first {count})", grep.pattern),
+            (true, false) => format!("grep '{}' ({count})", grep.pattern),
+        };
+        if grep.opened {
+            if let InputMode::Picker {
+                title: picker_title,
+                items,
+                action: PickerAction::Grep { matches },
+                ..
+            } = &mut self.input_mode
+            {
+                *picker_title = title;
+                items.extend(new_items);
+                matches.extend(new_pairs);
+            }
+        } else if matches!(self.input_mode, InputMode::Normal) && count > 0 {
+            let items = grep
+                .matches
+                .iter()
+                .map(|found| self.grep_item(found))
+                .collect();
+            let matches = grep
+                .matches
+                .iter()
+                .map(|found| (found.path.clone(), found.line))
+                .collect();
+            self.open_picker(title, items, PickerAction::Grep { matches });
+            self.status = "Enter opens the match in $EDITOR, Esc closes".into();
+            grep.opened = true;
+        } else if done && count == 0 {
+            self.status = format!("No matches for '{}'", grep.pattern);
+        }
+        if !done {
+            self.pending_grep = Some(grep);
+        } else if !grep.opened && count > 0 {
+            self.status = format!(
+                "{count} match(es) for '{}'; :grep again to list them",
+                grep.pattern
+            );
+        }
+    }
+
+    fn start_recursive_finder(&mut self) {
+        let token = self.next_token;
+        self.next_token += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.fs.request_finder(
+            self.current_dir.clone(),
+            self.walk_filter(),
+            token,
+            cancel.clone(),
+        );
+        let base = self.current_dir.clone();
+        self.start_fuzzy("Find (scanning)", Vec::new(), base, FuzzyAction::Reveal);
+        self.pending_finder = Some(PendingFinder { token, cancel });
+    }
+
+    fn command_recent(&mut self, args: &str) -> Result<()> {
+        let limit = match args.trim() {
+            "" => RECENT_MAX_RESULTS,
+            value => value
+                .parse::<usize>()
+                .ok()
+                .filter(|limit| *limit > 0)
+                .ok_or_else(|| anyhow!("Invalid count '{value}'"))?,
+        };
+        let token = self.next_token;
+        self.next_token += 1;
+        self.fs
+            .request_recent(self.current_dir.clone(), limit, self.walk_filter(), token)
+            .context("queue recent files scan")?;
+        self.status = "Scanning for recent files...".into();
+        self.pending_recent = Some(PendingRecent {
+            token,
+            opened: false,
+        });
+        Ok(())
+    }
+
+    fn command_locate(&mut self, args: &str) -> Result<()> {
+        let name = single_arg(args)?;
+        let token = self.next_token;
+        self.next_token += 1;
+        self.fs
+            .request_locate(
+                self.current_dir.clone(),
+                name.clone(),
+                self.locate_max_results,
+                self.walk_filter(),
+                token,
+            )
+            .context("queue locate")?;
+        self.status = format!("Locating '{name}' ...");
+        self.pending_locate = Some(PendingLocate { token, name });
+        Ok(())
+    }
+
+    fn command_hash(&mut self, args: &str) -> Result<()> {
+        let mut algorithm = HashAlgorithm::Sha256;
+        let mut copy = false;
+        for arg in args.split_whitespace() {
+            if arg == "-c" || arg == "--copy" {
+                copy = true;
+            } else {
+                algorithm = HashAlgorithm::parse(arg).ok_or_else(|| {
+                    anyhow!("Unknown algorithm '{arg}' (use md5, sha1 or sha256)")
+                })?;
+            }
+        }
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to hash"))?;
+        if entry.is_dir {
+            return Err(anyhow!("Cannot hash a directory"));
+        }
+        let path = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to hash"))?;
+        let token = self.next_token;
+        self.next_token += 1;
+        self.fs
+            .request_hash(path, entry.name.clone(), algorithm, token)
+            .context("queue hash")?;
+        self.pending_hash = Some(PendingHash { token, copy });
+        self.status = format!("Hashing {} ({})...", entry.name, algorithm.label());
+        Ok(())
+    }
+
+    fn command_cd(&mut self, target: &str) -> Result<()> {
+        let target = single_arg(target)?;
+        if target.is_empty() {
+            return Err(anyhow!("Usage: :cd <path>"));
+        }
+        let resolved = paths::resolve_existing_dir(&target, &self.current_dir)?;
+        self.navigate_to(resolved, None, "Changed directory")
+    }
+
+    fn navigate_to(
+        &mut self,
+        dir: PathBuf,
+        select: Option<String>,
+        message: impl Into<String>,
+    ) -> Result<()> {
+        let here = (
+            self.current_dir.clone(),
+            self.selected_entry().map(|entry| entry.name.clone()),
+        );
+        let moved = dir != here.0;
+        self.go_to(dir, select, message)?;
+        if moved {
+            self.history.record(here.0, here.1);
+        }
+        Ok(())
+    }
+
+    fn go_to(
+        &mut self,
+        dir: PathBuf,
+        select: Option<String>,
+        message: impl Into<String>,
+    ) -> Result<()> {
+        let previous = mem::replace(&mut self.current_dir, dir);
+        self.archive = None;
+        if let Err(err) = self.refresh_with_message(true, message) {
+            self.current_dir = previous;
+            return Err(err);
+        }
+        self.reset_search_state();
+        self.forget_marks();
+        self.pending_select = select;
+        self.collapse_root = None;
+        Ok(())
+    }
+
+    fn stash_tab(&mut self) -> Tab {
+        Tab {
+            dir: self.current_dir.clone(),
+            all_entries: mem::take(&mut self.all_entries),
+            entries: mem::take(&mut self.entries),
+            selected: self.selected,
+            last_search: self.last_search.take(),
+            filter: self.filter.take(),
+            marked: if self.global_marks {
+                HashSet::new()
+            } else {
+                mem::take(&mut self.marked)
+            },
+            archive: self.archive.take(),
+            history: mem::take(&mut self.history),
+        }
+    }
+
+    fn restore_tab(&mut self, tab: Tab, message: String) -> Result<()> {
+        self.pending_select = tab
+            .entries
+            .get(tab.selected)
+            .map(|entry| entry.name.clone());
+        self.current_dir = tab.dir;
+        self.all_entries = tab.all_entries;
+        self.entries = tab.entries;
+        self.selected = tab.selected;
+        self.last_search = tab.last_search;
+        self.filter = tab.filter;
+        if !self.global_marks {
+            self.marked = tab.marked;
+        }
+        self.archive = tab.archive;
+        self.history = tab.history;
+        self.visual = None;
+        self.collapse_root = None;
+        self.update_preview();
+        if self.archive.is_some() {
+            self.refresh_async(false)?;
+            self.status = message;
+            Ok(())
+        } else {
+            self.refresh_with_message(false, message)
+        }
+    }
+
+    fn tab_label(&self, index: usize) -> String {
+        let dir = if index == self.active_tab {
+            &self.current_dir
+        } else {
+            &self.tabs[index].dir
+        };
+        display_name(dir)
+    }
+
+    fn switch_tab(&mut self, index: usize) -> Result<()> {
+        if index >= self.tabs.len() {
+            return Err(anyhow!("No tab {}; {} open", index + 1, self.tabs.len()));
+        }
+        if index == self.active_tab {
+            self.status = if self.tabs.len() == 1 {
+                "Only one tab open; :tabnew opens another".into()
+            } else {
+                format!("Already on tab {}", index + 1)
+            };
+            return Ok(());
+        }
+        let target = mem::take(&mut self.tabs[index]);
+        self.tabs[self.active_tab] = self.stash_tab();
+        self.active_tab = index;
+        let message = format!(
+            "Tab {}/{}: {}",
+            index + 1,
+            self.tabs.len(),
+            target.dir.display()
+        );
+        self.restore_tab(target, message)
+    }
+
+    fn cycle_tab(&mut self, delta: isize) -> Result<()> {
+        let len = self.tabs.len() as isize;
+        let index = (self.active_tab as isize + delta).rem_euclid(len);
+        self.switch_tab(index as usize)
+    }
+
+    fn duplicate_tab(&self) -> Tab {
+        Tab {
+            dir: self.current_dir.clone(),
+            all_entries: self.all_entries.clone(),
+            entries: self.entries.clone(),
+            selected: self.selected,
+            filter: self.filter.clone(),
+            ..Tab::default()
+        }
+    }
+
+    fn command_dual(&mut self) {
+        self.clear_pending_count();
+        if self.dual.take().is_some() {
+            self.status = "Single pane".into();
+            return;
+        }
+        self.focus = Focus::List;
+        self.dual = Some(DualPane {
+            other: self.duplicate_tab(),
+            right_active: false,
+            token: None,
+        });
+        self.status =
+            "Dual pane: Tab switches sides, :copy and :move default to the other one".into();
+    }
+
+    fn command_miller(&mut self) -> Result<()> {
+        self.clear_pending_count();
+        self.layout.miller = !self.layout.miller;
+        if !self.layout.miller {
+            self.parent_listing = None;
+            self.status = "Standard layout".into();
+            return Ok(());
+        }
+        self.status = "Miller columns: parent, current and preview".into();
+        self.refresh_parent_column()
+    }
+
+    fn refresh_parent_column(&mut self) -> Result<()> {
+        let parent = self
+            .current_dir
+            .parent()
+            .filter(|_| self.layout.miller)
+            .map(Path::to_path_buf);
+        let Some(parent) = parent else {
+            self.parent_listing = None;
+            self.parent_token = None;
+            return Ok(());
+        };
+        if self
+            .parent_listing
+            .as_ref()
+            .is_some_and(|(dir, _)| *dir != parent)
+        {
+            self.parent_listing = None;
+        }
+        let token = self.next_token;
+        self.next_token += 1;
+        let mut options = self.scan_options;
+        options.large_dir_threshold = 0;
+        let ignore = self.scan_rules(&parent);
+        self.fs
+            .request_directory_scan(parent, token, options, ignore)
+            .context("queue directory scan")?;
+        self.parent_token = Some(token);
+        Ok(())
+    }
+
+    fn swap_panes(&mut self) -> Result<()> {
+        self.clear_pending_count();
+        let Some(mut dual) = self.dual.take() else {
+            return Ok(());
+        };
+        let target = mem::replace(&mut dual.other, self.stash_tab());
+        dual.right_active = !dual.right_active;
+        dual.token = None;
+        let side = if dual.right_active { "Right" } else { "Left" };
+        self.dual = Some(dual);
+        self.focus = Focus::List;
+        let message = format!("{side} pane: {}", target.dir.display());
+        self.restore_tab(target, message)
+    }
+
+    fn other_pane_dir(&self) -> Option<&Path> {
+        self.dual
+            .as_ref()
+            .filter(|dual| dual.other.archive.is_none())
+            .map(|dual| dual.other.dir.as_path())
+    }
+
+    fn refresh_other_pane(&mut self) -> Result<()> {
+        let Some(dir) = self.other_pane_dir().map(Path::to_path_buf) else {
+            return Ok(());
+        };
+        let token = self.next_token;
+        self.next_token += 1;
+        let mut options = self.scan_options;
+        options.large_dir_threshold = 0;
+        let ignore = self.scan_rules(&dir);
+        self.fs
+            .request_directory_scan(dir, token, options, ignore)
+            .context("queue directory scan")?;
+        if let Some(dual) = &mut self.dual {
+            dual.token = Some(token);
+        }
+        Ok(())
+    }
+
+    fn set_other_entries(&mut self, all_entries: Vec<FileEntry>) {
+        let entries = self.view_of(&all_entries);
+        let Some(dual) = &mut self.dual else {
+            return;
+        };
+        let other = &mut dual.other;
+        let selected_name = other
+            .entries
+            .get(other.selected)
+            .map(|entry| entry.name.clone());
+        other.selected = selected_name
+            .and_then(|name| entries.iter().position(|entry| entry.name == name))
+            .unwrap_or(other.selected)
+            .min(entries.len().saturating_sub(1));
+        other.all_entries = all_entries;
+        other.entries = entries;
+    }
+
+    fn command_tabnew(&mut self, args: &str) -> Result<()> {
+        let target = single_arg(args)?;
+        let tab = if target.is_empty() {
+            self.duplicate_tab()
+        } else {
+            Tab {
+                dir: paths::resolve_existing_dir(&target, &self.current_dir)?,
+                ..Tab::default()
+            }
+        };
+        self.tabs[self.active_tab] = self.stash_tab();
+        self.active_tab += 1;
+        self.tabs.insert(self.active_tab, Tab::default());
+        let message = format!(
+            "Opened tab {}/{}: {}",
+            self.active_tab + 1,
+            self.tabs.len(),
+            tab.dir.display()
+        );
+        self.restore_tab(tab, message)
+    }
+
+    fn command_tabclose(&mut self) -> Result<()> {
+        if self.tabs.len() == 1 {
+            return Err(anyhow!("Can't close the last tab"));
+        }
+        self.tabs.remove(self.active_tab);
+        let index = self.active_tab.min(self.tabs.len() - 1);
+        let target = mem::take(&mut self.tabs[index]);
+        self.active_tab = index;
+        let message = format!(
+            "Closed tab, now on {}/{}: {}",
+            index + 1,
+            self.tabs.len(),
+            target.dir.display()
+        );
+        self.restore_tab(target, message)
+    }
+
+    fn history_step(&mut self, back: bool) -> Result<()> {
+        let count = self.consume_count_or(1);
+        let mut here = (
+            self.current_dir.clone(),
+            self.selected_entry().map(|entry| entry.name.clone()),
+        );
+        let mut steps = 0;
+        while steps < count {
+            let next = if back {
+                self.history.back.pop()
+            } else {
+                self.history.forward.pop()
+            };
+            let Some(next) = next else {
+                break;
+            };
+            let previous = mem::replace(&mut here, next);
+            if back {
+                self.history.forward.push(previous);
+            } else {
+                self.history.back.push(previous);
+            }
+            steps += 1;
+        }
+        if steps == 0 {
+            self.status = if back {
+                "Already at the oldest directory in history".into()
+            } else {
+                "Already at the newest directory in history".into()
+            };
+            return Ok(());
+        }
+        let (dir, select) = here;
+        let message = format!(
+            "{} {}",
+            if back { "Back to" } else { "Forward to" },
+            dir.display()
+        );
+        self.go_to(dir, select, message)
+    }
+
+    fn reveal_path(&mut self, path: &Path) -> Result<()> {
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+        if !dir.is_dir() {
+            return Err(anyhow!("{} no longer exists", dir.display()));
+        }
+        let name = display_name(path);
+        self.navigate_to(
+            dir.to_path_buf(),
+            Some(name.clone()),
+            format!("Jumped to {name}"),
+        )
+    }
+
+    fn is_favorite(&self, entry: &FileEntry) -> bool {
+        !entry.is_dir
+            && !self.state.favorites.is_empty()
+            && self.state.favorites.contains(&entry.path)
+    }
+
+    fn toggle_favorite(&mut self) -> Result<()> {
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to favorite"))?;
+        self.ensure_outside_archive("Starring")?;
+        if entry.is_dir {
+            return Err(anyhow!(
+                "Only files can be favorites; pin directories with P"
+            ));
+        }
+        let path = entry.path.clone();
+        let added = if let Some(pos) = self.state.favorites.iter().position(|p| *p == path) {
+            self.state.favorites.remove(pos);
+            false
+        } else {
+            self.state.favorites.push(path);
+            true
+        };
+        self.save_state()?;
+        self.status = if added {
+            format!("Added {} to favorites", entry.name)
+        } else {
+            format!("Removed {} from favorites", entry.name)
+        };
+        Ok(())
+    }
+
+    fn set_bookmark(&mut self, name: char) -> Result<()> {
+        self.ensure_outside_archive("Bookmarking")?;
+        let dir = self.current_dir.clone();
+        self.state.bookmarks.insert(name.to_string(), dir.clone());
+        self.save_state()?;
+        self.status = format!("Bookmarked {} as '{name}'", dir.display());
+        Ok(())
+    }
+
+    fn jump_to_bookmark(&mut self, name: char) -> Result<()> {
+        let dir = self
+            .state
+            .bookmarks
+            .get(&name.to_string())
+            .cloned()
+            .ok_or_else(|| anyhow!("No bookmark '{name}'; set one with m{name}"))?;
+        if !dir.is_dir() {
+            return Err(anyhow!(
+                "Bookmark '{name}' points to {}, which no longer exists",
+                dir.display()
+            ));
+        }
+        let message = format!("Jumped to bookmark '{name}'");
+        self.navigate_to(dir, None, message)
+    }
+
+    fn command_bookmarks(&mut self, args: &str) -> Result<()> {
+        let args = parse_args(args)?;
+        match args.as_slice() {
+            [] => {
+                if self.state.bookmarks.is_empty() {
+                    self.status = "No bookmarks yet; press m and a letter to add one".into();
+                    return Ok(());
+                }
+                let (items, dirs) = self
+                    .state
+                    .bookmarks
+                    .iter()
+                    .map(|(name, dir)| {
+                        let item = if dir.is_dir() {
+                            format!("{name}  {}", dir.display())
+                        } else {
+                            format!("{name}  {} (missing)", dir.display())
+                        };
+                        (item, dir.clone())
+                    })
+                    .unzip();
+                self.open_picker("Bookmarks".into(), items, PickerAction::Bookmarks { dirs });
+                self.status = "Enter jumps to the bookmark, Esc closes".into();
+                Ok(())
+            }
+            [action, name] if action == "delete" => {
+                if self.state.bookmarks.remove(name).is_none() {
+                    return Err(anyhow!("No bookmark '{name}'"));
+                }
+                self.save_state()?;
+                self.status = format!("Deleted bookmark '{name}'");
+                Ok(())
+            }
+            _ => Err(anyhow!("Usage: :bookmarks [delete <name>]")),
+        }
+    }
+
+    fn command_favorites(&mut self, args: &str) -> Result<()> {
+        match args.trim() {
+            "" => {
+                if self.state.favorites.is_empty() {
+                    self.status = "No favorites yet; press * on a file to add one".into();
+                    return Ok(());
+                }
+                let paths = self.state.favorites.clone();
+                let items = paths
+                    .iter()
+                    .map(|path| {
+                        if path.exists() {
+                            path.display().to_string()
+                        } else {
+                            format!("{} (missing)", path.display())
+                        }
+                    })
+                    .collect();
+                self.open_picker("Favorites".into(), items, PickerAction::JumpTo { paths });
+                self.status = "Enter jumps to the favorite, Esc closes".into();
+                Ok(())
+            }
+            "prune" => {
+                let before = self.state.favorites.len();
+                self.state.favorites.retain(|path| path.exists());
+                let removed = before - self.state.favorites.len();
+                self.save_state()?;
+                self.status = format!("Pruned {removed} missing favorite(s)");
+                Ok(())
+            }
+            _ => Err(anyhow!("Usage: :favorites [prune]")),
+        }
+    }
+
+    fn execute_confirm_action(&mut self, action: ConfirmAction) -> Result<()> {
+        if let ConfirmAction::LoadLargeDir { path } = action {
+            self.large_dirs_allowed.insert(path);
+            return self.refresh_async(true);
+        }
+        if self.read_only {
+            self.status = "Read-only mode".into();
+            return Ok(());
+        }
+        match action {
+            ConfirmAction::Delete { path } => self.delete_marked(vec![path]),
+            ConfirmAction::Move { src, dest } => self.perform_move(src, dest),
+            ConfirmAction::Copy { src, dest } => {
+                self.run_operations(vec![Operation::Copy { src, dest }])
+            }
+            ConfirmAction::DeleteMarked { paths } => self.delete_marked(paths),
+            ConfirmAction::Transfer { operations } => self.run_operations(operations),
+            ConfirmAction::DeleteRange { paths, select } => {
+                self.delete_marked(paths)?;
+                self.pending_select = select;
+                Ok(())
+            }
+            ConfirmAction::Remove { paths } => self.remove_permanently(paths),
+            ConfirmAction::LoadLargeDir { .. } | ConfirmAction::Quit => Ok(()),
+        }
+    }
+
+    fn decline_large_dir(&mut self, path: PathBuf) {
+        if self.loaded_dir.as_deref() == Some(path.as_path()) {
+            self.status = "Kept the previous listing".into();
+            return;
+        }
+        let previous = self.loaded_dir.clone();
+        let Some(back) = previous.or_else(|| path.parent().map(Path::to_path_buf)) else {
+            self.status = format!("{} not loaded", path.display());
+            return;
+        };
+        let select = (path.parent() == Some(back.as_path())).then(|| display_name(&path));
+        if let Err(err) = self.navigate_to(back, select, format!("Skipped {}", path.display())) {
+            self.set_error(format!("Navigation failed: {err:#}"));
+        }
+    }
+
+    fn show_archive_dir(&mut self) {
+        let Some(view) = &self.archive else {
+            return;
+        };
+        let location = view.location();
+        self.all_entries = view.entries();
+        self.max_entry_size = self
+            .all_entries
+            .iter()
+            .filter_map(|entry| entry.size)
+            .max()
+            .unwrap_or(0);
+        self.rebuild_view();
+        if let Some(name) = self.pending_select.take()
+            && let Some(index) = self.entries.iter().position(|entry| entry.name == name)
+        {
+            self.selected = index;
+        }
+        self.update_preview();
+        self.status = self
+            .last_action_message
+            .take()
+            .unwrap_or_else(|| format!("{} entries in {location} (read-only)", self.entries.len()));
+    }
+
+    fn request_archive_listing(&mut self, archive: PathBuf, kind: ArchiveKind) {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.status = format!("Reading {} ...", display_name(&archive));
+        self.fs.request_archive_listing(archive, kind, token);
+        self.pending_archive = Some(token);
+    }
+
+    fn handle_archive_listed(
+        &mut self,
+        token: u64,
+        archive: PathBuf,
+        kind: ArchiveKind,
+        result: Result<Vec<ArchiveMember>, String>,
+    ) {
+        if self
+            .pending_archive
+            .take_if(|pending| *pending == token)
+            .is_none()
+        {
+            return;
+        }
+        let members = match result {
+            Ok(members) => members,
+            Err(err) => {
+                self.set_error(format!("Cannot open {}: {err}", display_name(&archive)));
+                return;
+            }
+        };
+        self.caches
+            .previews
+            .retain(|path, _| !path.starts_with(&archive));
+        match &mut self.archive {
+            Some(view) if view.archive == archive => view.members = members,
+            _ => {
+                self.archive = Some(ArchiveView {
+                    archive,
+                    kind,
+                    members,
+                    prefix: String::new(),
+                });
+                self.pending_token = None;
+                self.is_loading = false;
+                self.reset_search_state();
+                self.forget_marks();
+                self.selected = 0;
+            }
+        }
+        self.show_archive_dir();
+    }
+
+    fn ensure_outside_archive(&self, action: &str) -> Result<()> {
+        if self.archive.is_some() {
+            return Err(anyhow!("{action} is not available inside an archive"));
+        }
+        Ok(())
+    }
+
+    fn command_extract(&mut self) -> Result<()> {
+        let Some(view) = &self.archive else {
+            return Err(anyhow!("Enter a zip or tar archive first"));
+        };
+        let mut entries = self.marked_entries();
+        if entries.is_empty() {
+            entries.extend(self.selected_entry().cloned());
+        }
+        if entries.is_empty() {
+            return Err(anyhow!("No selection to extract"));
+        }
+        let dest_dir = self.current_dir.clone();
+        let conflicts: Vec<String> = entries
+            .iter()
+            .filter(|entry| dest_dir.join(&entry.name).exists())
+            .map(|entry| entry.name.clone())
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "{} name(s) already exist in {}\n{}",
+                conflicts.len(),
+                dest_dir.display(),
+                conflicts.join("\n")
+            ));
+        }
+        let selection: Vec<(String, bool)> = entries
+            .iter()
+            .map(|entry| (view.member_path(&entry.name), entry.is_dir))
+            .collect();
+        let total = view
+            .members
+            .iter()
+            .filter(|member| {
+                !member.is_dir
+                    && selection.iter().any(|(path, _)| {
+                        member.path == *path
+                            || member
+                                .path
+                                .strip_prefix(path.as_str())
+                                .is_some_and(|rest| rest.starts_with('/'))
+                    })
+            })
+            .count() as u64;
+        let (archive, kind, prefix) = (view.archive.clone(), view.kind, view.prefix.clone());
+        let label = format!("Extracting {} entries", selection.len());
+        self.marked.clear();
+        self.start_job(label, move |progress, cancel| {
+            extract_members(
+                &archive,
+                kind,
+                &prefix,
+                &selection,
+                &dest_dir,
+                &|extracted| progress(extracted, total),
+                cancel,
+            )
+        })
+    }
+
+    fn update_preview(&mut self) {
+        self.pending_preview = None;
+        self.preview_scroll = 0;
+        self.details_scroll = 0;
+        self.preview_query = None;
+        if self.is_loading {
+            self.preview = PreviewPane::loading();
+            return;
+        }
+        if self.entries.is_empty() {
+            self.preview = PreviewPane::empty();
+            return;
+        }
+        if let Some(entry) = self.selected_entry().cloned() {
+            if let Some(view) = &self.archive
+                && entry.is_dir
+            {
+                self.preview = preview_archive_dir(view, &view.member_path(&entry.name));
+                return;
+            }
+            if let Some(preview) = self.caches.preview(&entry) {
+                self.preview = preview;
+                return;
+            }
+            if let Some(view) = &self.archive {
+                let token = self.next_token;
+                self.next_token += 1;
+                let member = view.member_path(&entry.name);
+                let (archive, kind) = (view.archive.clone(), view.kind);
+                self.fs.request_archive_preview(
+                    archive,
+                    kind,
+                    member,
+                    entry,
+                    self.preview_options.clone(),
+                    token,
+                );
+                self.pending_preview = Some(token);
+                self.preview = PreviewPane::loading();
+                return;
+            }
+            if is_sqlite_file(&entry.path) || office_parts(&entry.path).is_some() {
+                let token = self.next_token;
+                self.next_token += 1;
+                self.preview =
+                    match self
+                        .fs
+                        .request_preview(entry, self.preview_options.clone(), token)
+                    {
+                        Ok(()) => {
+                            self.pending_preview = Some(token);
+                            PreviewPane::loading()
+                        }
+                        Err(err) => PreviewPane::error(format!("Preview error: {err:#}")),
+                    };
+                return;
+            }
+            let path = entry.path.clone();
+            match build_preview(&entry, &path, &self.preview_options) {
+                Ok(preview) => {
+                    self.caches.store_preview(&entry, preview.clone());
+                    self.preview = preview;
+                }
+                Err(err) => self.preview = PreviewPane::error(format!("Preview error: {err:#}")),
+            }
+        } else {
+            self.preview = PreviewPane::empty();
+        }
+    }
+
+    fn compute_destination(&self, target: &str, entry_name: &OsStr) -> Result<PathBuf> {
+        let trimmed = single_arg(target)?;
+        if trimmed.is_empty() {
+            return Err(anyhow!("Destination path required"));
+        }
+        let mut dest = paths::expand(&trimmed, &self.current_dir);
+        if paths::ends_with_separator(&trimmed) || dest.is_dir() {
+            dest.push(entry_name);
+        }
+        Ok(dest)
+    }
+
+    fn marked_destinations(&self, target: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        self.destinations(self.sorted_marks(), target)
+    }
+
+    fn destinations(&self, sources: Vec<PathBuf>, target: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let trimmed = single_arg(target)?;
+        if trimmed.is_empty() {
+            return Err(anyhow!("Destination path required"));
+        }
+        let dir = paths::expand(&trimmed, &self.current_dir);
+        if !dir.is_dir() {
+            return Err(anyhow!(
+                "Destination {} must be an existing directory",
+                dir.display()
+            ));
+        }
+        let mut pairs = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut seen = HashSet::new();
+        for src in sources {
+            let Some(name) = src.file_name() else {
+                continue;
+            };
+            let dest = dir.join(name);
+            if dest.exists() || !seen.insert(dest.clone()) {
+                conflicts.push(name.to_string_lossy().into_owned());
+            }
+            pairs.push((src, dest));
+        }
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "{} name(s) already exist in {}\n{}",
+                conflicts.len(),
+                dir.display(),
+                conflicts.join("\n")
+            ));
+        }
+        Ok(pairs)
+    }
+
+    fn validate_new_name(&self, input: &str, current: &str) -> Result<String> {
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            return Err(anyhow!("Name cannot be empty"));
+        }
+        if !current.is_empty() && trimmed == current {
+            return Err(anyhow!("Name is unchanged"));
+        }
+        if trimmed == "." || trimmed == ".." {
+            return Err(anyhow!("Invalid name '{}'", trimmed));
+        }
+        if trimmed.chars().any(|ch| ch == '/' || ch == '\\') {
+            return Err(anyhow!("Name cannot contain path separators"));
+        }
+        Ok(trimmed.to_string())
+    }
+
+    fn command_copy(&mut self, target: &str) -> Result<()> {
+        if !self.sorted_marks().is_empty() {
+            let operations = self
+                .marked_destinations(target)?
+                .into_iter()
+                .map(|(src, dest)| Operation::Copy { src, dest })
+                .collect();
+            return self.start_transfer(operations);
+        }
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to copy"))?;
+        let src = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to copy"))?;
+        let dest = self.compute_destination(target, entry.file_name())?;
+        if dest.exists() {
+            return Err(anyhow!("Destination {} already exists", dest.display()));
+        }
+        if self.queue_mode {
+            return self.enqueue(vec![Operation::Copy { src, dest }]);
+        }
+        if self.confirm_transfers {
+            self.input_mode = InputMode::Confirm {
+                message: format!(
+                    "Copy {} -> {}, proceed?",
+                    entry.name,
+                    canonical_destination(&dest).display()
+                ),
+                action: ConfirmAction::Copy { src, dest },
+            };
+            self.status = "Confirm copy with y/n".into();
+            return Ok(());
+        }
+        self.run_operations(vec![Operation::Copy { src, dest }])
+    }
+
+    fn command_move(&mut self, target: &str) -> Result<()> {
+        if !self.sorted_marks().is_empty() {
+            let pairs = self.marked_destinations(target)?;
+            for (src, _) in &pairs {
+                guard_current_dir(&self.current_dir, src, "move")?;
+            }
+            let operations = pairs
+                .into_iter()
+                .map(|(src, dest)| Operation::Move { src, dest })
+                .collect();
+            return self.start_transfer(operations);
+        }
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to move"))?;
+        let src = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to move"))?;
+        guard_current_dir(&self.current_dir, &src, "move")?;
+        let dest = self.compute_destination(target, entry.file_name())?;
+        if dest.exists() {
+            return Err(anyhow!("Destination {} already exists", dest.display()));
+        }
+        if self.queue_mode {
+            return self.enqueue(vec![Operation::Move { src, dest }]);
+        }
+        let cross_device = self.confirm_cross_device_move && is_cross_device(&src, &dest);
+        if self.confirm_transfers {
+            let mut message = format!(
+                "Move {} -> {}",
+                entry.name,
+                canonical_destination(&dest).display()
+            );
+            if cross_device {
+                message.push_str(" across filesystems (slow copy+delete)");
+            }
+            message.push_str(", proceed?");
+            self.input_mode = InputMode::Confirm {
+                message,
+                action: ConfirmAction::Move { src, dest },
+            };
+            self.status = "Confirm move with y/n".into();
+            return Ok(());
+        }
+        if cross_device {
+            self.input_mode = InputMode::Confirm {
+                message: "Moving across filesystems (slow copy+delete), continue?".into(),
+                action: ConfirmAction::Move { src, dest },
+            };
+            self.status = "Confirm cross-filesystem move with y/n".into();
+            return Ok(());
+        }
+        self.perform_move(src, dest)
+    }
+
+    fn perform_move(&mut self, src: PathBuf, dest: PathBuf) -> Result<()> {
+        guard_current_dir(&self.current_dir, &src, "move")?;
+        self.run_operations(vec![Operation::Move { src, dest }])
+    }
+
+    fn command_chmod(&mut self, args: &str) -> Result<()> {
+        let mut recursive = false;
+        let mut mode = None;
+        for arg in args.split_whitespace() {
+            match arg {
+                "-R" => recursive = true,
+                value => {
+                    let parsed = u32::from_str_radix(value, 8)
+                        .ok()
+                        .filter(|mode| *mode <= 0o7777)
+                        .ok_or_else(|| {
+                            anyhow!("Invalid mode '{value}', expected octal like 644")
+                        })?;
+                    mode = Some(parsed);
+                }
+            }
+        }
+        let mode = mode.ok_or_else(|| anyhow!("Usage: :chmod [-R] <octal>"))?;
+        let mut targets = self.sorted_marks();
+        if targets.is_empty() {
+            targets.push(
+                self.selected_path()
+                    .ok_or_else(|| anyhow!("No selection to chmod"))?,
+            );
+        }
+        let mut changed = 0;
+        let mut failures = Vec::new();
+        for target in &targets {
+            chmod_path(target, mode, recursive, &mut changed, &mut failures);
+        }
+        let mut message = format!("chmod {mode:o}: {changed} changed");
+        if !failures.is_empty() {
+            message.push_str(&format!(", {} failed", failures.len()));
+        }
+        self.refresh_with_message(false, message.clone())?;
+        if !failures.is_empty() {
+            self.show_error_toast(message, failures);
+        }
+        Ok(())
+    }
+
+    fn command_flatten(&mut self) -> Result<()> {
+        let entry = self
+            .selected_entry()
+            .cloned()
+            .ok_or_else(|| anyhow!("No selection to flatten"))?;
+        if !entry.is_dir {
+            return Err(anyhow!("'{}' is not a directory", entry.name));
+        }
+        let dir = self
+            .selected_path()
+            .ok_or_else(|| anyhow!("No selection to flatten"))?;
+        let children: Vec<OsString> = fs::read_dir(&dir)
+            .with_context(|| format!("reading directory {}", entry.name))?
+            .map(|item| {
+                let item = item.with_context(|| format!("reading directory {}", entry.name))?;
+                Ok(item.file_name())
+            })
+            .collect::<Result<_>>()?;
+        let conflicts: Vec<String> = children
+            .iter()
+            .filter(|name| *name != entry.file_name() && self.current_dir.join(name).exists())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(anyhow!(
+                "Cannot flatten {}: {} name(s) already exist here\n{}",
+                entry.name,
+                conflicts.len(),
+                conflicts.join("\n")
+            ));
+        }
+        let parent = self.current_dir.clone();
+        self.start_job(
+            format!("Flattening {}", entry.name),
+            move |progress, cancel| flatten_directory(&dir, &parent, children, progress, cancel),
+        )
+    }
+}
+
+#[derive(Clone)]
+struct FileEntry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+    is_executable: bool,
+    mode: Option<u32>,
+    dir_status: Option<DirStatus>,
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+    ignored: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DirStatus {
+    Empty,
+    Unreadable,
+}
+
+#[derive(Clone, Copy)]
+struct ScanOptions {
+    dir_indicators: bool,
+    retries: u32,
+    large_dir_threshold: usize,
+    gitignore: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            dir_indicators: false,
+            retries: 2,
+            large_dir_threshold: 100_000,
+            gitignore: false,
+        }
+    }
+}
+
+impl FileEntry {
+    fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or_default()
+    }
+
+    fn describe(&self, time_format: Option<&str>) -> String {
+        let kind = if self.is_dir { "Directory" } else { "File" };
+        let size = self
+            .size
+            .map(|s| format!("{s} bytes"))
+            .unwrap_or_else(|| "—".into());
+        let modified = match (self.modified, time_format) {
+            (Some(time), Some(format)) => format_time(time, format),
+            (time, _) => time
+                .and_then(|time| time.elapsed().ok())
+                .map(|elapsed| format!("{:?} ago", elapsed))
+                .unwrap_or_else(|| "unknown".into()),
+        };
+        format!(
+            "{kind}\nName: {}\nSize: {}\nModified: {}",
+            self.name, size, modified
+        )
+    }
+}
+
+type FsResult<T> = std::result::Result<T, String>;
+
+type JobFn = Box<dyn FnOnce(&dyn Fn(u64, u64), &AtomicBool) -> Result<String> + Send>;
+
+/// A file job requested while another was running; started when that one finishes.
+enum WaitingJob {
+    Operations(Vec<Operation>),
+    Job { label: String, job: JobFn },
+}
+
+enum FsEvent {
+    DirectoryLoaded {
+        path: PathBuf,
+        token: u64,
+        result: FsResult<Vec<FileEntry>>,
+        skipped: usize,
+    },
+    LargeDirectory {
+        path: PathBuf,
+        token: u64,
+        threshold: usize,
+    },
+    ScanRetry {
+        token: u64,
+        attempt: u32,
+        retries: u32,
+        error: String,
+    },
+    HashProgress {
+        token: u64,
+        name: String,
+        processed: u64,
+        total: u64,
+    },
+    HashComputed {
+        token: u64,
+        name: String,
+        algorithm: HashAlgorithm,
+        result: FsResult<String>,
+    },
+    TreeBuilt {
+        token: u64,
+        result: FsResult<Vec<String>>,
+    },
+    ZoxideMatches {
+        token: u64,
+        result: FsResult<Vec<PathBuf>>,
+    },
+    ArchiveListed {
+        token: u64,
+        archive: PathBuf,
+        kind: ArchiveKind,
+        result: Result<Vec<ArchiveMember>, String>,
+    },
+    PreviewLoaded {
+        token: u64,
+        entry: FileEntry,
+        result: FsResult<PreviewPane>,
+    },
+    LocateFinished {
+        token: u64,
+        paths: Vec<PathBuf>,
+    },
+    RecentFiles {
+        token: u64,
+        files: Vec<(PathBuf, SystemTime)>,
+        done: bool,
+    },
+    FinderPaths {
+        token: u64,
+        paths: Vec<PathBuf>,
+        done: bool,
+    },
+    FoundPaths {
+        token: u64,
+        paths: Vec<PathBuf>,
+        done: bool,
+    },
+    GlobMatches {
+        token: u64,
+        paths: Vec<PathBuf>,
+    },
+    GrepMatches {
+        token: u64,
+        matches: Vec<GrepMatch>,
+        done: bool,
+    },
+    ConfigChanged,
+    JobProgress {
+        token: u64,
+        label: String,
+        processed: u64,
+        total: u64,
+    },
+    JobFinished {
+        token: u64,
+        result: FsResult<String>,
+    },
+    OperationProgress {
+        token: u64,
+        label: &'static str,
+        done: u64,
+        total: u64,
+    },
+    OperationsFinished {
+        token: u64,
+        verb: &'static str,
+        results: Vec<(String, FsResult<Option<String>>)>,
+    },
+}
+
+#[derive(Clone)]
+struct FsDispatcher {
+    handle: Handle,
+    event_tx: UnboundedSender<FsEvent>,
+}
+
+impl FsDispatcher {
+    fn new(runtime: &Runtime) -> (Self, UnboundedReceiver<FsEvent>) {
+        let (event_tx, event_rx) = unbounded_channel();
+        let dispatcher = Self {
+            handle: runtime.handle().clone(),
+            event_tx,
+        };
+        (dispatcher, event_rx)
+    }
+
+    fn request_directory_scan(
+        &self,
+        path: PathBuf,
+        token: u64,
+        options: ScanOptions,
+        ignore: IgnoreRules,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let threshold = options.large_dir_threshold;
+            if threshold > 0
+                && let Ok(read) = fs::read_dir(&path)
+                && read.take(threshold + 1).count() > threshold
+            {
+                let _ = tx.send(FsEvent::LargeDirectory {
+                    path,
+                    token,
+                    threshold,
+                });
+                return;
+            }
+            let mut attempt = 0;
+            let mut skipped = 0;
+            let result = loop {
+                match read_directory(&path, options) {
+                    Ok((mut entries, unreadable)) => {
+                        if options.gitignore {
+                            mark_ignored(&mut entries, &ignore);
+                        }
+                        skipped = unreadable;
+                        break Ok(entries);
+                    }
+                    Err(err) if attempt < options.retries && is_transient_error(&err) => {
+                        attempt += 1;
+                        let _ = tx.send(FsEvent::ScanRetry {
+                            token,
+                            attempt,
+                            retries: options.retries,
+                            error: format!("{err:#}"),
+                        });
+                        thread::sleep(SCAN_RETRY_BACKOFF * attempt);
+                    }
+                    Err(err) => break Err(format!("{err:#}")),
+                }
+            };
+            let _ = tx.send(FsEvent::DirectoryLoaded {
+                path,
+                token,
+                result,
+                skipped,
+            });
+        });
+        Ok(())
+    }
+
+    fn request_hash(
+        &self,
+        path: PathBuf,
+        name: String,
+        algorithm: HashAlgorithm,
+        token: u64,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let progress = |processed, total| {
+                let _ = tx.send(FsEvent::HashProgress {
+                    token,
+                    name: name.clone(),
+                    processed,
+                    total,
+                });
+            };
+            let result = match algorithm {
+                HashAlgorithm::Md5 => hash_file::<md5::Md5>(&path, progress),
+                HashAlgorithm::Sha1 => hash_file::<sha1::Sha1>(&path, progress),
+                HashAlgorithm::Sha256 => hash_file::<sha2::Sha256>(&path, progress),
+            }
+            .map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::HashComputed {
+                token,
+                name,
+                algorithm,
+                result,
+            });
+        });
+        Ok(())
+    }
+
+    fn request_preview(&self, entry: FileEntry, options: PreviewOptions, token: u64) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let result =
+                build_preview(&entry, &entry.path, &options).map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::PreviewLoaded {
+                token,
+                entry,
+                result,
+            });
+        });
+        Ok(())
+    }
+
+    fn request_archive_preview(
+        &self,
+        archive: PathBuf,
+        kind: ArchiveKind,
+        member: String,
+        entry: FileEntry,
+        options: PreviewOptions,
+        token: u64,
+    ) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let result = preview_archive_member(&archive, kind, &member, &options)
+                .map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::PreviewLoaded {
+                token,
+                entry,
+                result,
+            });
+        });
+    }
+
+    fn request_archive_listing(&self, archive: PathBuf, kind: ArchiveKind, token: u64) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let result = list_archive(&archive, kind).map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::ArchiveListed {
+                token,
+                archive,
+                kind,
+                result,
+            });
+        });
+    }
+
+    fn request_operations(
+        &self,
+        token: u64,
+        operations: Vec<Operation>,
+        trash: TrashBackend,
+        cancel: Arc<AtomicBool>,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let (label, verb) = batch_verbs(&operations);
+            let weights: Vec<u64> = operations.iter().map(Operation::weight).collect();
+            let total = weights.iter().sum();
+            let progress = |done| {
+                let _ = tx.send(FsEvent::OperationProgress {
+                    token,
+                    label,
+                    done,
+                    total,
+                });
+            };
+            progress(0);
+            let mut done = 0;
+            let mut last_sent = Instant::now();
+            let mut results = Vec::new();
+            for (operation, weight) in operations.iter().zip(weights) {
+                if cancel.load(Ordering::Relaxed) {
+                    results.push((operation.describe(), Err("canceled".into())));
+                    continue;
+                }
+                let mut files = 0;
+                let result = operation
+                    .run(
+                        trash,
+                        &mut || {
+                            files += 1;
+                            if last_sent.elapsed() >= OPERATION_PROGRESS_INTERVAL {
+                                last_sent = Instant::now();
+                                progress(done + files.min(weight));
+                            }
+                        },
+                        &cancel,
+                    )
+                    .map_err(|err| format!("{err:#}"));
+                done += weight;
+                progress(done);
+                results.push((operation.describe(), result));
+            }
+            let _ = tx.send(FsEvent::OperationsFinished {
+                token,
+                verb,
+                results,
+            });
+        });
+        Ok(())
+    }
+
+    fn zoxide_add(&self, dir: PathBuf) {
+        self.handle.spawn_blocking(move || {
+            let _ = Command::new("zoxide")
+                .arg("add")
+                .arg("--")
+                .arg(dir)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        });
+    }
+
+    fn request_zoxide_query(&self, fragments: Vec<String>, exclude: PathBuf, token: u64) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let result = zoxide_query(&fragments, &exclude).map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::ZoxideMatches { token, result });
+        });
+    }
+
+    fn request_tree(
+        &self,
+        root: PathBuf,
+        depth: Option<usize>,
+        filter: WalkFilter,
+        token: u64,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let result = build_tree(&root, depth, filter).map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::TreeBuilt { token, result });
+        });
+        Ok(())
+    }
+
+    fn watch_config(&self, path: PathBuf) -> Result<RecommendedWatcher> {
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?
+            .to_path_buf();
+        let tx = self.event_tx.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok_and(|event| event.paths.contains(&path)) {
+                    let _ = tx.send(FsEvent::ConfigChanged);
+                }
+            })
+            .context("create config watcher")?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("watching {}", dir.display()))?;
+        Ok(watcher)
+    }
+
+    fn request_job<F>(
+        &self,
+        token: u64,
+        label: String,
+        cancel: Arc<AtomicBool>,
+        job: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&dyn Fn(u64, u64), &AtomicBool) -> Result<String> + Send + 'static,
+    {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let progress = |processed, total| {
+                let _ = tx.send(FsEvent::JobProgress {
+                    token,
+                    label: label.clone(),
+                    processed,
+                    total,
+                });
+            };
+            let result = job(&progress, &cancel).map_err(|err| format!("{err:#}"));
+            let _ = tx.send(FsEvent::JobFinished { token, result });
+        });
+        Ok(())
+    }
+
+    fn request_locate(
+        &self,
+        root: PathBuf,
+        name: String,
+        limit: usize,
+        filter: WalkFilter,
+        token: u64,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let paths = locate_paths(&root, &name, limit, filter);
+            let _ = tx.send(FsEvent::LocateFinished { token, paths });
+        });
+        Ok(())
+    }
+
+    fn request_finder(
+        &self,
+        root: PathBuf,
+        filter: WalkFilter,
+        token: u64,
+        cancel: Arc<AtomicBool>,
+    ) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let mut batch = Vec::new();
+            let mut last_sent = Instant::now();
+            walk_files(&root, filter, FINDER_MAX_FILES, &cancel, |path| {
+                batch.push(path);
+                if last_sent.elapsed() >= FINDER_STREAM_INTERVAL {
+                    last_sent = Instant::now();
+                    let _ = tx.send(FsEvent::FinderPaths {
+                        token,
+                        paths: mem::take(&mut batch),
+                        done: false,
+                    });
+                }
+                true
+            });
+            let _ = tx.send(FsEvent::FinderPaths {
+                token,
+                paths: batch,
+                done: true,
+            });
+        });
+    }
+
+    fn request_find(
+        &self,
+        root: PathBuf,
+        query: String,
+        filter: WalkFilter,
+        token: u64,
+        cancel: Arc<AtomicBool>,
+    ) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let mut batch = Vec::new();
+            let mut last_sent = Instant::now();
+            find_paths(&root, &query, filter, FIND_MAX_RESULTS, &cancel, |path| {
+                batch.push(path);
+                if last_sent.elapsed() >= FINDER_STREAM_INTERVAL {
+                    last_sent = Instant::now();
+                    batch.sort();
+                    let _ = tx.send(FsEvent::FoundPaths {
+                        token,
+                        paths: mem::take(&mut batch),
+                        done: false,
+                    });
+                }
+            });
+            batch.sort();
+            let _ = tx.send(FsEvent::FoundPaths {
+                token,
+                paths: batch,
+                done: true,
+            });
+        });
+    }
+
+    fn request_glob(&self, root: PathBuf, glob: Regex, filter: WalkFilter, token: u64) {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let mut paths = Vec::new();
+            walk_files(
+                &root,
+                filter,
+                FINDER_MAX_FILES,
+                &AtomicBool::new(false),
+                |path| {
+                    if path
+                        .strip_prefix(&root)
+                        .is_ok_and(|relative| glob.is_match(&relative.to_string_lossy()))
+                    {
+                        paths.push(path);
+                    }
+                    true
+                },
+            );
+            let _ = tx.send(FsEvent::GlobMatches { token, paths });
+        });
+    }
+
+    fn request_grep(
+        &self,
+        root: PathBuf,
+        regex: Regex,
+        filter: WalkFilter,
+        token: u64,
+        cancel: Arc<AtomicBool>,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let mut batch = Vec::new();
+            let mut total = 0;
+            let mut last_sent = Instant::now();
+            walk_files(&root, filter, usize::MAX, &cancel, |path| {
+                for found in grep_file(&path, &regex, GREP_MAX_MATCHES - total) {
+                    batch.push(found);
+                    total += 1;
+                }
+                if last_sent.elapsed() >= FINDER_STREAM_INTERVAL && !batch.is_empty() {
+                    last_sent = Instant::now();
+                    let _ = tx.send(FsEvent::GrepMatches {
+                        token,
+                        matches: mem::take(&mut batch),
+                        done: false,
+                    });
+                }
+                total < GREP_MAX_MATCHES
+            });
+            let _ = tx.send(FsEvent::GrepMatches {
+                token,
+                matches: batch,
+                done: true,
+            });
+        });
+        Ok(())
+    }
+
+    fn request_recent(
+        &self,
+        root: PathBuf,
+        limit: usize,
+        filter: WalkFilter,
+        token: u64,
+    ) -> Result<()> {
+        let tx = self.event_tx.clone();
+        self.handle.spawn_blocking(move || {
+            let mut last_sent = Instant::now();
+            let files = recent_files(&root, limit, RECENT_MAX_DEPTH, filter, |files| {
+                if last_sent.elapsed() >= RECENT_STREAM_INTERVAL {
+                    last_sent = Instant::now();
+                    let _ = tx.send(FsEvent::RecentFiles {
+                        token,
+                        files: files.to_vec(),
+                        done: false,
+                    });
+                }
+            });
+            let _ = tx.send(FsEvent::RecentFiles {
+                token,
+                files,
+                done: true,
+            });
+        });
+        Ok(())
+    }
+}
+
+struct TreeWalk {
+    lines: Vec<String>,
+    dirs: usize,
+    files: usize,
+    truncated: bool,
+}
+
+/// Which entries the recursive walkers visit. They follow the listing:
+/// dot-entries only while hidden files are shown, gitignored entries unless
+/// gitignore mode is `hide`.
+#[derive(Clone, Copy)]
+struct WalkFilter {
+    show_hidden: bool,
+    skip_ignored: bool,
+}
+
+/// Gitignore matchers in effect for one directory of a walk, innermost
+/// first. `in_repo` is false until the walk is inside a git repository.
+#[derive(Clone, Default)]
+struct IgnoreRules {
+    in_repo: bool,
+    matchers: Vec<Arc<ignore::gitignore::Gitignore>>,
+}
+
+impl WalkFilter {
+    /// Rules inherited by the walk root: the `.gitignore` files above it up
+    /// to the repository root, the repository's exclude file and the global
+    /// excludes file. Outside a repository nothing is ignored, as in git.
+    fn root_rules(self, root: &Path) -> IgnoreRules {
+        let mut rules = IgnoreRules::default();
+        if !self.skip_ignored {
+            return rules;
+        }
+        let Some(repo) = root
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join(".git").exists())
+        else {
+            return rules;
+        };
+        rules.in_repo = true;
+        for dir in root.ancestors().skip(1) {
+            rules
+                .matchers
+                .extend(gitignore_rules(dir, &dir.join(".gitignore")));
+            if dir == repo {
+                break;
+            }
+        }
+        rules.matchers.extend(repo_excludes(repo));
+        rules
+    }
+
+    /// Adds `dir`'s own ignore files in front of the rules it inherits.
+    fn dir_rules(self, dir: &Path, inherited: &IgnoreRules) -> IgnoreRules {
+        if !self.skip_ignored {
+            return inherited.clone();
+        }
+        let mut rules = inherited.clone();
+        if dir.join(".git").exists() {
+            rules.in_repo = true;
+            rules.matchers.splice(0..0, repo_excludes(dir));
+        }
+        if rules.in_repo
+            && let Some(own) = gitignore_rules(dir, &dir.join(".gitignore"))
+        {
+            rules.matchers.insert(0, own);
+        }
+        rules
+    }
+
+    fn keep(self, path: &Path, is_dir: bool, rules: &IgnoreRules) -> bool {
+        let name = display_name(path);
+        if !self.show_hidden && name.starts_with('.') {
+            return false;
+        }
+        if !self.skip_ignored {
+            return true;
+        }
+        if is_dir && name == ".git" {
+            return false;
+        }
+        !rules.ignores(path, is_dir)
+    }
+}
+
+impl IgnoreRules {
+    fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        for rules in &self.matchers {
+            if !path.starts_with(rules.path()) {
+                continue;
+            }
+            match rules.matched_path_or_any_parents(path, is_dir) {
+                ignore::Match::Ignore(_) => return true,
+                ignore::Match::Whitelist(_) => return false,
+                ignore::Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// The repository's `info/exclude` and the global excludes file.
+fn repo_excludes(repo: &Path) -> Vec<Arc<ignore::gitignore::Gitignore>> {
+    let mut rules: Vec<_> = gitignore_rules(repo, &repo.join(".git/info/exclude"))
+        .into_iter()
+        .collect();
+    let (global, _) = ignore::gitignore::GitignoreBuilder::new(repo).build_global();
+    if !global.is_empty() {
+        rules.push(Arc::new(global));
+    }
+    rules
+}
+
+fn gitignore_rules(root: &Path, file: &Path) -> Option<Arc<ignore::gitignore::Gitignore>> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = ignore::gitignore::GitignoreBuilder::new(root);
+    builder.add(file);
+    builder
+        .build()
+        .ok()
+        .filter(|rules| !rules.is_empty())
+        .map(Arc::new)
+}
+
+fn find_paths(
+    root: &Path,
+    query: &str,
+    filter: WalkFilter,
+    limit: usize,
+    cancel: &AtomicBool,
+    mut found: impl FnMut(PathBuf),
+) {
+    let needle = query.to_lowercase();
+    let mut count = 0;
+    let mut stack = vec![(root.to_path_buf(), filter.root_rules(root))];
+    while let Some((dir, rules)) = stack.pop() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let rules = filter.dir_rules(&dir, &rules);
+        for item in read.flatten() {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let path = item.path();
+            let is_dir = item.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+            if !filter.keep(&path, is_dir, &rules) {
+                continue;
+            }
+            if item
+                .file_name()
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&needle)
+            {
+                found(path.clone());
+                count += 1;
+                if count >= limit {
+                    return;
+                }
+            }
+            if is_dir {
+                stack.push((path, rules.clone()));
+            }
+        }
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn glob_regex(pattern: &str) -> Result<Regex> {
+    let mut source = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.next_if_eq(&'/').is_some() {
+                    source.push_str("(?:.*/)?");
+                } else {
+                    source.push_str(".*");
+                }
+            }
+            '*' => source.push_str("[^/]*"),
+            '?' => source.push_str("[^/]"),
+            '[' => {
+                source.push('[');
+                if chars.next_if(|&ch| ch == '!' || ch == '^').is_some() {
+                    source.push('^');
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('-') => source.push('-'),
+                        Some(ch) => source.push_str(&regex::escape(&ch.to_string())),
+                        None => return Err(anyhow!("unclosed '[' in glob '{pattern}'")),
+                    }
+                }
+                source.push(']');
+            }
+            _ => source.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    source.push('$');
+    Regex::new(&source).with_context(|| format!("invalid glob '{pattern}'"))
+}
+
+fn walk_files(
+    root: &Path,
+    filter: WalkFilter,
+    limit: usize,
+    cancel: &AtomicBool,
+    mut found: impl FnMut(PathBuf) -> bool,
+) {
+    let mut count = 0;
+    let mut queue = VecDeque::from([(root.to_path_buf(), filter.root_rules(root))]);
+    while let Some((dir, rules)) = queue.pop_front() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let rules = filter.dir_rules(&dir, &rules);
+        for item in read.flatten() {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let is_dir = item.file_type().is_ok_and(|kind| kind.is_dir());
+            if !filter.keep(&item.path(), is_dir, &rules) {
+                continue;
+            }
+            match item.file_type() {
+                Ok(kind) if kind.is_dir() => queue.push_back((item.path(), rules.clone())),
+                Ok(_) => {
+                    count += 1;
+                    if !found(item.path()) || count >= limit {
+                        return;
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+fn grep_file(path: &Path, regex: &Regex, limit: usize) -> Vec<GrepMatch> {
+    let readable =
+        fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.len() <= GREP_MAX_FILE_SIZE);
+    let mut bytes = Vec::new();
+    let read = readable
+        && fs::File::open(path)
+            .and_then(|file| file.take(GREP_MAX_FILE_SIZE + 1).read_to_end(&mut bytes))
+            .is_ok();
+    if !read || bytes.len() as u64 > GREP_MAX_FILE_SIZE {
+        return Vec::new();
+    }
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        return Vec::new();
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .take(limit)
+        .map(|(index, line)| GrepMatch {
+            path: path.to_path_buf(),
+            line: index + 1,
+            text: line.trim().chars().take(GREP_LINE_WIDTH).collect(),
+        })
+        .collect()
+}
+
+fn locate_paths(root: &Path, name: &str, limit: usize, filter: WalkFilter) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut queue = VecDeque::from([(root.to_path_buf(), filter.root_rules(root))]);
+    while let Some((dir, rules)) = queue.pop_front() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let rules = filter.dir_rules(&dir, &rules);
+        for item in read.flatten() {
+            let is_dir = item.file_type().is_ok_and(|kind| kind.is_dir());
+            if !filter.keep(&item.path(), is_dir, &rules) {
+                continue;
+            }
+            if item.file_name() == name {
+                found.push(item.path());
+                if found.len() >= limit {
+                    return found;
+                }
+            }
+            if is_dir {
+                queue.push_back((item.path(), rules.clone()));
+            }
+        }
+    }
+    found
+}
+
+fn recent_files(
+    root: &Path,
+    limit: usize,
+    max_depth: usize,
+    filter: WalkFilter,
+    mut progress: impl FnMut(&[(PathBuf, SystemTime)]),
+) -> Vec<(PathBuf, SystemTime)> {
+    let mut found: Vec<(PathBuf, SystemTime)> = Vec::new();
+    let mut stack = vec![(root.to_path_buf(), 0, filter.root_rules(root))];
+    while let Some((dir, depth, rules)) = stack.pop() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let rules = filter.dir_rules(&dir, &rules);
+        for item in read.flatten() {
+            let Ok(file_type) = item.file_type() else {
+                continue;
+            };
+            if !filter.keep(&item.path(), file_type.is_dir(), &rules) {
+                continue;
+            }
+            if file_type.is_dir() {
+                if depth < max_depth {
+                    stack.push((item.path(), depth + 1, rules.clone()));
+                }
+                continue;
+            }
+            let Some(modified) = file_type
+                .is_file()
+                .then(|| item.metadata().and_then(|meta| meta.modified()).ok())
+                .flatten()
+            else {
+                continue;
+            };
+            let pos = found.partition_point(|(_, time)| *time >= modified);
+            if pos < limit {
+                found.insert(pos, (item.path(), modified));
+                found.truncate(limit);
+            }
+        }
+        progress(&found);
+    }
+    found
+}
+
+fn build_tree(root: &Path, depth: Option<usize>, filter: WalkFilter) -> Result<Vec<String>> {
+    let mut walk = TreeWalk {
+        lines: vec![".".into()],
+        dirs: 0,
+        files: 0,
+        truncated: false,
+    };
+    let rules = filter.root_rules(root);
+    walk_tree(root, "", 1, depth, filter, &rules, &mut walk)?;
+    if walk.truncated {
+        walk.lines
+            .push(format!("... (truncated at {TREE_MAX_LINES} lines)"));
+    }
+    walk.lines.push(String::new());
+    walk.lines
+        .push(format!("{} directories, {} files", walk.dirs, walk.files));
+    Ok(walk.lines)
+}
+
+fn walk_tree(
+    dir: &Path,
+    prefix: &str,
+    level: usize,
+    depth: Option<usize>,
+    filter: WalkFilter,
+    rules: &IgnoreRules,
+    walk: &mut TreeWalk,
+) -> Result<()> {
+    let rules = filter.dir_rules(dir, rules);
+    let mut children: Vec<FileEntry> = fs::read_dir(dir)
+        .with_context(|| format!("read dir {}", dir.display()))?
+        .flatten()
+        .map(|item| FileEntry {
+            name: item.file_name().to_string_lossy().into_owned(),
+            path: item.path(),
+            is_dir: item.file_type().map(|ft| ft.is_dir()).unwrap_or(false),
+            is_executable: false,
+            mode: None,
+            dir_status: None,
+            size: None,
+            modified: None,
+            ignored: false,
+        })
+        .filter(|child| filter.keep(&child.path, child.is_dir, &rules))
+        .collect();
+    sort_entries(&mut children);
+    let count = children.len();
+    for (idx, child) in children.into_iter().enumerate() {
+        if walk.lines.len() >= TREE_MAX_LINES {
+            walk.truncated = true;
+            return Ok(());
+        }
+        let last = idx + 1 == count;
+        let branch = if last { "└── " } else { "├── " };
+        walk.lines.push(format!("{prefix}{branch}{}", child.name));
+        if child.is_dir {
+            walk.dirs += 1;
+            if depth.is_none_or(|max| level < max) {
+                let nested = format!("{prefix}{}", if last { "    " } else { "│   " });
+                if let Err(err) =
+                    walk_tree(&child.path, &nested, level + 1, depth, filter, &rules, walk)
+                {
+                    walk.lines.push(format!("{nested}[error: {err:#}]"));
+                }
+            }
+        } else {
+            walk.files += 1;
+        }
+    }
+    Ok(())
+}
+
+fn chunk_paths(path: &Path, total: u64, chunk: u64) -> Result<Vec<PathBuf>> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no file name", path.display()))?
+        .to_string_lossy();
+    let count = total.div_ceil(chunk);
+    if count < 2 {
+        return Err(anyhow!(
+            "{name} ({}) is not larger than {}",
+            format_size(total),
+            format_size(chunk)
+        ));
+    }
+    let width = count.to_string().len().max(3);
+    Ok((1..=count)
+        .map(|index| path.with_file_name(format!("{name}.part{index:0width$}")))
+        .collect())
+}
+
+fn copy_with_progress(
+    reader: &mut impl Read,
+    writer: &mut impl io::Write,
+    processed: &mut u64,
+    total: u64,
+    progress: &dyn Fn(u64, u64),
+    cancel: &AtomicBool,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; HASH_CHUNK_BYTES];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(io::Error::other("canceled"));
+        }
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read])?;
+        let before = *processed;
+        *processed += read as u64;
+        if before / HASH_PROGRESS_BYTES != *processed / HASH_PROGRESS_BYTES {
+            progress(*processed, total);
+        }
+    }
+}
+
+fn split_file(
+    path: &Path,
+    chunk: u64,
+    progress: &dyn Fn(u64, u64),
+    cancel: &AtomicBool,
+) -> Result<String> {
+    let mut source = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let total = source
+        .metadata()
+        .with_context(|| format!("reading {}", path.display()))?
+        .len();
+    let parts = chunk_paths(path, total, chunk)?;
+    let mut processed = 0;
+    for (index, part) in parts.iter().enumerate() {
+        let mut created = index;
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(part)
+            .and_then(|mut output| {
+                created += 1;
+                let mut limited = (&mut source).take(chunk);
+                copy_with_progress(
+                    &mut limited,
+                    &mut output,
+                    &mut processed,
+                    total,
+                    progress,
+                    cancel,
+                )
+            });
+        if let Err(err) = result {
+            for part in &parts[..created] {
+                let _ = fs::remove_file(part);
+            }
+            return Err(err).with_context(|| format!("writing {}", part.display()));
+        }
+    }
+    Ok(format!(
+        "Split {} into {} parts",
+        display_name(path),
+        parts.len()
+    ))
+}
+
+fn concat_files(
+    inputs: &[PathBuf],
+    output: &Path,
+    progress: &dyn Fn(u64, u64),
+    cancel: &AtomicBool,
+) -> Result<String> {
+    let total = inputs
+        .iter()
+        .filter_map(|input| fs::metadata(input).ok())
+        .map(|meta| meta.len())
+        .sum();
+    let mut writer = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .with_context(|| format!("creating {}", output.display()))?;
+    let mut processed = 0;
+    for input in inputs {
+        let result = fs::File::open(input)
+            .and_then(|mut reader| {
+                copy_with_progress(
+                    &mut reader,
+                    &mut writer,
+                    &mut processed,
+                    total,
+                    progress,
+                    cancel,
+                )
+            })
+            .with_context(|| format!("appending {}", input.display()));
+        if let Err(err) = result {
+            drop(writer);
+            let _ = fs::remove_file(output);
+            return Err(err);
+        }
+    }
+    Ok(format!(
+        "Concatenated {} files into {}",
+        inputs.len(),
+        display_name(output)
+    ))
+}
+
+fn hash_file<D: Digest>(path: &Path, progress: impl Fn(u64, u64)) -> Result<String> {
+    let mut file = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let total = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; HASH_CHUNK_BYTES];
+    let mut processed = 0u64;
+    let mut next_report = HASH_PROGRESS_BYTES;
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        processed += read as u64;
+        if processed >= next_report {
+            progress(processed, total);
+            next_report += HASH_PROGRESS_BYTES;
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+fn is_transient_error(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|err| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ResourceBusy
+            )
+        })
+}
+
+/// Lists `dir`, returning its entries and how many could not be read.
+fn read_directory(dir: &Path, options: ScanOptions) -> Result<(Vec<FileEntry>, usize)> {
+    let threads = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(SCAN_MAX_THREADS);
+    read_directory_with(dir, options, threads)
+}
+
+fn read_directory_with(
+    dir: &Path,
+    options: ScanOptions,
+    threads: usize,
+) -> Result<(Vec<FileEntry>, usize)> {
+    let mut skipped = 0;
+    let items: Vec<fs::DirEntry> = fs::read_dir(dir)
+        .with_context(|| format!("read dir {}", dir.display()))?
+        .filter_map(|res| res.map_err(|_| skipped += 1).ok())
+        .collect();
+    let mut entries: Vec<FileEntry> = if items.len() < PARALLEL_SCAN_THRESHOLD || threads < 2 {
+        items
+            .iter()
+            .filter_map(|item| stat_entry(item, options))
+            .collect()
+    } else {
+        let chunk_size = items.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = items
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .filter_map(|item| stat_entry(item, options))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().map_err(|_| anyhow!("scan worker panicked")))
+                .collect::<Result<Vec<_>>>()
+        })?
+        .into_iter()
+        .flatten()
+        .collect()
+    };
+
+    sort_entries(&mut entries);
+    Ok((entries, skipped))
+}
+
+fn mark_ignored(entries: &mut [FileEntry], rules: &IgnoreRules) {
+    for entry in entries {
+        entry.ignored = rules.ignores(&entry.path, entry.is_dir);
+    }
+}
+
+fn stat_entry(entry: &fs::DirEntry, options: ScanOptions) -> Option<FileEntry> {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let meta = entry.metadata().ok()?;
+    let size = (!meta.is_dir()).then_some(meta.len());
+    Some(FileEntry {
+        is_executable: !meta.is_dir() && is_executable(&name, &meta),
+        mode: file_mode(&meta),
+        dir_status: (options.dir_indicators && meta.is_dir())
+            .then(|| probe_directory(&entry.path()))
+            .flatten(),
+        path: entry.path(),
+        name,
+        is_dir: meta.is_dir(),
+        size,
+        modified: meta.modified().ok(),
+        ignored: false,
+    })
+}
+
+struct Toast {
+    title: String,
+    lines: Vec<String>,
+    is_error: bool,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Clone)]
+struct DiskSpace {
+    total: u64,
+    free: u64,
+    available: u64,
+}
+
+#[cfg(unix)]
+fn disk_space(dir: &Path) -> Result<DiskSpace> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .with_context(|| format!("invalid path {}", dir.display()))?;
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("statvfs {}", dir.display()));
+    }
+    let block = stat.f_frsize as u64;
+    Ok(DiskSpace {
+        total: stat.f_blocks as u64 * block,
+        free: stat.f_bfree as u64 * block,
+        available: stat.f_bavail as u64 * block,
+    })
+}
+
+#[cfg(not(unix))]
+fn disk_space(_dir: &Path) -> Result<DiskSpace> {
+    Err(anyhow!("free space is not supported on this platform"))
+}
+
+#[derive(Clone)]
+struct MountInfo {
+    fs_type: String,
+    mount_point: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+fn mount_info(dir: &Path) -> Option<MountInfo> {
+    let dir = fs::canonicalize(dir).ok()?;
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    let mut best: Option<MountInfo> = None;
+    for line in mounts.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(_), Some(mount_point), Some(fs_type)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let mount_point = PathBuf::from(unescape_mount_field(mount_point));
+        let longer = best.as_ref().is_none_or(|current| {
+            mount_point.as_os_str().len() >= current.mount_point.as_os_str().len()
+        });
+        if dir.starts_with(&mount_point) && longer {
+            best = Some(MountInfo {
+                fs_type: fs_type.to_string(),
+                mount_point,
+            });
+        }
+    }
+    best
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_info(_dir: &Path) -> Option<MountInfo> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn unescape_mount_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 4);
+        match code.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn probe_directory(path: &Path) -> Option<DirStatus> {
+    match fs::read_dir(path) {
+        Ok(mut items) => items.next().is_none().then_some(DirStatus::Empty),
+        Err(_) => Some(DirStatus::Unreadable),
+    }
+}
+
+fn sort_entries(entries: &mut [FileEntry]) {
+    sort_entries_by(entries, SortSpec::default(), true);
+}
+
+fn sort_entries_by(entries: &mut [FileEntry], spec: SortSpec, dirs_first: bool) {
+    entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
+        (true, false) if dirs_first => cmp::Ordering::Less,
+        (false, true) if dirs_first => cmp::Ordering::Greater,
+        _ => {
+            let by_name = a.name.to_lowercase().cmp(&b.name.to_lowercase());
+            let ordering = match spec.key {
+                SortKey::Name => by_name,
+                SortKey::Natural => natural_cmp(&a.name, &b.name).then(by_name),
+                SortKey::Size => a.size.cmp(&b.size).then(by_name),
+                SortKey::Modified => a.modified.cmp(&b.modified).then(by_name),
+                SortKey::Extension => sort_extension(a).cmp(&sort_extension(b)).then(by_name),
+            };
+            if spec.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+    });
+}
+
+fn natural_cmp(a: &str, b: &str) -> cmp::Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a.peek(), b.peek()) {
+            (None, None) => return cmp::Ordering::Equal,
+            (None, Some(_)) => return cmp::Ordering::Less,
+            (Some(_), None) => return cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_digits(&mut a), take_digits(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+        if ordering != cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect()
+}
+
+fn sort_extension(entry: &FileEntry) -> String {
+    Path::new(&entry.name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(meta.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "permissions are not supported on this platform",
+    ))
+}
+
+fn chmod_path(
+    path: &Path,
+    mode: u32,
+    recursive: bool,
+    changed: &mut usize,
+    failures: &mut Vec<String>,
+) {
+    match set_mode(path, mode) {
+        Ok(()) => *changed += 1,
+        Err(err) => failures.push(format!("{}: {err}", path.display())),
+    }
+    if !recursive || !fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir()) {
+        return;
+    }
+    match fs::read_dir(path) {
+        Ok(read) => {
+            for item in read {
+                match item {
+                    Ok(item) if item.file_type().is_ok_and(|ft| ft.is_symlink()) => {}
+                    Ok(item) => chmod_path(&item.path(), mode, recursive, changed, failures),
+                    Err(err) => failures.push(format!("{}: {err}", path.display())),
+                }
+            }
+        }
+        Err(err) => failures.push(format!("{}: {err}", path.display())),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(_name: &str, meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(name: &str, _meta: &fs::Metadata) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["exe", "bat", "cmd", "com"]
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+fn build_preview(entry: &FileEntry, path: &Path, options: &PreviewOptions) -> Result<PreviewPane> {
+    if entry.is_dir {
+        return preview_directory(path, options);
+    }
+    if let Some(ext) = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        && options.skip_extensions.contains(&ext)
+    {
+        return Ok(PreviewPane::new(
+            "Preview",
+            format!(
+                "{}\n\nPreview disabled for .{ext} files",
+                entry.describe(options.time_format.as_deref())
+            ),
+        ));
+    }
+    if let Some(part) = office_parts(path) {
+        return preview_office(path, part)
+            .or_else(|_| preview_archive(path, ArchiveKind::Zip))
+            .or_else(|_| preview_file(entry, path, options));
+    }
+    if let Some(kind) = ArchiveKind::detect(path) {
+        return preview_archive(path, kind);
+    }
+    #[cfg(feature = "sqlite")]
+    if is_sqlite_file(path)
+        && let Ok(preview) = preview_sqlite(path)
+    {
+        return Ok(preview);
+    }
+    preview_file(entry, path, options)
+}
+
+fn is_sqlite_file(path: &Path) -> bool {
+    cfg!(feature = "sqlite")
+        && path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| matches!(ext.as_str(), "db" | "sqlite" | "sqlite3"))
+}
+
+/// Builds a `file:` URI that opens the database immutable, so previewing never creates
+/// journal or WAL files next to it or waits on another writer's locks.
+#[cfg(feature = "sqlite")]
+fn sqlite_preview_uri(path: &Path) -> String {
+    let mut uri = String::from("file:");
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri.push_str("?mode=ro&immutable=1");
+    uri
+}
+
+#[cfg(feature = "sqlite")]
+fn preview_sqlite(path: &Path) -> Result<PreviewPane> {
+    let conn = rusqlite::Connection::open_with_flags(
+        sqlite_preview_uri(path),
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY
+            | rusqlite::OpenFlags::SQLITE_OPEN_URI
+            | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("opening {}", path.display()))?;
+    conn.busy_timeout(Duration::from_millis(200))?;
+    let mut statement = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let tables = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let mut rows = vec![
+        format!("SQLite database, {} table(s)", tables.len()),
+        String::new(),
+    ];
+    for table in tables.iter().take(PREVIEW_MAX_LINES) {
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")),
+            [],
+            |row| row.get(0),
+        )?;
+        rows.push(format!("{table}  ({count} rows)"));
+    }
+    if tables.len() > PREVIEW_MAX_LINES {
+        rows.push("...".into());
+    }
+    Ok(PreviewPane::new("Preview", rows.join("\n")))
+}
+
+fn office_parts(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "docx" => Some("word/document.xml"),
+        "xlsx" => Some("xl/sharedStrings.xml"),
+        "pptx" => Some("ppt/slides/"),
+        "odt" | "ods" | "odp" => Some("content.xml"),
+        _ => None,
+    }
+}
+
+fn preview_office(path: &Path, part: &str) -> Result<PreviewPane> {
+    let file = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut zip = zip::ZipArchive::new(io::BufReader::new(file))
+        .with_context(|| format!("reading {}", path.display()))?;
+    let mut names: Vec<String> = zip
+        .file_names()
+        .filter(|name| *name == part || (part.ends_with('/') && name.starts_with(part)))
+        .filter(|name| name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+    names.sort_by_key(|name| (name.len(), name.clone()));
+    if names.is_empty() {
+        return Err(anyhow!("{part} is missing"));
+    }
+    let mut text = String::new();
+    for name in names {
+        let mut xml = String::new();
+        zip.by_name(&name)
+            .with_context(|| format!("reading {name}"))?
+            .take(OFFICE_XML_MAX_BYTES)
+            .read_to_string(&mut xml)
+            .with_context(|| format!("reading {name}"))?;
+        text.push_str(&xml_text(&xml));
+        if text.lines().count() > PREVIEW_MAX_LINES {
+            break;
+        }
+    }
+    let mut lines: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    if lines.is_empty() {
+        return Ok(PreviewPane::new("Document", "<no text>"));
+    }
+    let truncated = lines.len() > PREVIEW_MAX_LINES;
+    lines.truncate(PREVIEW_MAX_LINES);
+    let mut body = lines.join("\n");
+    if truncated {
+        body.push_str("\n...");
+    }
+    Ok(PreviewPane::new("Document", body))
+}
+
+fn xml_text(xml: &str) -> String {
+    let mut text = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_xml_entities(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim_end_matches('/');
+        match tag.split_whitespace().next().unwrap_or_default() {
+            "/w:p" | "/a:p" | "/text:p" | "/text:h" | "/si" | "w:br" | "text:line-break" => {
+                text.push('\n')
+            }
+            "w:tab" | "text:tab" | "/table:table-cell" => text.push('\t'),
+            _ => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text
+}
+
+fn decode_xml_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let Some(end) = tail.find(';') else {
+            decoded.push_str(tail);
+            return decoded;
+        };
+        let entity = &tail[1..end];
+        let ch = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match ch {
+            Some(ch) => decoded.push(ch),
+            None => decoded.push_str(&tail[..=end]),
+        }
+        rest = &tail[end + 1..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn preview_directory(path: &Path, options: &PreviewOptions) -> Result<PreviewPane> {
+    let mut rows = Vec::new();
+    let mut entries =
+        fs::read_dir(path).with_context(|| format!("reading directory {}", path.display()))?;
+    for item in entries.by_ref().flatten().take(PREVIEW_DIR_ENTRIES) {
+        let name = item.file_name().to_string_lossy().into_owned();
+        let is_dir = item.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+        let mut row = format!("{} {}", if is_dir { "[D]" } else { "[F]" }, name);
+        if options.dir_details {
+            if is_dir {
+                if let Ok(children) = fs::read_dir(item.path()) {
+                    let count = children.take(PREVIEW_COUNT_LIMIT + 1).count();
+                    if count > PREVIEW_COUNT_LIMIT {
+                        row.push_str(&format!("  ({PREVIEW_COUNT_LIMIT}+ items)"));
+                    } else {
+                        row.push_str(&format!("  ({count} items)"));
+                    }
+                }
+            } else if let Ok(meta) = fs::metadata(item.path()) {
+                row.push_str(&format!("  ({})", format_size(meta.len())));
+            }
+        }
+        rows.push(row);
+    }
+    let mut body = if rows.is_empty() {
+        "Directory is empty".to_string()
+    } else {
+        rows.join("\n")
+    };
+    if entries.next().is_some() {
+        if !body.is_empty() {
+            body.push_str("\n...");
+        } else {
+            body = "...".into();
+        }
+    }
+    Ok(PreviewPane::new("Preview", body))
+}
+
+fn preview_file(entry: &FileEntry, path: &Path, options: &PreviewOptions) -> Result<PreviewPane> {
+    let mut file = fs::File::open(path).with_context(|| format!("opening {}", entry.name))?;
+    let mut buffer = Vec::new();
+    file.by_ref()
+        .take(PREVIEW_MAX_BYTES as u64)
+        .read_to_end(&mut buffer)
+        .with_context(|| format!("reading {}", entry.name))?;
+
+    if buffer.is_empty() {
+        return Ok(PreviewPane::new("Preview", "<empty file>"));
+    }
+
+    if is_text_data(&buffer) {
+        return Ok(text_preview(&buffer, options));
+    }
+
+    let file_type = describe_file_type(path);
+    Ok(PreviewPane::new(
+        "Preview",
+        format!("Non-text file\nType: {}", file_type),
+    ))
+}
+
+fn text_preview(buffer: &[u8], options: &PreviewOptions) -> PreviewPane {
+    let has_escapes = buffer.contains(&0x1b);
+    let mut body = String::new();
+    let mut styled = Vec::new();
+    for (idx, line) in String::from_utf8_lossy(buffer).lines().enumerate() {
+        if idx >= PREVIEW_MAX_LINES {
+            body.push_str("\n...");
+            styled.push(Line::raw("..."));
+            break;
+        }
+        if idx > 0 {
+            body.push('\n');
+        }
+        if !has_escapes {
+            body.push_str(line);
+        } else if options.ansi {
+            let (plain, styled_line) = parse_ansi_line(line);
+            body.push_str(&plain);
+            styled.push(styled_line);
+        } else {
+            body.push_str(&line.replace('\x1b', "^["));
+        }
+    }
+    let mut preview = PreviewPane::new("Preview", body);
+    if has_escapes && options.ansi {
+        preview.styled = Some(styled);
+    }
+    preview
+}
+
+fn parse_ansi_line(line: &str) -> (String, Line<'static>) {
+    let mut plain = String::new();
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            if ch == '\t' || !ch.is_control() {
+                text.push(ch);
+            }
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut final_byte = None;
+                for next in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&next) {
+                        final_byte = Some(next);
+                        break;
+                    }
+                    params.push(next);
+                }
+                if final_byte == Some('m') {
+                    if !text.is_empty() {
+                        plain.push_str(&text);
+                        spans.push(Span::styled(mem::take(&mut text), style));
+                    }
+                    style = apply_sgr(style, &params);
+                }
+            }
+            Some(']') => {
+                while let Some(next) = chars.next() {
+                    if next == '\x07' {
+                        break;
+                    }
+                    if next == '\x1b' {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        plain.push_str(&text);
+        spans.push(Span::styled(text, style));
+    }
+    (plain, Line::from(spans))
+}
+
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let mut codes = params
+        .split([';', ':'])
+        .map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            38 => match extended_color(&mut codes) {
+                Some(color) => style.fg(color),
+                None => style,
+            },
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(Color::Indexed((code - 40) as u8)),
+            48 => match extended_color(&mut codes) {
+                Some(color) => style.bg(color),
+                None => style,
+            },
+            49 => style.bg(Color::Reset),
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            _ => style,
+        };
+    }
+    style
+}
+
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::Indexed(codes.next()? as u8)),
+        2 => Some(Color::Rgb(
+            codes.next()? as u8,
+            codes.next()? as u8,
+            codes.next()? as u8,
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ArchiveMember {
+    path: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl ArchiveMember {
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    fn parent(&self) -> &str {
+        self.path.rsplit_once('/').map_or("", |(parent, _)| parent)
+    }
+
+    fn row(&self) -> String {
+        if self.is_dir {
+            format!("[D] {}", self.name())
+        } else {
+            format!("[F] {}  ({})", self.name(), format_size(self.size))
+        }
+    }
+}
+
+struct ArchiveView {
+    archive: PathBuf,
+    kind: ArchiveKind,
+    members: Vec<ArchiveMember>,
+    prefix: String,
+}
+
+impl ArchiveView {
+    fn location(&self) -> String {
+        if self.prefix.is_empty() {
+            self.archive.display().to_string()
+        } else {
+            format!("{}/{}", self.archive.display(), self.prefix)
+        }
+    }
+
+    fn member_path(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{name}", self.prefix)
+        }
+    }
+
+    fn children<'a>(&'a self, dir: &'a str) -> impl Iterator<Item = &'a ArchiveMember> {
+        self.members
+            .iter()
+            .filter(move |member| member.parent() == dir)
+    }
+
+    fn entries(&self) -> Vec<FileEntry> {
+        self.children(&self.prefix)
+            .map(|member| FileEntry {
+                name: member.name().to_string(),
+                path: self.archive.join(&member.path),
+                is_dir: member.is_dir,
+                is_executable: false,
+                mode: None,
+                dir_status: None,
+                size: (!member.is_dir).then_some(member.size),
+                modified: member.modified,
+                ignored: false,
+            })
+            .collect()
+    }
+}
+
+fn member_name(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(part) => parts.push(part.to_string_lossy()),
+            std::path::Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let seconds =
+        NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+            .and_hms_opt(
+                time.hour().into(),
+                time.minute().into(),
+                time.second().into(),
+            )?
+            .and_utc()
+            .timestamp();
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+fn for_each_member(
+    archive: &Path,
+    kind: ArchiveKind,
+    mut visit: impl FnMut(ArchiveMember, &mut dyn Read) -> Result<bool>,
+) -> Result<()> {
+    let file = fs::File::open(archive).with_context(|| format!("opening {}", archive.display()))?;
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(io::BufReader::new(file))
+                .with_context(|| format!("reading {}", archive.display()))?;
+            for index in 0..zip.len() {
+                let mut item = zip.by_index(index).context("reading zip member")?;
+                if item.is_symlink() {
+                    continue;
+                }
+                let Some(path) = item.enclosed_name().and_then(|path| member_name(&path)) else {
+                    continue;
+                };
+                let member = ArchiveMember {
+                    path,
+                    is_dir: item.is_dir(),
+                    size: item.size(),
+                    modified: item.last_modified().and_then(zip_time),
+                };
+                if !visit(member, &mut item)? {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        ArchiveKind::Tar => visit_tar(tar::Archive::new(file), &mut visit),
+        ArchiveKind::TarGz => visit_tar(tar::Archive::new(GzDecoder::new(file)), &mut visit),
+    }
+}
+
+fn visit_tar<R: Read>(
+    mut archive: tar::Archive<R>,
+    visit: &mut impl FnMut(ArchiveMember, &mut dyn Read) -> Result<bool>,
+) -> Result<()> {
+    for item in archive.entries().context("reading tar archive")? {
+        let mut item = item.context("reading tar member")?;
+        let kind = item.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        let Some(path) = item.path().ok().and_then(|path| member_name(&path)) else {
+            continue;
+        };
+        let member = ArchiveMember {
+            path,
+            is_dir: kind.is_dir(),
+            size: item.size(),
+            modified: item
+                .header()
+                .mtime()
+                .ok()
+                .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)),
+        };
+        if !visit(member, &mut item)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn list_archive(archive: &Path, kind: ArchiveKind) -> Result<Vec<ArchiveMember>> {
+    let mut members = BTreeMap::new();
+    for_each_member(archive, kind, |member, _| {
+        let mut parent = member.parent().to_string();
+        while !parent.is_empty() && !members.contains_key(&parent) {
+            let next = parent.rsplit_once('/').map_or("", |(up, _)| up).to_string();
+            members.insert(
+                parent.clone(),
+                ArchiveMember {
+                    path: parent,
+                    is_dir: true,
+                    size: 0,
+                    modified: None,
+                },
+            );
+            parent = next;
+        }
+        members.insert(member.path.clone(), member);
+        Ok(true)
+    })?;
+    Ok(members.into_values().collect())
+}
+
+fn read_archive_member(archive: &Path, kind: ArchiveKind, path: &str) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut found = false;
+    for_each_member(archive, kind, |member, reader| {
+        if member.path != path {
+            return Ok(true);
+        }
+        found = true;
+        reader
+            .take(PREVIEW_MAX_BYTES as u64)
+            .read_to_end(&mut buffer)
+            .with_context(|| format!("reading {path}"))?;
+        Ok(false)
+    })?;
+    if !found {
+        return Err(anyhow!("{path} is missing from the archive"));
+    }
+    Ok(buffer)
+}
+
+fn extract_members(
+    archive: &Path,
+    kind: ArchiveKind,
+    prefix: &str,
+    selection: &[(String, bool)],
+    dest_dir: &Path,
+    progress: &dyn Fn(u64),
+    cancel: &AtomicBool,
+) -> Result<String> {
+    let relative = |path: &str| -> PathBuf {
+        let path = if prefix.is_empty() {
+            path
+        } else {
+            path.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .unwrap_or(path)
+        };
+        dest_dir.join(path)
+    };
+    let created: Vec<PathBuf> = selection
+        .iter()
+        .map(|(path, _)| relative(path))
+        .filter(|dest| !dest.exists() && !dest.is_symlink())
+        .collect();
+    let mut extracted = 0;
+    let result = extract_selected(
+        archive,
+        kind,
+        selection,
+        &relative,
+        &mut extracted,
+        progress,
+        cancel,
+    );
+    if let Err(err) = result {
+        for dest in &created {
+            if dest.exists() || dest.is_symlink() {
+                let _ = remove_path(dest);
+            }
+        }
+        return Err(err);
+    }
+    Ok(format!(
+        "Extracted {extracted} file(s) to {}",
+        dest_dir.display()
+    ))
+}
+
+fn extract_selected(
+    archive: &Path,
+    kind: ArchiveKind,
+    selection: &[(String, bool)],
+    relative: &dyn Fn(&str) -> PathBuf,
+    extracted: &mut u64,
+    progress: &dyn Fn(u64),
+    cancel: &AtomicBool,
+) -> Result<()> {
+    for (path, _) in selection.iter().filter(|(_, is_dir)| *is_dir) {
+        let dest = relative(path);
+        fs::create_dir_all(&dest).with_context(|| format!("creating {}", dest.display()))?;
+    }
+    for_each_member(archive, kind, |member, reader| {
+        let selected = selection.iter().any(|(path, is_dir)| {
+            member.path == *path
+                || (*is_dir
+                    && member
+                        .path
+                        .strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.starts_with('/')))
+        });
+        if !selected {
+            return Ok(true);
+        }
+        if cancel.load(Ordering::Relaxed) {
+            return Err(anyhow!("Canceled"));
+        }
+        let dest = relative(&member.path);
+        if member.is_dir {
+            fs::create_dir_all(&dest).with_context(|| format!("creating {}", dest.display()))?;
+            return Ok(true);
+        }
+        ensure_parent_dir(&dest)?;
+        let mut file =
+            fs::File::create_new(&dest).with_context(|| format!("creating {}", dest.display()))?;
+        io::copy(reader, &mut file).with_context(|| format!("extracting {}", member.path))?;
+        *extracted += 1;
+        progress(*extracted);
+        Ok(true)
+    })
+}
+
+fn preview_archive(path: &Path, kind: ArchiveKind) -> Result<PreviewPane> {
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    for_each_member(path, kind, |member, _| {
+        if rows.len() >= PREVIEW_MAX_LINES {
+            truncated = true;
+            return Ok(false);
+        }
+        rows.push(if member.is_dir {
+            format!("[D] {}", member.path)
+        } else {
+            format!("[F] {}  ({})", member.path, format_size(member.size))
+        });
+        Ok(true)
+    })?;
+    if rows.is_empty() {
+        rows.push("Archive is empty".into());
+    }
+    if truncated {
+        rows.push("...".into());
+    }
+    Ok(PreviewPane::new(
+        "Archive (Enter to browse)",
+        rows.join("\n"),
+    ))
+}
+
+fn preview_archive_dir(view: &ArchiveView, path: &str) -> PreviewPane {
+    let children: Vec<&ArchiveMember> = view.children(path).collect();
+    let mut rows: Vec<String> = children
+        .iter()
+        .take(PREVIEW_DIR_ENTRIES)
+        .map(|member| member.row())
+        .collect();
+    if rows.is_empty() {
+        rows.push("Directory is empty".into());
+    } else if children.len() > PREVIEW_DIR_ENTRIES {
+        rows.push("...".into());
+    }
+    PreviewPane::new("Preview", rows.join("\n"))
+}
+
+fn preview_archive_member(
+    archive: &Path,
+    kind: ArchiveKind,
+    path: &str,
+    options: &PreviewOptions,
+) -> Result<PreviewPane> {
+    let buffer = read_archive_member(archive, kind, path)?;
+    if buffer.is_empty() {
+        return Ok(PreviewPane::new("Preview", "<empty file>"));
+    }
+    if is_text_data(&buffer) {
+        return Ok(text_preview(&buffer, options));
+    }
+    let file_type = infer::get(&buffer)
+        .map(|kind| format!("{} ({})", kind.mime_type(), kind.extension()))
+        .unwrap_or_else(|| "Unknown type".into());
+    Ok(PreviewPane::new(
+        "Preview",
+        format!("Non-text file\nType: {file_type}"),
+    ))
+}
+
+fn is_text_data(buffer: &[u8]) -> bool {
+    !matches!(content_inspector::inspect(buffer), ContentType::BINARY)
+}
+
+fn describe_file_type(path: &Path) -> String {
+    match infer::get_from_path(path) {
+        Ok(Some(kind)) => format!("{} ({})", kind.mime_type(), kind.extension()),
+        Ok(None) => "Unknown type".into(),
+        Err(_) => "Unknown type".into(),
+    }
+}
+
+#[cfg(unix)]
+fn is_cross_device(src: &Path, dest: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(src_meta) = fs::symlink_metadata(src) else {
+        return false;
+    };
+    dest.ancestors()
+        .find_map(|ancestor| fs::metadata(ancestor).ok())
+        .is_some_and(|dest_meta| dest_meta.dev() != src_meta.dev())
+}
+
+#[cfg(not(unix))]
+fn is_cross_device(_src: &Path, _dest: &Path) -> bool {
+    false
+}
+
+fn find_git_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn read_diff_side(path: &Path) -> Result<(String, bool)> {
+    let file = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut buffer = Vec::new();
+    file.take(DIFF_MAX_BYTES as u64 + 1)
+        .read_to_end(&mut buffer)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let truncated = buffer.len() > DIFF_MAX_BYTES;
+    buffer.truncate(DIFF_MAX_BYTES);
+    if !is_text_data(&buffer) {
+        return Err(anyhow!("{} is not a text file", display_name(path)));
+    }
+    Ok((String::from_utf8_lossy(&buffer).into_owned(), truncated))
+}
+
+fn build_diff(old: &Path, new: &Path) -> Result<Vec<Line<'static>>> {
+    let (old_text, old_truncated) = read_diff_side(old)?;
+    let (new_text, new_truncated) = read_diff_side(new)?;
+    let header = Style::default().add_modifier(Modifier::BOLD);
+    let mut lines = vec![
+        Line::styled(format!("--- {}", old.display()), header),
+        Line::styled(format!("+++ {}", new.display()), header),
+    ];
+    if old_truncated || new_truncated {
+        lines.push(Line::styled(
+            format!(
+                "[inputs truncated to the first {}]",
+                format_size(DIFF_MAX_BYTES as u64)
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    let diff = TextDiff::from_lines(&old_text, &new_text);
+    let groups = diff.grouped_ops(3);
+    if groups.is_empty() {
+        lines.push(Line::raw("Files are identical"));
+    }
+    for group in groups {
+        if let (Some(first), Some(last)) = (group.first(), group.last()) {
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            lines.push(Line::styled(
+                format!(
+                    "@@ -{},{} +{},{} @@",
+                    old_range.start + 1,
+                    old_range.len(),
+                    new_range.start + 1,
+                    new_range.len()
+                ),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let (sign, style) = match change.tag() {
+                    ChangeTag::Delete => ("-", Style::default().fg(Color::Red)),
+                    ChangeTag::Insert => ("+", Style::default().fg(Color::Green)),
+                    ChangeTag::Equal => (" ", Style::default()),
+                };
+                let text = change.value().trim_end_matches(['\n', '\r']);
+                lines.push(Line::styled(format!("{sign}{text}"), style));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[derive(Deserialize, Serialize)]
+struct TrashInfo {
+    original: PathBuf,
+    deleted_at: u64,
+    bytes: u64,
+}
+
+struct TrashItem {
+    item: PathBuf,
+    info_path: PathBuf,
+    info: TrashInfo,
+}
+
+fn note_path(dir: &Path) -> Option<PathBuf> {
+    let digest = sha2::Sha256::digest(dir.as_os_str().as_encoded_bytes());
+    let key: String = digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    config_dir().map(|config| {
+        config
+            .join("wayfinder")
+            .join("notes")
+            .join(format!("{key}.txt"))
+    })
+}
+
+fn load_note(dir: &Path) -> Result<String> {
+    let path = note_path(dir).ok_or_else(|| anyhow!("No config directory available"))?;
+    match fs::read(&path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+fn save_note(dir: &Path, text: &str) -> Result<()> {
+    let path = note_path(dir).ok_or_else(|| anyhow!("No config directory available"))?;
+    if text.trim().is_empty() {
+        return match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("removing {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    ensure_parent_dir(&path)?;
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, text).with_context(|| format!("writing {}", temp.display()))?;
+    fs::rename(&temp, &path).with_context(|| format!("writing {}", path.display()))
+}
+
+fn trash_dir() -> Result<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join("wayfinder").join("trash"))
+        .ok_or_else(|| anyhow!("No config directory available"))
+}
+
+/// Items wayfinder moved to the system trash, so `:restore` leaves other programs' items alone.
+#[derive(Default, Deserialize, Serialize)]
+struct SystemTrashLog {
+    #[serde(default)]
+    items: Vec<TrashInfo>,
+}
+
+impl SystemTrashLog {
+    fn path() -> Result<PathBuf> {
+        Ok(trash_dir()?.with_file_name("system-trash.toml"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| Ok(fs::read_to_string(path)?))
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn contains(&self, info: &TrashInfo) -> bool {
+        self.items.iter().any(|recorded| {
+            recorded.original == info.original && recorded.deleted_at.abs_diff(info.deleted_at) <= 2
+        })
+    }
+}
+
+fn record_system_trash(path: &Path) -> Result<()> {
+    let mut log = SystemTrashLog::load();
+    log.items.push(TrashInfo {
+        original: path.to_path_buf(),
+        deleted_at: unix_now(),
+        bytes: 0,
+    });
+    let excess = log.items.len().saturating_sub(TRASH_MAX_ITEMS);
+    log.items.drain(..excess);
+    let log_path = SystemTrashLog::path()?;
+    ensure_parent_dir(&log_path)?;
+    let contents = toml::to_string_pretty(&log).context("serializing trash log")?;
+    fs::write(&log_path, contents).with_context(|| format!("writing {}", log_path.display()))
+}
+
+fn trash_items(dir: &Path) -> Result<Vec<TrashItem>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut items = Vec::new();
+    for item in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let info_path = item?.path();
+        if info_path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let contents = fs::read_to_string(&info_path)
+            .with_context(|| format!("reading {}", info_path.display()))?;
+        let info: TrashInfo = toml::from_str(&contents)
+            .with_context(|| format!("parsing {}", info_path.display()))?;
+        items.push(TrashItem {
+            item: info_path.with_extension(""),
+            info_path,
+            info,
+        });
+    }
+    items.sort_by(|a, b| a.info_path.cmp(&b.info_path));
+    Ok(items)
+}
+
+#[cfg(all(
+    unix,
+    not(target_os = "macos"),
+    not(target_os = "ios"),
+    not(target_os = "android")
+))]
+fn system_trash_items() -> Vec<TrashItem> {
+    trash::os_limited::list()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|item| {
+            let info_path = PathBuf::from(&item.id);
+            let stored = info_path
+                .parent()?
+                .parent()?
+                .join("files")
+                .join(info_path.file_stem()?);
+            Some(TrashItem {
+                item: stored,
+                info_path,
+                info: TrashInfo {
+                    original: item.original_path(),
+                    deleted_at: u64::try_from(item.time_deleted).unwrap_or(0),
+                    bytes: 0,
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(all(
+    unix,
+    not(target_os = "macos"),
+    not(target_os = "ios"),
+    not(target_os = "android")
+)))]
+fn system_trash_items() -> Vec<TrashItem> {
+    Vec::new()
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("removing {}", path.display()))
+}
+
+/// Moves `path` to the configured trash. Trouble that did not stop the delete itself, like
+/// failing to evict old items, comes back as a note.
+fn soft_delete(path: &Path, backend: TrashBackend) -> Result<Option<String>> {
+    if backend == TrashBackend::System {
+        trash::delete(path).context(
+            "the system trash refused it (use :delete! or trash = \"internal\" instead)",
+        )?;
+        return Ok(record_system_trash(path)
+            .err()
+            .map(|err| format!("not recorded for :restore: {err:#}")));
+    }
+    trash_into(path, &trash_dir()?)
+}
+
+/// Moves `path` into the internal trash at `dir`, recording where it came from.
+fn trash_into(path: &Path, dir: &Path) -> Result<Option<String>> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let meta = fs::symlink_metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    if is_cross_device(path, dir) {
+        return Err(anyhow!(
+            "it is on another filesystem than {}, trashing would copy it (use :delete! instead)",
+            dir.display()
+        ));
+    }
+    let bytes = if meta.is_dir() {
+        summarize_tree(path, usize::MAX).bytes
+    } else {
+        meta.len()
+    };
+    if bytes > TRASH_MAX_BYTES {
+        return Err(anyhow!(
+            "{} is larger than the {} trash (use :delete! instead)",
+            format_size(bytes),
+            format_size(TRASH_MAX_BYTES)
+        ));
+    }
+    let note = evict_trash(dir, bytes)
+        .err()
+        .map(|err| format!("evicting old trash failed: {err:#}"));
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let id = format!("{:020}", now.as_nanos());
+    let info = TrashInfo {
+        original: path.to_path_buf(),
+        deleted_at: now.as_secs(),
+        bytes,
+    };
+    let info_path = dir.join(format!("{id}.toml"));
+    let contents = toml::to_string_pretty(&info).context("serializing trash info")?;
+    fs::write(&info_path, contents).with_context(|| format!("writing {}", info_path.display()))?;
+    if let Err(err) = move_path(path, &dir.join(&id)) {
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+    Ok(note)
+}
+
+/// Evicts the oldest items until one more item of `incoming` bytes fits under the caps.
+fn evict_trash(dir: &Path, incoming: u64) -> Result<()> {
+    let items = trash_items(dir)?;
+    let mut count = items.len() + 1;
+    let mut bytes: u64 = items.iter().map(|item| item.info.bytes).sum::<u64>() + incoming;
+    for item in items {
+        if count <= TRASH_MAX_ITEMS && bytes <= TRASH_MAX_BYTES {
+            break;
+        }
+        remove_trash_item(&item)?;
+        count -= 1;
+        bytes = bytes.saturating_sub(item.info.bytes);
+    }
+    Ok(())
+}
+
+fn remove_trash_item(item: &TrashItem) -> Result<()> {
+    if item.item.is_dir() {
+        fs::remove_dir_all(&item.item)
+    } else {
+        fs::remove_file(&item.item)
+    }
+    .with_context(|| format!("removing {}", item.item.display()))?;
+    fs::remove_file(&item.info_path)
+        .with_context(|| format!("removing {}", item.info_path.display()))
+}
+
+fn suffixed_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){extension}")))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+struct TreeSummary {
+    items: usize,
+    files: usize,
+    bytes: u64,
+    truncated: bool,
+}
+
+fn summarize_tree(root: &Path, limit: usize) -> TreeSummary {
+    let mut summary = TreeSummary {
+        items: 0,
+        files: 0,
+        bytes: 0,
+        truncated: false,
+    };
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for item in read_dir.flatten() {
+            if summary.items >= limit {
+                summary.truncated = true;
+                return summary;
+            }
+            summary.items += 1;
+            let Ok(meta) = fs::symlink_metadata(item.path()) else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(item.path());
+            } else {
+                summary.files += 1;
+                summary.bytes += meta.len();
+            }
+        }
+    }
+    summary
+}
+
+fn move_path(src: &Path, dest: &Path) -> Result<bool> {
+    move_path_cancellable(src, dest, &mut || {}, &AtomicBool::new(false))
+}
+
+/// Renames `src` to `dest`, copying and then removing the original only when
+/// they are on different filesystems. Returns whether it had to copy.
+fn move_path_cancellable(
+    src: &Path,
+    dest: &Path,
+    on_file: &mut dyn FnMut(),
+    cancel: &AtomicBool,
+) -> Result<bool> {
+    ensure_parent_dir(dest)?;
+    match fs::rename(src, dest) {
+        Ok(()) => return Ok(false),
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("moving {} to {}", src.display(), dest.display()));
+        }
+    }
+    let name = display_name(src);
+    if src.is_dir() && !src.is_symlink() {
+        copy_directory_with_progress(src, dest, on_file, cancel)?;
+        fs::remove_dir_all(src).with_context(|| format!("removing {name}"))?;
+    } else {
+        copy_file_cancellable(src, dest, cancel)?;
+        fs::remove_file(src).with_context(|| format!("removing {name}"))?;
+    }
+    Ok(true)
+}
+
+/// Moves `children` of `dir` into `parent` and removes `dir`. When a move
+/// fails or the job is canceled the moved children are put back, so the
+/// tree ends up either flattened or as it was.
+fn flatten_directory(
+    dir: &Path,
+    parent: &Path,
+    mut children: Vec<OsString>,
+    progress: &dyn Fn(u64, u64),
+    cancel: &AtomicBool,
+) -> Result<String> {
+    let name = display_name(dir);
+    let total = children.len() as u64;
+    children.sort();
+    let mut source = dir.to_path_buf();
+    if children
+        .iter()
+        .any(|child| Some(child.as_os_str()) == dir.file_name())
+    {
+        let temp = parent.join(format!(".{name}.flatten"));
+        if temp.exists() || temp.is_symlink() {
+            return Err(anyhow!("Temporary path {} already exists", temp.display()));
+        }
+        fs::rename(dir, &temp).with_context(|| format!("renaming {name}"))?;
+        source = temp;
+    }
+    let mut moved = Vec::new();
+    let result = children
+        .iter()
+        .try_for_each(|child| {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(anyhow!("canceled"));
+            }
+            let dest = parent.join(child);
+            if dest.exists() || dest.is_symlink() {
+                return Err(anyhow!("{} already exists", dest.display()));
+            }
+            move_path_cancellable(&source.join(child), &dest, &mut || {}, cancel)?;
+            moved.push(child);
+            progress(moved.len() as u64, total);
+            Ok(())
+        })
+        .and_then(|()| fs::remove_dir(&source).with_context(|| format!("removing {name}")));
+    let Err(err) = result else {
+        return Ok(format!("Flattened {name}: moved {total} item(s)"));
+    };
+    let mut stranded = Vec::new();
+    for child in moved.into_iter().rev() {
+        if move_path(&parent.join(child), &source.join(child)).is_err() {
+            stranded.push(child.to_string_lossy().into_owned());
+        }
+    }
+    let mut message = format!("Flattening {name} failed: {err:#}");
+    if !stranded.is_empty() {
+        message.push_str(&format!("\nCould not move back: {}", stranded.join(", ")));
+    }
+    let restored = source == dir || (stranded.is_empty() && fs::rename(&source, dir).is_ok());
+    if !restored {
+        message.push_str(&format!(
+            "\nThe remaining contents are in {}",
+            source.display()
+        ));
+    }
+    Err(anyhow!(message))
+}
+
+fn guard_current_dir(current_dir: &Path, target: &Path, action: &str) -> Result<()> {
+    let target = current_dir.join(target);
+    let target = fs::canonicalize(&target).unwrap_or(target);
+    let current = fs::canonicalize(current_dir).unwrap_or_else(|_| current_dir.to_path_buf());
+    if current.starts_with(&target) {
+        let relation = if current == target {
+            "the current directory"
+        } else {
+            "an ancestor of the current directory"
+        };
+        return Err(anyhow!(
+            "Refusing to {action} {}: it is {relation}",
+            target.display()
+        ));
+    }
+    Ok(())
+}
+
+fn canonical_destination(dest: &Path) -> PathBuf {
+    match (dest.parent(), dest.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| dest.to_path_buf()),
+        _ => dest.to_path_buf(),
+    }
+}
+
+fn ensure_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating parent {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+fn copy_file_cancellable(src: &Path, dest: &Path, cancel: &AtomicBool) -> Result<()> {
+    ensure_parent_dir(dest)?;
+    let mut reader = fs::File::open(src).with_context(|| format!("opening {}", src.display()))?;
+    let meta = reader
+        .metadata()
+        .with_context(|| format!("reading {}", src.display()))?;
+    let mut writer =
+        fs::File::create_new(dest).with_context(|| format!("creating {}", dest.display()))?;
+    let mut processed = 0;
+    let result = copy_with_progress(
+        &mut reader,
+        &mut writer,
+        &mut processed,
+        meta.len(),
+        &|_, _| {},
+        cancel,
+    )
+    .with_context(|| format!("copying {}", src.display()))
+    .and_then(|()| {
+        fs::set_permissions(dest, meta.permissions())
+            .with_context(|| format!("setting permissions of {}", dest.display()))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(dest);
+    }
+    result
+}
+
+fn copy_directory_with_progress(
+    src: &Path,
+    dest: &Path,
+    on_file: &mut dyn FnMut(),
+    cancel: &AtomicBool,
+) -> Result<()> {
+    if dest.exists() {
+        return Err(anyhow!("Destination {} already exists", dest.display()));
+    }
+    ensure_parent_dir(dest)?;
+    fs::create_dir(dest).with_context(|| format!("creating directory {}", dest.display()))?;
+    let mut options = DirCopyOptions::new();
+    options.content_only = true;
+    let mut current = String::new();
+    let result = copy_dir_with_progress(src, dest, &options, |process| {
+        if cancel.load(Ordering::Relaxed) {
+            return TransitProcessResult::Abort;
+        }
+        if process.file_name != current {
+            current = process.file_name;
+            on_file();
+        }
+        TransitProcessResult::ContinueOrAbort
+    })
+    .map(|_| ())
+    .with_context(|| format!("copying {} to {}", src.display(), dest.display()))
+    .and_then(|()| {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(anyhow!("Canceled"));
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_dir_all(dest);
+    }
+    result
+}
+
+fn copy_directory(src: &Path, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        return Err(anyhow!("Destination {} already exists", dest.display()));
+    }
+    ensure_parent_dir(dest)?;
+    fs::create_dir(dest).with_context(|| format!("creating directory {}", dest.display()))?;
+    let mut options = DirCopyOptions::new();
+    options.content_only = true;
+    copy_dir(src, dest, &options)
+        .map(|_| ())
+        .with_context(|| format!("copying {} to {}", src.display(), dest.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, buffer::Buffer};
+
+    use super::*;
+
+    fn test_app(dir: &Path) -> (Runtime, App) {
+        let (runtime, app, _rx) = test_app_with(dir, Config::default());
+        (runtime, app)
+    }
+
+    fn test_app_with(dir: &Path, config: Config) -> (Runtime, App, UnboundedReceiver<FsEvent>) {
+        let runtime = Runtime::new().unwrap();
+        let (fs, mut rx) = FsDispatcher::new(&runtime);
+        let mut app =
+            App::with_state(fs, config, PersistedState::default(), FrecencyDb::default()).unwrap();
+        app.current_dir = dir.to_path_buf();
+        app.refresh_async(true).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        (runtime, app, rx)
+    }
+
+    fn wait_loaded(runtime: &Runtime, app: &mut App, rx: &mut UnboundedReceiver<FsEvent>) {
+        while app.is_loading {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+    }
+
+    #[test]
+    fn guard_rejects_current_dir_and_ancestors() {
+        let root = tempfile::tempdir().unwrap();
+        let current = root.path().join("a").join("b");
+        fs::create_dir_all(current.join("c")).unwrap();
+
+        let err = guard_current_dir(&current, Path::new("."), "delete").unwrap_err();
+        assert!(err.to_string().contains("is the current directory"));
+        let err = guard_current_dir(&current, &root.path().join("a"), "move").unwrap_err();
+        assert!(err.to_string().contains("an ancestor"));
+        assert!(guard_current_dir(&current, Path::new(".."), "move").is_err());
+        assert!(guard_current_dir(&current, Path::new("c"), "delete").is_ok());
+        assert!(guard_current_dir(&current, &root.path().join("a/other"), "move").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn guard_sees_through_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let current = root.path().join("work");
+        fs::create_dir(&current).unwrap();
+        std::os::unix::fs::symlink(root.path(), current.join("up")).unwrap();
+
+        assert!(guard_current_dir(&current, Path::new("up"), "delete").is_err());
+    }
+
+    fn render_to_buffer(app: &App, width: u16, height: u16) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| render(frame, app)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn buffer_lines(buffer: &Buffer) -> Vec<String> {
+        let width = buffer.area.width as usize;
+        buffer
+            .content
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn render_lists_entries_with_header_and_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("notes.txt"), "first line\nsecond line\n").unwrap();
+        let mut config = Config::default();
+        config.scan.dir_indicators = true;
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+
+        let lines = buffer_lines(&render_to_buffer(&app, 80, 20));
+        assert!(lines[1].contains("Wayfinder"));
+        assert!(lines[1].contains(&dir.path().display().to_string()));
+        assert!(lines[4].contains("> [D] src (empty)"));
+        assert!(lines[5].contains("[F] notes.txt"));
+        assert!(lines[18].contains("Loaded 2 entries"));
+    }
+
+    #[test]
+    fn render_shows_file_preview_and_picker_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "first line\nsecond line\n").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        let text = buffer_lines(&render_to_buffer(&app, 80, 20)).join("\n");
+        assert!(text.contains("first line"));
+        assert!(text.contains("second line"));
+
+        app.open_picker(
+            "Pick one".into(),
+            vec!["alpha".into(), "beta".into()],
+            PickerAction::JumpTo { paths: Vec::new() },
+        );
+        let text = buffer_lines(&render_to_buffer(&app, 80, 20)).join("\n");
+        assert!(text.contains("Pick one"));
+        assert!(text.contains("> 1 alpha"));
+        assert!(text.contains("  2 beta"));
+    }
+
+    #[test]
+    fn parse_args_splits_on_whitespace() {
+        assert_eq!(parse_args("  a  b\tc ").unwrap(), vec!["a", "b", "c"]);
+        assert!(parse_args("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_args_handles_quotes() {
+        assert_eq!(
+            parse_args(r#""my file.txt" 'it''s' """#).unwrap(),
+            vec!["my file.txt", "its", ""]
+        );
+        assert_eq!(
+            parse_args(r#"'a\b' "c\"d""#).unwrap(),
+            vec![r"a\b", r#"c"d"#]
+        );
+        assert!(parse_args(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn parse_args_handles_escapes() {
+        assert_eq!(parse_args(r"my\ file.txt").unwrap(), vec!["my file.txt"]);
+        assert_eq!(parse_args(r"a\\b").unwrap(), vec![r"a\b"]);
+        assert!(parse_args("trailing\\").is_err());
+    }
+
+    #[test]
+    fn single_arg_requires_exactly_one() {
+        assert_eq!(single_arg(r#""my file.txt""#).unwrap(), "my file.txt");
+        assert!(single_arg("my file.txt").is_err());
+        assert!(single_arg("").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_directory_keeps_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let raw = OsStr::from_bytes(b"caf\xe9.txt");
+        let path = dir.path().join(raw);
+        fs::write(&path, b"hello").unwrap();
+
+        let (entries, _) = read_directory(dir.path(), ScanOptions::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.path, path);
+        assert_eq!(entry.file_name(), raw);
+        assert!(entry.name.contains('\u{FFFD}'));
+        assert_ne!(dir.path().join(&entry.name), path);
+        assert_eq!(fs::read(&entry.path).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn frecency_matches_fragments_in_order() {
+        let fragments = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(frecency_matches(
+            "/home/me/src/wayfinder",
+            &fragments(&["src", "way"])
+        ));
+        assert!(!frecency_matches(
+            "/home/me/src/wayfinder",
+            &fragments(&["way", "src"])
+        ));
+        assert!(!frecency_matches(
+            "/home/me/src/wayfinder",
+            &fragments(&["me"])
+        ));
+        assert!(frecency_matches("/home/me/Projects", &fragments(&["PROJ"])));
+        assert!(frecency_matches(
+            "/srv/\u{212a}elvin/x",
+            &fragments(&["\u{212a}", "x"])
+        ));
+    }
+
+    #[test]
+    fn grep_file_skips_special_and_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let regex = Regex::new("needle").unwrap();
+        let text = dir.path().join("notes.txt");
+        fs::write(&text, "hay\n  a needle here\nmore hay\nneedle again\n").unwrap();
+        let matches = grep_file(&text, &regex, 10);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            (matches[0].line, matches[0].text.as_str()),
+            (2, "a needle here")
+        );
+        assert_eq!(grep_file(&text, &regex, 1).len(), 1);
+
+        fs::write(dir.path().join("blob.bin"), b"needle\0").unwrap();
+        assert!(grep_file(&dir.path().join("blob.bin"), &regex, 10).is_empty());
+
+        let fifo = dir.path().join("pipe");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+        assert!(grep_file(&fifo, &regex, 10).is_empty());
+
+        std::os::unix::fs::symlink("/dev/zero", dir.path().join("zero")).unwrap();
+        assert!(grep_file(&dir.path().join("zero"), &regex, 10).is_empty());
+        std::os::unix::fs::symlink(&text, dir.path().join("link.txt")).unwrap();
+        assert_eq!(grep_file(&dir.path().join("link.txt"), &regex, 10).len(), 2);
+    }
+
+    #[test]
+    fn glob_regex_translates_wildcards_and_classes() {
+        let glob = |pattern| glob_regex(pattern).unwrap();
+        assert!(glob("*.rs").is_match("main.rs"));
+        assert!(!glob("*.rs").is_match("src/main.rs"));
+        assert!(glob("**/*.rs").is_match("main.rs"));
+        assert!(glob("**/*.rs").is_match("src/bin/main.rs"));
+        assert!(glob("file?.txt").is_match("file1.txt"));
+        assert!(!glob("file?.txt").is_match("file10.txt"));
+        assert!(glob("[a-c]x").is_match("bx"));
+        assert!(!glob("[!a-c]x").is_match("bx"));
+        assert!(glob("a.b+(c)").is_match("a.b+(c)"));
+        assert!(!glob("a.b").is_match("axb"));
+        assert!(glob_regex("[abc").is_err());
+    }
+
+    #[test]
+    fn frecency_prefers_recent_frequent_dirs() {
+        let root = tempfile::tempdir().unwrap();
+        let (old, busy) = (root.path().join("proj-old"), root.path().join("proj-busy"));
+        fs::create_dir(&old).unwrap();
+        fs::create_dir(&busy).unwrap();
+        let now = 10 * 24 * 60 * 60;
+        let mut db = FrecencyDb::default();
+        for _ in 0..3 {
+            db.visit(&old, 0);
+        }
+        db.visit(&busy, now);
+        db.visit(&root.path().join("gone"), now);
+
+        assert_eq!(db.ranked(now), vec![busy.clone(), old.clone()]);
+        let proj = vec!["proj".to_string()];
+        assert_eq!(db.best_match(&proj, now, root.path()), Some(busy.clone()));
+        assert_eq!(db.best_match(&proj, now, &busy), Some(old));
+        assert!(db.dirty);
+    }
+
+    #[test]
+    fn mark_ignored_follows_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join("build.log"), "").unwrap();
+        fs::write(dir.path().join("main.rs"), "").unwrap();
+
+        let (mut entries, _) = read_directory(dir.path(), ScanOptions::default()).unwrap();
+        let mut caches = Caches::default();
+        mark_ignored(&mut entries, &caches.ignore_rules(dir.path()));
+        let ignored: Vec<&str> = entries
+            .iter()
+            .filter(|entry| entry.ignored)
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(ignored.len(), 2);
+        assert!(ignored.contains(&"target") && ignored.contains(&"build.log"));
+    }
+
+    #[test]
+    fn test_app_uses_injected_config_and_skips_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+        fs::write(dir.path().join("shown"), "").unwrap();
+        let config = Config {
+            show_hidden: false,
+            ..Config::default()
+        };
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+
+        assert!(!app.persist_state);
+        assert!(app.config_watcher.is_none());
+        assert!(
+            app.frecency
+                .dirs
+                .keys()
+                .all(|visited| Path::new(visited).starts_with(dir.path()))
+        );
+        assert_eq!(app.entries.len(), 1);
+        assert_eq!(app.entries[0].name, "shown");
+        assert!(app.save_state().is_ok());
+    }
+
+    #[test]
+    fn since_filter_refreshes_the_preview() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("a-old.txt");
+        fs::write(&old, "stale contents\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60))
+            .unwrap();
+        fs::write(dir.path().join("b-new.txt"), "fresh contents\n").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        assert!(app.preview.body.contains("stale contents"));
+
+        app.run_command("since 1h".into());
+        assert_eq!(app.entries.len(), 1);
+        assert!(app.preview.body.contains("fresh contents"));
+    }
+
+    #[test]
+    fn sort_memory_waits_for_exit_and_stays_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.cycle_sort();
+        assert!(app.state_dirty);
+        app.run_command("sort name".into());
+        assert!(!app.state_dirty);
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("/saved".to_string(), DirOverride::default());
+        for n in 0..SORT_MEMORY_MAX_DIRS as u64 + 2 {
+            let settings = DirOverride {
+                remembered: Some(n),
+                ..DirOverride::default()
+            };
+            overrides.insert(format!("/dir{n}"), settings);
+        }
+        forget_oldest_sorts(&mut overrides);
+        assert_eq!(overrides.len(), SORT_MEMORY_MAX_DIRS + 1);
+        assert!(overrides.contains_key("/saved"));
+        assert!(!overrides.contains_key("/dir0") && !overrides.contains_key("/dir1"));
+    }
+
+    #[test]
+    fn natural_cmp_orders_digit_runs_by_value() {
+        use cmp::Ordering::*;
+
+        assert_eq!(natural_cmp("file2", "file10"), Less);
+        assert_eq!(natural_cmp("file010", "file9"), Greater);
+        assert_eq!(natural_cmp("file007", "file7"), Equal);
+        assert_eq!(natural_cmp("a1b2", "a1b10"), Less);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Greater);
+        assert_eq!(natural_cmp("Report", "report"), Equal);
+        assert_eq!(natural_cmp("alpha", "Beta"), Less);
+        assert_eq!(natural_cmp("img", "img1"), Less);
+        assert_eq!(natural_cmp("12345678901234567890", "9"), Greater);
+
+        let mut chars = "0042rest".chars().peekable();
+        assert_eq!(take_digits(&mut chars), "0042");
+        assert_eq!(chars.collect::<String>(), "rest");
+        let mut chars = "tail".chars().peekable();
+        assert_eq!(take_digits(&mut chars), "");
+    }
+
+    #[test]
+    fn finder_batches_keep_the_selected_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        let base = dir.path().to_path_buf();
+        app.start_fuzzy("Find", Vec::new(), base.clone(), FuzzyAction::Reveal);
+        app.pending_finder = Some(PendingFinder {
+            token: 7,
+            cancel: Arc::new(AtomicBool::new(false)),
+        });
+        let batch = |names: &[&str], done| FsEvent::FinderPaths {
+            token: 7,
+            paths: names.iter().map(|name| base.join(name)).collect(),
+            done,
+        };
+        app.handle_fs_event(batch(&["notes/todo.txt", "src/lib.rs"], false));
+        for ch in "s".chars() {
+            handle_key_event(&mut app, KeyEvent::from(KeyCode::Char(ch))).unwrap();
+        }
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Down)).unwrap();
+        let selected_path = |app: &App| {
+            let InputMode::Fuzzy {
+                buffer,
+                selected,
+                candidates,
+                base,
+                ..
+            } = &app.input_mode
+            else {
+                panic!("finder closed");
+            };
+            let (index, _) = fuzzy_filter(candidates, base, buffer)[*selected].clone();
+            candidates[index].clone()
+        };
+        let before = selected_path(&app);
+
+        app.handle_fs_event(batch(&["s.rs", "a/s", "ss"], true));
+        assert_eq!(selected_path(&app), before);
+        assert!(app.pending_finder.is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_preview_opens_immutable_without_side_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("odd name?#%.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('hi');")
+            .unwrap();
+        drop(conn);
+        assert!(sqlite_preview_uri(&path).ends_with("odd%20name%3F%23%25.db?mode=ro&immutable=1"));
+
+        let preview = preview_sqlite(&path).unwrap();
+        assert!(preview.body.contains("1 table(s)"));
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().flatten().collect();
+        assert_eq!(names.len(), 1);
+    }
+
+    #[test]
+    fn large_directories_ask_before_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..4 {
+            fs::write(dir.path().join(format!("f{n}")), "").unwrap();
+        }
+        let mut config = Config::default();
+        config.scan.large_dir_threshold = 3;
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected a confirmation");
+        };
+        assert!(message.contains("more than 3 entries"));
+        assert!(app.entries.is_empty());
+
+        let mut config = Config::default();
+        config.scan.large_dir_threshold = 4;
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        assert_eq!(app.entries.len(), 4);
+    }
+
+    #[test]
+    fn config_reload_keeps_runtime_toggles() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        let (shown, dirs_first) = (app.show_hidden, app.dirs_first);
+        app.show_hidden = !shown;
+
+        app.apply_config(Config::default());
+        assert_eq!(app.show_hidden, !shown);
+        assert_eq!(app.dirs_first, dirs_first);
+
+        app.apply_config(Config {
+            dirs_first: !dirs_first,
+            ..Config::default()
+        });
+        assert_eq!(app.show_hidden, !shown);
+        assert_eq!(app.dirs_first, !dirs_first);
+    }
+
+    #[test]
+    fn every_listed_command_is_dispatched() {
+        // These touch the user's config file or trash, so they are only checked to be listed.
+        let global = ["config", "restore"];
+        assert!(
+            global
+                .iter()
+                .all(|name| COMMANDS.iter().any(|spec| spec.name == *name))
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        for spec in COMMANDS.iter().filter(|spec| !global.contains(&spec.name)) {
+            app.cancel_overlay();
+            app.run_command(spec.name.into());
+            assert!(
+                !app.status.starts_with("Unknown command"),
+                ":{} is listed but not dispatched",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn macros_stop_at_usage_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config
+            .macros
+            .insert("setup".into(), "mkdir; touch later.txt".into());
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+
+        app.run_command("setup".into());
+        assert!(app.status.contains("setup stopped at 'mkdir'"));
+        assert!(app.error_status.is_some());
+        assert!(!dir.path().join("later.txt").exists());
+    }
+
+    #[test]
+    fn edit_text_inserts_and_moves_at_the_cursor() {
+        let mut buffer = String::from("héllo\nab");
+        let mut cursor = buffer.len();
+        let key = |code| KeyEvent::from(code);
+
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Up));
+        assert_eq!(cursor, "hé".len());
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Char('X')));
+        assert_eq!(buffer, "héXllo\nab");
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Left));
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Backspace));
+        assert_eq!(buffer, "hXllo\nab");
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Delete));
+        assert_eq!(buffer, "hllo\nab");
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::End));
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Enter));
+        assert_eq!(buffer, "hllo\n\nab");
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Down));
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Home));
+        assert_eq!(cursor, "hllo\n\n".len());
+        edit_text(&mut buffer, &mut cursor, key(KeyCode::Down));
+        assert_eq!(cursor, "hllo\n\n".len());
+        assert!(!edit_text(&mut buffer, &mut cursor, key(KeyCode::Tab)));
+    }
+
+    #[test]
+    fn slash_opens_the_fuzzy_finder_and_f_searches() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('/'))).unwrap();
+        assert!(matches!(app.input_mode, InputMode::Fuzzy { .. }));
+        app.cancel_overlay();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('f'))).unwrap();
+        assert!(matches!(app.input_mode, InputMode::Search { .. }));
+        app.cancel_overlay();
+        let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+        handle_key_event(&mut app, ctrl_p).unwrap();
+        assert!(matches!(app.input_mode, InputMode::Palette { .. }));
+    }
+
+    #[test]
+    fn restore_only_matches_recorded_system_trash_items() {
+        let info = |path: &str, deleted_at| TrashInfo {
+            original: PathBuf::from(path),
+            deleted_at,
+            bytes: 0,
+        };
+        let log = SystemTrashLog {
+            items: vec![info("/home/me/report.txt", 1_000)],
+        };
+        assert!(log.contains(&info("/home/me/report.txt", 1_001)));
+        assert!(!log.contains(&info("/home/me/report.txt", 1_010)));
+        assert!(!log.contains(&info("/home/me/other.txt", 1_000)));
+    }
+
+    #[test]
+    fn evict_trash_makes_room_before_adding() {
+        let dir = tempfile::tempdir().unwrap();
+        let add = |id: &str, bytes| {
+            let info = TrashInfo {
+                original: PathBuf::from(format!("/home/me/{id}")),
+                deleted_at: 0,
+                bytes,
+            };
+            fs::write(dir.path().join(id), "x").unwrap();
+            let contents = toml::to_string(&info).unwrap();
+            fs::write(dir.path().join(format!("{id}.toml")), contents).unwrap();
+        };
+        add("001", TRASH_MAX_BYTES - 100);
+        add("002", 50);
+
+        evict_trash(dir.path(), 40).unwrap();
+        assert_eq!(trash_items(dir.path()).unwrap().len(), 2);
+        evict_trash(dir.path(), 60).unwrap();
+        let left = trash_items(dir.path()).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].info.bytes, 50);
+        assert!(!dir.path().join("001").exists());
+    }
+
+    #[test]
+    fn copy_directory_copies_contents_into_the_new_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("nested/a.txt"), "a").unwrap();
+        let dest = dir.path().join("copy");
+
+        copy_directory(&src, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("nested/a.txt")).unwrap(), "a");
+        assert!(!dest.join("src").exists());
+        assert!(copy_directory(&src, &dest).is_err());
+    }
+
+    #[test]
+    fn file_jobs_wait_for_the_running_one() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let copy = |name: &str| Operation::Copy {
+            src: dir.path().join(name),
+            dest: dir.path().join(format!("copy-{name}")),
+        };
+
+        app.run_operations(vec![copy("a.txt")]).unwrap();
+        app.run_operations(vec![copy("b.txt")]).unwrap();
+        assert_eq!(app.waiting_jobs.len(), 1);
+        while app.pending_job.is_some() || app.is_loading {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        assert!(app.waiting_jobs.is_empty());
+        assert!(dir.path().join("copy-a.txt").exists());
+        assert!(dir.path().join("copy-b.txt").exists());
+    }
+
+    #[test]
+    fn canceled_copies_and_extracts_leave_nothing_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("big.bin");
+        fs::write(&src, vec![7u8; 256 * 1024]).unwrap();
+        let canceled = AtomicBool::new(true);
+        assert!(copy_file_cancellable(&src, &dir.path().join("copy.bin"), &canceled).is_err());
+        assert!(!dir.path().join("copy.bin").exists());
+
+        let archive = dir.path().join("pkg.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        for name in ["pkg/a.txt", "pkg/b.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &b"x"[..]).unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let cancel = AtomicBool::new(false);
+        let result = extract_members(
+            &archive,
+            ArchiveKind::Tar,
+            "",
+            &[("pkg".into(), true)],
+            &out,
+            &|extracted| cancel.store(extracted >= 1, Ordering::Relaxed),
+            &cancel,
+        );
+        assert!(result.is_err());
+        assert!(!out.join("pkg").exists());
+    }
+
+    #[test]
+    fn find_results_act_without_touching_marks() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("sub")).unwrap();
+        fs::create_dir(root.path().join("dest")).unwrap();
+        fs::write(root.path().join("note.txt"), "a").unwrap();
+        fs::write(root.path().join("sub/note.md"), "b").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(root.path(), Config::default());
+
+        app.run_command("find note".into());
+        while app.pending_find.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        let key = |app: &mut App, code| handle_key_event(app, KeyEvent::from(code)).unwrap();
+        key(&mut app, KeyCode::Char('a'));
+        key(&mut app, KeyCode::Char('c'));
+        assert!(matches!(app.input_mode, InputMode::Command { .. }));
+        key(&mut app, KeyCode::Esc);
+        assert!(app.marked.is_empty());
+        assert!(app.result_targets.is_none());
+
+        app.run_command("find note".into());
+        while app.pending_find.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        key(&mut app, KeyCode::Char('c'));
+        for ch in "dest".chars() {
+            key(&mut app, KeyCode::Char(ch));
+        }
+        key(&mut app, KeyCode::Enter);
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("copy did not ask for confirmation");
+        };
+        assert!(message.contains("2 result(s) from 2 directories (1 outside"));
+        assert!(app.marked.is_empty());
+    }
+
+    #[test]
+    fn archives_list_and_preview_in_the_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pkg.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        for (name, body) in [("a.txt", &b"alpha"[..]), ("b.txt", &b"beta"[..])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, body).unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let mut pump = |app: &mut App| {
+            while app.pending_archive.is_some() || app.pending_preview.is_some() {
+                let event = runtime.block_on(rx.recv()).unwrap();
+                app.handle_fs_event(event);
+            }
+        };
+
+        app.enter_selection().unwrap();
+        assert!(app.archive.is_none());
+        pump(&mut app);
+        assert_eq!(app.entries.len(), 2);
+        assert!(app.preview.body.contains("alpha"));
+
+        app.selected = 1;
+        app.update_preview();
+        pump(&mut app);
+        assert!(app.preview.body.contains("beta"));
+        app.selected = 0;
+        app.update_preview();
+        assert!(app.pending_preview.is_none());
+        assert!(app.preview.body.contains("alpha"));
+    }
+
+    #[test]
+    fn walkers_follow_hidden_and_gitignore_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::create_dir_all(dir.path().join("src/.cache")).unwrap();
+        fs::write(dir.path().join("src/.gitignore"), "gen.rs\n").unwrap();
+        for name in [
+            "src/main.rs",
+            "src/gen.rs",
+            "src/.cache/x",
+            "run.log",
+            ".env",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let walk = |filter| {
+            let mut found = Vec::new();
+            walk_files(
+                dir.path(),
+                filter,
+                usize::MAX,
+                &AtomicBool::new(false),
+                |path| {
+                    let relative = path.strip_prefix(dir.path()).unwrap();
+                    found.push(relative.to_string_lossy().into_owned());
+                    true
+                },
+            );
+            found.sort();
+            found
+        };
+
+        let everything = walk(WalkFilter {
+            show_hidden: true,
+            skip_ignored: false,
+        });
+        assert!(everything.contains(&".env".to_string()));
+        assert!(everything.contains(&"src/gen.rs".to_string()));
+        let visible = walk(WalkFilter {
+            show_hidden: false,
+            skip_ignored: true,
+        });
+        assert_eq!(visible, ["src/main.rs"]);
+        let tree = build_tree(
+            dir.path(),
+            None,
+            WalkFilter {
+                show_hidden: true,
+                skip_ignored: true,
+            },
+        )
+        .unwrap();
+        assert!(tree.iter().any(|line| line.ends_with(".env")));
+        assert!(
+            !tree
+                .iter()
+                .any(|line| line.ends_with("run.log") || line.ends_with(".git"))
+        );
+    }
+
+    #[test]
+    fn hidden_marks_are_left_out_of_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".env"), "").unwrap();
+        fs::write(dir.path().join("shown"), "").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.show_hidden = true;
+        app.marked.insert(dir.path().join(".env"));
+        app.marked.insert(dir.path().join("shown"));
+        assert_eq!(app.sorted_marks().len(), 2);
+        app.show_hidden = false;
+        assert_eq!(app.sorted_marks(), [dir.path().join("shown")]);
+    }
+
+    #[test]
+    fn operations_skip_marks_the_filter_hides() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("keep.rs"), "").unwrap();
+        fs::write(dir.path().join("other.txt"), "").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.marked.insert(dir.path().join("keep.rs"));
+        app.marked.insert(dir.path().join("other.txt"));
+        app.command_filter("*.rs");
+        assert_eq!(app.sorted_marks(), [dir.path().join("keep.rs")]);
+
+        app.request_delete_confirmation().unwrap();
+        let InputMode::Confirm {
+            action: ConfirmAction::DeleteMarked { paths },
+            ..
+        } = &app.input_mode
+        else {
+            panic!("delete did not ask for confirmation");
+        };
+        assert_eq!(paths, &[dir.path().join("keep.rs")]);
+    }
+
+    #[test]
+    fn recursive_select_walks_in_the_background() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/deep")).unwrap();
+        for name in ["top.rs", "src/a.rs", "src/deep/b.rs", "src/c.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+
+        app.run_command("select **/*.rs".into());
+        assert!(app.marked.is_empty());
+        while app.pending_glob.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        assert_eq!(app.marked.len(), 3);
+        assert!(app.status.contains("Marked 3 entries"));
+        assert!(app.status.contains("2 in subdirectories"));
+    }
+
+    #[test]
+    fn ignore_rules_are_cached_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let gitignore = dir.path().join(".gitignore");
+        fs::write(&gitignore, "*.log\n").unwrap();
+        let mut caches = Caches::default();
+
+        let first = caches.ignore_rules(dir.path());
+        let again = caches.ignore_rules(dir.path());
+        assert!(Arc::ptr_eq(&first.matchers[0], &again.matchers[0]));
+        assert!(first.ignores(&dir.path().join("a.log"), false));
+
+        fs::write(&gitignore, "*.tmp\n").unwrap();
+        let file = fs::File::options().write(true).open(&gitignore).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(60))
+            .unwrap();
+        let changed = caches.ignore_rules(dir.path());
+        assert!(!changed.ignores(&dir.path().join("a.log"), false));
+        assert!(changed.ignores(&dir.path().join("a.tmp"), false));
+    }
+
+    #[test]
+    fn preview_scroll_lands_on_the_line_when_earlier_lines_wrap() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.preview = PreviewPane::new(
+            "Preview",
+            format!("{}\nsecond\nneedle here", "long ".repeat(60)),
+        );
+        app.command_preview_line("3").unwrap();
+
+        let backend = TestBackend::new(40, 12);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| draw_preview(frame, frame.size(), &app))
+            .unwrap();
+        let lines = buffer_lines(terminal.backend().buffer());
+        assert!(lines[1].contains("needle here"), "{lines:?}");
+    }
+
+    /// Compares the serial and the parallel scan on a directory with
+    /// thousands of entries. Run with
+    /// `cargo test --release -- --ignored --nocapture read_directory_benchmark`.
+    #[test]
+    #[ignore]
+    fn read_directory_benchmark() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..5000 {
+            fs::write(dir.path().join(format!("file{index:05}.txt")), "x").unwrap();
+        }
+        let options = ScanOptions::default();
+        for threads in [1, SCAN_MAX_THREADS] {
+            let start = Instant::now();
+            for _ in 0..20 {
+                let (entries, _) = read_directory_with(dir.path(), options, threads).unwrap();
+                assert_eq!(entries.len(), 5000);
+            }
+            println!("{threads} thread(s): {:?} per scan", start.elapsed() / 20);
+        }
+        let (serial, _) = read_directory_with(dir.path(), options, 1).unwrap();
+        let (parallel, _) = read_directory_with(dir.path(), options, SCAN_MAX_THREADS).unwrap();
+        assert!(
+            serial
+                .iter()
+                .map(|entry| &entry.name)
+                .eq(parallel.iter().map(|entry| &entry.name))
+        );
+    }
+
+    #[test]
+    fn unreadable_entries_are_reported_in_the_status() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        assert!(app.error_status.is_none());
+
+        app.is_loading = true;
+        app.pending_token = Some(u64::MAX);
+        app.handle_fs_event(FsEvent::DirectoryLoaded {
+            path: dir.path().to_path_buf(),
+            token: u64::MAX,
+            result: Ok(app.all_entries.clone()),
+            skipped: 2,
+        });
+
+        assert_eq!(app.entries.len(), 1);
+        let error = app.error_status.as_deref().unwrap();
+        assert!(
+            error.starts_with("Skipped 2 unreadable entries in "),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn panic_hook_restores_the_terminal_only_for_the_ui_thread() {
+        let restored = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&restored);
+        install_panic_hook_with(move || flag.store(true, Ordering::SeqCst));
+
+        assert!(thread::spawn(|| panic!("worker")).join().is_err());
+        assert!(!restored.load(Ordering::SeqCst));
+        assert!(panic::catch_unwind(|| panic!("ui")).is_err());
+        assert!(restored.load(Ordering::SeqCst));
+
+        let _ = panic::take_hook();
+    }
+
+    fn finish_job(runtime: &Runtime, app: &mut App, rx: &mut UnboundedReceiver<FsEvent>) {
+        while app.pending_job.is_some() || app.is_loading {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+    }
+
+    fn select_name(app: &mut App, name: &str) {
+        app.selected = app
+            .entries
+            .iter()
+            .position(|entry| entry.name == name)
+            .unwrap();
+    }
+
+    #[test]
+    fn flatten_refuses_collisions_and_leaves_the_tree_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let wrap = dir.path().join("wrap");
+        fs::create_dir(&wrap).unwrap();
+        fs::write(wrap.join("a.txt"), "inner").unwrap();
+        fs::write(wrap.join("b.txt"), "b").unwrap();
+        fs::write(dir.path().join("a.txt"), "outer").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        select_name(&mut app, "wrap");
+
+        let err = app.command_flatten().unwrap_err().to_string();
+
+        assert!(err.contains("1 name(s) already exist"), "{err}");
+        assert!(err.ends_with("\na.txt"), "{err}");
+        assert!(app.pending_job.is_none());
+        assert_eq!(fs::read_to_string(wrap.join("a.txt")).unwrap(), "inner");
+        assert!(wrap.join("b.txt").exists());
+        assert!(!dir.path().join("b.txt").exists());
+    }
+
+    #[test]
+    fn flatten_handles_a_child_named_like_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let outer = dir.path().join("pkg");
+        fs::create_dir_all(outer.join("pkg")).unwrap();
+        fs::write(outer.join("pkg").join("lib.rs"), "lib").unwrap();
+        fs::write(outer.join("README"), "readme").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        select_name(&mut app, "pkg");
+
+        app.command_flatten().unwrap();
+        finish_job(&runtime, &mut app, &mut rx);
+
+        assert_eq!(app.status, "Flattened pkg: moved 2 item(s)");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("pkg").join("lib.rs")).unwrap(),
+            "lib"
+        );
+        assert!(dir.path().join("README").exists());
+        assert!(!dir.path().join(".pkg.flatten").exists());
+    }
+
+    #[test]
+    fn failed_flatten_puts_moved_children_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let wrap = dir.path().join("wrap");
+        fs::create_dir_all(wrap.join("wrap")).unwrap();
+        fs::write(wrap.join("a.txt"), "a").unwrap();
+        fs::write(wrap.join("b.txt"), "b").unwrap();
+        // Appears after the collision check, as if created while the job waited.
+        fs::write(dir.path().join("b.txt"), "late").unwrap();
+        let children = vec!["a.txt".into(), "b.txt".into(), "wrap".into()];
+
+        let err = flatten_directory(
+            &wrap,
+            dir.path(),
+            children,
+            &|_, _| {},
+            &AtomicBool::new(false),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.starts_with("Flattening wrap failed: "), "{err}");
+        assert!(err.contains("b.txt already exists"), "{err}");
+        assert!(wrap.join("a.txt").exists());
+        assert!(wrap.join("wrap").is_dir());
+        assert!(!dir.path().join("a.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+            "late"
+        );
+        assert!(!dir.path().join(".wrap.flatten").exists());
+    }
+
+    #[test]
+    fn cross_device_moves_copy_then_remove_and_honor_cancel() {
+        let Ok(other) = tempfile::tempdir_in("/dev/shm") else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("tree");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        if !is_cross_device(&src, other.path()) {
+            return;
+        }
+
+        let canceled = AtomicBool::new(true);
+        let dest = other.path().join("tree");
+        assert!(move_path_cancellable(&src, &dest, &mut || {}, &canceled).is_err());
+        assert!(src.join("a.txt").exists());
+        assert!(!dest.exists());
+
+        let mut files = 0;
+        let copied =
+            move_path_cancellable(&src, &dest, &mut || files += 1, &AtomicBool::new(false));
+        assert!(copied.unwrap());
+        assert_eq!(files, 1);
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a");
+        assert!(!src.exists());
+    }
+
+    #[test]
+    fn same_device_move_errors_are_not_retried_as_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("tree");
+        fs::create_dir_all(src.join("inner")).unwrap();
+
+        let err = move_path(&src, &src.join("inner").join("tree")).unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("moving "), "{err:#}");
+        assert!(!src.join("inner").join("tree").exists());
+    }
+
+    #[test]
+    fn batch_transfers_ask_first_when_confirm_transfers_is_set() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dest")).unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        let config = Config {
+            confirm_transfers: true,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+        app.marked.insert(dir.path().join("a.txt"));
+        app.marked.insert(dir.path().join("b.txt"));
+
+        app.run_command("copy dest".into());
+
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected a confirmation");
+        };
+        assert!(message.starts_with("Copy 2 item(s) to "), "{message}");
+        assert!(!dir.path().join("dest").join("a.txt").exists());
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('y'))).unwrap();
+        finish_job(&runtime, &mut app, &mut rx);
+        assert!(dir.path().join("dest").join("a.txt").exists());
+        assert!(dir.path().join("dest").join("b.txt").exists());
+
+        fs::write(dir.path().join("dest").join("c.txt"), "c").unwrap();
+        app.register = Some(Register {
+            kind: RegisterKind::Cut,
+            paths: vec![dir.path().join("dest").join("c.txt")],
+        });
+        app.paste_register().unwrap();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Confirm {
+                action: ConfirmAction::Transfer { .. },
+                ..
+            }
+        ));
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('n'))).unwrap();
+        assert!(dir.path().join("dest").join("c.txt").exists());
+        assert!(!dir.path().join("c.txt").exists());
+    }
+
+    #[test]
+    fn cross_device_batch_moves_ask_first() {
+        let Ok(other) = tempfile::tempdir_in("/dev/shm") else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let src = other.path().join("far.txt");
+        fs::write(&src, "far").unwrap();
+        if !is_cross_device(&src, dir.path()) {
+            return;
+        }
+        let config = Config {
+            confirm_cross_device_move: true,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+        app.register = Some(Register {
+            kind: RegisterKind::Cut,
+            paths: vec![src.clone()],
+        });
+
+        app.paste_register().unwrap();
+
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected a confirmation");
+        };
+        assert!(
+            message.ends_with(", 1 across filesystems (slow copy+delete), proceed?"),
+            "{message}"
+        );
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('y'))).unwrap();
+        finish_job(&runtime, &mut app, &mut rx);
+        assert!(!src.exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("far.txt")).unwrap(),
+            "far"
+        );
+    }
+
+    #[test]
+    fn rename_regex_reports_collisions_and_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bad = dir.path().join(OsStr::from_bytes(b"bad\xff.txt"));
+        for path in [
+            dir.path().join("a.txt"),
+            dir.path().join("a.md"),
+            dir.path().join("b1.txt"),
+            dir.path().join("b2.txt"),
+            bad.clone(),
+        ] {
+            fs::write(path, "x").unwrap();
+        }
+        let (_runtime, mut app) = test_app(dir.path());
+        for name in ["a.txt", "b1.txt", "b2.txt"] {
+            app.marked.insert(dir.path().join(name));
+        }
+        app.marked.insert(bad.clone());
+
+        let err = app
+            .command_rename_regex(r#"'\d?\.txt$' .md"#)
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.starts_with("3 name(s) cannot be renamed\n"), "{err}");
+        assert!(err.contains("a.txt: a.md already exists"), "{err}");
+        assert!(err.contains("b2.txt: b.md already exists"), "{err}");
+        assert!(
+            err.contains("bad\u{fffd}.txt: name is not valid UTF-8"),
+            "{err}"
+        );
+        assert!(bad.exists());
+        assert!(dir.path().join("b1.txt").exists());
+        assert!(!dir.path().join("b.md").exists());
+    }
+
+    #[test]
+    fn startup_config_problems_become_a_toast() {
+        let (config, problems) = startup_config(Ok((Config::default(), Vec::new())));
+        assert!(problems.is_none());
+        assert!(!config.read_only);
+
+        let warnings = vec!["unknown column 'colour'".to_string()];
+        let (_, problems) = startup_config(Ok((Config::default(), warnings.clone())));
+        assert_eq!(
+            problems,
+            Some(("Loaded config with 1 warning(s)".to_string(), warnings))
+        );
+
+        let (_, problems) = startup_config(Err(anyhow!("parse config: bad toml")));
+        let (title, lines) = problems.unwrap();
+        assert_eq!(title, "Config not loaded, using defaults");
+        assert_eq!(lines, ["parse config: bad toml"]);
+    }
+
+    #[test]
+    fn ignore_rules_follow_git_init_and_ignored_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target").join("debug");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        let mut caches = Caches::default();
+        assert!(!caches.ignore_rules(&target).in_repo);
+
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let rules = caches.ignore_rules(&target);
+        assert!(rules.in_repo);
+        assert!(rules.ignores(&target.join("app"), false));
+        assert!(!rules.ignores(&dir.path().join("src"), true));
+
+        fs::remove_dir(dir.path().join(".git")).unwrap();
+        assert!(!caches.ignore_rules(&target).in_repo);
+    }
+
+    #[test]
+    fn restore_over_an_occupied_path_trashes_the_occupant() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = dir.path().join("trash");
+        let work = dir.path().join("work");
+        fs::create_dir(&work).unwrap();
+        let path = work.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        trash_into(&path, &trash).unwrap();
+        fs::write(&path, "new").unwrap();
+        let (_runtime, mut app) = test_app(&work);
+        let [deleted] = <[TrashItem; 1]>::try_from(trash_items(&trash).unwrap())
+            .ok()
+            .unwrap();
+
+        app.finish_restore(&deleted.item, &deleted.info_path, &path, &path)
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!deleted.info_path.exists());
+        let [occupant] = <[TrashItem; 1]>::try_from(trash_items(&trash).unwrap())
+            .ok()
+            .unwrap();
+        assert_eq!(occupant.info.original, path);
+        assert_eq!(fs::read_to_string(&occupant.item).unwrap(), "new");
+        assert_eq!(fs::read_dir(&work).unwrap().count(), 1);
+        let message = app.last_action_message.as_deref().unwrap();
+        assert!(
+            message.ends_with("moved the old one to the trash"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn restore_with_a_suffix_keeps_the_occupant() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = dir.path().join("trash");
+        let work = dir.path().join("work");
+        fs::create_dir(&work).unwrap();
+        let path = work.join("a.txt");
+        fs::write(&path, "old").unwrap();
+        trash_into(&path, &trash).unwrap();
+        fs::write(&path, "new").unwrap();
+        let (_runtime, mut app) = test_app(&work);
+        let deleted = trash_items(&trash).unwrap().remove(0);
+
+        let dest = suffixed_path(&path);
+        app.finish_restore(&deleted.item, &deleted.info_path, &path, &dest)
+            .unwrap();
+
+        assert_eq!(dest, work.join("a (1).txt"));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(trash_items(&trash).unwrap().is_empty());
+    }
+
+    #[test]
+    fn counted_delete_near_the_end_stops_at_the_last_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        let (_runtime, mut app) = test_app(dir.path());
+        select_name(&mut app, "c.txt");
+
+        for c in ['3', 'd', 'd'] {
+            handle_key_event(&mut app, KeyEvent::from(KeyCode::Char(c))).unwrap();
+        }
+
+        let InputMode::Confirm {
+            message,
+            action: ConfirmAction::DeleteRange { paths, select },
+        } = &app.input_mode
+        else {
+            panic!("expected a range delete confirmation");
+        };
+        assert_eq!(message, "Delete 2 entries (c.txt, d.txt)?");
+        assert_eq!(paths, &[dir.path().join("c.txt"), dir.path().join("d.txt")]);
+        assert_eq!(select.as_deref(), Some("b.txt"));
+    }
+
+    #[test]
+    fn recursive_chmod_over_marks_counts_changes_and_failures() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("d");
+        fs::create_dir_all(tree.join("e")).unwrap();
+        fs::write(tree.join("x.txt"), "x").unwrap();
+        fs::write(tree.join("e").join("y.txt"), "y").unwrap();
+        fs::write(dir.path().join("f.txt"), "f").unwrap();
+        fs::write(dir.path().join("g.txt"), "g").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("g.txt"), tree.join("link")).unwrap();
+        let mode_of = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        fs::set_permissions(dir.path().join("g.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.marked.insert(tree.clone());
+        app.marked.insert(dir.path().join("f.txt"));
+        app.marked.insert(tree.join("e").join("gone.txt"));
+
+        app.command_chmod("-R 700").unwrap();
+
+        for path in [
+            tree.clone(),
+            tree.join("x.txt"),
+            tree.join("e"),
+            tree.join("e").join("y.txt"),
+            dir.path().join("f.txt"),
+        ] {
+            assert_eq!(mode_of(&path), 0o700, "{}", path.display());
+        }
+        assert_eq!(mode_of(&dir.path().join("g.txt")), 0o644);
+        let message = app.last_action_message.as_deref().unwrap();
+        assert_eq!(message, "chmod 700: 5 changed, 1 failed");
+        let toast = app.toast.as_ref().unwrap();
+        assert!(toast.lines[0].contains("gone.txt"), "{:?}", toast.lines);
+    }
+
+    #[test]
+    fn delete_confirmation_describes_the_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("d");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        fs::write(tree.join("a.txt"), "abc").unwrap();
+        fs::write(tree.join("sub").join("b.txt"), "defg").unwrap();
+        fs::write(dir.path().join("f.txt"), "12345").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        select_name(&mut app, "d");
+        app.request_delete_selected().unwrap();
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected a confirmation");
+        };
+        assert_eq!(
+            message,
+            &format!("Delete directory 'd' (3 items, {})?", format_size(7))
+        );
+
+        app.cancel_overlay();
+        select_name(&mut app, "f.txt");
+        app.request_delete_selected().unwrap();
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected a confirmation");
+        };
+        assert_eq!(message, &format!("Delete 'f.txt' ({})?", format_size(5)));
+    }
+
+    #[test]
+    fn pinned_directories_sort_first_and_are_labelled() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["alpha", "beta", "zulu"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        let (_runtime, mut app) = test_app(dir.path());
+
+        select_name(&mut app, "zulu");
+        app.toggle_pin().unwrap();
+        assert_eq!(app.status, "Pinned zulu");
+        let names: Vec<&str> = app
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["zulu", "alpha", "beta"]);
+        let lines = buffer_lines(&render_to_buffer(&app, 80, 20));
+        assert!(lines[4].contains("zulu") && lines[4].contains("[pinned]"));
+
+        select_name(&mut app, "zulu");
+        app.toggle_pin().unwrap();
+        assert_eq!(app.entries[2].name, "zulu");
+    }
+
+    #[test]
+    fn size_bars_scale_to_the_largest_entry() {
+        assert_eq!(size_bar(100, 100, 8), "█".repeat(8));
+        assert_eq!(size_bar(50, 100, 8), "█".repeat(4));
+        assert_eq!(size_bar(1, 16, 2), "▏");
+        assert_eq!(size_bar(5, 16, 2), "▋");
+        assert_eq!(size_bar(12, 16, 2), "█▌");
+        assert_eq!(size_bar(0, 100, 8), "");
+        assert_eq!(size_bar(10, 0, 8), "");
+    }
+
+    #[test]
+    fn executables_are_flagged_in_the_listing() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        fs::set_permissions(dir.path().join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        let (_runtime, app) = test_app(dir.path());
+
+        let flags: Vec<(&str, bool)> = app
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.is_executable))
+            .collect();
+        assert_eq!(flags, [("notes.txt", false), ("run.sh", true)]);
+        let text = buffer_lines(&render_to_buffer(&app, 80, 20)).join("\n");
+        assert!(text.contains("run.sh*"));
+        assert!(!text.contains("notes.txt*"));
+    }
+
+    #[test]
+    fn hash_reports_the_digest_of_the_selected_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("abc.txt"), "abc").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+
+        for (args, digest) in [
+            (
+                "",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            ("md5", "900150983cd24fb0d6963f7d28e17f72"),
+        ] {
+            app.command_hash(args).unwrap();
+            while app.pending_hash.is_some() {
+                let event = runtime.block_on(rx.recv()).unwrap();
+                app.handle_fs_event(event);
+            }
+            assert!(
+                app.status.ends_with(&format!(" abc.txt: {digest}")),
+                "{}",
+                app.status
+            );
+        }
+        assert!(app.command_hash("crc32").is_err());
+    }
+
+    #[test]
+    fn parse_duration_accepts_unit_suffixes() {
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(
+            parse_duration(" 2h ").unwrap(),
+            Duration::from_secs(2 * 3600)
+        );
+        assert_eq!(
+            parse_duration("3d").unwrap(),
+            Duration::from_secs(3 * 86400)
+        );
+        assert!(parse_duration("h").is_err());
+        let err = parse_duration("2w").unwrap_err().to_string();
+        assert_eq!(err, "Unknown duration unit 'w' (use s, m, h or d)");
+    }
+
+    #[test]
+    fn tree_export_draws_branches_and_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src").join("bin")).unwrap();
+        fs::write(dir.path().join("src").join("main.rs"), "").unwrap();
+        fs::write(dir.path().join("README"), "").unwrap();
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+        let filter = WalkFilter {
+            show_hidden: false,
+            skip_ignored: false,
+        };
+
+        let lines = build_tree(dir.path(), None, filter).unwrap();
+        assert_eq!(
+            lines,
+            [
+                ".",
+                "├── src",
+                "│   ├── bin",
+                "│   └── main.rs",
+                "└── README",
+                "",
+                "2 directories, 2 files",
+            ]
+        );
+
+        let shallow = build_tree(dir.path(), Some(1), filter).unwrap();
+        assert_eq!(shallow[1..3], ["├── src", "└── README"]);
+        assert_eq!(shallow.last().unwrap(), "1 directories, 1 files");
+    }
+
+    #[test]
+    fn diff_of_two_marked_files_shows_a_unified_hunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&old, "one\ntwo\nthree\n").unwrap();
+        fs::write(&new, "one\n2\nthree\n").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.marked.insert(old.clone());
+        app.marked.insert(new.clone());
+
+        app.command_diff("").unwrap();
+
+        assert_eq!(app.status, "Diff a.txt -> b.txt");
+        let InputMode::Viewer { lines, .. } = &app.input_mode else {
+            panic!("expected the diff viewer");
+        };
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            text,
+            [
+                format!("--- {}", old.display()),
+                format!("+++ {}", new.display()),
+                "@@ -1,3 +1,3 @@".into(),
+                " one".into(),
+                "-two".into(),
+                "+2".into(),
+                " three".into(),
+            ]
+        );
+        let same = build_diff(&old, &old).unwrap();
+        assert_eq!(same[2].to_string(), "Files are identical");
+    }
+
+    #[test]
+    fn status_messages_clear_after_their_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            status_timeout: Some(Duration::from_secs(5)),
+            error_status_timeout: None,
+            ..Config::default()
+        };
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+        let start = Instant::now();
+
+        app.status = "Copied".into();
+        app.tick(start);
+        app.tick(start + Duration::from_secs(4));
+        assert_eq!(app.status, "Copied");
+        app.tick(start + Duration::from_secs(5));
+        assert!(app.status.is_empty());
+
+        app.set_error("Copy failed");
+        app.tick(start);
+        app.tick(start + Duration::from_secs(600));
+        assert_eq!(app.status, "Copy failed");
+    }
+
+    #[test]
+    fn shell_commands_substitute_the_quoted_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("it's a file.txt");
+        fs::write(&path, "contents").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        app.command_shell("cat {}").unwrap();
+
+        let Some(ExternalCommand::Run { dir: cwd, command }) = &app.pending_external else {
+            panic!("expected a pending shell command");
+        };
+        assert_eq!(cwd, dir.path());
+        assert_eq!(command, &format!("cat {}", shell_quote(&path)));
+        let output = Command::new("sh").arg("-c").arg(command).output().unwrap();
+        assert_eq!(output.stdout, b"contents");
+
+        app.command_shell("").unwrap();
+        assert!(matches!(
+            app.pending_external,
+            Some(ExternalCommand::Shell { .. })
+        ));
+    }
+
+    #[test]
+    fn mark_summary_counts_sizes_dirs_and_marks_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "abc").unwrap();
+        fs::write(dir.path().join("b.txt"), "defgh").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(other.path().join("c.txt"), "c").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        assert_eq!(app.mark_summary(), None);
+
+        for path in [
+            dir.path().join("a.txt"),
+            dir.path().join("b.txt"),
+            dir.path().join("sub"),
+            other.path().join("c.txt"),
+        ] {
+            app.marked.insert(path);
+        }
+
+        let summary = "4 marked, 8 B + 1 dir(s) + 1 elsewhere";
+        assert_eq!(app.mark_summary().as_deref(), Some(summary));
+        let text = buffer_lines(&render_to_buffer(&app, 100, 20)).join("\n");
+        assert!(text.contains(summary));
+    }
+
+    #[test]
+    fn long_names_are_cut_in_the_middle_or_scrolled() {
+        assert_eq!(truncate_middle("short.txt", 20), "short.txt");
+        assert_eq!(truncate_middle("a-very-long-name.txt", 9), "a-ve….txt");
+        assert_eq!(truncate_middle("日本語のファイル.txt", 9), "日本….txt");
+        assert_eq!(truncate_middle("anything", 0), "");
+
+        assert_eq!(marquee("short", 10, 3), "short");
+        assert_eq!(marquee("abcdefgh", 4, 0), "abcd");
+        assert_eq!(marquee("abcdefgh", 4, 6), "gh  ");
+        assert_eq!(marquee("abcdefgh", 4, 11), "abcd");
+    }
+
+    #[test]
+    fn open_with_lists_matching_apps_and_launches_terminal_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+        let app_entry =
+            |name: &str, command: &str, extensions: &[&str], terminal: bool| OpenWithApp {
+                name: Some(name.into()),
+                command: command.into(),
+                extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+                terminal,
+            };
+        let config = Config {
+            open_with: vec![
+                app_entry("Vim", "vim -R", &[".RS"], true),
+                app_entry("Viewer", "feh", &["png"], false),
+                app_entry("Pager", "less", &["*"], true),
+            ],
+            ..Config::default()
+        };
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+
+        app.command_open_with().unwrap();
+        let InputMode::Picker { title, items, .. } = &app.input_mode else {
+            panic!("expected the open-with picker");
+        };
+        assert_eq!(title, "Open main.rs with");
+        assert_eq!(items, &["Vim (terminal)", "Pager (terminal)"]);
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Enter)).unwrap();
+        let Some(ExternalCommand::OpenWith { command, path }) = &app.pending_external else {
+            panic!("expected a terminal launch");
+        };
+        assert_eq!(command, &["vim", "-R"]);
+        assert_eq!(path, &dir.path().join("main.rs"));
+    }
+
+    #[test]
+    fn favorites_toggle_list_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        select_name(&mut app, "sub");
+        assert!(app.toggle_favorite().is_err());
+        for name in ["a.txt", "b.txt"] {
+            select_name(&mut app, name);
+            app.toggle_favorite().unwrap();
+        }
+        assert_eq!(app.status, "Added b.txt to favorites");
+        let text = buffer_lines(&render_to_buffer(&app, 80, 20)).join("\n");
+        assert!(text.contains("a.txt ★"));
+
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+        app.command_favorites("").unwrap();
+        let InputMode::Picker { items, .. } = &app.input_mode else {
+            panic!("expected the favorites picker");
+        };
+        assert_eq!(
+            items,
+            &[
+                dir.path().join("a.txt").display().to_string(),
+                format!("{} (missing)", dir.path().join("b.txt").display()),
+            ]
+        );
+        app.cancel_overlay();
+        app.command_favorites("prune").unwrap();
+        assert_eq!(app.status, "Pruned 1 missing favorite(s)");
+        assert_eq!(app.state.favorites, [dir.path().join("a.txt")]);
+
+        select_name(&mut app, "a.txt");
+        app.toggle_favorite().unwrap();
+        assert!(app.state.favorites.is_empty());
+    }
+
+    #[test]
+    fn footer_segments_fit_the_width() {
+        let segments = [
+            "Loaded 12 entries".to_string(),
+            "3 marked, 2 KiB".into(),
+            "j/k".into(),
+        ];
+
+        assert_eq!(
+            fit_segments(&segments, 80),
+            "Loaded 12 entries | 3 marked, 2 KiB | j/k"
+        );
+        // A later segment is cut when at least FOOTER_MIN_SEGMENT columns remain.
+        assert_eq!(
+            fit_segments(&segments, 30),
+            "Loaded 12 entries | 3 marked,…"
+        );
+        // Otherwise it is dropped rather than shown as a stub.
+        assert_eq!(fit_segments(&segments, 24), "Loaded 12 entries");
+        // The first segment is always shown, truncated if need be.
+        assert_eq!(fit_segments(&segments, 10), "Loaded 12…");
+        assert_eq!(truncate_end("日本語", 4), "日…");
+    }
+
+    #[test]
+    fn directory_previews_show_sizes_and_counts_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), vec![b'x'; 2048]).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/a"), "").unwrap();
+        fs::write(dir.path().join("sub/b"), "").unwrap();
+
+        let plain = preview_directory(dir.path(), &PreviewOptions::default()).unwrap();
+        let mut rows: Vec<&str> = plain.body.lines().collect();
+        rows.sort();
+        assert_eq!(rows, ["[D] sub", "[F] notes.txt"]);
+
+        let options = PreviewOptions {
+            dir_details: true,
+            ..PreviewOptions::default()
+        };
+        let detailed = preview_directory(dir.path(), &options).unwrap();
+        let mut rows: Vec<&str> = detailed.body.lines().collect();
+        rows.sort();
+        assert_eq!(
+            rows,
+            [
+                "[D] sub  (2 items)".to_string(),
+                format!("[F] notes.txt  ({})", format_size(2048)),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_paths_use_the_git_root_or_the_given_base() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let sub = dir.path().join("src");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("main.rs"), "").unwrap();
+        let (_runtime, mut app) = test_app(&sub);
+        select_name(&mut app, "main.rs");
+
+        let (text, note) = app.relative_path_text("").unwrap();
+        assert_eq!(text, "src/main.rs");
+        assert_eq!(
+            note,
+            format!("relative to git root {}", dir.path().display())
+        );
+
+        let (text, _) = app.relative_path_text(".").unwrap();
+        assert_eq!(text, "main.rs");
+
+        let other = tempfile::tempdir().unwrap();
+        let (text, note) = app
+            .relative_path_text(other.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(text, sub.join("main.rs").display().to_string());
+        assert!(note.starts_with("absolute, not under"));
+    }
+
+    #[test]
+    fn configured_columns_render_in_order_and_unknown_ones_warn() {
+        let (config, warnings) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[columns]\norder = [\"size\", \"bogus\", \"name\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.columns, [ListColumn::Size, ListColumn::Name]);
+        assert_eq!(
+            warnings,
+            ["Unknown column 'bogus' in /cfg/config.toml, skipping"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("data.bin"), vec![0u8; 2048]).unwrap();
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 12));
+        let row = lines.iter().find(|line| line.contains("data.bin")).unwrap();
+        let size = row.find(&format_size(2048)).unwrap();
+        assert!(size < row.find("data.bin").unwrap());
+        assert!(!row.contains("[F]"));
+    }
+
+    #[test]
+    fn read_only_mode_refuses_mutating_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("keep.txt"), "").unwrap();
+        let config = Config {
+            read_only: true,
+            ..Config::default()
+        };
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+
+        app.run_command("mkdir new".into());
+        assert_eq!(app.status, "mkdir: read-only mode");
+        assert!(!dir.path().join("new").exists());
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('d'))).unwrap();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('d'))).unwrap();
+        assert_eq!(app.status, "delete: read-only mode");
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert!(dir.path().join("keep.txt").exists());
+
+        app.run_command("sort name".into());
+        assert!(!app.status.contains("read-only"));
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 12));
+        assert!(lines.iter().any(|line| line.contains("[read-only]")));
+    }
+
+    #[test]
+    fn layout_ratios_are_validated_and_drive_the_split() {
+        let (config, warnings) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[layout]\nlist_width = 30\ndetail_height = 95\n",
+        )
+        .unwrap();
+        assert_eq!(config.layout.list_width, 30);
+        assert_eq!(config.layout.detail_height, 25);
+        assert_eq!(
+            warnings,
+            ["layout.detail_height 95 in /cfg/config.toml is out of range (5-80)"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        let top: Vec<char> = lines[3].chars().collect();
+        assert_eq!(top[0], '┌');
+        assert_eq!(top[30], '┌');
+        assert!(top[1..30].iter().all(|&ch| ch != '┌'));
+    }
 
-    fn command_rename(&mut self, new_name: &str) -> Result<()> {
-        let entry = self
-            .selected_entry()
-            .cloned()
-            .ok_or_else(|| anyhow!("No selection to rename"))?;
-        let new_name = self.validate_new_name(new_name, &entry.name)?;
-        let src = self
-            .selected_path()
-            .ok_or_else(|| anyhow!("No selection to rename"))?;
-        let dest = self.current_dir.join(&new_name);
-        if dest.exists() {
-            return Err(anyhow!("A file named '{}' already exists", new_name));
+    #[test]
+    fn refreshed_changes_are_highlighted_then_fade() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        assert!(
+            app.entries
+                .iter()
+                .all(|entry| app.change_highlight(entry).is_none())
+        );
+
+        fs::write(dir.path().join("a.txt"), "grown").unwrap();
+        fs::write(dir.path().join("c.txt"), "c").unwrap();
+        app.refresh_async(true).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        let flashing: Vec<&str> = app
+            .entries
+            .iter()
+            .filter(|entry| app.change_highlight(entry).is_some())
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(flashing, ["a.txt", "c.txt"]);
+
+        let faded = Instant::now() - CHANGE_HIGHLIGHT;
+        for at in app.changed_at.values_mut() {
+            *at = faded;
         }
-        fs::rename(&src, &dest)
-            .with_context(|| format!("renaming {} -> {}", entry.name, new_name))?;
-        self.refresh_with_message(true, format!("Renamed {} -> {}", entry.name, new_name))?;
-        Ok(())
+        assert!(
+            app.entries
+                .iter()
+                .all(|entry| app.change_highlight(entry).is_none())
+        );
     }
 
-    fn request_delete_confirmation(&mut self) -> Result<()> {
-        let entry = self
-            .selected_entry()
-            .cloned()
-            .ok_or_else(|| anyhow!("No selection to delete"))?;
-        let path = self
-            .selected_path()
-            .ok_or_else(|| anyhow!("No selection to delete"))?;
-        let message = format!("Delete '{}'?", entry.name);
-        self.input_mode = InputMode::Confirm {
-            message,
-            action: ConfirmAction::Delete { entry, path },
+    #[test]
+    fn new_creates_the_file_then_queues_the_editor() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("taken.txt"), "keep").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        app.run_command("new notes.md".into());
+        assert!(dir.path().join("notes.md").is_file());
+        assert_eq!(app.pending_select.as_deref(), Some("notes.md"));
+        let Some(ExternalCommand::Edit { paths, name }) = &app.pending_external else {
+            panic!("expected a pending editor launch");
         };
-        self.status = "Confirm delete with y/n".into();
-        Ok(())
+        assert_eq!(paths, &[dir.path().join("notes.md")]);
+        assert_eq!(name, "notes.md");
+
+        app.pending_external = None;
+        app.run_command("new taken.txt".into());
+        assert!(app.status.starts_with("new failed"));
+        assert!(app.pending_external.is_none());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("taken.txt")).unwrap(),
+            "keep"
+        );
     }
 
-    fn command_delete(&mut self, entry: FileEntry, path: PathBuf) -> Result<()> {
-        let entry = self
-            .entries
-            .iter()
-            .find(|e| e.name == entry.name)
-            .cloned()
-            .unwrap_or(entry);
-        if entry.is_dir {
-            fs::remove_dir_all(&path)
-                .with_context(|| format!("removing directory {}", entry.name))?;
-        } else {
-            fs::remove_file(&path).with_context(|| format!("removing file {}", entry.name))?;
+    #[test]
+    fn long_listings_get_a_scrollbar_that_follows_the_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..3 {
+            fs::write(dir.path().join(format!("file{i:02}")), "").unwrap();
         }
-        self.refresh_with_message(true, format!("Deleted {}", entry.name))?;
-        Ok(())
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let thumb_rows = |app: &App| -> Vec<usize> {
+            buffer_lines(&render_to_buffer(app, 100, 20))
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.chars().nth(49) == Some('█'))
+                .map(|(row, _)| row)
+                .collect()
+        };
+        assert!(thumb_rows(&app).is_empty());
+
+        for i in 3..60 {
+            fs::write(dir.path().join(format!("file{i:02}")), "").unwrap();
+        }
+        app.refresh_async(true).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        let top = thumb_rows(&app);
+        assert!(!top.is_empty());
+        app.selected = app.entries.len() - 1;
+        let bottom = thumb_rows(&app);
+        assert!(bottom[0] > top[top.len() - 1]);
     }
 
-    fn command_mkdir(&mut self, name: &str) -> Result<()> {
-        let name = self.validate_new_name(name, "")?;
-        let path = self.current_dir.join(&name);
-        fs::create_dir(&path).with_context(|| format!("creating directory {}", name))?;
-        self.refresh_with_message(false, format!("Created directory {}", name))?;
-        Ok(())
+    #[test]
+    fn dir_overrides_apply_on_load_and_can_be_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("downloads");
+        fs::create_dir(&sub).unwrap();
+        let contents = format!(
+            "[dir_overrides.\"{}\"]\nsort = \"size\"\nreverse = true\nsince = \"1h\"\n\n[dir_overrides.\"/elsewhere\"]\nsort = \"bogus\"\n",
+            sub.display()
+        );
+        let (config, warnings) = parse_config(Path::new("/cfg/config.toml"), &contents).unwrap();
+        assert_eq!(
+            warnings,
+            ["Unknown sort 'bogus' for /elsewhere in /cfg/config.toml, skipping"]
+        );
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+        assert!(app.sort.key == SortKey::Name && app.modified_within.is_none());
+
+        app.navigate_to(sub.clone(), None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert!(app.sort.key == SortKey::Size && app.sort.reverse);
+        assert_eq!(app.modified_within.as_ref().unwrap().0, "1h");
+
+        app.navigate_to(dir.path().to_path_buf(), None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert!(app.sort.key == SortKey::Name && !app.sort.reverse);
+        assert!(app.modified_within.is_none());
+
+        app.run_command("sort modified".into());
+        app.run_command("sort save".into());
+        app.navigate_to(sub, None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        app.navigate_to(dir.path().to_path_buf(), None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert!(app.sort.key == SortKey::Modified);
     }
 
-    fn command_touch(&mut self, name: &str) -> Result<()> {
-        let name = self.validate_new_name(name, "")?;
-        let path = self.current_dir.join(&name);
-        fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&path)
-            .with_context(|| format!("creating file {}", name))?;
-        self.refresh_with_message(false, format!("Touched {}", name))?;
-        Ok(())
+    #[test]
+    fn pline_scrolls_the_preview_and_clamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let text: String = (1..=200).map(|n| format!("row-{n:03}\n")).collect();
+        fs::write(dir.path().join("long.txt"), text).unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        let total = app.preview.body.lines().count();
+        app.run_command("pline 40".into());
+        assert_eq!(app.preview_scroll, 39);
+        assert_eq!(app.status, format!("Preview at line 40 of {total}"));
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 30));
+        assert!(lines.iter().any(|line| line.contains("row-040")));
+        assert!(!lines.iter().any(|line| line.contains("row-039")));
+
+        app.run_command("pline 5000".into());
+        assert_eq!(app.preview_scroll, total - 1);
+
+        app.run_command("pline top".into());
+        assert_eq!(app.status, "pline failed: Usage: :pline <line number>");
+        assert_eq!(app.preview_scroll, total - 1);
     }
 
-    fn command_edit(&mut self) -> Result<()> {
-        let entry = self
-            .selected_entry()
-            .cloned()
-            .ok_or_else(|| anyhow!("No selection to edit"))?;
-        if entry.is_dir {
-            return Err(anyhow!("Cannot edit a directory"));
-        }
-        let path = self
-            .selected_path()
-            .ok_or_else(|| anyhow!("No selection to edit"))?;
-        self.pending_external = Some(ExternalCommand::Edit {
-            path,
-            name: entry.name.clone(),
-        });
-        self.status = format!("Launching editor for {}", entry.name);
-        Ok(())
+    #[test]
+    fn directory_indicators_are_optional_and_flag_unreadable_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("empty")).unwrap();
+        fs::create_dir(dir.path().join("full")).unwrap();
+        fs::write(dir.path().join("full/a"), "").unwrap();
+        assert!(probe_directory(&dir.path().join("empty")) == Some(DirStatus::Empty));
+        assert!(probe_directory(&dir.path().join("full")).is_none());
+        assert!(probe_directory(&dir.path().join("gone")) == Some(DirStatus::Unreadable));
+
+        let (_runtime, app) = test_app(dir.path());
+        assert!(app.entries.iter().all(|entry| entry.dir_status.is_none()));
+
+        let mut config = Config::default();
+        config.scan.dir_indicators = true;
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+        select_name(&mut app, "full");
+        app.entries[app.selected].dir_status = Some(DirStatus::Unreadable);
+        let lines = buffer_lines(&render_to_buffer(&app, 80, 20));
+        assert!(lines.iter().any(|line| line.contains("[D] empty (empty)")));
+        assert!(lines.iter().any(|line| line.contains("[D] full 🔒")));
     }
 
-    fn command_shell(&mut self) -> Result<()> {
-        let dir = self.current_dir.clone();
-        self.pending_external = Some(ExternalCommand::Shell { dir: dir.clone() });
-        self.status = format!("Launching shell in {}", dir.display());
-        Ok(())
+    #[test]
+    fn paste_writes_clipboard_text_and_copies_clipboard_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+
+        app.paste_text("", "snippet").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        app.paste_text("", "again").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("clipboard.txt")).unwrap(),
+            "snippet"
+        );
+        let second = dir.path().join("clipboard (1).txt");
+        assert_eq!(fs::read_to_string(second).unwrap(), "again");
+        assert_eq!(app.selected_entry().unwrap().name, "clipboard (1).txt");
+
+        app.paste_text("named.md", "# title").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.status, "Pasted 7 B into named.md");
+        assert!(app.paste_text("named.md", "clash").is_err());
+        assert!(app.paste_text("other.md", "").is_err());
+
+        let source = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("named.md"), "from elsewhere").unwrap();
+        fs::create_dir(source.path().join("folder")).unwrap();
+        fs::write(source.path().join("folder/inner"), "x").unwrap();
+        app.paste_files(&[source.path().join("named.md"), source.path().join("folder")])
+            .unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("named.md")).unwrap(),
+            "# title"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("named (1).md")).unwrap(),
+            "from elsewhere"
+        );
+        assert!(dir.path().join("folder/inner").is_file());
     }
 
-    fn command_cd(&mut self, target: &str) -> Result<()> {
-        let target = target.trim();
-        if target.is_empty() {
-            return Err(anyhow!("Usage: :cd <path>"));
+    #[test]
+    fn emptying_a_directory_moves_up_only_when_configured() {
+        for auto_parent in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let sub = dir.path().join("sub");
+            fs::create_dir(&sub).unwrap();
+            fs::write(sub.join("last.txt"), "").unwrap();
+            let config = Config {
+                auto_parent_on_empty: auto_parent,
+                ..Config::default()
+            };
+            let (runtime, mut app, mut rx) = test_app_with(&sub, config);
+
+            app.run_operations(vec![Operation::Remove {
+                path: sub.join("last.txt"),
+            }])
+            .unwrap();
+            finish_job(&runtime, &mut app, &mut rx);
+
+            if auto_parent {
+                assert_eq!(app.current_dir, dir.path());
+                assert_eq!(app.selected_entry().unwrap().name, "sub");
+                assert_eq!(app.status, "sub is empty, moved up");
+            } else {
+                assert_eq!(app.current_dir, sub);
+                assert!(app.entries.is_empty());
+            }
         }
-        let path = Path::new(target);
-        let mut resolved = if path.is_absolute() {
-            path.to_path_buf()
-        } else {
-            self.current_dir.join(path)
+    }
+
+    #[test]
+    fn yank_parts_cover_name_stem_and_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("report.final.pdf"), "").unwrap();
+        fs::write(dir.path().join("Makefile"), "").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        select_name(&mut app, "report.final.pdf");
+        assert_eq!(app.selection_part_text("name").unwrap(), "report.final.pdf");
+        assert_eq!(app.selection_part_text("stem").unwrap(), "report.final");
+        assert_eq!(
+            app.selection_part_text("path").unwrap(),
+            dir.path().join("report.final.pdf").display().to_string()
+        );
+
+        select_name(&mut app, "Makefile");
+        assert_eq!(app.selection_part_text("stem").unwrap(), "Makefile");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mount_info_picks_the_longest_mount_and_shows_in_the_header() {
+        assert_eq!(unescape_mount_field("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(unescape_mount_field("/odd\\x"), "/odd\\x");
+        let proc = mount_info(Path::new("/proc/self")).unwrap();
+        assert_eq!(proc.fs_type, "proc");
+        assert_eq!(proc.mount_point, Path::new("/proc"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let expected = mount_info(dir.path()).unwrap();
+        let (_runtime, app) = test_app(dir.path());
+        let lines = buffer_lines(&render_to_buffer(&app, 120, 12));
+        assert!(!lines[1].contains(&format!("[{} on", expected.fs_type)));
+
+        let config = Config {
+            show_mount_info: true,
+            ..Config::default()
         };
-        resolved = fs::canonicalize(&resolved)
-            .with_context(|| format!("resolving directory {}", target))?;
-        if !resolved.is_dir() {
-            return Err(anyhow!("{} is not a directory", resolved.display()));
-        }
-        self.current_dir = resolved;
-        self.reset_search_state();
-        self.refresh_with_message(true, "Changed directory")?;
-        Ok(())
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let lines = buffer_lines(&render_to_buffer(&app, 120, 12));
+        let label = format!(
+            "[{} on {}]",
+            expected.fs_type,
+            expected.mount_point.display()
+        );
+        assert!(lines[1].contains(&label), "{}", lines[1]);
     }
 
-    fn execute_confirm_action(&mut self, action: ConfirmAction) -> Result<()> {
-        match action {
-            ConfirmAction::Delete { entry, path } => self.command_delete(entry, path),
+    #[test]
+    fn extension_sort_groups_by_lowercased_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.TXT", "a.txt", "c.rs", "README", "z.Rs", ".env"] {
+            fs::write(dir.path().join(name), "").unwrap();
         }
+        fs::create_dir(dir.path().join("zdir.d")).unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        app.show_hidden = true;
+
+        app.run_command("sort ext".into());
+        let names: Vec<&str> = app
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["zdir.d", ".env", "README", "c.rs", "z.Rs", "a.txt", "b.TXT"]
+        );
     }
 
-    fn update_preview(&mut self) {
-        if self.is_loading {
-            self.preview = PreviewPane::loading();
-            return;
-        }
-        if self.entries.is_empty() {
-            self.preview = PreviewPane::empty();
-            return;
+    #[test]
+    fn configured_extensions_skip_the_preview() {
+        let (config, _) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "preview_skip_extensions = [\".LOG\", \"bin\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.preview.skip_extensions, ["log", "bin"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.Log"), "secret line\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "visible line\n").unwrap();
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+
+        select_name(&mut app, "app.Log");
+        app.update_preview();
+        assert!(
+            app.preview
+                .body
+                .ends_with("Preview disabled for .log files")
+        );
+        assert!(!app.preview.body.contains("secret line"));
+
+        select_name(&mut app, "notes.txt");
+        app.update_preview();
+        assert!(app.preview.body.contains("visible line"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_refuse_to_remove_the_directory_being_viewed() {
+        let root = tempfile::tempdir().unwrap();
+        let current = root.path().join("work");
+        fs::create_dir(&current).unwrap();
+        std::os::unix::fs::symlink(root.path(), current.join("up")).unwrap();
+        let (_runtime, mut app) = test_app(&current);
+        select_name(&mut app, "up");
+
+        app.run_command("delete".into());
+        assert!(app.status.starts_with("Delete failed: "), "{}", app.status);
+        assert!(app.status.contains("an ancestor"));
+        assert!(matches!(app.input_mode, InputMode::Normal));
+
+        app.run_command("delete!".into());
+        assert!(app.status.starts_with("Delete failed: "));
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert!(current.join("up").exists());
+    }
+
+    #[test]
+    fn time_format_is_validated_and_used_for_absolute_times() {
+        let (config, warnings) =
+            parse_config(Path::new("/cfg/config.toml"), "time_format = \"%Q\"\n").unwrap();
+        assert!(config.time_format.is_none());
+        assert_eq!(
+            warnings,
+            [format!(
+                "Invalid time_format '%Q' in /cfg/config.toml, using {DEFAULT_TIME_FORMAT}"
+            )]
+        );
+
+        let (config, warnings) =
+            parse_config(Path::new("/cfg/config.toml"), "time_format = \"year %Y\"\n").unwrap();
+        assert!(warnings.is_empty());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.txt");
+        fs::write(&path, "").unwrap();
+        let mid_2020 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_593_561_600);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mid_2020)
+            .unwrap();
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+        let entry = app.entries[0].clone();
+        assert!(
+            entry
+                .describe(app.absolute_time_format())
+                .contains("Modified: year 2020")
+        );
+
+        app.toggle_time_display();
+        assert!(app.absolute_time_format().is_none());
+        assert!(entry.describe(app.absolute_time_format()).ends_with(" ago"));
+    }
+
+    #[test]
+    fn count_prefixed_percent_jumps_through_the_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..10 {
+            fs::write(dir.path().join(format!("f{i}")), "").unwrap();
         }
-        if let Some(entry) = self.selected_entry().cloned() {
-            let path = self.current_dir.join(&entry.name);
-            match build_preview(&entry, &path) {
-                Ok(preview) => self.preview = preview,
-                Err(err) => self.preview = PreviewPane::error(format!("Preview error: {err:#}")),
+        let (_runtime, mut app) = test_app(dir.path());
+        let press = |app: &mut App, keys: &str| {
+            for ch in keys.chars() {
+                handle_key_event(app, KeyEvent::from(KeyCode::Char(ch))).unwrap();
             }
-        } else {
-            self.preview = PreviewPane::empty();
-        }
+        };
+
+        press(&mut app, "50%");
+        assert_eq!(app.selected, 4);
+        assert!(app.pending_count.is_none());
+        press(&mut app, "250%");
+        assert_eq!(app.selected, 9);
+        press(&mut app, "1%");
+        assert_eq!(app.selected, 0);
+
+        press(&mut app, "%");
+        assert_eq!(app.status, "Prefix % with a percentage, e.g. 50%");
+        assert_eq!(app.selected, 0);
     }
 
-    fn compute_destination(&self, target: &str, entry_name: &str) -> Result<PathBuf> {
-        let trimmed = target.trim();
-        if trimmed.is_empty() {
-            return Err(anyhow!("Destination path required"));
+    #[test]
+    fn bulk_results_toast_expires_but_errors_wait_for_a_key() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "").unwrap();
+        fs::write(dir.path().join("b"), "").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+
+        app.run_operations(vec![
+            Operation::Remove {
+                path: dir.path().join("a"),
+            },
+            Operation::Remove {
+                path: dir.path().join("b"),
+            },
+        ])
+        .unwrap();
+        finish_job(&runtime, &mut app, &mut rx);
+        let toast = app.toast.as_ref().unwrap();
+        assert!(!toast.is_error);
+        assert_eq!(toast.lines.len(), 2);
+        assert!(toast.lines.iter().all(|line| line.starts_with("ok  ")));
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        assert!(lines.iter().any(|line| line.contains(&toast.title)));
+        app.tick(Instant::now() + TOAST_DURATION);
+        assert!(app.toast.is_none());
+
+        app.set_error("Copy failed\nfirst detail\nsecond detail");
+        assert_eq!(app.status, "Copy failed");
+        assert_eq!(
+            app.toast.as_ref().unwrap().lines,
+            ["first detail", "second detail"]
+        );
+        app.tick(Instant::now() + TOAST_DURATION * 10);
+        assert!(app.toast.is_some());
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('j'))).unwrap();
+        assert!(app.toast.is_none());
+    }
+
+    #[test]
+    fn locate_lists_exact_name_matches_and_jumps_to_one() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["a", "b/c", ".hidden", "d"] {
+            fs::create_dir_all(dir.path().join(path)).unwrap();
         }
-        let mut dest = PathBuf::from(trimmed);
-        if dest.is_relative() {
-            dest = self.current_dir.join(dest);
+        for path in [
+            "b/c/target.txt",
+            "a/target.txt",
+            ".hidden/target.txt",
+            "d/target.txt.bak",
+        ] {
+            fs::write(dir.path().join(path), "").unwrap();
         }
-        let hint_dir = trimmed.ends_with('/') || trimmed.ends_with('\\');
-        if hint_dir || dest.is_dir() {
-            dest.push(entry_name);
+        let filter = WalkFilter {
+            show_hidden: false,
+            skip_ignored: false,
+        };
+        assert_eq!(
+            locate_paths(dir.path(), "target.txt", 1, filter),
+            [dir.path().join("a/target.txt")]
+        );
+
+        let config = Config {
+            show_hidden: false,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+        app.run_command("locate target.txt".into());
+        while app.pending_locate.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
         }
-        Ok(dest)
+        assert_eq!(app.status, "Found 2 'target.txt'");
+        let InputMode::Picker { items, .. } = &app.input_mode else {
+            panic!("expected the locate picker");
+        };
+        assert_eq!(items, &["a/target.txt", "b/c/target.txt"]);
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Down)).unwrap();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Enter)).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path().join("b/c"));
+        assert_eq!(app.selected_entry().unwrap().name, "target.txt");
+
+        app.run_command("locate missing".into());
+        while app.pending_locate.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        assert_eq!(app.status, "No file named 'missing'");
     }
 
-    fn validate_new_name(&self, input: &str, current: &str) -> Result<String> {
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            return Err(anyhow!("Name cannot be empty"));
+    #[test]
+    fn single_child_chains_collapse_both_ways() {
+        let dir = tempfile::tempdir().unwrap();
+        let leaf = dir.path().join("com/example/app");
+        fs::create_dir_all(&leaf).unwrap();
+        fs::write(leaf.join("Main.java"), "").unwrap();
+        fs::write(leaf.join("Util.java"), "").unwrap();
+        fs::write(dir.path().join("README"), "").unwrap();
+        let config = Config {
+            collapse_single_child: true,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+
+        select_name(&mut app, "com");
+        app.enter_selection().unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, leaf);
+        assert_eq!(app.entries.len(), 2);
+
+        app.open_parent().unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path());
+        assert_eq!(app.selected_entry().unwrap().name, "com");
+
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        select_name(&mut app, "com");
+        app.enter_selection().unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path().join("com"));
+    }
+
+    #[test]
+    fn base16_theme_files_map_onto_the_ui_or_warn() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut scheme = String::from("scheme: \"Test\"\n");
+        for slot in 0..16 {
+            scheme.push_str(&format!(
+                "base0{slot:X}: \"{:02x}0000\" # slot\n",
+                slot * 16
+            ));
         }
-        if !current.is_empty() && trimmed == current {
-            return Err(anyhow!("Name is unchanged"));
+        fs::write(dir.path().join("good.yaml"), &scheme).unwrap();
+        fs::write(
+            dir.path().join("short.yaml"),
+            scheme.replace("base08: \"800000\" # slot\n", ""),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("bad.yaml"),
+            scheme.replace("base08: \"800000\"", "base08: \"zz\""),
+        )
+        .unwrap();
+        let config_path = dir.path().join("config.toml");
+
+        let (config, warnings) =
+            parse_config(&config_path, "theme_file = \"good.yaml\"\n").unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(config.theme.error, Color::Rgb(0x80, 0, 0));
+        assert_eq!(config.theme.selection_bg, Color::Rgb(0xb0, 0, 0));
+        assert_eq!(config.theme.footer, Color::Rgb(0x40, 0, 0));
+
+        for (file, problem) in [
+            ("short.yaml", "missing base08"),
+            ("bad.yaml", "invalid color 'zz'"),
+        ] {
+            let (config, warnings) =
+                parse_config(&config_path, &format!("theme_file = \"{file}\"\n")).unwrap();
+            assert_eq!(config.theme.error, Theme::default().error);
+            assert_eq!(warnings.len(), 1);
+            assert!(warnings[0].contains(problem), "{}", warnings[0]);
+            assert!(warnings[0].ends_with("using default colors"));
         }
-        if trimmed == "." || trimmed == ".." {
-            return Err(anyhow!("Invalid name '{}'", trimmed));
+    }
+
+    #[test]
+    fn ansi_previews_are_styled_or_shown_escaped() {
+        let (plain, line) =
+            parse_ansi_line("\x1b]0;title\x07\x1b[1;31mred\x1b[0m plain \x1b[38;2;1;2;3mrgb\x1b[K");
+        assert_eq!(plain, "red plain rgb");
+        let styles: Vec<(&str, Style)> = line
+            .spans
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                (
+                    "red",
+                    Style::default()
+                        .fg(Color::Indexed(1))
+                        .add_modifier(Modifier::BOLD)
+                ),
+                (" plain ", Style::default()),
+                ("rgb", Style::default().fg(Color::Rgb(1, 2, 3))),
+            ]
+        );
+
+        let text = b"\x1b[32mok\x1b[0m\nsecond";
+        let styled = text_preview(
+            text,
+            &PreviewOptions {
+                ansi: true,
+                ..PreviewOptions::default()
+            },
+        );
+        assert_eq!(styled.body, "ok\nsecond");
+        assert_eq!(styled.styled.as_ref().unwrap().len(), 2);
+
+        let raw = text_preview(text, &PreviewOptions::default());
+        assert_eq!(raw.body, "^[[32mok^[[0m\nsecond");
+        assert!(raw.styled.is_none());
+    }
+
+    #[test]
+    fn hard_refresh_drops_cached_previews_but_plain_refresh_keeps_them() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "real contents\n").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let entry = app.entries[0].clone();
+        app.caches
+            .store_preview(&entry, PreviewPane::new("Preview", "stale cached preview"));
+        app.caches.mounts.insert(dir.path().to_path_buf(), None);
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('r'))).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.preview.body, "stale cached preview");
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('R'))).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.status, "Cleared caches and reloaded");
+        assert!(app.preview.body.contains("real contents"));
+        assert!(app.caches.mounts.is_empty());
+    }
+
+    #[test]
+    fn files_over_warn_size_use_the_warning_color() {
+        assert_eq!(parse_size("1.5KiB").unwrap(), 1536);
+        assert_eq!(parse_size("2MB").unwrap(), 2_000_000);
+        assert!(parse_size("1XB").is_err());
+        let (config, warnings) =
+            parse_config(Path::new("/cfg/config.toml"), "warn_size = \"1KiB\"\n").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(config.warn_size, Some(1024));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("adir")).unwrap();
+        fs::write(dir.path().join("big.bin"), vec![0u8; 1024]).unwrap();
+        fs::write(dir.path().join("small.bin"), vec![0u8; 1023]).unwrap();
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let buffer = render_to_buffer(&app, 100, 12);
+        let lines = buffer_lines(&buffer);
+        let color_of = |name: &str| {
+            let row = lines.iter().position(|line| line.contains(name)).unwrap();
+            let col = lines[row][..lines[row].find(name).unwrap()].chars().count();
+            buffer.get(col as u16, row as u16).fg
+        };
+        assert_eq!(color_of("big.bin"), app.theme.warning);
+        assert_ne!(color_of("small.bin"), app.theme.warning);
+    }
+
+    #[test]
+    fn config_changes_are_debounced_and_applied_live() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), Config::default());
+
+        let before = Instant::now();
+        app.handle_fs_event(FsEvent::ConfigChanged);
+        let due = app.config_reload_at.unwrap();
+        assert!(due >= before + CONFIG_RELOAD_DEBOUNCE);
+        app.handle_fs_event(FsEvent::ConfigChanged);
+        assert!(app.config_reload_at.unwrap() >= due);
+        app.tick(before);
+        assert!(app.config_reload_at.is_some());
+
+        // A runtime toggle survives a reload that leaves that setting alone.
+        app.toggle_hidden();
+        assert!(!app.show_hidden);
+        let (config, _) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "warn_size = \"1KiB\"\n[command_aliases]\nrm = \"delete\"\n",
+        )
+        .unwrap();
+        app.apply_config(config);
+        assert!(!app.show_hidden);
+        assert_eq!(app.warn_size, Some(1024));
+        assert_eq!(app.resolve_command_alias("rm"), "delete");
+
+        let (config, _) =
+            parse_config(Path::new("/cfg/config.toml"), "show_hidden = false\n").unwrap();
+        app.apply_config(config);
+        let (config, _) =
+            parse_config(Path::new("/cfg/config.toml"), "show_hidden = true\n").unwrap();
+        app.apply_config(config);
+        assert!(app.show_hidden);
+        assert!(parse_config(Path::new("/cfg/config.toml"), "show_hidden = [").is_err());
+    }
+
+    #[test]
+    fn grouped_view_sections_entries_under_headers() {
+        let (config, _) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[groups]\ncode = [\".nix\"]\n",
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        for name in ["a.nix", "b.png", "c.bin", "d.md", "e.rs"] {
+            fs::write(dir.path().join(name), "").unwrap();
         }
-        if trimmed.chars().any(|ch| ch == '/' || ch == '\\') {
-            return Err(anyhow!("Name cannot contain path separators"));
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+        app.run_command("group".into());
+        assert_eq!(app.status, "Grouping entries by type");
+
+        let names: Vec<&str> = app
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["src", "b.png", "d.md", "a.nix", "e.rs", "c.bin"]);
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        let rows: Vec<String> = lines[4..15]
+            .iter()
+            .map(|line| line.chars().take(50).collect())
+            .collect();
+        let rows: Vec<&str> = rows
+            .iter()
+            .map(|row| row.trim_matches(['│', ' ']))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "── Directories (1)",
+                "> [D] src",
+                "── Images (1)",
+                "[F] b.png",
+                "── Documents (1)",
+                "[F] d.md",
+                "── Code (2)",
+                "[F] a.nix",
+                "[F] e.rs",
+                "── Other (1)",
+                "[F] c.bin",
+            ]
+        );
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('j'))).unwrap();
+        assert_eq!(app.selected_entry().unwrap().name, "b.png");
+    }
+
+    #[test]
+    fn tab_moves_focus_and_j_scrolls_the_focused_pane() {
+        let dir = tempfile::tempdir().unwrap();
+        let text: String = (1..=30).map(|n| format!("line {n}\n")).collect();
+        fs::write(dir.path().join("a.txt"), text).unwrap();
+        fs::write(dir.path().join("b.txt"), "").unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+        let key =
+            |app: &mut App, code: KeyCode| handle_key_event(app, KeyEvent::from(code)).unwrap();
+
+        key(&mut app, KeyCode::Tab);
+        key(&mut app, KeyCode::Tab);
+        assert_eq!(app.status, "Focus: preview");
+        for ch in ['5', 'j'] {
+            key(&mut app, KeyCode::Char(ch));
         }
-        Ok(trimmed.to_string())
+        assert_eq!(app.preview_scroll, 5);
+        assert_eq!(app.selected, 0);
+        let buffer = render_to_buffer(&app, 100, 20);
+        let lines = buffer_lines(&buffer);
+        let row = lines
+            .iter()
+            .position(|line| line.contains("┌Preview"))
+            .unwrap();
+        let col = lines[row][..lines[row].find("┌Preview").unwrap()]
+            .chars()
+            .count();
+        assert_eq!(buffer.get(col as u16, row as u16).fg, app.theme.accent);
+        assert_ne!(buffer.get(0, 3).fg, app.theme.accent);
+
+        key(&mut app, KeyCode::BackTab);
+        key(&mut app, KeyCode::BackTab);
+        assert_eq!(app.status, "Focus: file list");
+        key(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected, 1);
+    }
+
+    #[test]
+    fn split_then_cat_round_trips_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..2500u32).map(|n| (n % 251) as u8).collect();
+        fs::write(dir.path().join("data.bin"), &data).unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        select_name(&mut app, "data.bin");
+
+        app.run_command("split 1KiB".into());
+        finish_job(&runtime, &mut app, &mut rx);
+        let parts: Vec<PathBuf> = (1..=3)
+            .map(|n| dir.path().join(format!("data.bin.part00{n}")))
+            .collect();
+        let sizes: Vec<u64> = parts
+            .iter()
+            .map(|part| fs::metadata(part).unwrap().len())
+            .collect();
+        assert_eq!(sizes, [1024, 1024, 452]);
+
+        select_name(&mut app, "data.bin");
+        app.run_command("split 1KiB".into());
+        assert!(
+            app.status
+                .starts_with("split failed: 3 part name(s) already exist")
+        );
+        app.run_command("split 4KiB".into());
+        assert!(app.status.contains("is not larger than"));
+
+        app.marked.extend(parts);
+        app.run_command("cat joined.bin".into());
+        finish_job(&runtime, &mut app, &mut rx);
+        assert_eq!(fs::read(dir.path().join("joined.bin")).unwrap(), data);
+        assert!(app.marked.is_empty());
+
+        app.marked.insert(dir.path().join("data.bin"));
+        app.run_command("cat joined.bin".into());
+        assert_eq!(app.status, "cat failed: 'joined.bin' already exists");
+    }
+
+    #[test]
+    fn df_overlay_shows_a_gauge_or_the_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app) = test_app(dir.path());
+
+        app.input_mode = InputMode::DiskUsage {
+            dir: dir.path().to_path_buf(),
+            space: Ok(DiskSpace {
+                total: 1000 << 20,
+                free: 50 << 20,
+                available: 40 << 20,
+            }),
+        };
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 24));
+        assert!(lines.iter().any(|line| line.contains("95% used")));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("Used 950.0 MiB of 1000.0 MiB, 40.0 MiB available"))
+        );
+
+        app.input_mode = InputMode::DiskUsage {
+            dir: dir.path().to_path_buf(),
+            space: Err("statvfs failed".into()),
+        };
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 24));
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("Could not read free space: statvfs failed"))
+        );
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Esc)).unwrap();
+        assert!(matches!(app.input_mode, InputMode::Normal));
+        assert_eq!(app.status, "Disk usage closed");
+
+        app.run_command("df".into());
+        assert!(matches!(
+            app.input_mode,
+            InputMode::DiskUsage { space: Ok(_), .. }
+        ));
     }
 
-    fn command_copy(&mut self, target: &str) -> Result<()> {
-        let entry = self
-            .selected_entry()
-            .cloned()
-            .ok_or_else(|| anyhow!("No selection to copy"))?;
-        let src = self
-            .selected_path()
-            .ok_or_else(|| anyhow!("No selection to copy"))?;
-        let dest = self.compute_destination(target, &entry.name)?;
-        if dest.exists() {
-            return Err(anyhow!("Destination {} already exists", dest.display()));
+    #[test]
+    fn global_marks_survive_navigation_and_feed_bulk_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["a", "b", "dest"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
         }
-        if entry.is_dir {
-            copy_directory(&src, &dest)?;
-        } else {
-            ensure_parent_dir(&dest)?;
-            fs::copy(&src, &dest)
-                .with_context(|| format!("copying {} to {}", entry.name, dest.display()))?;
+        fs::write(dir.path().join("a/x.txt"), "x").unwrap();
+        fs::write(dir.path().join("b/y.txt"), "y").unwrap();
+        let config = Config {
+            global_marks: true,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(&dir.path().join("a"), config);
+
+        app.toggle_mark();
+        app.navigate_to(dir.path().join("b"), None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        app.toggle_mark();
+        assert_eq!(
+            app.mark_summary().unwrap(),
+            "2 marked globally, 1 B + 1 elsewhere"
+        );
+
+        app.run_command(format!("copy {}", dir.path().join("dest").display()));
+        finish_job(&runtime, &mut app, &mut rx);
+        assert!(dir.path().join("dest/x.txt").is_file());
+        assert!(dir.path().join("dest/y.txt").is_file());
+        assert!(app.marked.is_empty());
+
+        app.run_command("marks global".into());
+        assert_eq!(app.status, "Marks are cleared when leaving a directory");
+        app.toggle_mark();
+        app.navigate_to(dir.path().join("a"), None, "").unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert!(app.marked.is_empty());
+    }
+
+    #[test]
+    fn relative_numbers_and_count_previews_guide_counted_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..12 {
+            fs::write(dir.path().join(format!("f{i:02}")), "").unwrap();
         }
-        self.refresh_with_message(
-            false,
-            format!("Copied {} to {}", entry.name, dest.display()),
-        )?;
-        Ok(())
+        let (_runtime, mut app) = test_app(dir.path());
+        app.selected = 3;
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('5'))).unwrap();
+        assert_eq!(app.status, "Count: 5 (j → entry 9, k → entry 1)");
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('0'))).unwrap();
+        assert_eq!(app.status, "Count: 50 (j → entry 12, k → entry 1)");
+        app.clear_pending_count();
+
+        app.run_command("relnum".into());
+        assert_eq!(app.status, "Relative numbers on");
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        let row = |name: &str| {
+            let line = lines
+                .iter()
+                .find(|line| line.contains(&format!("] {name}")))
+                .unwrap();
+            line.chars().skip(1).take(7).collect::<String>()
+        };
+        assert_eq!(row("f01"), "   2 [F");
+        assert_eq!(row("f03"), ">  4 [F");
+        assert_eq!(row("f05"), "   2 [F");
     }
 
-    fn command_move(&mut self, target: &str) -> Result<()> {
-        let entry = self
-            .selected_entry()
-            .cloned()
-            .ok_or_else(|| anyhow!("No selection to move"))?;
-        let src = self
-            .selected_path()
-            .ok_or_else(|| anyhow!("No selection to move"))?;
-        let dest = self.compute_destination(target, &entry.name)?;
-        if dest.exists() {
-            return Err(anyhow!("Destination {} already exists", dest.display()));
+    #[test]
+    fn queued_operations_wait_for_review_then_run_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest");
+        fs::create_dir(&dest).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.path().join(name), name).unwrap();
         }
-        if let Err(err) = fs::rename(&src, &dest) {
-            eprintln!(
-                "rename failed {}; falling back to copy/remove: {err}",
-                entry.name
-            );
-            if entry.is_dir {
-                copy_directory(&src, &dest)?;
-                fs::remove_dir_all(&src).with_context(|| format!("removing {}", entry.name))?;
-            } else {
-                ensure_parent_dir(&dest)?;
-                fs::copy(&src, &dest)
-                    .with_context(|| format!("copying {} to {}", entry.name, dest.display()))?;
-                fs::remove_file(&src).with_context(|| format!("removing {}", entry.name))?;
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let key =
+            |app: &mut App, code: KeyCode| handle_key_event(app, KeyEvent::from(code)).unwrap();
+
+        app.run_command("queue on".into());
+        select_name(&mut app, "a.txt");
+        app.run_command(format!("copy {}", dest.display()));
+        select_name(&mut app, "b.txt");
+        app.run_command(format!("move {}", dest.display()));
+        select_name(&mut app, "c.txt");
+        app.run_command("delete!".into());
+        assert_eq!(
+            app.status,
+            "Queued 1 operation(s), 3 pending (:queue to review)"
+        );
+        assert!(dir.path().join("b.txt").exists() && dir.path().join("c.txt").exists());
+        assert!(!dest.join("a.txt").exists());
+
+        app.run_command("queue".into());
+        let InputMode::Picker { items, .. } = &app.input_mode else {
+            panic!("expected the queue review");
+        };
+        assert_eq!(
+            items,
+            &[
+                format!(
+                    "copy {} -> {}",
+                    dir.path().join("a.txt").display(),
+                    dest.join("a.txt").display()
+                ),
+                format!(
+                    "move {} -> {}",
+                    dir.path().join("b.txt").display(),
+                    dest.join("b.txt").display()
+                ),
+                format!("delete {} permanently", dir.path().join("c.txt").display()),
+            ]
+        );
+        key(&mut app, KeyCode::Down);
+        key(&mut app, KeyCode::Char('d'));
+        assert_eq!(app.queued.len(), 2);
+        key(&mut app, KeyCode::Enter);
+        finish_job(&runtime, &mut app, &mut rx);
+
+        assert!(app.queued.is_empty());
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a.txt");
+        assert!(dir.path().join("a.txt").exists());
+        assert!(dir.path().join("b.txt").exists() && !dest.join("b.txt").exists());
+        assert!(!dir.path().join("c.txt").exists());
+
+        app.run_command("queue clear".into());
+        assert_eq!(app.status, "Discarded 0 queued operation(s)");
+    }
+
+    #[test]
+    fn quitting_with_queued_operations_asks_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let key = |app: &mut App, ch: char| {
+            handle_key_event(app, KeyEvent::from(KeyCode::Char(ch))).unwrap()
+        };
+        assert!(key(&mut app, 'q'));
+
+        app.enqueue(vec![Operation::Remove {
+            path: dir.path().join("a.txt"),
+        }])
+        .unwrap();
+        assert!(!key(&mut app, 'q'));
+        let InputMode::Confirm { message, .. } = &app.input_mode else {
+            panic!("expected the quit prompt");
+        };
+        assert_eq!(message, "1 queued operation(s) have not run.");
+        assert!(!key(&mut app, 'c'));
+        assert_eq!(app.status, "Quit canceled");
+        assert_eq!(app.queued.len(), 1);
+
+        key(&mut app, 'q');
+        assert!(key(&mut app, 'd'));
+        assert!(dir.path().join("a.txt").exists());
+
+        app.input_mode = InputMode::Normal;
+        key(&mut app, 'q');
+        assert!(!key(&mut app, 'e'));
+        finish_job(&runtime, &mut app, &mut rx);
+        assert!(!dir.path().join("a.txt").exists());
+        assert!(app.quit_requested);
+    }
+
+    #[test]
+    fn office_documents_preview_as_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let write_zip = |name: &str, parts: &[(&str, &str)]| {
+            let path = dir.path().join(name);
+            let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            for (part, xml) in parts {
+                zip.start_file(*part, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                io::Write::write_all(&mut zip, xml.as_bytes()).unwrap();
             }
+            zip.finish().unwrap();
+            path
+        };
+
+        let docx = write_zip(
+            "report.docx",
+            &[(
+                "word/document.xml",
+                "<w:body><w:p><w:r><w:t>Q&amp;A</w:t><w:tab/><w:t>notes</w:t></w:r></w:p>\
+                 <w:p><w:t>second &#x263A;</w:t></w:p></w:body>",
+            )],
+        );
+        let preview = preview_office(&docx, office_parts(&docx).unwrap()).unwrap();
+        assert_eq!(preview.title, "Document");
+        assert_eq!(preview.body, "Q&A\tnotes\nsecond ☺");
+
+        let pptx = write_zip(
+            "deck.pptx",
+            &[
+                ("ppt/slides/slide10.xml", "<a:p>ten</a:p>"),
+                ("ppt/slides/slide2.xml", "<a:p>two</a:p>"),
+            ],
+        );
+        let preview = preview_office(&pptx, office_parts(&pptx).unwrap()).unwrap();
+        assert_eq!(preview.body, "two\nten");
+
+        let broken = write_zip("broken.odt", &[("mimetype", "x")]);
+        assert!(preview_office(&broken, "content.xml").is_err());
+        fs::write(dir.path().join("plain.docx"), "not a zip").unwrap();
+        let (_runtime, app) = test_app(dir.path());
+        let entry = app.entries.iter().find(|e| e.name == "plain.docx").unwrap();
+        let preview = build_preview(entry, &entry.path, &app.preview_options).unwrap();
+        assert_eq!(preview.body, "not a zip");
+    }
+    #[test]
+    fn recent_lists_the_newest_files_and_jumps_to_one() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::create_dir_all(dir.path().join(".hidden")).unwrap();
+        let now = SystemTime::now();
+        for (path, age) in [
+            ("old.txt", 300),
+            ("a/newest.txt", 10),
+            ("a/b/deep.txt", 20),
+            (".hidden/secret.txt", 1),
+            ("middle.txt", 100),
+        ] {
+            let file = fs::File::create(dir.path().join(path)).unwrap();
+            file.set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+        let filter = WalkFilter {
+            show_hidden: false,
+            skip_ignored: false,
+        };
+        let names = |found: Vec<(PathBuf, SystemTime)>| -> Vec<PathBuf> {
+            found
+                .into_iter()
+                .map(|(path, _)| path.strip_prefix(dir.path()).unwrap().to_path_buf())
+                .collect()
+        };
+        assert_eq!(
+            names(recent_files(dir.path(), 3, 8, filter, |_| {})),
+            [
+                PathBuf::from("a/newest.txt"),
+                PathBuf::from("a/b/deep.txt"),
+                PathBuf::from("middle.txt"),
+            ]
+        );
+        assert_eq!(
+            names(recent_files(dir.path(), 10, 1, filter, |_| {})),
+            [
+                PathBuf::from("a/newest.txt"),
+                PathBuf::from("middle.txt"),
+                PathBuf::from("old.txt"),
+            ]
+        );
+
+        let config = Config {
+            show_hidden: false,
+            ..Config::default()
+        };
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), config);
+        app.run_command("recent 2".into());
+        while app.pending_recent.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
         }
+        assert_eq!(app.status, "2 most recently modified files");
+        let InputMode::Picker { title, items, .. } = &app.input_mode else {
+            panic!("expected the recent picker");
+        };
+        assert_eq!(title, "Recent files (2)");
+        assert!(items[0].ends_with("  a/newest.txt"), "{items:?}");
+        assert!(items[1].ends_with("  a/b/deep.txt"), "{items:?}");
 
-        self.refresh_with_message(true, format!("Moved {} to {}", entry.name, dest.display()))?;
-        Ok(())
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Down)).unwrap();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Enter)).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path().join("a/b"));
+        assert_eq!(app.selected_entry().unwrap().name, "deep.txt");
+
+        app.run_command("recent 0".into());
+        assert_eq!(app.status, "recent failed: Invalid count '0'");
     }
-}
+    #[test]
+    fn vertical_orientation_stacks_the_list_above_the_preview() {
+        let (config, warnings) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[layout]\norientation = \"sideways\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.layout.orientation, Orientation::Auto);
+        assert_eq!(
+            warnings,
+            [
+                "Unknown layout.orientation 'sideways' in /cfg/config.toml (use auto, horizontal or vertical)"
+            ]
+        );
+        assert!(!Orientation::Auto.is_vertical(Rect::new(0, 0, 100, 30)));
+        assert!(Orientation::Auto.is_vertical(Rect::new(0, 0, 40, 30)));
+        assert!(!Orientation::Horizontal.is_vertical(Rect::new(0, 0, 40, 30)));
+        assert!(Orientation::Vertical.is_vertical(Rect::new(0, 0, 100, 30)));
 
-#[derive(Clone)]
-struct FileEntry {
-    name: String,
-    is_dir: bool,
-    size: Option<u64>,
-    modified: Option<SystemTime>,
-}
+        let corners = |app: &App, width: u16, height: u16| -> Vec<(usize, usize)> {
+            buffer_lines(&render_to_buffer(app, width, height))
+                .iter()
+                .enumerate()
+                .take(height as usize - 3)
+                .skip(3)
+                .flat_map(|(row, line)| {
+                    line.chars()
+                        .enumerate()
+                        .filter(|&(_, ch)| ch == '┌')
+                        .map(move |(col, _)| (row, col))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        };
+        let dir = tempfile::tempdir().unwrap();
 
-impl FileEntry {
-    fn describe(&self) -> String {
-        let kind = if self.is_dir { "Directory" } else { "File" };
-        let size = self
-            .size
-            .map(|s| format!("{s} bytes"))
-            .unwrap_or_else(|| "—".into());
-        let modified = self
-            .modified
-            .and_then(|time| time.elapsed().ok())
-            .map(|elapsed| format!("{:?} ago", elapsed))
-            .unwrap_or_else(|| "unknown".into());
-        format!(
-            "{kind}\nName: {}\nSize: {}\nModified: {}",
-            self.name, size, modified
+        // Forced vertical on a wide terminal: details sit beside the preview.
+        let (config, _) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[layout]\norientation = \"vertical\"\n",
         )
-    }
-}
+        .unwrap();
+        let (_runtime, app, _rx) = test_app_with(dir.path(), config);
+        let found = corners(&app, 100, 40);
+        assert_eq!(found.len(), 3, "{found:?}");
+        assert_eq!(found[0], (3, 0));
+        assert_eq!(found[1].1, 0);
+        assert_eq!(found[2].0, found[1].0);
+        assert!(found[2].1 > 0);
 
-type FsResult<T> = std::result::Result<T, String>;
+        // Auto on a tall, narrow terminal: everything stacks in one column.
+        let (_runtime, app, _rx) = test_app_with(dir.path(), Config::default());
+        let found = corners(&app, 30, 60);
+        assert_eq!(found.len(), 3, "{found:?}");
+        assert!(found.iter().all(|&(_, col)| col == 0), "{found:?}");
 
-enum FsEvent {
-    DirectoryLoaded {
-        path: PathBuf,
-        token: u64,
-        result: FsResult<Vec<FileEntry>>,
-    },
-}
+        // Auto on a wide terminal keeps the horizontal split.
+        let found = corners(&app, 100, 20);
+        assert_eq!(found[0], (3, 0));
+        assert_eq!(found[1], (3, 50));
+    }
+    #[test]
+    fn scans_retry_transient_errors_but_fail_fast_on_permanent_ones() {
+        let transient = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut))
+            .context("read dir /mnt/share");
+        assert!(is_transient_error(&transient));
+        let missing = anyhow::Error::from(io::Error::from(io::ErrorKind::NotFound))
+            .context("read dir /mnt/share");
+        assert!(!is_transient_error(&missing));
+        assert!(!is_transient_error(&anyhow!("not an io error")));
 
-#[derive(Clone)]
-struct FsDispatcher {
-    handle: Handle,
-    event_tx: UnboundedSender<FsEvent>,
-}
+        let (config, _) =
+            parse_config(Path::new("/cfg/config.toml"), "scan_retries = 5\n").unwrap();
+        assert_eq!(config.scan.retries, 5);
 
-impl FsDispatcher {
-    fn new(runtime: &Runtime) -> (Self, UnboundedReceiver<FsEvent>) {
-        let (event_tx, event_rx) = unbounded_channel();
-        let dispatcher = Self {
-            handle: runtime.handle().clone(),
-            event_tx,
+        let dir = tempfile::tempdir().unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let gone = dir.path().join("gone");
+        let token = app.next_token;
+        app.next_token += 1;
+        let ignore = app.scan_rules(&gone);
+        app.fs
+            .request_directory_scan(gone, token, app.scan_options, ignore)
+            .unwrap();
+        let FsEvent::DirectoryLoaded { result, .. } = runtime.block_on(rx.recv()).unwrap() else {
+            panic!("a missing directory should fail without retrying");
         };
-        (dispatcher, event_rx)
-    }
+        assert!(result.is_err());
 
-    fn request_directory_scan(&self, path: PathBuf, token: u64) -> Result<()> {
-        let tx = self.event_tx.clone();
-        self.handle.spawn_blocking(move || {
-            let result = read_directory(&path).map_err(|err| format!("{err:#}"));
-            let _ = tx.send(FsEvent::DirectoryLoaded {
-                path,
-                token,
-                result,
-            });
+        app.pending_token = Some(token);
+        app.handle_fs_event(FsEvent::ScanRetry {
+            token,
+            attempt: 1,
+            retries: 2,
+            error: "timed out".into(),
         });
-        Ok(())
+        assert_eq!(app.status, "Retrying scan (1/2): timed out");
+        app.handle_fs_event(FsEvent::ScanRetry {
+            token: token + 100,
+            attempt: 2,
+            retries: 2,
+            error: "stale".into(),
+        });
+        assert_eq!(app.status, "Retrying scan (1/2): timed out");
     }
-}
+    #[test]
+    fn space_and_visual_mode_mark_entries_that_survive_sorting() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let marked_names = |app: &App| -> Vec<String> {
+            app.sorted_marks()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        select_name(&mut app, "a.txt");
 
-fn read_directory(dir: &Path) -> Result<Vec<FileEntry>> {
-    let mut entries: Vec<FileEntry> = fs::read_dir(dir)
-        .with_context(|| format!("read dir {}", dir.display()))?
-        .filter_map(|res| match res {
-            Ok(entry) => Some(entry),
-            Err(err) => {
-                eprintln!("Skipping entry: {err}");
-                None
-            }
-        })
-        .filter_map(|entry| {
-            let name = entry.file_name().to_string_lossy().into_owned();
-            let meta = entry.metadata().ok()?;
-            let size = (!meta.is_dir()).then_some(meta.len());
-            Some(FileEntry {
-                name,
-                is_dir: meta.is_dir(),
-                size,
-                modified: meta.modified().ok(),
-            })
-        })
-        .collect();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char(' '))).unwrap();
+        assert_eq!(app.selected_entry().unwrap().name, "b.txt");
+        assert_eq!(app.status, "1 marked");
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char(' '))).unwrap();
+        assert_eq!(marked_names(&app), ["a.txt", "b.txt"]);
 
-    entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-        (true, false) => cmp::Ordering::Less,
-        (false, true) => cmp::Ordering::Greater,
-        _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-    });
-    Ok(entries)
-}
+        select_name(&mut app, "d.txt");
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('v'))).unwrap();
+        assert!(app.footer_text(80).contains("VISUAL"));
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('j'))).unwrap();
+        assert_eq!(marked_names(&app), ["a.txt", "b.txt", "d.txt", "e.txt"]);
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Esc)).unwrap();
+        assert!(app.visual.is_none());
+        assert_eq!(app.status, "Visual mode off, 4 marked");
 
-fn build_preview(entry: &FileEntry, path: &Path) -> Result<PreviewPane> {
-    if entry.is_dir {
-        return preview_directory(path);
+        // Unmarking the selected entry with Space toggles it back off.
+        select_name(&mut app, "b.txt");
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char(' '))).unwrap();
+        assert_eq!(marked_names(&app), ["a.txt", "d.txt", "e.txt"]);
+
+        app.run_command("sort!".into());
+        app.refresh_async(true).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        let shown: Vec<&str> = app
+            .entries
+            .iter()
+            .filter(|entry| app.is_marked(entry))
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(shown, ["e.txt", "d.txt", "a.txt"]);
+
+        fs::create_dir(dir.path().join("dest")).unwrap();
+        app.run_command("copy dest".into());
+        finish_job(&runtime, &mut app, &mut rx);
+        for name in ["a.txt", "d.txt", "e.txt"] {
+            assert!(dir.path().join("dest").join(name).exists(), "{name}");
+        }
+        assert!(!dir.path().join("dest/b.txt").exists());
     }
-    preview_file(entry, path)
-}
+    #[test]
+    fn yanked_entries_paste_repeatedly_but_cut_ones_move_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dest")).unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let press = |app: &mut App, ch: char| {
+            handle_key_event(app, KeyEvent::from(KeyCode::Char(ch))).unwrap();
+        };
 
-fn preview_directory(path: &Path) -> Result<PreviewPane> {
-    let mut rows = Vec::new();
-    let mut entries =
-        fs::read_dir(path).with_context(|| format!("reading directory {}", path.display()))?;
-    for item in entries.by_ref().flatten().take(PREVIEW_DIR_ENTRIES) {
-        let name = item.file_name().to_string_lossy().into_owned();
-        let is_dir = item.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
-        rows.push(format!("{} {}", if is_dir { "[D]" } else { "[F]" }, name));
+        select_name(&mut app, "a.txt");
+        press(&mut app, 'y');
+        assert_eq!(app.status, "Press y again to yank");
+        press(&mut app, 'y');
+        assert_eq!(app.status, "Yanked 1 item(s), pp to paste");
+        assert!(app.footer_text(80).contains("[yank 1]"));
+
+        app.run_command(format!("cd {}", dir.path().join("dest").display()));
+        wait_loaded(&runtime, &mut app, &mut rx);
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        finish_job(&runtime, &mut app, &mut rx);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dest/a.txt")).unwrap(),
+            "a"
+        );
+        assert!(dir.path().join("a.txt").exists());
+        assert!(app.register.is_some());
+
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        assert_eq!(app.status, "Paste failed: 1 name(s) already exist here");
+        assert_eq!(app.toast.as_ref().unwrap().lines, ["a.txt"]);
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Esc)).unwrap();
+
+        app.run_command(format!("cd {}", dir.path().display()));
+        wait_loaded(&runtime, &mut app, &mut rx);
+        select_name(&mut app, "b.txt");
+        press(&mut app, 'x');
+        press(&mut app, 'x');
+        assert!(app.footer_text(80).contains("[cut 1]"));
+        app.run_command(format!("cd {}", dir.path().join("dest").display()));
+        wait_loaded(&runtime, &mut app, &mut rx);
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        finish_job(&runtime, &mut app, &mut rx);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dest/b.txt")).unwrap(),
+            "b"
+        );
+        assert!(!dir.path().join("b.txt").exists());
+        assert!(app.register.is_none());
     }
-    let mut body = if rows.is_empty() {
-        "Directory is empty".to_string()
-    } else {
-        rows.join("\n")
-    };
-    if entries.next().is_some() {
-        if !body.is_empty() {
-            body.push_str("\n...");
-        } else {
-            body = "...".into();
-        }
+    #[test]
+    fn tabs_keep_their_own_directory_selection_and_marks() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("one")).unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        fs::write(dir.path().join("one/inner.txt"), "inner").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        select_name(&mut app, "b.txt");
+        app.marked.insert(dir.path().join("a.txt"));
+
+        app.run_command("tabnew one".into());
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path().join("one"));
+        assert_eq!(app.active_tab, 1);
+        assert!(app.marked.is_empty());
+        let header = buffer_lines(&render_to_buffer(&app, 120, 20)).join("\n");
+        let root_name = display_name(dir.path());
+        assert!(header.contains(&format!(" 1 {root_name} ")), "{header}");
+        assert!(header.contains(" 2 one "), "{header}");
+
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('g'))).unwrap();
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Char('t'))).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.active_tab, 0);
+        assert_eq!(app.current_dir, dir.path());
+        assert_eq!(app.selected_entry().unwrap().name, "b.txt");
+        assert_eq!(app.sorted_marks(), [dir.path().join("a.txt")]);
+
+        handle_key_event(
+            &mut app,
+            KeyEvent::new(KeyCode::Char('2'), KeyModifiers::ALT),
+        )
+        .unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path().join("one"));
+        assert_eq!(app.selected_entry().unwrap().name, "inner.txt");
+
+        app.run_command("tabclose".into());
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.tabs.len(), 1);
+        assert_eq!(app.current_dir, dir.path());
+        app.run_command("tabclose".into());
+        assert_eq!(app.status, "tabclose failed: Can't close the last tab");
+        handle_key_event(
+            &mut app,
+            KeyEvent::new(KeyCode::Char('3'), KeyModifiers::ALT),
+        )
+        .unwrap();
+        assert_eq!(app.status, "Tab switch failed: No tab 3; 1 open");
     }
-    Ok(PreviewPane::new("Preview", body))
-}
+    #[test]
+    fn dual_pane_switches_sides_and_copies_to_the_other_one() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dest")).unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
 
-fn preview_file(entry: &FileEntry, path: &Path) -> Result<PreviewPane> {
-    let mut file = fs::File::open(path).with_context(|| format!("opening {}", entry.name))?;
-    let mut buffer = Vec::new();
-    file.by_ref()
-        .take(PREVIEW_MAX_BYTES as u64)
-        .read_to_end(&mut buffer)
-        .with_context(|| format!("reading {}", entry.name))?;
+        app.run_command("dual".into());
+        assert!(app.dual.is_some());
+        app.run_command(format!("cd {}", dir.path().join("dest").display()));
+        wait_loaded(&runtime, &mut app, &mut rx);
+        let screen = buffer_lines(&render_to_buffer(&app, 120, 20)).join("\n");
+        assert!(screen.contains("a.txt"), "{screen}");
+        assert!(!screen.contains("Details"), "{screen}");
 
-    if buffer.is_empty() {
-        return Ok(PreviewPane::new("Preview", "<empty file>"));
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Tab)).unwrap();
+        wait_loaded(&runtime, &mut app, &mut rx);
+        assert_eq!(app.current_dir, dir.path());
+        assert!(app.dual.as_ref().unwrap().right_active);
+        assert_eq!(
+            app.other_pane_dir(),
+            Some(dir.path().join("dest").as_path())
+        );
+
+        select_name(&mut app, "a.txt");
+        app.run_command("copy".into());
+        finish_job(&runtime, &mut app, &mut rx);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dest/a.txt")).unwrap(),
+            "a"
+        );
+        while app.dual.as_ref().unwrap().token.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
+        }
+        let other = &app.dual.as_ref().unwrap().other;
+        assert!(other.entries.iter().any(|entry| entry.name == "a.txt"));
+
+        app.run_command("dual".into());
+        assert!(app.dual.is_none());
+        app.run_command("copy".into());
+        assert_eq!(app.status, "Usage: :copy <destination>");
     }
+    #[test]
+    fn miller_columns_show_the_parent_listing_beside_the_current_one() {
+        let (config, warnings) = parse_config(
+            Path::new("/cfg/config.toml"),
+            "[layout]\nmiller = true\nparent_width = 60\n",
+        )
+        .unwrap();
+        assert!(config.layout.miller);
+        assert_eq!(config.layout.parent_width, 20);
+        assert_eq!(
+            warnings,
+            ["layout.parent_width 60 in /cfg/config.toml is out of range (10-40)"]
+        );
 
-    if is_text_data(&buffer) {
-        let mut body = String::new();
-        for (idx, line) in String::from_utf8_lossy(&buffer).lines().enumerate() {
-            if idx >= PREVIEW_MAX_LINES {
-                body.push_str("\n...");
-                break;
-            }
-            if idx > 0 {
-                body.push('\n');
-            }
-            body.push_str(line);
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("alpha")).unwrap();
+        fs::create_dir(dir.path().join("beta")).unwrap();
+        fs::write(dir.path().join("beta/inside.txt"), "inside").unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(&dir.path().join("beta"), Config::default());
+
+        app.run_command("miller".into());
+        assert_eq!(app.status, "Miller columns: parent, current and preview");
+        while app.parent_token.is_some() {
+            let event = runtime.block_on(rx.recv()).unwrap();
+            app.handle_fs_event(event);
         }
-        return Ok(PreviewPane::new("Preview", body));
+        let (parent, entries) = app.parent_listing.as_ref().unwrap();
+        assert_eq!(parent, dir.path());
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["alpha", "beta"]);
+
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        let top: Vec<char> = lines[3].chars().collect();
+        let corners: Vec<usize> = (0..top.len()).filter(|&col| top[col] == '┌').collect();
+        assert_eq!(corners, [0, 20, 60]);
+        let parent_column: String = lines[4].chars().take(20).collect();
+        assert!(parent_column.contains("alpha"), "{parent_column}");
+        assert!(lines.iter().any(|line| line.contains("inside.txt")));
+
+        app.run_command("miller".into());
+        assert!(app.parent_listing.is_none());
+        let lines = buffer_lines(&render_to_buffer(&app, 100, 20));
+        assert_eq!(lines[3].matches('┌').count(), 2);
     }
+    #[test]
+    fn history_goes_back_and_forward_reselecting_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in ["a", "b"] {
+            fs::create_dir(dir.path().join(path)).unwrap();
+        }
+        for path in ["b/x.txt", "b/y.txt", "a/z.txt"] {
+            fs::write(dir.path().join(path), "").unwrap();
+        }
+        let (runtime, mut app, mut rx) = test_app_with(dir.path(), Config::default());
+        let mut press = |app: &mut App, key: KeyEvent| {
+            handle_key_event(app, key).unwrap();
+            wait_loaded(&runtime, app, &mut rx);
+        };
 
-    let file_type = describe_file_type(path);
-    Ok(PreviewPane::new(
-        "Preview",
-        format!("Non-text file\nType: {}", file_type),
-    ))
-}
+        select_name(&mut app, "b");
+        press(&mut app, KeyEvent::from(KeyCode::Char('l')));
+        select_name(&mut app, "y.txt");
+        press(&mut app, KeyEvent::from(KeyCode::Char('h')));
+        select_name(&mut app, "a");
+        press(&mut app, KeyEvent::from(KeyCode::Char('l')));
+        assert_eq!(app.current_dir, dir.path().join("a"));
 
-fn is_text_data(buffer: &[u8]) -> bool {
-    !matches!(content_inspector::inspect(buffer), ContentType::BINARY)
-}
+        press(&mut app, KeyEvent::from(KeyCode::Char('H')));
+        assert_eq!(app.current_dir, dir.path());
+        assert_eq!(app.selected_entry().unwrap().name, "a");
+        press(&mut app, KeyEvent::from(KeyCode::Char('H')));
+        assert_eq!(app.current_dir, dir.path().join("b"));
+        assert_eq!(app.selected_entry().unwrap().name, "y.txt");
+        press(
+            &mut app,
+            KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL),
+        );
+        assert_eq!(app.current_dir, dir.path());
+        assert_eq!(app.selected_entry().unwrap().name, "b");
+        press(&mut app, KeyEvent::from(KeyCode::Char('H')));
+        assert_eq!(app.status, "Already at the oldest directory in history");
 
-fn describe_file_type(path: &Path) -> String {
-    match infer::get_from_path(path) {
-        Ok(Some(kind)) => format!("{} ({})", kind.mime_type(), kind.extension()),
-        Ok(None) => "Unknown type".into(),
-        Err(_) => "Unknown type".into(),
+        press(&mut app, KeyEvent::from(KeyCode::Char('3')));
+        press(&mut app, KeyEvent::from(KeyCode::Char('L')));
+        assert_eq!(app.current_dir, dir.path().join("a"));
+        press(&mut app, KeyEvent::from(KeyCode::Char('L')));
+        assert_eq!(app.status, "Already at the newest directory in history");
     }
-}
+    #[test]
+    fn bookmarks_jump_back_and_are_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("deep")).unwrap();
+        fs::create_dir(dir.path().join("gone")).unwrap();
+        let (runtime, mut app, mut rx) = test_app_with(&dir.path().join("deep"), Config::default());
+        let mut press = |app: &mut App, ch: char| {
+            handle_key_event(app, KeyEvent::from(KeyCode::Char(ch))).unwrap();
+            wait_loaded(&runtime, app, &mut rx);
+        };
 
-fn ensure_parent_dir(path: &Path) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("creating parent {}", parent.display()))?;
+        press(&mut app, 'm');
+        assert_eq!(app.status, "Bookmark the current directory as (a-z, 0-9)");
+        press(&mut app, 'w');
+        assert_eq!(app.state.bookmarks.get("w"), Some(&dir.path().join("deep")));
+        app.state
+            .bookmarks
+            .insert("g".into(), dir.path().join("gone"));
+        fs::remove_dir(dir.path().join("gone")).unwrap();
+
+        press(&mut app, 'h');
+        assert_eq!(app.current_dir, dir.path());
+        press(&mut app, '\'');
+        press(&mut app, 'w');
+        assert_eq!(app.current_dir, dir.path().join("deep"));
+        assert_eq!(app.status, "Jumped to bookmark 'w'");
+        press(&mut app, '\'');
+        press(&mut app, 'q');
+        assert_eq!(
+            app.status,
+            "Bookmark failed: No bookmark 'q'; set one with mq"
+        );
+
+        app.run_command("bookmarks".into());
+        let InputMode::Picker { items, .. } = &app.input_mode else {
+            panic!("expected the bookmarks picker");
+        };
+        assert_eq!(
+            items,
+            &[
+                format!("g  {} (missing)", dir.path().join("gone").display()),
+                format!("w  {}", dir.path().join("deep").display()),
+            ]
+        );
+        handle_key_event(&mut app, KeyEvent::from(KeyCode::Esc)).unwrap();
+
+        app.run_command("bookmarks delete g".into());
+        assert_eq!(app.status, "Deleted bookmark 'g'");
+        assert!(!app.state.bookmarks.contains_key("g"));
     }
-    Ok(())
-}
+    #[test]
+    fn zoxide_matches_complete_cd_and_cycle_with_tab() {
+        let (config, _) = parse_config(Path::new("/cfg/config.toml"), "zoxide = true\n").unwrap();
+        assert!(config.zoxide);
 
-fn copy_directory(src: &Path, dest: &Path) -> Result<()> {
-    if dest.exists() {
-        return Err(anyhow!("Destination {} already exists", dest.display()));
+        let dir = tempfile::tempdir().unwrap();
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), config);
+        let tab = |app: &mut App| {
+            handle_key_event(app, KeyEvent::from(KeyCode::Tab)).unwrap();
+        };
+        let command = |app: &App| -> (String, Option<String>) {
+            let InputMode::Command { buffer, feedback } = &app.input_mode else {
+                panic!("expected the command prompt");
+            };
+            (buffer.clone(), feedback.clone())
+        };
+        app.input_mode = InputMode::Command {
+            buffer: "cd proj".into(),
+            feedback: None,
+        };
+
+        tab(&mut app);
+        let token = app.pending_zoxide.unwrap();
+        assert_eq!(command(&app).1.as_deref(), Some("Asking zoxide..."));
+        app.handle_fs_event(FsEvent::ZoxideMatches {
+            token: token + 1,
+            result: Ok(vec![PathBuf::from("/stale")]),
+        });
+        assert_eq!(command(&app).0, "cd proj");
+
+        app.handle_fs_event(FsEvent::ZoxideMatches {
+            token,
+            result: Ok(vec![
+                PathBuf::from("/src/proj"),
+                PathBuf::from("/tmp/project"),
+            ]),
+        });
+        assert_eq!(
+            command(&app),
+            (
+                "cd '/src/proj'".into(),
+                Some("1/2, Tab for the next match".into())
+            )
+        );
+        tab(&mut app);
+        assert_eq!(command(&app).0, "cd '/tmp/project'");
+        tab(&mut app);
+        assert_eq!(command(&app).0, "cd '/src/proj'");
+
+        app.cd_completions = None;
+        tab(&mut app);
+        let token = app.pending_zoxide.unwrap();
+        app.handle_fs_event(FsEvent::ZoxideMatches {
+            token,
+            result: Err("zoxide exited with exit status: 2".into()),
+        });
+        assert_eq!(
+            command(&app).1.as_deref(),
+            Some("zoxide failed: zoxide exited with exit status: 2")
+        );
+    }
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn database_files_preview_as_plain_files_without_the_sqlite_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.db"), "not really a database\n").unwrap();
+        let (_runtime, mut app, _rx) = test_app_with(dir.path(), Config::default());
+        select_name(&mut app, "notes.db");
+
+        assert!(!is_sqlite_file(&dir.path().join("notes.db")));
+        assert!(app.pending_preview.is_none());
+        assert!(app.preview.body.contains("not really a database"));
     }
-    ensure_parent_dir(dest)?;
-    fs::create_dir(dest).with_context(|| format!("creating directory {}", dest.display()))?;
-    let mut options = DirCopyOptions::new();
-    options.copy_inside = true;
-    copy_dir(src, dest, &options)
-        .map(|_| ())
-        .with_context(|| format!("copying {} to {}", src.display(), dest.display()))
 }
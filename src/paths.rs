@@ -0,0 +1,240 @@
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use dirs::home_dir;
+
+pub fn expand(input: &str, base: &Path) -> PathBuf {
+    normalize(&base.join(expand_user(input)))
+}
+
+pub fn expand_user(input: &str) -> PathBuf {
+    if let Some(rest) = input.strip_prefix('~') {
+        let (user, tail) = rest.split_once('/').unwrap_or((rest, ""));
+        let home = if user.is_empty() {
+            home_dir()
+        } else {
+            user_home(user)
+        };
+        if let Some(home) = home {
+            let tail = expand_vars(tail);
+            return if tail.is_empty() {
+                home
+            } else {
+                home.join(tail)
+            };
+        }
+    }
+    PathBuf::from(expand_vars(input))
+}
+
+pub fn resolve_existing_dir(input: &str, base: &Path) -> Result<PathBuf> {
+    let path = expand(input, base);
+    let resolved = fs::canonicalize(&path)
+        .with_context(|| format!("resolving directory {}", path.display()))?;
+    if !resolved.is_dir() {
+        return Err(anyhow!("{} is not a directory", resolved.display()));
+    }
+    Ok(resolved)
+}
+
+pub fn ends_with_separator(input: &str) -> bool {
+    input.ends_with('/') || input.ends_with('\\')
+}
+
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn expand_vars(input: &str) -> String {
+    expand_vars_with(input, |name| env::var(name).ok())
+}
+
+fn expand_vars_with(input: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match (!name.is_empty()).then(|| lookup(name)).flatten() {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[pos..=pos + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<PathBuf> {
+    use std::{
+        ffi::{CStr, CString, OsStr},
+        os::unix::ffi::OsStrExt,
+    };
+
+    let name = CString::new(user).ok()?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if status != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &str) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_joins_relative_input_to_base() {
+        let base = Path::new("/srv/data");
+        assert_eq!(expand("logs", base), PathBuf::from("/srv/data/logs"));
+        assert_eq!(expand("", base), PathBuf::from("/srv/data"));
+        assert_eq!(expand(".", base), PathBuf::from("/srv/data"));
+    }
+
+    #[test]
+    fn expand_keeps_absolute_input() {
+        let base = Path::new("/srv/data");
+        assert_eq!(expand("/etc/ssh", base), PathBuf::from("/etc/ssh"));
+    }
+
+    #[test]
+    fn expand_resolves_dot_components() {
+        let base = Path::new("/srv/data");
+        assert_eq!(expand("a/./b/../c", base), PathBuf::from("/srv/data/a/c"));
+        assert_eq!(expand("..", base), PathBuf::from("/srv"));
+        assert_eq!(expand("../../../..", base), PathBuf::from("/"));
+        assert_eq!(expand("/..", base), PathBuf::from("/"));
+        assert_eq!(normalize(Path::new("../a/../..")), PathBuf::from("../.."));
+    }
+
+    #[test]
+    fn expand_drops_trailing_separators() {
+        let base = Path::new("/srv");
+        assert_eq!(expand("data/", base), PathBuf::from("/srv/data"));
+        assert_eq!(expand("data//", base), PathBuf::from("/srv/data"));
+        assert!(ends_with_separator("data/"));
+        assert!(ends_with_separator("data\\"));
+        assert!(!ends_with_separator("data"));
+    }
+
+    #[test]
+    fn expand_replaces_home_tilde() {
+        let Some(home) = home_dir() else {
+            return;
+        };
+        let base = Path::new("/srv");
+        assert_eq!(expand("~", base), home);
+        assert_eq!(expand("~/", base), home);
+        assert_eq!(
+            expand("~/notes/todo.txt", base),
+            home.join("notes/todo.txt")
+        );
+        assert_eq!(expand("a/~", base), PathBuf::from("/srv/a/~"));
+        assert_eq!(expand("~/..", base), normalize(&home.join("..")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn expand_looks_up_other_users() {
+        let root = user_home("root").expect("root has a passwd entry");
+        assert_eq!(expand("~root/x", Path::new("/srv")), root.join("x"));
+        assert_eq!(
+            expand("~no_such_user_wayfinder/x", Path::new("/srv")),
+            PathBuf::from("/srv/~no_such_user_wayfinder/x")
+        );
+    }
+
+    fn tools_var(name: &str) -> Option<String> {
+        (name == "TOOLS").then(|| "/opt/tools".to_string())
+    }
+
+    #[test]
+    fn expand_vars_substitutes_known_variables() {
+        assert_eq!(expand_vars_with("$TOOLS/bin", tools_var), "/opt/tools/bin");
+        assert_eq!(
+            expand_vars_with("${TOOLS}-old", tools_var),
+            "/opt/tools-old"
+        );
+        assert_eq!(expand_vars_with("a$TOOLS.b", tools_var), "a/opt/tools.b");
+    }
+
+    #[test]
+    fn expand_vars_leaves_missing_variables_alone() {
+        assert_eq!(expand_vars_with("$MISSING/x", tools_var), "$MISSING/x");
+        assert_eq!(expand_vars_with("${MISSING}", tools_var), "${MISSING}");
+        assert_eq!(expand_vars_with("cost$", tools_var), "cost$");
+        assert_eq!(expand_vars_with("${unclosed", tools_var), "${unclosed");
+        assert_eq!(expand_vars_with("$$TOOLS", tools_var), "$/opt/tools");
+    }
+
+    #[test]
+    fn resolve_existing_dir_canonicalizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        assert_eq!(
+            resolve_existing_dir("sub/", &root).unwrap(),
+            root.join("sub")
+        );
+        assert_eq!(
+            resolve_existing_dir("sub/../sub/.", &root).unwrap(),
+            root.join("sub")
+        );
+    }
+
+    #[test]
+    fn resolve_existing_dir_rejects_files_and_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("file.txt"), "x").unwrap();
+        let err = resolve_existing_dir("file.txt", dir.path()).unwrap_err();
+        assert!(err.to_string().contains("is not a directory"));
+        assert!(resolve_existing_dir("missing", dir.path()).is_err());
+    }
+}